@@ -0,0 +1,540 @@
+//! `bank apply`: bring the filesystem in line with a manifest, after showing
+//! a plan of what would change
+
+use crate::hash::{self, HashAlgorithm};
+use crate::journal;
+use crate::json;
+use crate::manifest::{format_time, kind_name, Entry, Facts, Manifest};
+use crate::owner;
+use crate::scaffold;
+use crate::vfs::{FileKind, Filesystem, Metadata, RecordingFs};
+use anyhow::{bail, Context, Result};
+use clap::{Args as ClapArgs, ValueEnum};
+use colored::*;
+use dialoguer::{theme::ColorfulTheme, Confirm, Input};
+use std::collections::HashMap;
+use std::io::{ErrorKind, IsTerminal};
+use std::path::{Path, PathBuf};
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct ApplyArgs {
+    /// Manifest to apply (YAML, or JSON for .json files)
+    #[arg(value_name = "MANIFEST")]
+    pub manifest: PathBuf,
+
+    /// Only print the plan; change nothing
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// How to print the plan: text, or JSON for other tools (with --dry-run)
+    #[arg(long = "output", value_name = "FORMAT", value_enum, default_value = "text", requires = "dry_run")]
+    pub output: PlanFormat,
+
+    /// Value for a `${NAME}` placeholder in the manifest (repeatable); missing ones are asked for on a terminal
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = scaffold::parse_var)]
+    pub vars: Vec<(String, String)>,
+
+    /// Profile that `when: profile=...` conditions are checked against (default: $BANK_PROFILE)
+    #[arg(long = "profile", value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Apply the plan without asking for confirmation
+    #[arg(short = 'y', long = "yes")]
+    pub yes: bool,
+
+    /// Exit with status 10 instead of 0 when everything already matches the manifest
+    #[arg(long = "changed-exit-code")]
+    pub changed_exit_code: bool,
+
+    /// Do not record this run in the undo journal
+    #[arg(long = "no-journal")]
+    pub no_journal: bool,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlanFormat {
+    Text,
+    Json,
+}
+
+/// One attribute that differs from the manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    pub attribute: &'static str,
+    pub current: String,
+    pub desired: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Create,
+    Update(Vec<Difference>),
+    Unchanged,
+    /// The manifest cannot be applied to this path without losing data
+    Conflict(String),
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Create => "create",
+            Action::Update(_) => "update",
+            Action::Unchanged => "unchanged",
+            Action::Conflict(_) => "conflict",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    /// The desired state
+    pub entry: Entry,
+    /// What is on disk now, if anything
+    pub current: Option<Metadata>,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub steps: Vec<Step>,
+}
+
+impl Plan {
+    /// Compare every manifest entry with what is on disk
+    pub fn new(fs: &dyn Filesystem, manifest: &Manifest) -> Result<Self> {
+        let steps = manifest
+            .entries
+            .iter()
+            .map(|entry| {
+                let current = match fs.symlink_metadata(Path::new(&entry.path)) {
+                    Ok(metadata) => Some(metadata),
+                    Err(err) if err.kind() == ErrorKind::NotFound => None,
+                    Err(err) => return Err(err).with_context(|| format!("Failed to read metadata for {}", entry.path)),
+                };
+                let action = plan_entry(fs, entry, current.as_ref())?;
+                Ok(Step { entry: entry.clone(), current, action })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { steps })
+    }
+
+    fn count(&self, matches: fn(&Action) -> bool) -> usize {
+        self.steps.iter().filter(|step| matches(&step.action)).count()
+    }
+
+    pub fn conflicts(&self) -> usize {
+        self.count(|action| matches!(action, Action::Conflict(_)))
+    }
+
+    /// Entries that would be created or updated
+    pub fn changes(&self) -> usize {
+        self.count(|action| matches!(action, Action::Create | Action::Update(_)))
+    }
+
+    /// Diff-style listing: `+` create, `~` update, `=` unchanged, `!` conflict
+    pub fn print(&self) {
+        for step in &self.steps {
+            let path = &step.entry.path;
+            match &step.action {
+                Action::Create => {
+                    let line = format!("+ {} ({})", path, describe_new(&step.entry));
+                    println!("{}", line.green());
+                }
+                Action::Update(differences) => {
+                    println!("{}", format!("~ {}", path).yellow());
+                    for difference in differences {
+                        println!("    {}: {} → {}", difference.attribute, difference.current.red(), difference.desired.green());
+                    }
+                }
+                Action::Unchanged => println!("{}", format!("= {}", path).bright_black()),
+                Action::Conflict(reason) => println!("{}", format!("! {}: {}", path, reason).red()),
+            }
+        }
+        let creates = self.count(|action| matches!(action, Action::Create));
+        let updates = self.count(|action| matches!(action, Action::Update(_)));
+        let unchanged = self.count(|action| matches!(action, Action::Unchanged));
+        println!(
+            "{} to create, {} to update, {} unchanged, {} conflicts",
+            creates.to_string().green(),
+            updates.to_string().yellow(),
+            unchanged.to_string().bright_black(),
+            self.conflicts().to_string().red()
+        );
+    }
+
+    /// Every step with its action and the current and desired state, for other tools
+    pub fn to_json(&self) -> String {
+        let steps: Vec<String> = self
+            .steps
+            .iter()
+            .map(|step| {
+                let mut fields = vec![
+                    format!("\"path\": {}", json::quote(&step.entry.path)),
+                    format!("\"action\": {}", json::quote(step.action.name())),
+                ];
+                if let Action::Conflict(reason) = &step.action {
+                    fields.push(format!("\"reason\": {}", json::quote(reason)));
+                }
+                let current = step
+                    .current
+                    .as_ref()
+                    .map_or("null".to_string(), |metadata| state_json(&Entry::from_metadata(&step.entry.path, metadata)));
+                fields.push(format!("\"current\": {}", current));
+                fields.push(format!("\"desired\": {}", state_json(&step.entry)));
+                if let Action::Update(differences) = &step.action {
+                    let changes: Vec<String> = differences
+                        .iter()
+                        .map(|difference| {
+                            format!(
+                                "{{\"attribute\": {}, \"current\": {}, \"desired\": {}}}",
+                                json::quote(difference.attribute),
+                                json::quote(&difference.current),
+                                json::quote(&difference.desired)
+                            )
+                        })
+                        .collect();
+                    fields.push(format!("\"changes\": [{}]", changes.join(", ")));
+                }
+                format!("    {{{}}}", fields.join(", "))
+            })
+            .collect();
+        let steps = if steps.is_empty() { "[]".to_string() } else { format!("[\n{}\n  ]", steps.join(",\n")) };
+        format!(
+            "{{\n  \"version\": 1,\n  \"summary\": {{\"create\": {}, \"update\": {}, \"unchanged\": {}, \"conflict\": {}}},\n  \"steps\": {}\n}}\n",
+            self.count(|action| matches!(action, Action::Create)),
+            self.count(|action| matches!(action, Action::Update(_))),
+            self.count(|action| matches!(action, Action::Unchanged)),
+            self.conflicts(),
+            steps
+        )
+    }
+
+    /// Print the plan in `format`, failing if it has conflicts
+    pub fn show(&self, format: PlanFormat) -> Result<()> {
+        match format {
+            PlanFormat::Text => self.print(),
+            PlanFormat::Json => print!("{}", self.to_json()),
+        }
+        if self.conflicts() > 0 {
+            bail!("{} conflicts; nothing was applied", self.conflicts());
+        }
+        Ok(())
+    }
+}
+
+/// The attributes of an entry as a JSON object, without its path
+fn state_json(entry: &Entry) -> String {
+    let fields: Vec<String> = entry
+        .fields()
+        .into_iter()
+        .filter(|(key, _)| *key != "path")
+        .map(|(key, value)| format!("{}: {}", json::quote(key), value))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn plan_entry(fs: &dyn Filesystem, entry: &Entry, current: Option<&Metadata>) -> Result<Action> {
+    let path = Path::new(&entry.path);
+    let metadata = match current {
+        Some(metadata) => metadata,
+        None if entry.kind == FileKind::Symlink => {
+            return Ok(Action::Conflict("manifests do not record symlink targets, so it cannot be created".to_string()));
+        }
+        None => return Ok(Action::Create),
+    };
+
+    if metadata.kind != entry.kind {
+        return Ok(Action::Conflict(format!(
+            "exists as {}, the manifest wants {}",
+            kind_name(metadata.kind),
+            kind_name(entry.kind)
+        )));
+    }
+    if let (Some(expected), FileKind::File) = (&entry.hash, entry.kind) {
+        let (name, _) = expected.split_once(':').with_context(|| format!("Invalid hash '{}' for {}", expected, entry.path))?;
+        let algorithm = HashAlgorithm::from_str(name, true)
+            .map_err(|_| anyhow::anyhow!("Unknown hash algorithm '{}' for {}", name, entry.path))?;
+        let actual = hash::hash_file(path, algorithm).with_context(|| format!("Failed to hash {}", entry.path))?;
+        if actual != *expected {
+            return Ok(Action::Conflict("its contents differ from the recorded hash".to_string()));
+        }
+    }
+    if let (Some(expected), FileKind::File) = (&entry.content, entry.kind) {
+        let actual = fs.read_file(path).with_context(|| format!("Failed to read {}", entry.path))?;
+        if actual != expected.as_bytes() {
+            return Ok(Action::Conflict("its contents differ from the manifest".to_string()));
+        }
+    }
+
+    let mut differences = Vec::new();
+    if let Some(mode) = entry.mode.filter(|mode| *mode != metadata.mode & 0o7777) {
+        differences.push(Difference {
+            attribute: "mode",
+            current: format!("{:04o}", metadata.mode & 0o7777),
+            desired: format!("{:04o}", mode),
+        });
+    }
+    if let Some(spec) = &entry.owner {
+        if !owner::matches(spec, metadata.uid, metadata.gid).with_context(|| format!("Invalid owner for {}", entry.path))? {
+            differences.push(Difference {
+                attribute: "owner",
+                current: owner::describe(metadata.uid, metadata.gid),
+                desired: spec.clone(),
+            });
+        }
+    }
+    if let Some(mtime) = entry.mtime.filter(|mtime| *mtime != metadata.modified) {
+        differences.push(Difference {
+            attribute: "mtime",
+            current: format_time(metadata.modified),
+            desired: format_time(mtime),
+        });
+    }
+    Ok(if differences.is_empty() { Action::Unchanged } else { Action::Update(differences) })
+}
+
+/// `dir, mode 0750, owner root` for a path about to be created
+fn describe_new(entry: &Entry) -> String {
+    let mut parts = vec![kind_name(entry.kind).to_string()];
+    if let Some(mode) = entry.mode {
+        parts.push(format!("mode {:04o}", mode));
+    }
+    if let Some(owner) = &entry.owner {
+        parts.push(format!("owner {}", owner));
+    }
+    if let Some(mtime) = entry.mtime {
+        parts.push(format!("mtime {}", format_time(mtime)));
+    }
+    if let Some(content) = &entry.content {
+        parts.push(format!("{} bytes", content.len()));
+    }
+    parts.join(", ")
+}
+
+/// Apply a manifest; returns whether anything had to change
+pub fn run(fs: &dyn Filesystem, args: &ApplyArgs) -> Result<bool> {
+    let (mut manifest, mut positions) = Manifest::load(&args.manifest)?;
+    let profile = args.profile.clone().or_else(|| std::env::var("BANK_PROFILE").ok().filter(|profile| !profile.is_empty()));
+    let env = |name: &str| std::env::var(name).ok();
+    let facts = Facts { os: std::env::consts::OS, profile: profile.as_deref(), env: &env };
+    manifest.retain_when(&mut positions, &facts).with_context(|| format!("Invalid manifest {}", args.manifest.display()))?;
+    let vars = resolve_vars(&manifest, &args.vars)?;
+    manifest.substitute(&vars).with_context(|| format!("Invalid manifest {}", args.manifest.display()))?;
+    let problems = manifest.validate(fs, Path::new("."), &positions);
+    if !problems.is_empty() {
+        bail!("{} problem(s) in manifest {}; nothing was applied:\n  {}", problems.len(), args.manifest.display(), problems.join("\n  "));
+    }
+    let plan = Plan::new(fs, &manifest)?;
+    plan.show(args.output)?;
+    if args.dry_run {
+        return Ok(plan.changes() > 0);
+    }
+    if plan.changes() == 0 {
+        println!("{} Nothing to do", "✓".bright_green());
+        return Ok(false);
+    }
+    if !args.yes {
+        let proceed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Apply these changes?")
+            .default(false)
+            .interact()
+            .context("Confirmation needs a terminal; pass --yes to skip it")?;
+        if !proceed {
+            bail!("Aborted; nothing was applied");
+        }
+    }
+
+    let recording = RecordingFs::new(fs);
+    let result = execute(&recording, &plan, args.verbose);
+    if !args.no_journal {
+        if let Err(err) = journal::record(&recording, None) {
+            println!("{} {:#}", "Warning:".yellow(), err);
+        }
+    }
+    result?;
+    println!("{} Applied {} changes from {}", "✓".bright_green(), plan.changes().to_string().cyan(), args.manifest.display());
+    Ok(true)
+}
+
+/// A value for every placeholder the manifest uses: from `--var`, else asked
+/// for on a terminal
+fn resolve_vars(manifest: &Manifest, given: &[(String, String)]) -> Result<HashMap<String, String>> {
+    let mut vars: HashMap<String, String> = given.iter().cloned().collect();
+    let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+    for name in manifest.variables()? {
+        if vars.contains_key(&name) {
+            continue;
+        }
+        if !interactive {
+            bail!("No value for '{}' and no terminal to ask on; pass --var {}=VALUE", name, name);
+        }
+        let value = Input::<String>::with_theme(&ColorfulTheme::default())
+            .with_prompt(&name)
+            .interact_text()
+            .context("Prompting needs a terminal; pass --var NAME=VALUE")?;
+        vars.insert(name, value);
+    }
+    Ok(vars)
+}
+
+/// Create the missing paths in manifest order, then set attributes deepest
+/// first so creating children does not disturb a directory's mtime afterwards.
+/// A path that exists by now (a directory made as an earlier entry's parent,
+/// or by someone else since the plan) only needs to be of the right kind.
+pub fn execute(fs: &dyn Filesystem, plan: &Plan, verbose: bool) -> Result<()> {
+    for step in plan.steps.iter().filter(|step| step.action == Action::Create) {
+        let path = Path::new(&step.entry.path);
+        if let Ok(metadata) = fs.symlink_metadata(path) {
+            if let Action::Conflict(reason) = plan_entry(fs, &step.entry, Some(&metadata))? {
+                bail!("{} appeared after the plan was made and {}; stopped", path.display(), reason);
+            }
+            continue;
+        }
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs.create_dir_all(parent).with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        let created = match (step.entry.kind, &step.entry.content) {
+            (FileKind::Directory, _) => fs.create_dir(path),
+            (_, Some(content)) => fs.write_file(path, content.as_bytes()),
+            _ => fs.create_file(path),
+        };
+        created.with_context(|| format!("Failed to create {}", path.display()))?;
+        if verbose {
+            println!("Created {}: {}", kind_name(step.entry.kind), path.display().to_string().green());
+        }
+    }
+
+    let mut pending: Vec<&Step> = plan
+        .steps
+        .iter()
+        .filter(|step| matches!(step.action, Action::Create | Action::Update(_)))
+        .collect();
+    pending.sort_by_key(|step| std::cmp::Reverse(Path::new(&step.entry.path).components().count()));
+    for step in pending {
+        apply_attributes(fs, &step.entry)?;
+        if verbose && matches!(step.action, Action::Update(_)) {
+            println!("Updated: {}", step.entry.path.yellow());
+        }
+    }
+    Ok(())
+}
+
+fn apply_attributes(fs: &dyn Filesystem, entry: &Entry) -> Result<()> {
+    let path = Path::new(&entry.path);
+    if let Some(owner) = &entry.owner {
+        owner::set_owner(fs, path, owner, entry.kind == FileKind::Symlink)?;
+    }
+    if let Some(mode) = entry.mode {
+        fs.set_permissions(path, mode)
+            .with_context(|| format!("Failed to set permissions for {}", path.display()))?;
+    }
+    if let Some(mtime) = entry.mtime {
+        let accessed = fs.symlink_metadata(path).with_context(|| format!("Failed to read metadata for {}", path.display()))?.accessed;
+        fs.set_times(path, accessed, mtime)
+            .with_context(|| format!("Failed to set timestamps for {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_plan_and_execute() {
+        let memory = MemoryFs::new();
+        memory.create_dir(Path::new("etc")).unwrap();
+        memory.create_file(Path::new("etc/motd")).unwrap();
+        memory.set_permissions(Path::new("etc/motd"), 0o644).unwrap();
+        memory.create_file(Path::new("etc/hosts")).unwrap();
+        memory.set_permissions(Path::new("etc/hosts"), 0o644).unwrap();
+        memory.create_file(Path::new("etc/app")).unwrap();
+
+        let stamp = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let manifest = Manifest::parse_yaml(
+            "entries:\n\
+             \x20 - path: srv/data/\n\
+             \x20   mode: 0750\n\
+             \x20   mtime: 2023-11-14T22:13:20Z\n\
+             \x20 - path: srv/data/seed.txt\n\
+             \x20 - path: etc/motd\n\
+             \x20   mode: 0600\n\
+             \x20 - path: etc/hosts\n\
+             \x20   mode: 0644\n",
+        )
+        .unwrap();
+        let plan = Plan::new(&memory, &manifest).unwrap();
+        let actions: Vec<&Action> = plan.steps.iter().map(|step| &step.action).collect();
+        assert_eq!(actions[0], &Action::Create);
+        assert_eq!(actions[1], &Action::Create);
+        assert_eq!(
+            actions[2],
+            &Action::Update(vec![Difference { attribute: "mode", current: "0644".to_string(), desired: "0600".to_string() }])
+        );
+        assert_eq!(actions[3], &Action::Unchanged);
+        assert_eq!((plan.changes(), plan.conflicts()), (3, 0));
+
+        let json = plan.to_json();
+        assert!(crate::json::parse(&json).is_ok());
+        assert!(json.contains("\"summary\": {\"create\": 2, \"update\": 1, \"unchanged\": 1, \"conflict\": 0}"));
+        assert!(json.contains("{\"path\": \"srv/data/seed.txt\", \"action\": \"create\", \"current\": null, \"desired\": {\"type\": \"file\"}}"));
+        assert!(json.contains("\"changes\": [{\"attribute\": \"mode\", \"current\": \"0644\", \"desired\": \"0600\"}]"));
+
+        execute(&memory, &plan, false).unwrap();
+        assert!(memory.exists(Path::new("srv/data/seed.txt")));
+        let data = memory.metadata(Path::new("srv/data")).unwrap();
+        assert_eq!((data.mode, data.modified), (0o750, stamp));
+        assert_eq!(memory.metadata(Path::new("etc/motd")).unwrap().mode, 0o600);
+        assert_eq!(Plan::new(&memory, &manifest).unwrap().changes(), 0);
+
+        // A path of the wrong type is a conflict, never replaced
+        let manifest = Manifest::parse_yaml("entries:\n  - path: etc/app\n    type: dir\n  - path: lnk\n    type: symlink\n").unwrap();
+        let plan = Plan::new(&memory, &manifest).unwrap();
+        assert_eq!(plan.conflicts(), 2);
+        assert!(matches!(&plan.steps[0].action, Action::Conflict(reason) if reason == "exists as file, the manifest wants dir"));
+    }
+
+    #[test]
+    fn test_execute_accepts_paths_that_exist_by_now() {
+        let memory = MemoryFs::new();
+        // The directory comes after a file inside it, which creates it first
+        let manifest = Manifest::parse_yaml(
+            "entries:\n  - path: srv/app/log.txt\n  - path: srv/app/\n    mode: 0700\n  - path: srv/cache/\n  - path: srv/motd\n",
+        )
+        .unwrap();
+        let plan = Plan::new(&memory, &manifest).unwrap();
+        assert_eq!(plan.changes(), 4);
+        memory.create_dir_all(Path::new("srv/cache")).unwrap();
+        execute(&memory, &plan, false).unwrap();
+        assert_eq!(memory.metadata(Path::new("srv/app")).unwrap().mode, 0o700);
+        assert_eq!(Plan::new(&memory, &manifest).unwrap().changes(), 0);
+
+        // Unless it turned up as something else
+        memory.remove_file(Path::new("srv/motd")).unwrap();
+        memory.create_dir(Path::new("srv/motd")).unwrap();
+        let error = execute(&memory, &plan, false).unwrap_err().to_string();
+        assert!(error.contains("srv/motd appeared after the plan was made and exists as dir, the manifest wants file"), "{}", error);
+    }
+
+    #[test]
+    fn test_embedded_content() {
+        let memory = MemoryFs::new();
+        let manifest = Manifest::parse_yaml("entries:\n  - path: app.toml\n    content: \"port = 8080\\n\"\n").unwrap();
+        let plan = Plan::new(&memory, &manifest).unwrap();
+        assert_eq!(plan.steps[0].action, Action::Create);
+        execute(&memory, &plan, false).unwrap();
+        assert_eq!(memory.contents("app.toml").as_deref(), Some(&b"port = 8080\n"[..]));
+        assert_eq!(Plan::new(&memory, &manifest).unwrap().changes(), 0);
+
+        // Contents that were edited since are not overwritten
+        memory.write_file(Path::new("app.toml"), b"port = 9090\n").unwrap();
+        assert_eq!(Plan::new(&memory, &manifest).unwrap().conflicts(), 1);
+        assert!(Manifest::parse_yaml("entries:\n  - path: d/\n    content: x\n").is_err());
+    }
+}
@@ -0,0 +1,378 @@
+//! Archive integration: reading entry listings from tar/zip files and
+//! appending empty entries to them
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path};
+use std::process::Command;
+
+const TAR_BLOCK: usize = 512;
+
+/// An empty file or directory to be written into an archive
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    /// Relative entry name, without a trailing `/`
+    pub name: String,
+    pub is_dir: bool,
+    pub mode: u32,
+    /// Modification time in seconds since the Unix epoch
+    pub mtime: i64,
+}
+
+/// Returns true if the archive should be handled as a zip file rather than a tarball
+fn is_zip(archive: &Path) -> bool {
+    archive
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// List the entries an archive would produce when extracted, without extracting it.
+///
+/// Directory entries keep their trailing `/` so the usual detection rules create
+/// them as directories. Listing is delegated to the system `tar` (which detects
+/// gzip/bzip2/xz compression itself) or `unzip` for `.zip` files.
+pub fn list_entries(archive: &Path) -> Result<Vec<String>> {
+    if !archive.is_file() {
+        anyhow::bail!("Archive does not exist: {}", archive.display());
+    }
+
+    let mut command = if is_zip(archive) {
+        let mut command = Command::new("unzip");
+        command.arg("-Z1").arg(archive);
+        command
+    } else {
+        let mut command = Command::new("tar");
+        command.arg("-tf").arg(archive);
+        command
+    };
+
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run archive lister for {}", archive.display()))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to list archive {}: {}",
+            archive.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let listing = String::from_utf8_lossy(&output.stdout);
+    let mut entries = Vec::new();
+    for line in listing.lines() {
+        if let Some(entry) = normalize_entry(line)? {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Append empty entries to a tar or zip archive, creating the archive if it does not exist.
+///
+/// Compressed tarballs cannot be appended to in place, so only plain `.tar` and
+/// `.zip` targets are accepted.
+pub fn append_entries(archive: &Path, entries: &[ArchiveEntry]) -> Result<()> {
+    let name = archive.to_string_lossy();
+    if is_zip(archive) {
+        append_zip(archive, entries)
+    } else if name.ends_with(".tar") {
+        append_tar(archive, entries)
+    } else {
+        anyhow::bail!(
+            "Unsupported archive type for --into-archive: {} (expected .tar or .zip)",
+            archive.display()
+        )
+    }
+}
+
+/// Normalize a requested path into an archive entry name
+pub fn entry_name(path: &str) -> Result<String> {
+    match normalize_entry(path)? {
+        Some(name) => Ok(name.trim_end_matches('/').to_string()),
+        None => anyhow::bail!("Cannot add an archive entry for the archive root: {}", path),
+    }
+}
+
+fn append_tar(archive: &Path, entries: &[ArchiveEntry]) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(archive)
+        .with_context(|| format!("Failed to open archive {}", archive.display()))?;
+
+    // Find the end-of-archive marker so new entries replace it
+    let end = tar_data_end(&mut file)
+        .with_context(|| format!("Failed to read tar archive {}", archive.display()))?;
+    file.set_len(end)?;
+    file.seek(SeekFrom::Start(end))?;
+
+    for entry in entries {
+        file.write_all(&tar_header(entry)?)?;
+    }
+    file.write_all(&[0u8; TAR_BLOCK * 2])?;
+    file.flush()
+        .with_context(|| format!("Failed to write tar archive {}", archive.display()))?;
+    Ok(())
+}
+
+/// Offset of the first zero block, i.e. where the existing entries end
+fn tar_data_end(file: &mut fs::File) -> Result<u64> {
+    let len = file.metadata()?.len();
+    let mut offset = 0u64;
+    let mut header = [0u8; TAR_BLOCK];
+    file.seek(SeekFrom::Start(0))?;
+    while offset + TAR_BLOCK as u64 <= len {
+        file.read_exact(&mut header)?;
+        if header.iter().all(|&b| b == 0) {
+            return Ok(offset);
+        }
+        let size = parse_octal(&header[124..136])
+            .ok_or_else(|| anyhow::anyhow!("Corrupt tar header at offset {}", offset))?;
+        let data_blocks = size.div_ceil(TAR_BLOCK as u64);
+        offset += TAR_BLOCK as u64 * (1 + data_blocks);
+        file.seek(SeekFrom::Start(offset))?;
+    }
+    Ok(offset.min(len))
+}
+
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let text: String = field
+        .iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| b as char)
+        .collect();
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let text = format!("{:0width$o}", value, width = digits);
+    field[..digits].copy_from_slice(text.as_bytes());
+    field[digits] = 0;
+}
+
+/// Build a ustar header block for an empty entry
+fn tar_header(entry: &ArchiveEntry) -> Result<[u8; TAR_BLOCK]> {
+    let mut header = [0u8; TAR_BLOCK];
+    let mut name = entry.name.clone();
+    if entry.is_dir {
+        name.push('/');
+    }
+
+    // Names over 100 bytes are split into the ustar prefix field at a `/`
+    let (prefix, name) = if name.len() <= 100 {
+        (String::new(), name)
+    } else {
+        let split = name
+            .char_indices()
+            .filter(|&(i, c)| c == '/' && i <= 155 && name.len() - i - 1 <= 100)
+            .map(|(i, _)| i)
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Path too long for a tar entry: {}", entry.name))?;
+        (name[..split].to_string(), name[split + 1..].to_string())
+    };
+
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut header[100..108], u64::from(entry.mode & 0o7777));
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], 0);
+    write_octal(&mut header[136..148], entry.mtime.max(0) as u64);
+    header[156] = if entry.is_dir { b'5' } else { b'0' };
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    // The checksum is computed with its own field filled with spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+    let text = format!("{:06o}\0 ", checksum);
+    header[148..156].copy_from_slice(text.as_bytes());
+    Ok(header)
+}
+
+const ZIP_LOCAL_SIG: u32 = 0x0403_4b50;
+const ZIP_CENTRAL_SIG: u32 = 0x0201_4b50;
+const ZIP_END_SIG: u32 = 0x0605_4b50;
+
+fn append_zip(archive: &Path, entries: &[ArchiveEntry]) -> Result<()> {
+    let existing = if archive.exists() {
+        fs::read(archive).with_context(|| format!("Failed to read zip archive {}", archive.display()))?
+    } else {
+        Vec::new()
+    };
+
+    // Keep existing local entries and central directory; new local headers go
+    // where the old central directory started.
+    let (mut data, mut central, mut count) = if existing.is_empty() {
+        (Vec::new(), Vec::new(), 0u16)
+    } else {
+        let (cd_offset, cd_size, count) = parse_zip_end(&existing)
+            .ok_or_else(|| anyhow::anyhow!("Not a valid zip archive: {}", archive.display()))?;
+        let central = existing[cd_offset..cd_offset + cd_size].to_vec();
+        (existing[..cd_offset].to_vec(), central, count)
+    };
+
+    for entry in entries {
+        let mut name = entry.name.clone();
+        if entry.is_dir {
+            name.push('/');
+        }
+        let (dos_time, dos_date) = dos_datetime(entry.mtime);
+        let offset = u32::try_from(data.len()).context("Zip archive too large")?;
+
+        data.extend_from_slice(&ZIP_LOCAL_SIG.to_le_bytes());
+        data.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        data.extend_from_slice(&0u16.to_le_bytes()); // flags
+        data.extend_from_slice(&0u16.to_le_bytes()); // stored
+        data.extend_from_slice(&dos_time.to_le_bytes());
+        data.extend_from_slice(&dos_date.to_le_bytes());
+        data.extend_from_slice(&[0u8; 12]); // crc32, compressed and uncompressed size
+        data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes()); // extra length
+        data.extend_from_slice(name.as_bytes());
+
+        let file_type = if entry.is_dir { 0o040000 } else { 0o100000 };
+        let external = (file_type | (entry.mode & 0o7777)) << 16 | u32::from(entry.is_dir) << 4;
+        central.extend_from_slice(&ZIP_CENTRAL_SIG.to_le_bytes());
+        central.extend_from_slice(&(3u16 << 8 | 20).to_le_bytes()); // made by Unix
+        central.extend_from_slice(&20u16.to_le_bytes());
+        central.extend_from_slice(&0u16.to_le_bytes());
+        central.extend_from_slice(&0u16.to_le_bytes());
+        central.extend_from_slice(&dos_time.to_le_bytes());
+        central.extend_from_slice(&dos_date.to_le_bytes());
+        central.extend_from_slice(&[0u8; 12]);
+        central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        central.extend_from_slice(&[0u8; 8]); // extra, comment, disk, internal attributes
+        central.extend_from_slice(&external.to_le_bytes());
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+        count = count.checked_add(1).context("Too many zip entries")?;
+    }
+
+    let cd_offset = u32::try_from(data.len()).context("Zip archive too large")?;
+    let cd_size = central.len() as u32;
+    data.extend_from_slice(&central);
+    data.extend_from_slice(&ZIP_END_SIG.to_le_bytes());
+    data.extend_from_slice(&[0u8; 4]); // disk numbers
+    data.extend_from_slice(&count.to_le_bytes());
+    data.extend_from_slice(&count.to_le_bytes());
+    data.extend_from_slice(&cd_size.to_le_bytes());
+    data.extend_from_slice(&cd_offset.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes()); // comment length
+
+    fs::write(archive, data).with_context(|| format!("Failed to write zip archive {}", archive.display()))
+}
+
+/// Locate the end-of-central-directory record: (offset, size, entry count)
+fn parse_zip_end(data: &[u8]) -> Option<(usize, usize, u16)> {
+    let min = data.len().checked_sub(22)?;
+    let start = min.saturating_sub(u16::MAX as usize);
+    let pos = (start..=min)
+        .rev()
+        .find(|&i| data[i..i + 4] == ZIP_END_SIG.to_le_bytes())?;
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let read_u32 = |at: usize| u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+    let count = read_u16(pos + 10);
+    let size = read_u32(pos + 12) as usize;
+    let offset = read_u32(pos + 16) as usize;
+    (offset + size <= pos).then_some((offset, size, count))
+}
+
+/// Convert a Unix timestamp to MS-DOS (time, date), clamped to the representable range
+fn dos_datetime(mtime: i64) -> (u16, u16) {
+    let dt = DateTime::<Utc>::from_timestamp(mtime, 0).unwrap_or_default();
+    if dt.year() < 1980 {
+        return (0, 1 << 5 | 1);
+    }
+    let time = (dt.hour() << 11 | dt.minute() << 5 | (dt.second() / 2)) as u16;
+    let date = (((dt.year() - 1980).min(127) as u32) << 9 | dt.month() << 5 | dt.day()) as u16;
+    (time, date)
+}
+
+/// Make an archive entry name safe to create relative to the current directory.
+///
+/// Leading `/` and `./` are stripped like `tar` does; entries that would escape
+/// through `..` are rejected. Returns `None` for entries that name the archive root.
+fn normalize_entry(entry: &str) -> Result<Option<String>> {
+    let is_dir = entry.ends_with('/');
+    let mut parts = Vec::new();
+    for component in Path::new(entry).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+            Component::ParentDir => {
+                anyhow::bail!("Refusing archive entry that escapes the target directory: {}", entry)
+            }
+        }
+    }
+
+    if parts.is_empty() {
+        return Ok(None);
+    }
+
+    let mut normalized = parts.join("/");
+    if is_dir {
+        normalized.push('/');
+    }
+    Ok(Some(normalized))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_entry() {
+        assert_eq!(normalize_entry("./data/").unwrap(), Some("data/".to_string()));
+        assert_eq!(normalize_entry("/etc/app.conf").unwrap(), Some("etc/app.conf".to_string()));
+        assert_eq!(normalize_entry("./").unwrap(), None);
+        assert!(normalize_entry("../outside.txt").is_err());
+    }
+
+    fn entry(name: &str, is_dir: bool) -> ArchiveEntry {
+        ArchiveEntry {
+            name: name.to_string(),
+            is_dir,
+            mode: if is_dir { 0o755 } else { 0o640 },
+            mtime: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn test_append_tar_keeps_existing_entries() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let archive = temp_dir.path().join("out.tar");
+
+        append_entries(&archive, &[entry("data", true)]).unwrap();
+        append_entries(&archive, &[entry("data/_SUCCESS", false)]).unwrap();
+
+        let data = fs::read(&archive).unwrap();
+        assert_eq!(data.len(), TAR_BLOCK * 4);
+        assert_eq!(&data[..5], b"data/");
+        assert_eq!(&data[TAR_BLOCK..TAR_BLOCK + 13], b"data/_SUCCESS");
+        assert_eq!(parse_octal(&data[TAR_BLOCK + 100..TAR_BLOCK + 108]), Some(0o640));
+    }
+
+    #[test]
+    fn test_append_zip_updates_central_directory() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let archive = temp_dir.path().join("out.zip");
+
+        append_entries(&archive, &[entry("a", true)]).unwrap();
+        append_entries(&archive, &[entry("a/b.txt", false)]).unwrap();
+
+        let data = fs::read(&archive).unwrap();
+        let (offset, _, count) = parse_zip_end(&data).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(data[offset..offset + 4], ZIP_CENTRAL_SIG.to_le_bytes());
+    }
+}
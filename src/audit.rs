@@ -0,0 +1,157 @@
+//! `bank audit`: check a tree against a permissions policy without changing
+//! anything
+//!
+//! Every directory and file below each DIR is checked, with its path relative
+//! to DIR matched against the policy's globs; symlinks are skipped, since
+//! their own mode means nothing. The violations are printed as a table or as
+//! JSON, and the run fails if there are any, so it can gate a pipeline.
+
+use crate::json;
+use crate::policy::{Policy, Violation};
+use crate::vfs::{FileKind, Filesystem};
+use anyhow::{bail, Context, Result};
+use clap::{Args as ClapArgs, ValueEnum};
+use colored::*;
+use std::path::{Path, PathBuf};
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct AuditArgs {
+    /// Directories to check
+    #[arg(value_name = "DIR", required = true)]
+    pub dirs: Vec<PathBuf>,
+
+    /// Policy file mapping globs to required modes and owners (TOML)
+    #[arg(long = "policy", value_name = "FILE")]
+    pub policy: PathBuf,
+
+    /// How to print the report: a table, or JSON for other tools
+    #[arg(long = "output", value_name = "FORMAT", value_enum, default_value = "table")]
+    pub output: ReportFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Table,
+    Json,
+}
+
+/// The violations below `dir`, with the number of paths checked
+pub fn audit(fs: &dyn Filesystem, dir: &Path, policy: &Policy) -> Result<(Vec<Violation>, usize)> {
+    if !fs.is_dir(dir) {
+        bail!("Not a directory: {}", dir.display());
+    }
+    let mut violations = Vec::new();
+    let mut checked = 0;
+    walk(fs, dir, "", policy, &mut violations, &mut checked)?;
+    Ok((violations, checked))
+}
+
+fn walk(fs: &dyn Filesystem, root: &Path, relative: &str, policy: &Policy, violations: &mut Vec<Violation>, checked: &mut usize) -> Result<()> {
+    let dir = root.join(relative);
+    for entry in fs.read_dir(&dir).with_context(|| format!("Failed to read directory {}", dir.display()))? {
+        if entry.kind == FileKind::Symlink {
+            continue;
+        }
+        let child = if relative.is_empty() { entry.name } else { format!("{}/{}", relative, entry.name) };
+        let path = root.join(&child);
+        let metadata = fs.symlink_metadata(&path).with_context(|| format!("Failed to read metadata for {}", path.display()))?;
+        violations.extend(policy.check(&child, &metadata)?);
+        *checked += 1;
+        if entry.kind == FileKind::Directory {
+            walk(fs, root, &child, policy, violations, checked)?;
+        }
+    }
+    Ok(())
+}
+
+/// Aligned columns, one row per violation
+pub fn print_table(rows: &[(String, &Violation)]) {
+    let headers = ["PATH", "RULE", "ATTRIBUTE", "FOUND", "REQUIRED"];
+    let cells: Vec<[&str; 5]> = rows
+        .iter()
+        .map(|(path, violation)| [path.as_str(), &violation.rule, violation.attribute, &violation.found, &violation.required])
+        .collect();
+    let mut widths = headers.map(str::len);
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |row: [&str; 5]| {
+        let padded: Vec<String> = row.iter().zip(widths).map(|(cell, width)| format!("{:width$}", cell, width = width)).collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    println!("{}", line(headers).bold());
+    for row in cells {
+        println!("{}", line(row));
+    }
+}
+
+fn to_json(rows: &[(String, &Violation)], checked: usize) -> String {
+    let items: Vec<String> = rows
+        .iter()
+        .map(|(path, violation)| {
+            format!(
+                "    {{\"path\": {}, \"rule\": {}, \"attribute\": {}, \"found\": {}, \"required\": {}}}",
+                json::quote(path),
+                json::quote(&violation.rule),
+                json::quote(violation.attribute),
+                json::quote(&violation.found),
+                json::quote(&violation.required)
+            )
+        })
+        .collect();
+    let items = if items.is_empty() { "[]".to_string() } else { format!("[\n{}\n  ]", items.join(",\n")) };
+    format!("{{\n  \"version\": 1,\n  \"checked\": {},\n  \"violations\": {}\n}}\n", checked, items)
+}
+
+pub fn run(fs: &dyn Filesystem, args: &AuditArgs) -> Result<()> {
+    let policy = Policy::load(&args.policy)?;
+    let mut found = Vec::new();
+    let mut checked = 0;
+    for dir in &args.dirs {
+        let (violations, count) = audit(fs, dir, &policy)?;
+        found.extend(violations.into_iter().map(|violation| (dir.join(&violation.path).display().to_string(), violation)));
+        checked += count;
+    }
+    let rows: Vec<(String, &Violation)> = found.iter().map(|(path, violation)| (path.clone(), violation)).collect();
+
+    match args.output {
+        ReportFormat::Json => print!("{}", to_json(&rows, checked)),
+        ReportFormat::Table if rows.is_empty() => {
+            println!("{} {} paths comply with {}", "✓".bright_green(), checked.to_string().cyan(), args.policy.display());
+        }
+        ReportFormat::Table => print_table(&rows),
+    }
+    if !rows.is_empty() {
+        bail!("{} violation(s) of {} in {} paths checked", rows.len(), args.policy.display(), checked);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[test]
+    fn test_audit_reports_violations() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("srv/bin")).unwrap();
+        memory.write_file(Path::new("srv/bin/run"), b"").unwrap();
+        memory.set_permissions(Path::new("srv/bin/run"), 0o644).unwrap();
+        memory.write_file(Path::new("srv/site.key"), b"").unwrap();
+        memory.set_permissions(Path::new("srv/site.key"), 0o600).unwrap();
+
+        let policy = Policy::parse("[rules.\"bin/*\"]\nmode = \"0755\"\n\n[rules.\"*.key\"]\nmax-mode = \"0640\"\n").unwrap();
+        let (violations, checked) = audit(&memory, Path::new("srv"), &policy).unwrap();
+        assert_eq!(checked, 3);
+        assert_eq!(violations.len(), 1);
+        assert_eq!((violations[0].path.as_str(), violations[0].found.as_str()), ("bin/run", "0644"));
+
+        let rows = vec![("srv/bin/run".to_string(), &violations[0])];
+        let json = to_json(&rows, checked);
+        assert!(crate::json::parse(&json).is_ok());
+        assert!(json.contains("{\"path\": \"srv/bin/run\", \"rule\": \"bin/*\", \"attribute\": \"mode\", \"found\": \"0644\", \"required\": \"0755\"}"));
+    }
+}
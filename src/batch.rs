@@ -0,0 +1,363 @@
+//! `--batch-file`: provisioning rows with their own type, mode, owner and timestamp
+
+use crate::json::{self, Value};
+use anyhow::{bail, Context, Result};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+const COLUMNS: [&str; 5] = ["path", "type", "mode", "owner", "timestamp"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowKind {
+    File,
+    Directory,
+}
+
+/// One row of a batch file; unset attributes fall back to the command line
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchRow {
+    pub path: String,
+    pub kind: Option<RowKind>,
+    pub mode: Option<String>,
+    pub owner: Option<String>,
+    /// Any date accepted by `--date`
+    pub timestamp: Option<String>,
+}
+
+impl BatchRow {
+    fn set(&mut self, column: &str, value: String) -> Result<()> {
+        if value.is_empty() {
+            return Ok(());
+        }
+        match column {
+            "path" => self.path = value,
+            "type" => {
+                self.kind = Some(match value.to_ascii_lowercase().as_str() {
+                    "f" | "file" => RowKind::File,
+                    "d" | "dir" | "directory" => RowKind::Directory,
+                    _ => bail!("unknown type '{}' (expected file or dir)", value),
+                })
+            }
+            "mode" => self.mode = Some(value),
+            "owner" => self.owner = Some(value),
+            "timestamp" => self.timestamp = Some(value),
+            _ => bail!("unknown column '{}' (expected {})", column, COLUMNS.join(", ")),
+        }
+        Ok(())
+    }
+}
+
+/// Parse an inline `--spec`: `PATH::KEY=VALUE,...` with the keys `type`, `mode`,
+/// `owner` and `mtime` (any date accepted by `--date`)
+pub fn parse_spec(spec: &str) -> Result<BatchRow> {
+    // The last `::`, so the attributes never contain one and the path may
+    let Some((path, attributes)) = spec.rsplit_once("::") else {
+        bail!("'{}' has no attributes (expected PATH::mode=750,owner=app,...)", spec);
+    };
+    if path.is_empty() {
+        bail!("'{}' has an empty path", spec);
+    }
+    let mut row = BatchRow { path: path.to_string(), ..BatchRow::default() };
+    for attribute in attributes.split(',').filter(|attribute| !attribute.trim().is_empty()) {
+        let Some((key, value)) = attribute.split_once('=') else {
+            bail!("'{}' in {} is not KEY=VALUE", attribute, spec);
+        };
+        let column = match key.trim() {
+            "mtime" | "timestamp" => "timestamp",
+            "type" | "mode" | "owner" => key.trim(),
+            _ => bail!("unknown attribute '{}' in {} (expected type, mode, owner or mtime)", key.trim(), spec),
+        };
+        row.set(column, value.trim().to_string()).with_context(|| format!("Invalid spec {}", spec))?;
+    }
+    Ok(row)
+}
+
+/// Read a batch file: `.json` files hold an array of objects, anything else is CSV
+/// with a header row
+pub fn load(path: &Path) -> Result<Vec<BatchRow>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read batch file {}", path.display()))?;
+    let rows = if is_json(path) { parse_json(&text) } else { parse_csv(&text) };
+    rows.with_context(|| format!("Invalid batch file {}", path.display()))
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Open a CSV batch file to read its rows one at a time (see `CsvRows`)
+pub fn stream(path: &Path) -> Result<CsvRows<BufReader<File>>> {
+    if is_json(path) {
+        bail!("Batch file {} is JSON, which is read as a whole; use CSV to stream it", path.display());
+    }
+    let file = File::open(path).with_context(|| format!("Failed to read batch file {}", path.display()))?;
+    CsvRows::new(BufReader::new(file), path)
+}
+
+pub fn parse_csv(text: &str) -> Result<Vec<BatchRow>> {
+    let mut records = csv_records(text)?.into_iter();
+    let Some((_, header)) = records.next() else {
+        return Ok(Vec::new());
+    };
+    let header = parse_header(header)?;
+    records.map(|(line, fields)| parse_row(&header, line, fields)).collect()
+}
+
+/// The lowercased column names of a header row
+fn parse_header(header: Vec<String>) -> Result<Vec<String>> {
+    let header: Vec<String> = header.iter().map(|name| name.trim().to_ascii_lowercase()).collect();
+    if !header.iter().any(|name| name == "path") {
+        bail!("the header row must include a 'path' column");
+    }
+    if let Some(unknown) = header.iter().find(|name| !COLUMNS.contains(&name.as_str())) {
+        bail!("unknown column '{}' (expected {})", unknown, COLUMNS.join(", "));
+    }
+    Ok(header)
+}
+
+fn parse_row(header: &[String], line: usize, fields: Vec<String>) -> Result<BatchRow> {
+    if fields.len() != header.len() {
+        bail!("line {}: expected {} fields, found {}", line, header.len(), fields.len());
+    }
+    let mut row = BatchRow::default();
+    for (column, value) in header.iter().zip(fields) {
+        row.set(column, value).with_context(|| format!("line {}", line))?;
+    }
+    if row.path.is_empty() {
+        bail!("line {}: the path is empty", line);
+    }
+    Ok(row)
+}
+
+/// The rows of a CSV batch file, parsed as they are read, so only the current
+/// record is held in memory however long the file is
+pub struct CsvRows<R> {
+    reader: R,
+    path: PathBuf,
+    parser: CsvParser,
+    header: Vec<String>,
+    done: bool,
+}
+
+impl<R: BufRead> CsvRows<R> {
+    pub fn new(reader: R, path: &Path) -> Result<Self> {
+        let mut rows = Self { reader, path: path.to_path_buf(), parser: CsvParser::new(), header: Vec::new(), done: false };
+        if let Some((_, header)) = rows.next_record()? {
+            rows.header = parse_header(header).with_context(|| format!("Invalid batch file {}", path.display()))?;
+        }
+        Ok(rows)
+    }
+
+    /// Feed the parser line by line until it completes a record
+    fn next_record(&mut self) -> Result<Option<(usize, Vec<String>)>> {
+        let mut text = String::new();
+        while self.parser.records.is_empty() && !self.done {
+            text.clear();
+            let read = self
+                .reader
+                .read_line(&mut text)
+                .with_context(|| format!("Failed to read batch file {}", self.path.display()))?;
+            if read == 0 {
+                self.done = true;
+                self.parser.finish().with_context(|| format!("Invalid batch file {}", self.path.display()))?;
+            } else {
+                self.parser.feed(&text);
+            }
+        }
+        Ok(self.parser.records.pop_front())
+    }
+}
+
+impl<R: BufRead> Iterator for CsvRows<R> {
+    type Item = Result<BatchRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.next_record() {
+            Ok(record) => record?,
+            Err(err) => {
+                // A read error is not retried
+                self.done = true;
+                return Some(Err(err));
+            }
+        };
+        let (line, fields) = record;
+        Some(parse_row(&self.header, line, fields).with_context(|| format!("Invalid batch file {}", self.path.display())))
+    }
+}
+
+/// Split RFC 4180 CSV into records, each tagged with the line it starts on.
+/// Blank lines are skipped.
+fn csv_records(text: &str) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut parser = CsvParser::new();
+    parser.feed(text);
+    parser.finish()?;
+    Ok(parser.records.into())
+}
+
+/// The state of splitting CSV into records, fed a piece of text at a time;
+/// each piece must end at a line break, except the last
+struct CsvParser {
+    records: VecDeque<(usize, Vec<String>)>,
+    fields: Vec<String>,
+    field: String,
+    in_quotes: bool,
+    line: usize,
+    start_line: usize,
+}
+
+impl CsvParser {
+    fn new() -> Self {
+        Self { records: VecDeque::new(), fields: Vec::new(), field: String::new(), in_quotes: false, line: 1, start_line: 1 }
+    }
+
+    fn feed(&mut self, text: &str) {
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if self.in_quotes && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    self.field.push('"');
+                }
+                '"' if self.in_quotes => self.in_quotes = false,
+                '"' if self.field.is_empty() => self.in_quotes = true,
+                '\n' if self.in_quotes => {
+                    self.line += 1;
+                    self.field.push(c);
+                }
+                ',' if !self.in_quotes => self.fields.push(std::mem::take(&mut self.field)),
+                '\r' if !self.in_quotes && chars.peek() == Some(&'\n') => {}
+                '\n' => {
+                    self.end_record();
+                    self.line += 1;
+                    self.start_line = self.line;
+                }
+                _ => self.field.push(c),
+            }
+        }
+    }
+
+    fn end_record(&mut self) {
+        self.fields.push(std::mem::take(&mut self.field));
+        let record = std::mem::take(&mut self.fields);
+        if record.len() > 1 || !record[0].is_empty() {
+            self.records.push_back((self.start_line, record));
+        }
+    }
+
+    /// Complete the last record when the text does not end with a line break
+    fn finish(&mut self) -> Result<()> {
+        if self.in_quotes {
+            bail!("line {}: unterminated quoted field", self.start_line);
+        }
+        if !self.field.is_empty() || !self.fields.is_empty() {
+            self.end_record();
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_json(text: &str) -> Result<Vec<BatchRow>> {
+    let Value::Array(items) = json::parse(text)? else {
+        bail!("expected an array of objects");
+    };
+
+    let mut rows = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        let entry = index + 1;
+        let Value::Object(members) = item else {
+            bail!("entry {}: expected an object, found {}", entry, item.type_name());
+        };
+        let mut row = BatchRow::default();
+        for (column, value) in members {
+            let value = match value {
+                Value::String(text) => text.clone(),
+                // Modes are octal digits, so a bare 644 means "644"
+                Value::Number(number) if column == "mode" && number.fract() == 0.0 && *number >= 0.0 => {
+                    format!("{}", *number as u64)
+                }
+                Value::Null => continue,
+                other => bail!("entry {}: '{}' must be a string, found {}", entry, column, other.type_name()),
+            };
+            row.set(column, value).with_context(|| format!("entry {}", entry))?;
+        }
+        if row.path.is_empty() {
+            bail!("entry {}: missing 'path'", entry);
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_rows() {
+        let rows = parse_csv(
+            "path,type,mode,owner,timestamp\n\
+             srv/data,dir,750,root:root,\n\
+             \"srv/a,b.conf\",file,,,2024-01-01 12:00:00\r\n\
+             \n\
+             \"srv/\"\"quoted\"\"\",,0600,,\n",
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].kind, Some(RowKind::Directory));
+        assert_eq!(rows[0].mode.as_deref(), Some("750"));
+        assert_eq!(rows[0].owner.as_deref(), Some("root:root"));
+        assert_eq!(rows[1].path, "srv/a,b.conf");
+        assert_eq!(rows[1].timestamp.as_deref(), Some("2024-01-01 12:00:00"));
+        assert_eq!(rows[2].path, "srv/\"quoted\"");
+        assert_eq!(rows[2].kind, None);
+
+        assert!(parse_csv("path,color\nx,red\n").is_err());
+        assert!(parse_csv("type\nfile\n").is_err());
+        assert!(parse_csv("path,type\nx,socket\n").is_err());
+        assert!(parse_csv("path,mode\nx\n").is_err());
+    }
+
+    #[test]
+    fn test_csv_rows_stream_like_parse_csv() {
+        let text = "path,mode\nsrv/a,750\n\n\"srv/two\nlines\",\nsrv/b,";
+        let streamed = CsvRows::new(text.as_bytes(), Path::new("rows.csv")).unwrap().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(streamed, parse_csv(text).unwrap());
+        assert_eq!(streamed[1].path, "srv/two\nlines");
+
+        let mut rows = CsvRows::new("path,mode\nok,\nbad\n".as_bytes(), Path::new("rows.csv")).unwrap();
+        assert_eq!(rows.next().unwrap().unwrap().path, "ok");
+        let err = rows.next().unwrap().unwrap_err();
+        assert!(format!("{:#}", err).contains("line 3: expected 2 fields"));
+        assert!(CsvRows::new("path\n\"open\n".as_bytes(), Path::new("rows.csv")).unwrap().next().unwrap().is_err());
+        assert!(CsvRows::new("color\nred\n".as_bytes(), Path::new("rows.csv")).is_err());
+    }
+
+    #[test]
+    fn test_parse_json_rows() {
+        let rows = parse_json(r#"[{"path": "etc/app", "type": "dir", "mode": 755}, {"path": "etc/app/app.conf", "owner": null}]"#).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].mode.as_deref(), Some("755"));
+        assert_eq!(rows[1].kind, None);
+        assert!(parse_json(r#"{"path": "x"}"#).is_err());
+        assert!(parse_json(r#"[{"type": "file"}]"#).is_err());
+        assert!(parse_json(r#"[{"path": "x", "mode": true}]"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        let row = parse_spec("srv/data/::mode=750,owner=app:app,mtime=2024-01-01").unwrap();
+        assert_eq!(row.path, "srv/data/");
+        assert_eq!(row.mode.as_deref(), Some("750"));
+        assert_eq!(row.owner.as_deref(), Some("app:app"));
+        assert_eq!(row.timestamp.as_deref(), Some("2024-01-01"));
+        let row = parse_spec("a::b.txt::type=file").unwrap();
+        assert_eq!((row.path.as_str(), row.kind), ("a::b.txt", Some(RowKind::File)));
+
+        assert!(parse_spec("plain.txt").is_err());
+        assert!(parse_spec("x::color=red").is_err());
+        assert!(parse_spec("x::mode").is_err());
+        assert!(parse_spec("::mode=600").is_err());
+    }
+}
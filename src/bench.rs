@@ -0,0 +1,146 @@
+//! `bank bench`: time the executor on a synthetic workload
+//!
+//! The workload is `--paths` files spread over directories nested `--depth`
+//! levels deep (100 files per directory, 16 subdirectories per level), created
+//! with `-p` like a provisioning list would be. Each configuration creates it
+//! afresh in its own directory, path by path through the same code as a normal
+//! run, and is timed and counted: the filesystem calls column counts calls into
+//! the filesystem layer, each of which is one or (for times and permissions
+//! set through a descriptor) a few syscalls. Runs are serial, so `--jobs` is
+//! not part of the comparison.
+
+use crate::vfs::{CountingFs, Filesystem};
+use crate::{process_single_path, secure, Args};
+use anyhow::{Context, Result};
+use clap::{Args as ClapArgs, Parser};
+use colored::*;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const FILES_PER_DIR: u64 = 100;
+const FANOUT: u64 = 16;
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct BenchArgs {
+    /// How many files each configuration creates
+    #[arg(long = "paths", value_name = "N", default_value_t = 10_000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub paths: u64,
+
+    /// How many levels the workload's paths have, the file included
+    #[arg(long = "depth", value_name = "N", default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..=64))]
+    pub depth: u64,
+
+    /// Where to create the workload (default: a new directory in the system temp directory)
+    #[arg(long = "dir", value_name = "DIR")]
+    pub dir: Option<PathBuf>,
+
+    /// Leave the workload in place instead of removing it
+    #[arg(long = "keep")]
+    pub keep: bool,
+}
+
+/// The relative paths of the workload, each file below its directories
+fn workload(paths: u64, depth: u64) -> Vec<String> {
+    (0..paths)
+        .map(|index| {
+            let mut dir = index / FILES_PER_DIR;
+            let mut parts: Vec<String> = (1..depth)
+                .map(|_| {
+                    let part = format!("d{:02}", dir % FANOUT);
+                    dir /= FANOUT;
+                    part
+                })
+                .collect();
+            parts.reverse();
+            parts.push(format!("f{}.txt", index));
+            parts.join("/")
+        })
+        .collect()
+}
+
+struct Configuration {
+    name: &'static str,
+    /// Options for the run, after `bank`
+    options: &'static [&'static str],
+    secure: bool,
+}
+
+const CONFIGURATIONS: [Configuration; 3] = [
+    Configuration { name: "plain", options: &["-p"], secure: false },
+    Configuration { name: "with mode and time", options: &["-p", "-m", "640", "-t", "202401010000"], secure: false },
+    Configuration { name: "--secure", options: &["-p", "--secure"], secure: true },
+];
+
+/// Create every path of `workload` below `dir`; the seconds it took and the
+/// filesystem calls it made
+fn run_configuration(fs: &dyn Filesystem, configuration: &Configuration, dir: &Path, workload: &[String]) -> Result<(f64, u64)> {
+    // A single path argument, so nothing is printed per path
+    let argv = ["bank"].iter().chain(configuration.options).chain(&["PATH"]).copied();
+    let args = Args::try_parse_from(argv)?;
+    let counting = CountingFs::new(fs);
+    let started = Instant::now();
+    for path in workload {
+        process_single_path(&counting, &dir.join(path).to_string_lossy(), &args)?;
+    }
+    Ok((started.elapsed().as_secs_f64(), counting.calls()))
+}
+
+pub fn run(fs: &dyn Filesystem, args: &BenchArgs) -> Result<()> {
+    let base = match &args.dir {
+        Some(dir) => dir.clone(),
+        None => secure::make_temp_dir("bank-bench-").context("Failed to create a temporary directory")?,
+    };
+    fs.create_dir_all(&base).with_context(|| format!("Failed to create directory {}", base.display()))?;
+    let workload = workload(args.paths, args.depth);
+    println!("Creating {} paths, {} levels deep, in {}", args.paths, args.depth, base.display().to_string().cyan());
+    println!("{:<20} {:>9} {:>11} {:>10} {:>10}", "configuration", "seconds", "paths/sec", "fs calls", "per path");
+
+    let mut result = Ok(());
+    for (index, configuration) in CONFIGURATIONS.iter().enumerate() {
+        let dir = base.join(format!("run-{}", index + 1));
+        let outcome = match fs.create_dir(&dir) {
+            Ok(()) => {
+                let fs: &dyn Filesystem = if configuration.secure { &secure::SecureFs } else { fs };
+                let outcome = run_configuration(fs, configuration, &dir, &workload);
+                if !args.keep {
+                    std::fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+                }
+                outcome
+            }
+            // Not ours to remove: under --dir, an existing run-N is the user's
+            Err(err) => Err(err).with_context(|| format!("Failed to create directory {}", dir.display())),
+        };
+        match outcome {
+            Ok((seconds, calls)) => println!(
+                "{:<20} {:>9.3} {:>11.0} {:>10} {:>10.1}",
+                configuration.name,
+                seconds,
+                args.paths as f64 / seconds.max(f64::EPSILON),
+                calls,
+                calls as f64 / args.paths as f64
+            ),
+            Err(err) => {
+                result = Err(err.context(format!("The {} configuration failed", configuration.name)));
+                break;
+            }
+        }
+    }
+    if !args.keep && args.dir.is_none() {
+        std::fs::remove_dir(&base).with_context(|| format!("Failed to remove {}", base.display()))?;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workload_spreads_files_over_directories() {
+        let paths = workload(1_700, 3);
+        assert_eq!(paths[0], "d00/d00/f0.txt");
+        assert_eq!(paths[100], "d00/d01/f100.txt");
+        assert_eq!(paths[1_699], "d01/d00/f1699.txt");
+        assert_eq!(workload(2, 1), ["f0.txt", "f1.txt"]);
+    }
+}
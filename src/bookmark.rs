@@ -0,0 +1,165 @@
+//! Directory bookmarks: `bank bookmark add docs ~/work/docs`, then
+//! `bank @docs/meeting.md`
+//!
+//! Bookmarks live in `$XDG_CONFIG_HOME/bank/bookmarks.json` as an object of
+//! names to absolute directories. A path argument starting with `@name` (alone
+//! or followed by `/`) is expanded with the other shell-style expansions, so
+//! `--no-expand` keeps it literally.
+
+use crate::expand;
+use crate::json::{self, Value};
+use crate::scaffold;
+use crate::vfs::Filesystem;
+use anyhow::{bail, Context, Result};
+use clap::{Args as ClapArgs, Subcommand};
+use colored::*;
+use std::path::{Path, PathBuf};
+
+pub const BOOKMARKS_FILE: &str = "bookmarks.json";
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct BookmarkArgs {
+    #[command(subcommand)]
+    pub command: BookmarkCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum BookmarkCommand {
+    /// Bookmark DIR (default: the current directory) as NAME, replacing an existing NAME
+    Add {
+        #[arg(value_name = "NAME")]
+        name: String,
+        #[arg(value_name = "DIR", default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Forget a bookmark
+    Remove {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    /// Show every bookmark
+    List,
+}
+
+pub type Bookmarks = Vec<(String, PathBuf)>;
+
+fn bookmarks_file() -> Result<PathBuf> {
+    scaffold::config_dir()
+        .map(|dir| dir.join(BOOKMARKS_FILE))
+        .context("Cannot locate the bookmarks: neither XDG_CONFIG_HOME nor HOME is set")
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || "-_.".contains(c)) {
+        bail!("Bookmark names may only contain letters, digits, '-', '_' and '.': {}", name);
+    }
+    Ok(())
+}
+
+fn parse(text: &str, origin: &Path) -> Result<Bookmarks> {
+    let value = json::parse(text).with_context(|| format!("Failed to parse {}", origin.display()))?;
+    let Value::Object(members) = value else {
+        bail!("{} must hold an object of bookmark names to directories", origin.display());
+    };
+    members
+        .into_iter()
+        .map(|(name, dir)| match dir.as_str() {
+            Some(dir) => Ok((name, PathBuf::from(dir))),
+            None => bail!("Bookmark '{}' in {} must be a string, not {}", name, origin.display(), dir.type_name()),
+        })
+        .collect()
+}
+
+/// The saved bookmarks; none if the file does not exist yet
+pub fn load(fs: &dyn Filesystem) -> Result<Bookmarks> {
+    let file = bookmarks_file()?;
+    match fs.read_file(&file) {
+        Ok(bytes) => parse(&String::from_utf8_lossy(&bytes), &file),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", file.display())),
+    }
+}
+
+fn save(fs: &dyn Filesystem, bookmarks: &Bookmarks) -> Result<()> {
+    let file = bookmarks_file()?;
+    let lines: Vec<String> = bookmarks
+        .iter()
+        .map(|(name, dir)| format!("  {}: {}", json::quote(name), json::quote(&dir.to_string_lossy())))
+        .collect();
+    let text = if lines.is_empty() { "{}\n".to_string() } else { format!("{{\n{}\n}}\n", lines.join(",\n")) };
+    if let Some(parent) = file.parent() {
+        fs.create_dir_all(parent).with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    fs.write_file(&file, text.as_bytes()).with_context(|| format!("Failed to write {}", file.display()))
+}
+
+/// Replace a leading `@name` with its directory, expanding variables in the
+/// rest; other paths are returned as they are
+pub fn expand(bookmarks: &Bookmarks, path: &str) -> Result<String> {
+    let Some(rest) = path.strip_prefix('@') else {
+        return Ok(path.to_string());
+    };
+    let (name, tail) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let Some((_, dir)) = bookmarks.iter().find(|(bookmark, _)| bookmark == name) else {
+        bail!("Unknown bookmark @{} in {} (see `bank bookmark list`; --no-expand keeps it literally)", name, path);
+    };
+    let tail = expand::expand_path(tail).with_context(|| format!("Cannot expand {}", path))?;
+    let dir = dir.to_string_lossy();
+    match dir.trim_end_matches('/') {
+        "" if tail.is_empty() => Ok("/".to_string()),
+        base => Ok(format!("{}{}", base, tail)),
+    }
+}
+
+pub fn run(fs: &dyn Filesystem, args: &BookmarkArgs) -> Result<()> {
+    let mut bookmarks = load(fs)?;
+    match &args.command {
+        BookmarkCommand::Add { name, dir } => {
+            check_name(name)?;
+            if !fs.is_dir(dir) {
+                bail!("{} is not a directory", dir.display());
+            }
+            let dir = fs.canonicalize(dir).with_context(|| format!("Failed to resolve {}", dir.display()))?;
+            bookmarks.retain(|(bookmark, _)| bookmark != name);
+            bookmarks.push((name.clone(), dir.clone()));
+            bookmarks.sort();
+            save(fs, &bookmarks)?;
+            println!("{} @{} -> {}", "✓".bright_green(), name.cyan(), dir.display().to_string().green());
+        }
+        BookmarkCommand::Remove { name } => {
+            let before = bookmarks.len();
+            bookmarks.retain(|(bookmark, _)| bookmark != name);
+            if bookmarks.len() == before {
+                bail!("No bookmark named {}", name);
+            }
+            save(fs, &bookmarks)?;
+            println!("{} Removed @{}", "✓".bright_green(), name.cyan());
+        }
+        BookmarkCommand::List => {
+            let width = bookmarks.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+            for (name, dir) in &bookmarks {
+                println!("@{:<width$}  {}", name, dir.display(), width = width);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let bookmarks = parse(r#"{"docs": "/home/dev/work/docs", "x": "/"}"#, Path::new("bookmarks.json")).unwrap();
+        assert_eq!(expand(&bookmarks, "@docs/meeting.md").unwrap(), "/home/dev/work/docs/meeting.md");
+        assert_eq!(expand(&bookmarks, "@docs").unwrap(), "/home/dev/work/docs");
+        assert_eq!(expand(&bookmarks, "notes/@docs").unwrap(), "notes/@docs");
+        assert_eq!(expand(&bookmarks, "@x/a").unwrap(), "/a");
+        assert!(expand(&bookmarks, "@missing/a").is_err());
+
+        assert!(parse(r#"{"docs": 1}"#, Path::new("bookmarks.json")).is_err());
+        assert!(check_name("my-docs_2").is_ok());
+        assert!(check_name("a/b").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! cargo-generate templates as scaffolds for `bank new`
+//!
+//! A directory is read as one when it has a `cargo-generate.toml`, or a
+//! `Cargo.toml` that uses `{{project-name}}`. The whole directory is the
+//! project, with cargo-generate's own variables filled in: `project-name`
+//! (DEST's name in kebab case), `crate_name` (in snake case), `crate_type`,
+//! `authors` (from `$CARGO_NAME`/`$CARGO_EMAIL` or git's `user.name` and
+//! `user.email`) and `os-arch`. From `cargo-generate.toml`:
+//!
+//! - `[placeholders]` are asked for like `_scaffold.json` variables, with
+//!   their `prompt`, `default`, `choices` and `regex` (`type = "bool"`
+//!   takes `true` or `false`);
+//! - `[template] ignore` and the `.genignore` file leave paths out, and
+//!   `[template] exclude` copies them without rendering.
+//!
+//! A `.liquid` suffix is dropped from file names. Only plain `{{ name }}`
+//! placeholders and the tags bank knows are rendered, not Liquid filters or
+//! `{% if %}`; `include`, `[conditional]` and `[hooks]` are not supported.
+
+use crate::json::Value;
+use crate::scaffold::{Layout, Variable};
+use crate::template::Vars;
+use crate::toml;
+use anyhow::{bail, Context, Result};
+use colored::*;
+use regex::Regex;
+use std::path::Path;
+use std::process::Command;
+
+/// The file that configures a cargo-generate template
+pub const CONFIG_FILE: &str = "cargo-generate.toml";
+
+/// Paths to leave out, one glob per line
+const IGNORE_FILE: &str = ".genignore";
+
+pub fn is_template(dir: &Path) -> bool {
+    dir.join(CONFIG_FILE).is_file()
+        || std::fs::read_to_string(dir.join("Cargo.toml")).is_ok_and(|manifest| manifest.contains("{{project-name}}"))
+}
+
+/// The variables and rules of the template in `dir`, for a project in `destination`
+pub fn layout(dir: &Path, destination: &Path) -> Result<Layout> {
+    let config = dir.join(CONFIG_FILE);
+    let document = match std::fs::read_to_string(&config) {
+        Ok(text) => toml::parse(&text).with_context(|| format!("Invalid {}", config.display()))?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Value::Object(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", config.display())),
+    };
+    let declarations = match document.get("placeholders") {
+        Some(Value::Object(placeholders)) => placeholders
+            .iter()
+            .map(|(name, fields)| placeholder(name, fields).with_context(|| format!("Invalid {}: placeholder '{}'", config.display(), name)))
+            .collect::<Result<_>>()?,
+        Some(other) => bail!("Invalid {}: 'placeholders' must be a table, found {}", config.display(), other.type_name()),
+        None => Vec::new(),
+    };
+
+    let template = document.get("template");
+    let globs = |key: &str| -> Result<Vec<String>> {
+        match template.and_then(|template| template.get(key)) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| item.as_str().map(str::to_string))
+                .collect::<Option<_>>()
+                .with_context(|| format!("Invalid {}: 'template.{}' must list strings", config.display(), key)),
+            Some(other) => bail!("Invalid {}: 'template.{}' must be an array, found {}", config.display(), key, other.type_name()),
+            None => Ok(Vec::new()),
+        }
+    };
+    let mut ignore = vec![CONFIG_FILE.to_string(), IGNORE_FILE.to_string()];
+    ignore.extend(globs("ignore")?);
+    if let Ok(text) = std::fs::read_to_string(dir.join(IGNORE_FILE)) {
+        ignore.extend(text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(str::to_string));
+    }
+    let verbatim = globs("exclude")?;
+
+    let unsupported: Vec<&str> = [
+        ("include", template.and_then(|template| template.get("include"))),
+        ("conditional", document.get("conditional")),
+        ("hooks", document.get("hooks")),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.map(|_| name))
+    .collect();
+    if !unsupported.is_empty() {
+        println!("{} {}: {} not supported and ignored", "Warning:".yellow(), config.display(), unsupported.join(", "));
+    }
+
+    Ok(Layout {
+        root: dir.to_path_buf(),
+        declarations,
+        origin: config,
+        namespace: "",
+        constants: constants(destination),
+        verbatim,
+        ignore,
+        suffix: ".liquid",
+    })
+}
+
+fn placeholder(name: &str, fields: &Value) -> Result<Variable> {
+    let text = |key: &str| -> Result<Option<String>> {
+        match fields.get(key) {
+            None => Ok(None),
+            Some(Value::String(text)) => Ok(Some(text.clone())),
+            Some(Value::Bool(flag)) => Ok(Some(flag.to_string())),
+            Some(other) => bail!("'{}' must be a string, found {}", key, other.type_name()),
+        }
+    };
+    let boolean = match text("type")?.as_deref() {
+        None | Some("string") => false,
+        Some("bool") => true,
+        Some(other) => bail!("unknown type '{}'", other),
+    };
+    let choices = match fields.get("choices") {
+        Some(Value::Array(items)) => {
+            let choices: Vec<String> =
+                items.iter().map(|item| item.as_str().map(regex::escape)).collect::<Option<_>>().context("'choices' must list strings")?;
+            Some(choices.join("|"))
+        }
+        Some(other) => bail!("'choices' must be an array, found {}", other.type_name()),
+        None => None,
+    };
+    let pattern = match (boolean, choices, text("regex")?) {
+        (true, _, _) => Some("true|false".to_string()),
+        (false, Some(choices), _) => Some(choices),
+        (false, None, regex) => regex,
+    };
+    Ok(Variable {
+        name: name.to_string(),
+        prompt: text("prompt")?,
+        default: text("default")?,
+        pattern: match pattern {
+            Some(pattern) => Some(Regex::new(&format!("^(?:{})$", pattern)).with_context(|| format!("invalid regex '{}'", pattern))?),
+            None => None,
+        },
+    })
+}
+
+/// The variables cargo-generate sets itself
+fn constants(destination: &Path) -> Vars {
+    let name = std::path::absolute(destination)
+        .ok()
+        .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+    let project_name = name.trim().to_lowercase().replace(['_', ' '], "-");
+    Vars::from([
+        ("crate_name".to_string(), project_name.replace('-', "_")),
+        ("project-name".to_string(), project_name),
+        ("crate_type".to_string(), "bin".to_string()),
+        ("authors".to_string(), authors()),
+        ("os-arch".to_string(), format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)),
+    ])
+}
+
+/// `Name <email>`, as cargo new would put it in `authors`
+fn authors() -> String {
+    let setting = |variable: &str, key: &str| {
+        std::env::var(variable).ok().filter(|value| !value.is_empty()).or_else(|| {
+            let output = Command::new("git").args(["config", "--get", key]).output().ok()?;
+            let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (output.status.success() && !value.is_empty()).then_some(value)
+        })
+    };
+    match (setting("CARGO_NAME", "user.name"), setting("CARGO_EMAIL", "user.email")) {
+        (Some(name), Some(email)) => format!("{} <{}>", name, email),
+        (Some(name), None) => name,
+        (None, Some(email)) => format!("<{}>", email),
+        (None, None) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scaffold::{render_tree, resolve_vars};
+    use crate::template::Loader;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cargo_generate_template() {
+        let temp_dir = TempDir::new().unwrap();
+        let template = temp_dir.path();
+        std::fs::write(
+            template.join(CONFIG_FILE),
+            r#"
+            [template]
+            ignore = ["notes"]
+            exclude = ["assets/*"]
+
+            [placeholders]
+            license = { type = "string", prompt = "License?", choices = ["MIT", "Apache-2.0"], default = "MIT" }
+            serde = { type = "bool", prompt = "Use serde?", default = false }
+            "#,
+        )
+        .unwrap();
+        std::fs::write(template.join(IGNORE_FILE), "# scratch files\n*.bak\n").unwrap();
+        std::fs::write(template.join("Cargo.toml.liquid"), "[package]\nname = \"{{project-name}}\"\nlicense = \"{{ license }}\"\n").unwrap();
+        std::fs::create_dir_all(template.join("src")).unwrap();
+        std::fs::write(template.join("src/main.rs"), "// {{crate_name}}, serde: {{serde}}\n").unwrap();
+        std::fs::write(template.join("src/main.rs.bak"), "").unwrap();
+        std::fs::create_dir_all(template.join("assets")).unwrap();
+        std::fs::write(template.join("assets/page.html"), "{{ kept }}").unwrap();
+        std::fs::create_dir_all(template.join("notes")).unwrap();
+        std::fs::write(template.join("notes/todo.md"), "").unwrap();
+        assert!(is_template(template));
+
+        let layout = layout(template, Path::new("/tmp/My_Tool")).unwrap();
+        assert_eq!(layout.constants["project-name"], "my-tool");
+        assert_eq!(layout.constants["crate_name"], "my_tool");
+        let loader = Loader::new(Vec::new());
+        let bad = [("serde".to_string(), "yes".to_string())];
+        assert!(resolve_vars(&layout.declarations, &bad, &loader, &layout.origin, layout.constants.clone(), false).is_err());
+        let vars = resolve_vars(&layout.declarations, &[], &loader, &layout.origin, layout.constants.clone(), false).unwrap();
+
+        let items = render_tree(&layout, &loader, &vars).unwrap();
+        let paths: Vec<&Path> = items.iter().map(|item| item.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("Cargo.toml"), Path::new("assets"), Path::new("assets/page.html"), Path::new("src"), Path::new("src/main.rs")]);
+        assert_eq!(items[0].contents.as_deref(), Some(&b"[package]\nname = \"my-tool\"\nlicense = \"MIT\"\n"[..]));
+        assert_eq!(items[2].contents.as_deref(), Some(&b"{{ kept }}"[..]));
+        assert_eq!(items[4].contents.as_deref(), Some(&b"// my_tool, serde: false\n"[..]));
+    }
+
+    #[test]
+    fn test_placeholders_cannot_escape_destination() {
+        let temp_dir = TempDir::new().unwrap();
+        let template = temp_dir.path();
+        std::fs::write(template.join(CONFIG_FILE), "[placeholders]\ndir = { type = \"string\", prompt = \"Dir?\", default = \"../../pwned\" }\n").unwrap();
+        std::fs::write(template.join("{{dir}}.liquid"), "").unwrap();
+
+        let layout = layout(template, Path::new("/tmp/demo")).unwrap();
+        let loader = Loader::new(Vec::new());
+        let vars = resolve_vars(&layout.declarations, &[], &loader, &layout.origin, layout.constants.clone(), false).unwrap();
+        assert!(render_tree(&layout, &loader, &vars).is_err());
+        let absolute = [("dir".to_string(), "/etc/cron.d".to_string())];
+        let vars = resolve_vars(&layout.declarations, &absolute, &loader, &layout.origin, layout.constants.clone(), false).unwrap();
+        assert!(render_tree(&layout, &loader, &vars).is_err());
+        let plain = [("dir".to_string(), "notes".to_string())];
+        let vars = resolve_vars(&layout.declarations, &plain, &loader, &layout.origin, layout.constants.clone(), false).unwrap();
+        assert_eq!(render_tree(&layout, &loader, &vars).unwrap()[0].path, Path::new("notes"));
+    }
+}
@@ -0,0 +1,234 @@
+//! `bank clone-structure`: replicate a directory skeleton without its files
+
+use crate::glob::any_match;
+use crate::keep::{self, KeepArgs};
+use crate::vfs::{FileKind, Filesystem};
+use anyhow::{Context, Result};
+use clap::Args as ClapArgs;
+use colored::*;
+use std::path::{Path, PathBuf};
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct CloneStructureArgs {
+    /// Directory whose hierarchy should be replicated
+    #[arg(value_name = "SRC")]
+    pub source: PathBuf,
+
+    /// Directory under which the hierarchy is recreated
+    #[arg(value_name = "DEST")]
+    pub destination: PathBuf,
+
+    /// Copy permission bits from the source directories
+    #[arg(long = "copy-modes")]
+    pub copy_modes: bool,
+
+    /// Copy access and modification times from the source directories
+    #[arg(long = "copy-times")]
+    pub copy_times: bool,
+
+    /// Only replicate directories whose relative path matches GLOB (repeatable)
+    #[arg(long = "include", value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Skip directories matching GLOB, together with everything below them (repeatable)
+    #[arg(long = "exclude", value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    #[command(flatten)]
+    pub keep: KeepArgs,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+pub fn run(fs: &dyn Filesystem, args: &CloneStructureArgs) -> Result<()> {
+    if !fs.is_dir(&args.source) {
+        anyhow::bail!("Source is not a directory: {}", args.source.display());
+    }
+
+    // Collect the whole source hierarchy before creating anything, so cloning
+    // into a subdirectory of SRC cannot feed on its own output.
+    let skip = fs.canonicalize(&args.destination).ok();
+    let mut relative_dirs = Vec::new();
+    collect_directories(fs, &args.source, "", args, skip.as_deref(), &mut relative_dirs)?;
+
+    let keep_file = args.keep.resolved()?;
+    let mut new_dirs = Vec::new();
+    if !fs.exists(&args.destination) {
+        new_dirs.push(args.destination.clone());
+    }
+    fs.create_dir_all(&args.destination)
+        .with_context(|| format!("Failed to create directory {}", args.destination.display()))?;
+
+    let mut created = vec![(args.source.clone(), args.destination.clone())];
+    for relative in &relative_dirs {
+        let source = args.source.join(relative);
+        let target = args.destination.join(relative);
+        if !fs.exists(&target) {
+            new_dirs.push(target.clone());
+        }
+        fs.create_dir_all(&target)
+            .with_context(|| format!("Failed to create directory {}", target.display()))?;
+        if args.verbose {
+            println!("{} Created: {}", "✓".bright_green(), target.display().to_string().green());
+        }
+        created.push((source, target));
+    }
+
+    // Once the whole skeleton exists, so only its leaves are still empty
+    if let Some(name) = &keep_file {
+        for dir in &new_dirs {
+            keep::fill(fs, dir, name).with_context(|| format!("Failed to create {}", dir.join(name).display()))?;
+        }
+    }
+
+    if args.copy_modes {
+        for (source, target) in &created {
+            let mode = fs
+                .metadata(source)
+                .with_context(|| format!("Failed to read metadata for {}", source.display()))?
+                .mode;
+            fs.set_permissions(target, mode)
+                .with_context(|| format!("Failed to set permissions for {}", target.display()))?;
+        }
+    }
+
+    // Children first: creating a subdirectory bumps its parent's mtime
+    if args.copy_times {
+        for (source, target) in created.iter().rev() {
+            let metadata = fs
+                .metadata(source)
+                .with_context(|| format!("Failed to read metadata for {}", source.display()))?;
+            fs.set_times(target, metadata.accessed, metadata.modified)
+                .with_context(|| format!("Failed to set timestamps for {}", target.display()))?;
+        }
+    }
+
+    if args.verbose {
+        println!(
+            "Replicated {} directories into {}",
+            relative_dirs.len().to_string().cyan(),
+            args.destination.display().to_string().green()
+        );
+    }
+
+    Ok(())
+}
+
+/// Walk `root/relative` depth-first in sorted order, pushing every directory that passes the filters
+fn collect_directories(
+    fs: &dyn Filesystem,
+    root: &Path,
+    relative: &str,
+    args: &CloneStructureArgs,
+    skip: Option<&Path>,
+    out: &mut Vec<String>,
+) -> Result<()> {
+    let dir = root.join(relative);
+    let entries = fs
+        .read_dir(&dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+
+    for entry in entries {
+        // Symlinks to directories are not followed
+        if entry.kind != FileKind::Directory {
+            continue;
+        }
+        if skip.is_some_and(|skip| fs.canonicalize(&dir.join(&entry.name)).ok().as_deref() == Some(skip)) {
+            continue;
+        }
+
+        let child = if relative.is_empty() {
+            entry.name
+        } else {
+            format!("{}/{}", relative, entry.name)
+        };
+
+        if any_match(&args.exclude, &child) {
+            continue;
+        }
+        if args.include.is_empty() || any_match(&args.include, &child) {
+            out.push(child.clone());
+        }
+        collect_directories(fs, root, &child, args, skip, out)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{LocalFs, MemoryFs};
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    fn clone_args(source: &Path, destination: &Path) -> CloneStructureArgs {
+        CloneStructureArgs {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            copy_modes: false,
+            copy_times: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            keep: KeepArgs::default(),
+            verbose: false,
+        }
+    }
+
+    #[test]
+    fn test_clone_structure_skips_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("src");
+        fs::create_dir_all(source.join("a/b")).unwrap();
+        fs::create_dir_all(source.join("node_modules/pkg")).unwrap();
+        fs::write(source.join("a/file.txt"), "content").unwrap();
+
+        let destination = temp_dir.path().join("out");
+        let mut args = clone_args(&source, &destination);
+        args.exclude = vec!["node_modules".to_string()];
+        args.keep.keep_file = Some(".gitkeep".to_string());
+        run(&LocalFs, &args).unwrap();
+
+        assert!(destination.join("a/b").is_dir());
+        assert!(destination.join("a/b/.gitkeep").is_file());
+        assert!(!destination.join("a/.gitkeep").exists());
+        assert!(!destination.join("a/file.txt").exists());
+        assert!(!destination.join("node_modules").exists());
+    }
+
+    #[test]
+    fn test_clone_structure_copies_modes_and_times() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("src");
+        fs::create_dir_all(source.join("private")).unwrap();
+        fs::set_permissions(source.join("private"), fs::Permissions::from_mode(0o700)).unwrap();
+        let stamp = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_times(source.join("private"), stamp, stamp).unwrap();
+
+        let destination = temp_dir.path().join("out");
+        let mut args = clone_args(&source, &destination);
+        args.copy_modes = true;
+        args.copy_times = true;
+        run(&LocalFs, &args).unwrap();
+
+        let metadata = fs::metadata(destination.join("private")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+        assert_eq!(filetime::FileTime::from_last_modification_time(&metadata), stamp);
+    }
+
+    #[test]
+    fn test_clone_structure_in_memory() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("src/a/b")).unwrap();
+        memory.create_file(Path::new("src/a/notes.txt")).unwrap();
+
+        let args = clone_args(Path::new("src"), Path::new("out"));
+        run(&memory, &args).unwrap();
+
+        assert!(memory.is_dir(Path::new("out/a/b")));
+        assert!(!memory.exists(Path::new("out/a/notes.txt")));
+    }
+}
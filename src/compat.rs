@@ -0,0 +1,636 @@
+//! `--compat gnu|posix`: behave exactly like coreutils `touch` and `mkdir`
+//!
+//! Selected with `bank --compat gnu touch ARGS...` or by invoking the binary as
+//! `touch`/`mkdir` (e.g. through a symlink). Flags follow the emulated tool, not
+//! bank: no type heuristics, no colors, GNU-style diagnostics on stderr and exit
+//! status 1 on any failure.
+
+use crate::mode;
+use crate::vfs::Filesystem;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use clap::ValueEnum;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Standard {
+    /// GNU coreutils: long options, option permutation, `-h`, `-v`
+    Gnu,
+    /// POSIX utilities: short options only, parsing stops at the first operand
+    Posix,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    Touch,
+    Mkdir,
+}
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Touch => "touch",
+            Tool::Mkdir => "mkdir",
+        }
+    }
+
+    fn options(self) -> &'static [OptSpec] {
+        match self {
+            Tool::Touch => TOUCH_OPTIONS,
+            Tool::Mkdir => MKDIR_OPTIONS,
+        }
+    }
+}
+
+/// One option of the emulated tool
+struct OptSpec {
+    name: &'static str,
+    short: Option<char>,
+    long: Option<&'static str>,
+    takes_value: bool,
+    gnu_only: bool,
+}
+
+const fn opt(name: &'static str, short: Option<char>, long: Option<&'static str>, takes_value: bool, gnu_only: bool) -> OptSpec {
+    OptSpec { name, short, long, takes_value, gnu_only }
+}
+
+const TOUCH_OPTIONS: &[OptSpec] = &[
+    opt("atime", Some('a'), None, false, false),
+    opt("no-create", Some('c'), Some("no-create"), false, false),
+    opt("date", Some('d'), Some("date"), true, false),
+    opt("ignored", Some('f'), None, false, true),
+    opt("no-dereference", Some('h'), Some("no-dereference"), false, true),
+    opt("mtime", Some('m'), None, false, false),
+    opt("reference", Some('r'), Some("reference"), true, false),
+    opt("stamp", Some('t'), None, true, false),
+    opt("time", None, Some("time"), true, true),
+    opt("help", None, Some("help"), false, true),
+    opt("version", None, Some("version"), false, true),
+];
+
+const MKDIR_OPTIONS: &[OptSpec] = &[
+    opt("mode", Some('m'), Some("mode"), true, false),
+    opt("parents", Some('p'), Some("parents"), false, false),
+    opt("verbose", Some('v'), Some("verbose"), false, true),
+    opt("help", None, Some("help"), false, true),
+    opt("version", None, Some("version"), false, true),
+];
+
+/// Run in compatibility mode if `argv` asks for it, returning the exit status
+pub fn dispatch(argv: &[String]) -> Option<i32> {
+    let program = argv.first().map(|arg| Path::new(arg).file_name().unwrap_or_default().to_string_lossy().into_owned());
+    match program.as_deref() {
+        Some("touch") => return Some(run(Tool::Touch, Standard::Gnu, &argv[1..])),
+        Some("mkdir") => return Some(run(Tool::Mkdir, Standard::Gnu, &argv[1..])),
+        _ => {}
+    }
+
+    let (standard, rest) = match argv.get(1).map(String::as_str) {
+        Some("--compat") => (argv.get(2).map(String::as_str), argv.get(3..).unwrap_or_default()),
+        Some(arg) if arg.starts_with("--compat=") => (Some(&arg["--compat=".len()..]), &argv[2..]),
+        _ => return None,
+    };
+    let standard = match standard.map(|value| Standard::from_str(value, true)) {
+        Some(Ok(standard)) => standard,
+        _ => {
+            eprintln!("bank: --compat expects 'gnu' or 'posix'");
+            return Some(1);
+        }
+    };
+    let tool = match rest.first().map(String::as_str) {
+        Some("touch") => Tool::Touch,
+        Some("mkdir") => Tool::Mkdir,
+        _ => {
+            eprintln!("bank: --compat must be followed by the emulated tool: touch or mkdir");
+            return Some(1);
+        }
+    };
+    Some(run(tool, standard, &rest[1..]))
+}
+
+fn run(tool: Tool, standard: Standard, args: &[String]) -> i32 {
+    let parsed = match parse_options(tool, standard, args) {
+        Ok(parsed) => parsed,
+        Err(message) => return usage_error(tool, &message),
+    };
+
+    if parsed.has("help") {
+        print_help(tool);
+        return 0;
+    }
+    if parsed.has("version") {
+        println!("{} (bank) {}", tool.name(), env!("CARGO_PKG_VERSION"));
+        return 0;
+    }
+
+    match tool {
+        Tool::Touch => run_touch(&crate::vfs::LocalFs, &parsed),
+        Tool::Mkdir => run_mkdir(&crate::vfs::LocalFs, &parsed, mode::current_umask()),
+    }
+}
+
+#[derive(Debug, Default)]
+struct Parsed {
+    options: Vec<(&'static str, Option<String>)>,
+    operands: Vec<String>,
+}
+
+impl Parsed {
+    fn has(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| *option == name)
+    }
+
+    /// Last value given for an option, as getopt-based tools use
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(option, _)| *option == name).and_then(|(_, value)| value.as_deref())
+    }
+}
+
+/// getopt_long-style parsing: clustered short options, attached or separate
+/// values, unique long-option prefixes and `--`. GNU permutes operands and
+/// options; POSIX stops at the first operand.
+fn parse_options(tool: Tool, standard: Standard, args: &[String]) -> Result<Parsed, String> {
+    let specs: Vec<&OptSpec> = tool
+        .options()
+        .iter()
+        .filter(|spec| standard == Standard::Gnu || !spec.gnu_only)
+        .collect();
+    let mut parsed = Parsed::default();
+    let mut index = 0;
+
+    while index < args.len() {
+        let arg = &args[index];
+        index += 1;
+
+        if arg == "--" {
+            parsed.operands.extend(args[index..].iter().cloned());
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--").filter(|_| standard == Standard::Gnu) {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let spec = find_long(&specs, name, arg)?;
+            let long_name = spec.long.unwrap_or(name);
+            let value = match (spec.takes_value, inline) {
+                (true, Some(value)) => Some(value),
+                (true, None) => match args.get(index) {
+                    Some(value) => {
+                        index += 1;
+                        Some(value.clone())
+                    }
+                    None => return Err(format!("option '--{}' requires an argument", long_name)),
+                },
+                (false, Some(_)) => return Err(format!("option '--{}' doesn't allow an argument", long_name)),
+                (false, None) => None,
+            };
+            parsed.options.push((spec.name, value));
+            continue;
+        }
+
+        if arg.len() > 1 && arg.starts_with('-') {
+            let cluster: Vec<char> = arg[1..].chars().collect();
+            let mut position = 0;
+            while position < cluster.len() {
+                let letter = cluster[position];
+                position += 1;
+                let spec = specs
+                    .iter()
+                    .find(|spec| spec.short == Some(letter))
+                    .ok_or_else(|| format!("invalid option -- '{}'", letter))?;
+                if !spec.takes_value {
+                    parsed.options.push((spec.name, None));
+                    continue;
+                }
+                let value = if position < cluster.len() {
+                    cluster[position..].iter().collect()
+                } else {
+                    match args.get(index) {
+                        Some(value) => {
+                            index += 1;
+                            value.clone()
+                        }
+                        None => return Err(format!("option requires an argument -- '{}'", letter)),
+                    }
+                };
+                parsed.options.push((spec.name, Some(value)));
+                break;
+            }
+            continue;
+        }
+
+        parsed.operands.push(arg.clone());
+        if standard == Standard::Posix {
+            parsed.operands.extend(args[index..].iter().cloned());
+            break;
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn find_long<'a>(specs: &[&'a OptSpec], name: &str, arg: &str) -> Result<&'a OptSpec, String> {
+    if let Some(spec) = specs.iter().find(|spec| spec.long == Some(name)) {
+        return Ok(spec);
+    }
+    let candidates: Vec<&&OptSpec> = specs
+        .iter()
+        .filter(|spec| !name.is_empty() && spec.long.is_some_and(|long| long.starts_with(name)))
+        .collect();
+    match candidates.as_slice() {
+        [spec] => Ok(spec),
+        [] => Err(format!("unrecognized option '{}'", arg.split('=').next().unwrap_or(arg))),
+        many => {
+            let names: Vec<String> = many.iter().map(|spec| format!("'--{}'", spec.long.unwrap_or(""))).collect();
+            Err(format!("option '{}' is ambiguous; possibilities: {}", arg, names.join(" ")))
+        }
+    }
+}
+
+fn usage_error(tool: Tool, message: &str) -> i32 {
+    eprintln!("{}: {}", tool.name(), message);
+    usage_error_hint(tool)
+}
+
+fn usage_error_hint(tool: Tool) -> i32 {
+    eprintln!("Try '{} --help' for more information.", tool.name());
+    1
+}
+
+fn print_help(tool: Tool) {
+    match tool {
+        Tool::Touch => println!(
+            "Usage: touch [OPTION]... FILE...\n\
+             Update the access and modification times of each FILE to the current time.\n\n\
+             A FILE argument that does not exist is created empty, unless -c or -h\n\
+             is supplied.\n\n\
+             \x20 -a                     change only the access time\n\
+             \x20 -c, --no-create        do not create any files\n\
+             \x20 -d, --date=STRING      parse STRING and use it instead of current time\n\
+             \x20 -f                     (ignored)\n\
+             \x20 -h, --no-dereference   affect each symbolic link instead of any referenced\n\
+             \x20                          file\n\
+             \x20 -m                     change only the modification time\n\
+             \x20 -r, --reference=FILE   use this file's times instead of current time\n\
+             \x20 -t STAMP               use [[CC]YY]MMDDhhmm[.ss] instead of current time\n\
+             \x20     --time=WORD        change the specified time:\n\
+             \x20                          WORD is access, atime, or use: equivalent to -a\n\
+             \x20                          WORD is modify or mtime: equivalent to -m\n\
+             \x20     --help             display this help and exit\n\
+             \x20     --version          output version information and exit"
+        ),
+        Tool::Mkdir => println!(
+            "Usage: mkdir [OPTION]... DIRECTORY...\n\
+             Create the DIRECTORY(ies), if they do not already exist.\n\n\
+             \x20 -m, --mode=MODE   set file mode (as in chmod), not a=rwx - umask\n\
+             \x20 -p, --parents     no error if existing, make parent directories as needed\n\
+             \x20 -v, --verbose     print a message for each created directory\n\
+             \x20     --help        display this help and exit\n\
+             \x20     --version     output version information and exit"
+        ),
+    }
+}
+
+/// Error text without Rust's " (os error N)" suffix, as coreutils prints it
+fn os_message(err: &io::Error) -> String {
+    let text = err.to_string();
+    match text.find(" (os error") {
+        Some(end) => text[..end].to_string(),
+        None => text,
+    }
+}
+
+fn run_touch(fs: &dyn Filesystem, parsed: &Parsed) -> i32 {
+    let tool = Tool::Touch;
+    let mut only_atime = parsed.has("atime");
+    let mut only_mtime = parsed.has("mtime");
+    if let Some(word) = parsed.value("time") {
+        match word {
+            "access" | "atime" | "use" => only_atime = true,
+            "modify" | "mtime" => only_mtime = true,
+            _ => {
+                eprintln!("{}: invalid argument '{}' for '--time'", tool.name(), word);
+                eprintln!("Valid arguments are:\n  - 'atime', 'access', 'use'\n  - 'mtime', 'modify'");
+                return usage_error_hint(tool);
+            }
+        }
+    }
+    let (change_atime, change_mtime) = match (only_atime, only_mtime) {
+        (false, false) => (true, true),
+        other => other,
+    };
+    let no_create = parsed.has("no-create");
+    let no_dereference = parsed.has("no-dereference");
+
+    let date = parsed.value("date");
+    let stamp = parsed.value("stamp");
+    let reference = parsed.value("reference");
+    if stamp.is_some() && (date.is_some() || reference.is_some()) {
+        return usage_error(tool, "cannot specify times from more than one source");
+    }
+
+    // (atime, mtime); None means "now"
+    let times: Option<(SystemTime, SystemTime)> = if let Some(reference) = reference {
+        if date.is_some() {
+            eprintln!("{}: --date relative to --reference is not supported", tool.name());
+            return 1;
+        }
+        let metadata = if no_dereference {
+            fs.symlink_metadata(Path::new(reference))
+        } else {
+            fs.metadata(Path::new(reference))
+        };
+        match metadata {
+            Ok(metadata) => Some((metadata.accessed, metadata.modified)),
+            Err(err) => {
+                eprintln!("{}: failed to get attributes of '{}': {}", tool.name(), reference, os_message(&err));
+                return 1;
+            }
+        }
+    } else if let Some(date) = date {
+        match parse_gnu_date(date) {
+            Some(time) => Some((time, time)),
+            None => {
+                eprintln!("{}: invalid date format '{}'", tool.name(), date);
+                return 1;
+            }
+        }
+    } else if let Some(stamp) = stamp {
+        match parse_posix_stamp(stamp) {
+            Some(time) => Some((time, time)),
+            None => {
+                eprintln!("{}: invalid date format '{}'", tool.name(), stamp);
+                return 1;
+            }
+        }
+    } else {
+        None
+    };
+
+    if parsed.operands.is_empty() {
+        return usage_error(tool, "missing file operand");
+    }
+
+    let mut status = 0;
+    for file in &parsed.operands {
+        let path = Path::new(file);
+        let exists = if no_dereference { fs.symlink_metadata(path).is_ok() } else { fs.exists(path) };
+        if !exists {
+            if no_create {
+                continue;
+            }
+            if !no_dereference {
+                if let Err(err) = fs.create_file(path) {
+                    eprintln!("{}: cannot touch '{}': {}", tool.name(), file, os_message(&err));
+                    status = 1;
+                    continue;
+                }
+            }
+        }
+
+        if let Err(err) = touch_times(fs, path, times, change_atime, change_mtime, no_dereference) {
+            if no_create && err.kind() == io::ErrorKind::NotFound {
+                continue;
+            }
+            eprintln!("{}: setting times of '{}': {}", tool.name(), file, os_message(&err));
+            status = 1;
+        }
+    }
+    status
+}
+
+fn touch_times(
+    fs: &dyn Filesystem,
+    path: &Path,
+    times: Option<(SystemTime, SystemTime)>,
+    change_atime: bool,
+    change_mtime: bool,
+    no_dereference: bool,
+) -> io::Result<()> {
+    let now = SystemTime::now();
+    let (atime, mtime) = times.unwrap_or((now, now));
+    let current = if change_atime && change_mtime {
+        None
+    } else if no_dereference {
+        Some(fs.symlink_metadata(path)?)
+    } else {
+        Some(fs.metadata(path)?)
+    };
+    let atime = match &current {
+        Some(metadata) if !change_atime => metadata.accessed,
+        _ => atime,
+    };
+    let mtime = match &current {
+        Some(metadata) if !change_mtime => metadata.modified,
+        _ => mtime,
+    };
+
+    if no_dereference {
+        // The Filesystem trait always follows symlinks
+        filetime::set_symlink_file_times(
+            path,
+            filetime::FileTime::from_system_time(atime),
+            filetime::FileTime::from_system_time(mtime),
+        )
+    } else {
+        fs.set_times(path, atime, mtime)
+    }
+}
+
+/// The subset of GNU date syntax bank understands: `@EPOCH`, RFC 3339, and
+/// `YYYY-MM-DD[( |T)HH:MM[:SS[.frac]]]` in local time
+fn parse_gnu_date(text: &str) -> Option<SystemTime> {
+    let text = text.trim();
+    if let Some(epoch) = text.strip_prefix('@') {
+        let seconds: f64 = epoch.parse().ok()?;
+        return if seconds >= 0.0 {
+            Some(UNIX_EPOCH + Duration::from_secs_f64(seconds))
+        } else {
+            Some(UNIX_EPOCH - Duration::from_secs_f64(-seconds))
+        };
+    }
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(text) {
+        return Some(parsed.into());
+    }
+    for format in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(text, format) {
+            return local_time(naive);
+        }
+    }
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+    local_time(date.and_hms_opt(0, 0, 0)?)
+}
+
+/// `[[CC]YY]MMDDhhmm[.ss]` in local time; two-digit years 69-99 are 19xx, 00-68 are 20xx
+fn parse_posix_stamp(stamp: &str) -> Option<SystemTime> {
+    let (base, seconds) = match stamp.split_once('.') {
+        Some((base, seconds)) if seconds.len() == 2 => (base, seconds.parse::<u32>().ok()?),
+        Some(_) => return None,
+        None => (stamp, 0),
+    };
+    if !base.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| base[range].parse::<u32>().ok();
+    let (year, rest) = match base.len() {
+        8 => (Local::now().format("%Y").to_string().parse().ok()?, base),
+        10 => {
+            let yy = field(0..2)? as i32;
+            (if yy >= 69 { 1900 + yy } else { 2000 + yy }, &base[2..])
+        }
+        12 => (field(0..4)? as i32, &base[4..]),
+        _ => return None,
+    };
+    let part = |range: std::ops::Range<usize>| rest[range].parse::<u32>().ok();
+    // POSIX allows a leap second; the clock cannot represent it
+    let seconds = seconds.min(59);
+    let naive = NaiveDate::from_ymd_opt(year, part(0..2)?, part(2..4)?)?.and_hms_opt(part(4..6)?, part(6..8)?, seconds)?;
+    local_time(naive)
+}
+
+fn local_time(naive: NaiveDateTime) -> Option<SystemTime> {
+    Local.from_local_datetime(&naive).earliest().map(SystemTime::from)
+}
+
+fn run_mkdir(fs: &dyn Filesystem, parsed: &Parsed, umask: u32) -> i32 {
+    let tool = Tool::Mkdir;
+    let mode = match parsed.value("mode") {
+        Some(text) => match mode::parse_mode(text, umask) {
+            Some(mode) => Some(mode),
+            None => {
+                eprintln!("{}: invalid mode '{}'", tool.name(), text);
+                return 1;
+            }
+        },
+        None => None,
+    };
+    let parents = parsed.has("parents");
+    let verbose = parsed.has("verbose");
+
+    if parsed.operands.is_empty() {
+        return usage_error(tool, "missing operand");
+    }
+
+    let mut status = 0;
+    for dir in &parsed.operands {
+        let path = Path::new(dir);
+        if parents {
+            let mut ancestors: Vec<&Path> = path.ancestors().skip(1).filter(|a| !a.as_os_str().is_empty()).collect();
+            ancestors.reverse();
+            let mut failed = false;
+            for ancestor in ancestors {
+                if fs.is_dir(ancestor) {
+                    continue;
+                }
+                match fs.create_dir(ancestor) {
+                    Ok(()) => {
+                        if verbose {
+                            println!("{}: created directory '{}'", tool.name(), ancestor.display());
+                        }
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::AlreadyExists && fs.is_dir(ancestor) => {}
+                    Err(err) => {
+                        eprintln!("{}: cannot create directory '{}': {}", tool.name(), ancestor.display(), os_message(&err));
+                        failed = true;
+                        break;
+                    }
+                }
+            }
+            if failed {
+                status = 1;
+                continue;
+            }
+        }
+
+        match fs.create_dir(path) {
+            Ok(()) => {
+                if verbose {
+                    println!("{}: created directory '{}'", tool.name(), dir);
+                }
+                if let Some(mode) = mode {
+                    if let Err(err) = fs.set_permissions(path, mode) {
+                        eprintln!("{}: cannot set permissions of '{}': {}", tool.name(), dir, os_message(&err));
+                        status = 1;
+                    }
+                }
+            }
+            Err(err) if parents && err.kind() == io::ErrorKind::AlreadyExists && fs.is_dir(path) => {}
+            Err(err) => {
+                eprintln!("{}: cannot create directory '{}': {}", tool.name(), dir, os_message(&err));
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_getopt_style_parsing() {
+        let parsed = parse_options(Tool::Touch, Standard::Gnu, &strings(&["-cm", "a", "-d2024-01-01", "--refer=ref", "b"])).unwrap();
+        assert!(parsed.has("no-create") && parsed.has("mtime"));
+        assert_eq!(parsed.value("date"), Some("2024-01-01"));
+        assert_eq!(parsed.value("reference"), Some("ref"));
+        assert_eq!(parsed.operands, vec!["a", "b"]);
+
+        // POSIX stops at the first operand and has no long options
+        let parsed = parse_options(Tool::Touch, Standard::Posix, &strings(&["a", "-c"])).unwrap();
+        assert_eq!(parsed.operands, vec!["a", "-c"]);
+        assert!(parse_options(Tool::Touch, Standard::Posix, &strings(&["-h", "a"])).is_err());
+
+        assert_eq!(
+            parse_options(Tool::Mkdir, Standard::Gnu, &strings(&["-x"])).unwrap_err(),
+            "invalid option -- 'x'"
+        );
+        assert_eq!(
+            parse_options(Tool::Touch, Standard::Gnu, &strings(&["-t"])).unwrap_err(),
+            "option requires an argument -- 't'"
+        );
+        assert!(parse_options(Tool::Touch, Standard::Gnu, &strings(&["--no-create=x"])).is_err());
+    }
+
+    #[test]
+    fn test_posix_stamp() {
+        let expected = local_time(NaiveDate::from_ymd_opt(1969, 12, 31).unwrap().and_hms_opt(23, 59, 30).unwrap());
+        assert_eq!(parse_posix_stamp("6912312359.30"), expected);
+        let expected = local_time(NaiveDate::from_ymd_opt(2068, 1, 2).unwrap().and_hms_opt(3, 4, 0).unwrap());
+        assert_eq!(parse_posix_stamp("6801020304"), expected);
+        assert!(parse_posix_stamp("202413011200").is_none());
+        assert!(parse_posix_stamp("12011200.5").is_none());
+        assert_eq!(parse_gnu_date("@0"), Some(UNIX_EPOCH));
+    }
+
+    #[test]
+    fn test_touch_and_mkdir_semantics() {
+        let memory = MemoryFs::new();
+        let parsed = parse_options(Tool::Touch, Standard::Gnu, &strings(&["-c", "missing", "new"])).unwrap();
+        // -c skips missing files silently, so nothing is created and nothing fails
+        assert_eq!(run_touch(&memory, &parsed), 0);
+        assert!(!memory.exists(Path::new("new")));
+
+        let parsed = parse_options(Tool::Touch, Standard::Gnu, &strings(&["a", "nodir/b"])).unwrap();
+        assert_eq!(run_touch(&memory, &parsed), 1);
+        assert!(memory.exists(Path::new("a")));
+
+        let parsed = parse_options(Tool::Mkdir, Standard::Gnu, &strings(&["-p", "-m", "700", "x/y/z"])).unwrap();
+        assert_eq!(run_mkdir(&memory, &parsed, 0o022), 0);
+        assert_eq!(memory.metadata(Path::new("x/y/z")).unwrap().mode, 0o700);
+        assert_eq!(memory.metadata(Path::new("x/y")).unwrap().mode, 0o755);
+
+        // Existing directories are an error without -p
+        let parsed = parse_options(Tool::Mkdir, Standard::Gnu, &strings(&["x"])).unwrap();
+        assert_eq!(run_mkdir(&memory, &parsed, 0o022), 1);
+    }
+}
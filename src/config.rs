@@ -0,0 +1,111 @@
+//! `$XDG_CONFIG_HOME/bank/config.toml` (else `~/.config/bank/config.toml`):
+//! settings that apply to every run
+//!
+//! ```toml
+//! # Modes for new paths matching a glob when no --mode is given
+//! [modes]
+//! "**/bin/*" = "755"
+//! "**/*.key" = "600"
+//! ```
+//!
+//! Top-level settings go before the first table:
+//!
+//! ```toml
+//! # Refuse to create anything outside these (~ and $VAR are expanded)
+//! allowed-roots = ["~/work", "/srv/data"]
+//! # Remember where runs create things, as if --history were always given
+//! history = true
+//! ```
+
+use crate::expand;
+use crate::json::Value;
+use crate::policy;
+use crate::scaffold;
+use crate::toml;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Default modes by glob, in file order
+    pub modes: Vec<(String, u32)>,
+    /// The only directories paths may be created in; anywhere if empty
+    pub allowed_roots: Vec<PathBuf>,
+    /// Whether every run keeps the places history
+    pub history: bool,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        scaffold::config_dir().map(|dir| dir.join(CONFIG_FILE))
+    }
+
+    /// The user's configuration; empty if there is no config file
+    pub fn load() -> Result<Self> {
+        match Self::path() {
+            Some(path) if path.is_file() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let Value::Object(members) = toml::parse(text)? else {
+            bail!("expected a table");
+        };
+        let mut config = Self::default();
+        for (key, value) in &members {
+            match key.as_str() {
+                "modes" => config.modes = policy::parse_modes(value)?,
+                "allowed-roots" => config.allowed_roots = parse_roots(value)?,
+                "history" => match value {
+                    Value::Bool(history) => config.history = *history,
+                    other => bail!("'history' must be a boolean, found {}", other.type_name()),
+                },
+                other => bail!("unknown setting '{}'", other),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn parse_roots(value: &Value) -> Result<Vec<PathBuf>> {
+    let Value::Array(items) = value else {
+        bail!("'allowed-roots' must be an array, found {}", value.type_name());
+    };
+    items
+        .iter()
+        .map(|item| {
+            let text = item.as_str().with_context(|| format!("'allowed-roots' must list strings, found {}", item.type_name()))?;
+            let root = PathBuf::from(expand::expand_path(text)?);
+            if !root.is_absolute() {
+                bail!("allowed root '{}' is not an absolute path", text);
+            }
+            Ok(root)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse("allowed-roots = [\"/srv/data\", \"~/work\"]\n\n[modes]\n\"**/bin/*\" = \"755\"\n").unwrap();
+        assert_eq!(config.modes, [("**/bin/*".to_string(), 0o755)]);
+        assert_eq!(config.allowed_roots[0], PathBuf::from("/srv/data"));
+        assert!(config.allowed_roots[1].is_absolute() && config.allowed_roots[1].ends_with("work"));
+        assert!(!config.history);
+        assert!(Config::parse("history = true\n").unwrap().history);
+        assert!(Config::parse("history = \"yes\"\n").is_err());
+        assert!(Config::parse("allowed-roots = [\"work\"]\n").is_err());
+        assert!(Config::parse("colour = \"red\"\n").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! `--root` confinement: keep every target strictly inside a directory
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Resolve `path_str` inside `root`, rejecting lexical `..` escapes and absolute
+/// paths outside of it. Returns the target as a path under `root`, keeping any
+/// trailing separator.
+pub fn confine_lexically(root: &Path, path_str: &str) -> Result<String> {
+    let path = Path::new(path_str);
+    let relative = if path.is_absolute() {
+        path.strip_prefix(root)
+            .map_err(|_| anyhow::anyhow!("Path is outside the --root directory: {}", path_str))?
+    } else {
+        path
+    };
+
+    let mut parts: Vec<&std::ffi::OsStr> = Vec::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if parts.pop().is_none() {
+                    anyhow::bail!("Path escapes the --root directory: {}", path_str);
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                anyhow::bail!("Path is outside the --root directory: {}", path_str)
+            }
+        }
+    }
+
+    let mut confined = root.to_path_buf();
+    confined.extend(parts);
+    let mut confined = confined.to_string_lossy().into_owned();
+    if path_str.ends_with('/') && !confined.ends_with('/') {
+        confined.push('/');
+    }
+    Ok(confined)
+}
+
+/// Verify that the existing part of `target` (already under `root`) does not
+/// leave `root` through symlinks.
+///
+/// Uses `openat2(RESOLVE_BENEATH)` on Linux and falls back to comparing
+/// canonicalized paths where it is unavailable. Components that don't exist yet
+/// are plain names after lexical confinement, so checking the longest existing
+/// prefix is enough.
+pub fn verify_beneath(root: &Path, target: &str) -> Result<()> {
+    let relative = Path::new(target)
+        .strip_prefix(root)
+        .with_context(|| format!("Path is outside the --root directory: {}", target))?
+        .to_path_buf();
+    let root_dir = File::open(root).with_context(|| format!("Failed to open --root directory {}", root.display()))?;
+
+    let mut candidate = relative;
+    loop {
+        match resolve_beneath(&root_dir, &candidate) {
+            Some(Ok(())) => return Ok(()),
+            Some(Err(err)) if err.raw_os_error() == Some(libc::EXDEV) || err.raw_os_error() == Some(libc::ELOOP) => {
+                anyhow::bail!("Path escapes the --root directory through a symlink: {}", target)
+            }
+            // Missing: the created part will be a plain name under the parent
+            Some(Err(err)) if err.kind() == io::ErrorKind::NotFound => {}
+            // Anything else leaves the path unverified, so refuse it
+            Some(Err(err)) => return Err(err).with_context(|| format!("Failed to verify {} is beneath the --root directory", target)),
+            None => return verify_by_canonicalizing(root, &candidate, target),
+        }
+        if !candidate.pop() {
+            return Ok(());
+        }
+    }
+}
+
+/// openat2 with RESOLVE_BENEATH; `None` when the kernel doesn't support it
+#[cfg(target_os = "linux")]
+fn resolve_beneath(root_dir: &File, relative: &Path) -> Option<io::Result<()>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+
+    let relative = if relative.as_os_str().is_empty() { Path::new(".") } else { relative };
+    let c_path = CString::new(relative.as_os_str().as_bytes()).ok()?;
+    // SAFETY: open_how is a plain C struct for which all-zero is valid
+    let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+    how.flags = (libc::O_PATH | libc::O_CLOEXEC) as u64;
+    how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_MAGICLINKS;
+    // SAFETY: all pointers are valid for the duration of the call and the size matches `how`
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            root_dir.as_raw_fd(),
+            c_path.as_ptr(),
+            &how as *const libc::open_how,
+            std::mem::size_of::<libc::open_how>(),
+        )
+    };
+    if fd >= 0 {
+        // SAFETY: fd was just returned by openat2 and is owned here
+        unsafe { libc::close(fd as libc::c_int) };
+        return Some(Ok(()));
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::ENOSYS) | Some(libc::EPERM) => None,
+        _ => Some(Err(err)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resolve_beneath(_root_dir: &File, _relative: &Path) -> Option<io::Result<()>> {
+    None
+}
+
+fn verify_by_canonicalizing(root: &Path, relative: &Path, target: &str) -> Result<()> {
+    let canonical_root = std::fs::canonicalize(root)
+        .with_context(|| format!("Failed to resolve --root directory {}", root.display()))?;
+
+    let mut candidate: PathBuf = relative.to_path_buf();
+    loop {
+        let full = root.join(&candidate);
+        match std::fs::symlink_metadata(&full) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err).with_context(|| format!("Failed to verify {} is beneath the --root directory", target)),
+            Ok(_) => {
+                let resolved = std::fs::canonicalize(&full)
+                    .with_context(|| format!("Failed to resolve {} (dangling symlink?)", full.display()))?;
+                if !resolved.starts_with(&canonical_root) {
+                    anyhow::bail!("Path escapes the --root directory through a symlink: {}", target);
+                }
+                return Ok(());
+            }
+        }
+        if !candidate.pop() {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lexical_confinement() {
+        let root = Path::new("/srv/build");
+        assert_eq!(confine_lexically(root, "out/a.txt").unwrap(), "/srv/build/out/a.txt");
+        assert_eq!(confine_lexically(root, "out/../logs/").unwrap(), "/srv/build/logs/");
+        assert_eq!(confine_lexically(root, "/srv/build/x").unwrap(), "/srv/build/x");
+        assert!(confine_lexically(root, "../etc/passwd").is_err());
+        assert!(confine_lexically(root, "a/../../b").is_err());
+        assert!(confine_lexically(root, "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_symlink_escapes_are_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("root");
+        let outside = temp_dir.path().join("outside");
+        std::fs::create_dir_all(root.join("inner")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("escape")).unwrap();
+        std::os::unix::fs::symlink("inner", root.join("alias")).unwrap();
+
+        let target = confine_lexically(&root, "escape/file.txt").unwrap();
+        assert!(verify_beneath(&root, &target).is_err());
+
+        let target = confine_lexically(&root, "alias/new/file.txt").unwrap();
+        assert!(verify_beneath(&root, &target).is_ok());
+
+        // A resolution error other than a missing name fails closed
+        std::fs::write(root.join("file.txt"), "").unwrap();
+        let target = confine_lexically(&root, "file.txt/new").unwrap();
+        assert!(verify_beneath(&root, &target).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! `--on-conflict`: one request per target, whatever the input repeated
+//!
+//! Paths are compared lexically (`a/./b/` is `a/b`). A target requested more
+//! than once is processed once, where it first appeared; a batch row or spec
+//! takes precedence over a plain path, so its attributes are not lost. The
+//! requests for one target conflict when they ask for a file and a directory,
+//! or set different modes, owners or timestamps. With `merge` they are
+//! combined, later values winning as they would if each was applied in turn;
+//! `warn` does the same and reports each conflict; `error` reports them all
+//! and creates nothing. A file and a directory cannot be merged.
+//!
+//! `--stream` creates each request as it is read, so a repeat is compared
+//! with what came before instead: it is skipped unless it asks for something
+//! new, which is then applied on top, and `error` stops the run at the first
+//! conflict, after the earlier requests were created.
+
+use crate::batch::{BatchRow, RowKind};
+use crate::normalize;
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    Merge,
+    Warn,
+    Error,
+}
+
+/// The paths with the batch rows that follow `batch_start`, as main keeps them
+pub struct Requests<'a> {
+    pub paths: &'a mut Vec<String>,
+    pub rows: &'a mut Vec<BatchRow>,
+    pub batch_start: &'a mut usize,
+}
+
+impl Requests<'_> {
+    fn row(&self, index: usize) -> Option<&BatchRow> {
+        index.checked_sub(*self.batch_start).and_then(|row| self.rows.get(row))
+    }
+}
+
+/// What a request asks for: the kind from a trailing `/` or a row's type
+fn kind(path_str: &str, row: Option<&BatchRow>) -> Option<RowKind> {
+    row.and_then(|row| row.kind).or_else(|| path_str.ends_with('/').then_some(RowKind::Directory))
+}
+
+fn target(path_str: &str) -> PathBuf {
+    normalize::lexical(Path::new(path_str))
+}
+
+/// Drop repeated targets and combine what their requests asked for; the
+/// conflicts found, to print as warnings (empty unless `action` is `Warn`)
+pub fn resolve(requests: Requests, action: OnConflict) -> Result<Vec<String>> {
+    // Every occurrence of each target, in input order
+    let mut occurrences: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    let mut targets = Vec::new();
+    for (index, path_str) in requests.paths.iter().enumerate() {
+        let key = target(path_str);
+        let seen = occurrences.entry(key.clone()).or_default();
+        if seen.is_empty() {
+            targets.push(key);
+        }
+        seen.push(index);
+    }
+    if targets.len() == requests.paths.len() {
+        return Ok(Vec::new());
+    }
+
+    let mut conflicts = Vec::new();
+    let mut kept: HashMap<usize, BatchRow> = HashMap::new();
+    for key in &targets {
+        let indices = &occurrences[key];
+        if indices.len() == 1 {
+            continue;
+        }
+        let shown = requests.paths[indices[0]].clone();
+        let keep = indices.iter().copied().find(|&index| requests.row(index).is_some()).unwrap_or(indices[0]);
+        let mut merged = BatchRow { path: requests.paths[keep].clone(), ..BatchRow::default() };
+        for &index in indices {
+            let path_str = &requests.paths[index];
+            let row = requests.row(index);
+            if let Some(wanted) = kind(path_str, row) {
+                match merged.kind {
+                    Some(previous) if previous != wanted => {
+                        bail!("{} is requested as both a file and a directory", shown);
+                    }
+                    _ => merged.kind = Some(wanted),
+                }
+            }
+            let Some(row) = row else { continue };
+            for (name, value, merged_value) in [
+                ("modes", &row.mode, &mut merged.mode),
+                ("owners", &row.owner, &mut merged.owner),
+                ("timestamps", &row.timestamp, &mut merged.timestamp),
+            ] {
+                let Some(value) = value else { continue };
+                if let Some(previous) = merged_value.as_ref().filter(|previous| !same(name, previous, value)) {
+                    conflicts.push(format!("{} is requested with different {}: {} and {}", shown, name, previous, value));
+                }
+                *merged_value = Some(value.clone());
+            }
+        }
+        kept.insert(keep, merged);
+    }
+    if action == OnConflict::Error && !conflicts.is_empty() {
+        bail!("{} conflicting request(s) found:\n  {}", conflicts.len(), conflicts.join("\n  "));
+    }
+
+    // Rebuild the paths and rows with each target once
+    let first: HashMap<&PathBuf, usize> = targets.iter().map(|key| (key, kept_index(&occurrences[key], &kept))).collect();
+    let batch_end = *requests.batch_start + requests.rows.len();
+    let (mut paths, mut rows, mut batch_start) = (Vec::new(), Vec::new(), 0);
+    for (index, path_str) in requests.paths.iter().enumerate() {
+        if first[&target(path_str)] != index {
+            continue;
+        }
+        if index < *requests.batch_start {
+            batch_start += 1;
+        }
+        let merged = kept.remove(&index);
+        match (index >= *requests.batch_start && index < batch_end, merged) {
+            (true, Some(row)) => rows.push(row),
+            (true, None) => rows.push(requests.rows[index - *requests.batch_start].clone()),
+            // A plain path stands for a directory with its trailing slash
+            (false, Some(row)) if row.kind == Some(RowKind::Directory) && !path_str.ends_with('/') => {
+                paths.push(format!("{}/", path_str));
+                continue;
+            }
+            (false, _) => {}
+        }
+        paths.push(path_str.clone());
+    }
+    *requests.paths = paths;
+    *requests.rows = rows;
+    *requests.batch_start = batch_start;
+    Ok(if action == OnConflict::Warn { conflicts } else { Vec::new() })
+}
+
+/// What each target of a stream was requested with so far
+#[derive(Default)]
+pub struct Streamed {
+    seen: HashMap<PathBuf, BatchRow>,
+}
+
+impl Streamed {
+    /// Whether a request read from a stream should be processed, and the
+    /// conflicts with earlier requests for its target, to print as warnings
+    /// (empty unless `action` is `Warn`)
+    pub fn admit(&mut self, path_str: &str, row: Option<&BatchRow>, action: OnConflict) -> Result<(bool, Vec<String>)> {
+        let wanted = kind(path_str, row);
+        let Some(merged) = self.seen.get_mut(&target(path_str)) else {
+            let first = BatchRow { path: String::new(), kind: wanted, ..row.cloned().unwrap_or_default() };
+            self.seen.insert(target(path_str), first);
+            return Ok((true, Vec::new()));
+        };
+        if let Some(wanted) = wanted {
+            match merged.kind {
+                Some(previous) if previous != wanted => bail!("{} is requested as both a file and a directory", path_str),
+                _ => merged.kind = Some(wanted),
+            }
+        }
+        let Some(row) = row else { return Ok((false, Vec::new())) };
+        let mut conflicts = Vec::new();
+        let mut changes = false;
+        for (name, value, merged_value) in [
+            ("modes", &row.mode, &mut merged.mode),
+            ("owners", &row.owner, &mut merged.owner),
+            ("timestamps", &row.timestamp, &mut merged.timestamp),
+        ] {
+            let Some(value) = value else { continue };
+            match merged_value.as_ref() {
+                Some(previous) if same(name, previous, value) => continue,
+                Some(previous) => conflicts.push(format!("{} is requested with different {}: {} and {}", path_str, name, previous, value)),
+                None => {}
+            }
+            *merged_value = Some(value.clone());
+            changes = true;
+        }
+        if action == OnConflict::Error && !conflicts.is_empty() {
+            bail!("{} conflicting request(s) found:\n  {}", conflicts.len(), conflicts.join("\n  "));
+        }
+        Ok((changes, if action == OnConflict::Warn { conflicts } else { Vec::new() }))
+    }
+}
+
+/// Where a repeated target is kept: its first row, or else its first path
+fn kept_index(indices: &[usize], kept: &HashMap<usize, BatchRow>) -> usize {
+    indices.iter().copied().find(|index| kept.contains_key(index)).unwrap_or(indices[0])
+}
+
+/// Whether two values of an attribute ask for the same thing; modes are
+/// compared as numbers when both are octal
+fn same(name: &str, a: &str, b: &str) -> bool {
+    if name == "modes" {
+        if let (Ok(a), Ok(b)) = (u32::from_str_radix(a, 8), u32::from_str_radix(b, 8)) {
+            return a == b;
+        }
+    }
+    a == b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(path: &str, kind: Option<RowKind>, mode: Option<&str>) -> BatchRow {
+        BatchRow { path: path.to_string(), kind, mode: mode.map(str::to_string), ..BatchRow::default() }
+    }
+
+    #[test]
+    fn test_resolve_drops_repeated_targets() {
+        let mut paths: Vec<String> = ["a/", "b.txt", "./a", "b.txt", "c/", "c"].iter().map(|p| p.to_string()).collect();
+        let mut rows = vec![row("c/", None, Some("750")), row("c", None, Some("0750"))];
+        let mut batch_start = 4;
+        let warnings = resolve(Requests { paths: &mut paths, rows: &mut rows, batch_start: &mut batch_start }, OnConflict::Warn).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(paths, ["a/", "b.txt", "c/"]);
+        assert_eq!(batch_start, 2);
+        assert_eq!(rows, [row("c/", Some(RowKind::Directory), Some("0750"))]);
+    }
+
+    #[test]
+    fn test_resolve_merges_a_plain_path_into_its_row() {
+        let mut paths: Vec<String> = ["srv/", "srv"].iter().map(|p| p.to_string()).collect();
+        let mut rows = vec![row("srv", None, Some("700"))];
+        let mut batch_start = 1;
+        resolve(Requests { paths: &mut paths, rows: &mut rows, batch_start: &mut batch_start }, OnConflict::Merge).unwrap();
+        assert_eq!(paths, ["srv"]);
+        assert_eq!(batch_start, 0);
+        assert_eq!(rows, [row("srv", Some(RowKind::Directory), Some("700"))]);
+    }
+
+    #[test]
+    fn test_resolve_reports_conflicts() {
+        let requests = || (vec!["x".to_string(), "x".to_string()], vec![row("x", None, Some("600")), row("x", None, Some("644"))]);
+
+        let (mut paths, mut rows) = requests();
+        let warnings = resolve(Requests { paths: &mut paths, rows: &mut rows, batch_start: &mut 0 }, OnConflict::Warn).unwrap();
+        assert_eq!(warnings, ["x is requested with different modes: 600 and 644"]);
+        assert_eq!(rows, [row("x", None, Some("644"))]);
+
+        let (mut paths, mut rows) = requests();
+        let err = resolve(Requests { paths: &mut paths, rows: &mut rows, batch_start: &mut 0 }, OnConflict::Error).unwrap_err();
+        assert!(err.to_string().starts_with("1 conflicting request(s) found"));
+
+        let mut paths = vec!["y/".to_string(), "y".to_string()];
+        let mut rows = vec![row("y", Some(RowKind::File), None)];
+        let err = resolve(Requests { paths: &mut paths, rows: &mut rows, batch_start: &mut 1 }, OnConflict::Merge).unwrap_err();
+        assert_eq!(err.to_string(), "y/ is requested as both a file and a directory");
+    }
+
+    #[test]
+    fn test_streamed_repeats() {
+        let mut streamed = Streamed::default();
+        assert_eq!(streamed.admit("a/", None, OnConflict::Warn).unwrap(), (true, Vec::new()));
+        assert_eq!(streamed.admit("./a", None, OnConflict::Warn).unwrap(), (false, Vec::new()));
+        assert_eq!(streamed.admit("a", Some(&row("a", None, Some("750"))), OnConflict::Warn).unwrap(), (true, Vec::new()));
+        assert_eq!(streamed.admit("a", Some(&row("a", None, Some("0750"))), OnConflict::Warn).unwrap(), (false, Vec::new()));
+        assert_eq!(
+            streamed.admit("a", Some(&row("a", None, Some("700"))), OnConflict::Warn).unwrap(),
+            (true, vec!["a is requested with different modes: 750 and 700".to_string()])
+        );
+        assert!(streamed.admit("a", Some(&row("a", Some(RowKind::File), None)), OnConflict::Merge).is_err());
+        assert!(streamed.admit("a/", Some(&row("a/", None, Some("755"))), OnConflict::Error).is_err());
+    }
+}
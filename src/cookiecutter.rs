@@ -0,0 +1,230 @@
+//! Cookiecutter templates as scaffolds for `bank new`
+//!
+//! A cookiecutter template has a `cookiecutter.json` of variables and a single
+//! directory named like `{{cookiecutter.project_slug}}` holding the project.
+//! bank renders that directory's contents into DEST, with the variables known
+//! by their cookiecutter names (`{{ cookiecutter.author }}`):
+//!
+//! - a string is asked for with itself as the default, a list is a choice
+//!   whose first item is the default, and booleans and numbers are defaults
+//!   as Jinja would print them;
+//! - `__prompts__` gives the question to ask for a variable (plain strings);
+//! - other names starting with `_` are not asked for, but string values are
+//!   still usable in templates, and `_copy_without_render` lists the globs
+//!   of files copied as they are.
+//!
+//! Only the Jinja that bank's templates share is understood: `{{ name }}`
+//! and the tags bank knows. A default using filters or method calls (such as
+//! `{{ cookiecutter.name.lower() }}`) is not evaluated, so the variable is
+//! asked for without one; hooks are not run.
+
+use crate::json::{self, Value};
+use crate::scaffold::{Layout, Variable};
+use crate::template::Vars;
+use anyhow::{bail, Context, Result};
+use colored::*;
+use regex::Regex;
+use std::path::Path;
+
+/// The file that makes a directory a cookiecutter template
+pub const CONFIG_FILE: &str = "cookiecutter.json";
+
+/// The prefix variables have in cookiecutter templates
+pub const NAMESPACE: &str = "cookiecutter.";
+
+pub fn is_template(dir: &Path) -> bool {
+    dir.join(CONFIG_FILE).is_file()
+}
+
+/// The project directory and variables of the template in `dir`
+pub fn layout(dir: &Path) -> Result<Layout> {
+    let config = dir.join(CONFIG_FILE);
+    let text = std::fs::read_to_string(&config).with_context(|| format!("Failed to read {}", config.display()))?;
+    let (declarations, constants, verbatim) = parse_config(&text).with_context(|| format!("Invalid {}", config.display()))?;
+
+    let mut projects = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry.with_context(|| format!("Failed to read {}", dir.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.contains("{{") && entry.path().is_dir() {
+            projects.push(name);
+        }
+    }
+    let project = match projects.as_slice() {
+        [project] => project.clone(),
+        [] => bail!("{} has no project directory (one named like {{{{cookiecutter.project_slug}}}})", dir.display()),
+        _ => bail!("{} has more than one project directory: {}", dir.display(), projects.join(", ")),
+    };
+    if dir.join("hooks").is_dir() {
+        println!("{} {}: cookiecutter hooks are not run", "Warning:".yellow(), dir.join("hooks").display());
+    }
+    // Cookiecutter's globs include the project directory; bank's are inside it
+    let verbatim = verbatim
+        .into_iter()
+        .map(|pattern| pattern.strip_prefix(&format!("{}/", project)).map(str::to_string).unwrap_or(pattern))
+        .collect();
+    Ok(Layout {
+        root: dir.join(project),
+        declarations,
+        origin: config,
+        namespace: NAMESPACE,
+        constants,
+        verbatim,
+        ignore: Vec::new(),
+        suffix: "",
+    })
+}
+
+fn parse_config(text: &str) -> Result<(Vec<Variable>, Vars, Vec<String>)> {
+    let Value::Object(members) = json::parse(text)? else {
+        bail!("expected an object of variables");
+    };
+    let prompts = match members.iter().find(|(name, _)| name == "__prompts__") {
+        Some((_, Value::Object(prompts))) => prompts.clone(),
+        _ => Vec::new(),
+    };
+
+    let mut declarations = Vec::new();
+    let mut constants = Vars::new();
+    let mut verbatim = Vec::new();
+    for (name, value) in &members {
+        if name == "_copy_without_render" {
+            let Value::Array(patterns) = value else {
+                bail!("'_copy_without_render' must be an array, found {}", value.type_name());
+            };
+            for pattern in patterns {
+                verbatim.push(pattern.as_str().context("'_copy_without_render' must list strings")?.to_string());
+            }
+            continue;
+        }
+        if name.starts_with('_') {
+            if let Value::String(text) = value {
+                constants.insert(format!("{}{}", NAMESPACE, name), text.clone());
+            }
+            continue;
+        }
+        let question = prompts.iter().find(|(key, _)| key == name).and_then(|(_, prompt)| prompt.as_str());
+        let (default, choices) = match value {
+            Value::String(text) => (Some(text.clone()), None),
+            Value::Bool(flag) => (Some(if *flag { "True" } else { "False" }.to_string()), None),
+            Value::Number(number) => (Some(number.to_string()), None),
+            Value::Array(items) => {
+                let choices = items
+                    .iter()
+                    .map(|item| item.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>()
+                    .with_context(|| format!("'{}': only choices of strings are supported", name))?;
+                (choices.first().cloned(), Some(choices))
+            }
+            // Dictionary variables have nothing to ask for
+            Value::Object(_) | Value::Null => continue,
+        };
+        let prompt = match &choices {
+            Some(choices) => format!("{} ({})", question.unwrap_or(name), choices.join(", ")),
+            None => question.unwrap_or(name).to_string(),
+        };
+        let pattern = match &choices {
+            Some(choices) => {
+                let alternatives: Vec<String> = choices.iter().map(|choice| regex::escape(choice)).collect();
+                Some(Regex::new(&format!("^(?:{})$", alternatives.join("|")))?)
+            }
+            None => None,
+        };
+        declarations.push(Variable {
+            name: format!("{}{}", NAMESPACE, name),
+            prompt: Some(prompt),
+            default: default.filter(|default| is_plain(default)),
+            pattern,
+        });
+    }
+    Ok((declarations, constants, verbatim))
+}
+
+/// Whether every `{{ }}` in `text` names a variable, so bank can render it
+fn is_plain(text: &str) -> bool {
+    if text.contains("{%") {
+        return false;
+    }
+    text.split("{{").skip(1).all(|rest| {
+        rest.split_once("}}").is_some_and(|(body, _)| {
+            let body = body.trim();
+            !body.is_empty() && body.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scaffold::{render_tree, resolve_vars};
+    use crate::template::Loader;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cookiecutter_template() {
+        let temp_dir = TempDir::new().unwrap();
+        let template = temp_dir.path();
+        std::fs::write(
+            template.join(CONFIG_FILE),
+            r#"{
+                "project_name": "Demo",
+                "project_slug": "{{ cookiecutter.project_name.lower() }}",
+                "author": "{{ cookiecutter.project_name }} team",
+                "license": ["MIT", "BSD-3-Clause"],
+                "use_ci": true,
+                "__prompts__": {"author": "Who maintains it?"},
+                "_version": "2",
+                "_copy_without_render": ["{{cookiecutter.project_slug}}/*.html"]
+            }"#,
+        )
+        .unwrap();
+        let project = template.join("{{cookiecutter.project_slug}}");
+        std::fs::create_dir_all(project.join("{{ cookiecutter.project_slug }}")).unwrap();
+        std::fs::write(project.join("README.md"), "# {{ cookiecutter.project_name }} by {{ cookiecutter.author }} (v{{ cookiecutter._version }})\n").unwrap();
+        std::fs::write(project.join("index.html"), "{{ not rendered }}").unwrap();
+
+        let layout = layout(template).unwrap();
+        assert_eq!(layout.root, project);
+        assert_eq!(layout.verbatim, ["*.html"]);
+        let names: Vec<&str> = layout.declarations.iter().map(|variable| variable.name.as_str()).collect();
+        assert_eq!(names, ["cookiecutter.project_name", "cookiecutter.project_slug", "cookiecutter.author", "cookiecutter.license", "cookiecutter.use_ci"]);
+        assert_eq!(layout.declarations[1].default, None);
+        assert_eq!(layout.declarations[2].prompt.as_deref(), Some("Who maintains it?"));
+        assert_eq!(layout.declarations[3].prompt.as_deref(), Some("license (MIT, BSD-3-Clause)"));
+
+        let loader = Loader::new(Vec::new());
+        let given = [("cookiecutter.project_slug".to_string(), "demo".to_string())];
+        let vars = resolve_vars(&layout.declarations, &given, &loader, &layout.origin, layout.constants.clone(), false).unwrap();
+        assert_eq!(vars["cookiecutter.author"], "Demo team");
+        assert_eq!(vars["cookiecutter.license"], "MIT");
+        assert_eq!(vars["cookiecutter.use_ci"], "True");
+        let bad = [given[0].clone(), ("cookiecutter.license".to_string(), "GPL".to_string())];
+        assert!(resolve_vars(&layout.declarations, &bad, &loader, &layout.origin, Vars::new(), false).is_err());
+
+        let items = render_tree(&layout, &loader, &vars).unwrap();
+        let paths: Vec<&Path> = items.iter().map(|item| item.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("README.md"), Path::new("index.html"), Path::new("demo")]);
+        assert_eq!(items[0].contents.as_deref(), Some(&b"# Demo by Demo team (v2)\n"[..]));
+        assert_eq!(items[1].contents.as_deref(), Some(&b"{{ not rendered }}"[..]));
+    }
+
+    #[test]
+    fn test_rendered_names_stay_inside_destination() {
+        let temp_dir = TempDir::new().unwrap();
+        let template = temp_dir.path();
+        std::fs::write(template.join(CONFIG_FILE), r#"{"name": "demo", "_escape": "../../pwned"}"#).unwrap();
+        let project = template.join("{{cookiecutter.name}}");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("{{cookiecutter._escape}}"), "").unwrap();
+
+        let layout = layout(template).unwrap();
+        let loader = Loader::new(Vec::new());
+        let vars = resolve_vars(&layout.declarations, &[], &loader, &layout.origin, layout.constants.clone(), false).unwrap();
+        assert!(render_tree(&layout, &loader, &vars).is_err());
+        let nested = [("cookiecutter.name".to_string(), "a/b".to_string())];
+        std::fs::remove_file(project.join("{{cookiecutter._escape}}")).unwrap();
+        std::fs::write(project.join("{{cookiecutter.name}}.txt"), "").unwrap();
+        let vars = resolve_vars(&layout.declarations, &nested, &loader, &layout.origin, layout.constants.clone(), false).unwrap();
+        assert!(render_tree(&layout, &loader, &vars).is_err());
+    }
+}
@@ -0,0 +1,403 @@
+//! EditorConfig support for files bank writes contents into, so generated
+//! files already follow the repository's `charset`, `end_of_line`,
+//! `insert_final_newline` and indentation settings
+//!
+//! `.editorconfig` files are read from the file's directory upwards until one
+//! declares `root = true`; closer files and later sections win. Options
+//! given on the command line (`--encoding`, `--eol`) win over both, and
+//! `$BANK_EOL` supplies the line ending when no `.editorconfig` sets one.
+
+use crate::glob::glob_match;
+use anyhow::{bail, Context, Result};
+use clap::{Args as ClapArgs, ValueEnum};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const FILE_NAME: &str = ".editorconfig";
+
+/// Environment variable with the default `--eol`
+const EOL_ENV: &str = "BANK_EOL";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    /// UTF-8 with a byte order mark, for tools on Windows that expect one
+    Utf8Bom,
+    Utf16le,
+    Latin1,
+}
+
+impl Encoding {
+    /// The EditorConfig `charset` value for this encoding
+    fn charset(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16le => "utf-16le",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eol {
+    Lf,
+    Crlf,
+    /// CRLF on Windows, LF everywhere else
+    Native,
+}
+
+impl Eol {
+    /// The EditorConfig `end_of_line` value for this line ending
+    fn end_of_line(self) -> &'static str {
+        match self {
+            Eol::Lf => "lf",
+            Eol::Crlf => "crlf",
+            Eol::Native if cfg!(windows) => "crlf",
+            Eol::Native => "lf",
+        }
+    }
+}
+
+/// Formatting options for commands that write file contents
+#[derive(ClapArgs, Debug, Clone, Default)]
+pub struct FormatArgs {
+    /// Encoding of written files, instead of the .editorconfig charset
+    #[arg(long = "encoding", value_name = "ENCODING")]
+    pub encoding: Option<Encoding>,
+
+    /// Line endings of written files, instead of the .editorconfig end_of_line
+    /// (default: $BANK_EOL)
+    #[arg(long = "eol", value_name = "EOL")]
+    pub eol: Option<Eol>,
+}
+
+impl FormatArgs {
+    /// Apply the options over `properties`; `default_eol` is only consulted
+    /// when neither `--eol` nor the properties set the line ending
+    fn override_properties(&self, properties: &mut Properties, default_eol: impl FnOnce() -> Result<Option<Eol>>) -> Result<()> {
+        if let Some(encoding) = self.encoding {
+            properties.0.insert("charset".to_string(), encoding.charset().to_string());
+        }
+        let eol = match self.eol {
+            Some(eol) => Some(eol),
+            None if properties.get("end_of_line").is_some() => None,
+            None => default_eol()?,
+        };
+        if let Some(eol) = eol {
+            properties.0.insert("end_of_line".to_string(), eol.end_of_line().to_string());
+        }
+        Ok(())
+    }
+}
+
+/// `$BANK_EOL`, if set
+fn default_eol() -> Result<Option<Eol>> {
+    match std::env::var(EOL_ENV) {
+        Ok(value) if !value.trim().is_empty() => Eol::from_str(value.trim(), true)
+            .map(Some)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Invalid {}: {}", EOL_ENV, value)),
+        _ => Ok(None),
+    }
+}
+
+/// The properties that apply to one file, lowercased
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Properties(HashMap<String, String>);
+
+impl Properties {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    fn indent_size(&self) -> Option<usize> {
+        match self.get("indent_size") {
+            Some("tab") => self.get("tab_width").and_then(|width| width.parse().ok()),
+            Some(size) => size.parse().ok(),
+            None => self.get("tab_width").and_then(|width| width.parse().ok()),
+        }
+    }
+}
+
+/// One parsed `.editorconfig`: whether it is the root, and its sections in order
+struct Config {
+    root: bool,
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+fn parse(text: &str) -> Config {
+    let mut config = Config { root: false, sections: Vec::new() };
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(glob) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            config.sections.push((glob.to_string(), Vec::new()));
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim().to_lowercase();
+        match config.sections.last_mut() {
+            Some((_, properties)) => properties.push((key, value)),
+            None if key == "root" => config.root = value == "true",
+            None => {}
+        }
+    }
+    config
+}
+
+/// Expand `{a,b}` alternatives and `{1..3}` ranges into plain globs
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+    let mut depth = 0;
+    let mut close = None;
+    for (index, c) in pattern[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(open + index);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let Some(close) = close else {
+        return vec![pattern.to_string()];
+    };
+    let (prefix, body, suffix) = (&pattern[..open], &pattern[open + 1..close], &pattern[close + 1..]);
+
+    let alternatives: Vec<String> = match body.split_once("..").and_then(|(low, high)| Some((low.parse::<i64>().ok()?, high.parse::<i64>().ok()?))) {
+        Some((low, high)) => (low.min(high)..=low.max(high)).map(|n| n.to_string()).collect(),
+        None => split_alternatives(body),
+    };
+    alternatives
+        .iter()
+        .flat_map(|alternative| expand_braces(&format!("{}{}{}", prefix, alternative, suffix)))
+        .collect()
+}
+
+/// Split at the commas that are not inside a nested `{...}`
+fn split_alternatives(body: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut depth = 0;
+    for c in body.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        parts.last_mut().expect("starts with one part").push(c);
+    }
+    parts
+}
+
+/// Whether a section glob from the `.editorconfig` in `base` covers `path`
+fn section_matches(glob: &str, base: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(base) else {
+        return false;
+    };
+    let relative = relative.to_string_lossy();
+    // A leading `/` anchors the glob to the directory of the .editorconfig
+    let glob = glob.strip_prefix('/').unwrap_or(glob);
+    expand_braces(glob).iter().any(|pattern| glob_match(pattern, &relative))
+}
+
+/// The properties that apply to `path`
+pub fn properties_for(path: &Path) -> Result<Properties> {
+    let path = std::path::absolute(path).with_context(|| format!("Failed to resolve {}", path.display()))?;
+    let mut configs: Vec<(PathBuf, Config)> = Vec::new();
+    for directory in path.ancestors().skip(1) {
+        let candidate = directory.join(FILE_NAME);
+        if !candidate.is_file() {
+            continue;
+        }
+        let text = std::fs::read_to_string(&candidate).with_context(|| format!("Failed to read {}", candidate.display()))?;
+        let config = parse(&text);
+        let root = config.root;
+        configs.push((directory.to_path_buf(), config));
+        if root {
+            break;
+        }
+    }
+
+    let mut properties = HashMap::new();
+    for (directory, config) in configs.iter().rev() {
+        for (glob, section) in &config.sections {
+            if section_matches(glob, directory, &path) {
+                for (key, value) in section {
+                    properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+    properties.retain(|_, value| value != "unset");
+    Ok(Properties(properties))
+}
+
+/// Rewrite `text` to follow `properties` and encode it in the configured charset
+pub fn format(text: &str, properties: &Properties) -> Result<Vec<u8>> {
+    let eol = match properties.get("end_of_line") {
+        Some("crlf") => Some("\r\n"),
+        Some("cr") => Some("\r"),
+        Some("lf") => Some("\n"),
+        _ => None,
+    };
+    let detected = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let newline = eol.unwrap_or(detected);
+
+    let mut lines: Vec<String> = text.replace("\r\n", "\n").split('\n').map(String::from).collect();
+    let had_final_newline = lines.last().is_some_and(String::is_empty);
+    if had_final_newline {
+        lines.pop();
+    }
+    if let Some(size) = properties.indent_size().filter(|size| *size > 0) {
+        match properties.get("indent_style") {
+            Some("space") => lines.iter_mut().for_each(|line| *line = tabs_to_spaces(line, size)),
+            Some("tab") => lines.iter_mut().for_each(|line| *line = spaces_to_tabs(line, size)),
+            _ => {}
+        }
+    }
+
+    let mut output = lines.join(newline);
+    let final_newline = match properties.get("insert_final_newline") {
+        Some("true") => !output.is_empty(),
+        Some("false") => false,
+        _ => had_final_newline,
+    };
+    if final_newline {
+        output.push_str(newline);
+    }
+    encode(&output, properties.get("charset"))
+}
+
+/// The leading whitespace of `line` as a column count, and the rest of the line
+fn indentation(line: &str, size: usize) -> (usize, &str) {
+    let rest = line.trim_start_matches(['\t', ' ']);
+    let width = line[..line.len() - rest.len()]
+        .chars()
+        .fold(0, |width, c| if c == '\t' { (width / size + 1) * size } else { width + 1 });
+    (width, rest)
+}
+
+fn tabs_to_spaces(line: &str, size: usize) -> String {
+    let (width, rest) = indentation(line, size);
+    format!("{}{}", " ".repeat(width), rest)
+}
+
+fn spaces_to_tabs(line: &str, size: usize) -> String {
+    let (width, rest) = indentation(line, size);
+    format!("{}{}{}", "\t".repeat(width / size), " ".repeat(width % size), rest)
+}
+
+fn encode(text: &str, charset: Option<&str>) -> Result<Vec<u8>> {
+    Ok(match charset {
+        Some("utf-8-bom") => [&[0xef, 0xbb, 0xbf][..], text.as_bytes()].concat(),
+        Some("utf-16le") => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        Some("utf-16be") => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        Some("latin1") => {
+            let mut bytes = Vec::with_capacity(text.len());
+            for c in text.chars() {
+                match u8::try_from(u32::from(c)) {
+                    Ok(byte) => bytes.push(byte),
+                    Err(_) => bail!("'{}' cannot be written with charset latin1", c),
+                }
+            }
+            bytes
+        }
+        _ => text.as_bytes().to_vec(),
+    })
+}
+
+/// Format the contents about to be written to `path`; binary contents are left alone
+pub fn apply(path: &Path, contents: &[u8], options: &FormatArgs) -> Result<Vec<u8>> {
+    let Ok(text) = std::str::from_utf8(contents) else {
+        return Ok(contents.to_vec());
+    };
+    let mut properties = properties_for(path)?;
+    options.override_properties(&mut properties, default_eol)?;
+    if properties.0.is_empty() {
+        return Ok(contents.to_vec());
+    }
+    format(text, &properties).with_context(|| format!("Failed to apply .editorconfig to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_properties_cascade() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("web/src")).unwrap();
+        std::fs::write(
+            root.join(".editorconfig"),
+            "root = true\n\n[*]\nend_of_line = lf\ninsert_final_newline = true\n\n[*.{js,ts}]\nindent_style = space\nindent_size = 4\n\n[Makefile]\nindent_style = tab\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("web/.editorconfig"), "[src/**.ts]\nindent_size = 2\nend_of_line = CRLF\n").unwrap();
+
+        let ts = properties_for(&root.join("web/src/app.ts")).unwrap();
+        assert_eq!(ts.get("indent_size"), Some("2"));
+        assert_eq!(ts.get("end_of_line"), Some("crlf"));
+        assert_eq!(ts.get("indent_style"), Some("space"));
+        let js = properties_for(&root.join("web/app.js")).unwrap();
+        assert_eq!(js.get("indent_size"), Some("4"));
+        assert_eq!(js.get("end_of_line"), Some("lf"));
+        assert_eq!(properties_for(&root.join("Makefile")).unwrap().get("indent_style"), Some("tab"));
+        assert_eq!(properties_for(&root.join("notes.txt")).unwrap().get("indent_style"), None);
+    }
+
+    #[test]
+    fn test_format() {
+        let properties = |pairs: &[(&str, &str)]| Properties(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect());
+
+        let spaces = properties(&[("indent_style", "space"), ("indent_size", "4"), ("end_of_line", "crlf"), ("insert_final_newline", "true")]);
+        assert_eq!(format("fn main() {\n\tlet x = 1;\n}", &spaces).unwrap(), b"fn main() {\r\n    let x = 1;\r\n}\r\n");
+
+        let tabs = properties(&[("indent_style", "tab"), ("indent_size", "2"), ("insert_final_newline", "false")]);
+        assert_eq!(format("a:\n     b\n", &tabs).unwrap(), b"a:\n\t\t b");
+
+        let mut bom = Properties::default();
+        FormatArgs { encoding: Some(Encoding::Utf8Bom), eol: None }.override_properties(&mut bom, || Ok(None)).unwrap();
+        assert_eq!(format("x\n", &bom).unwrap(), b"\xef\xbb\xbfx\n");
+        // --eol wins over the .editorconfig
+        let mut native = properties(&[("end_of_line", "crlf")]);
+        FormatArgs { encoding: None, eol: Some(Eol::Native) }.override_properties(&mut native, || Ok(Some(Eol::Crlf))).unwrap();
+        assert_eq!(format("a\r\nb\r\n", &native).unwrap(), if cfg!(windows) { &b"a\r\nb\r\n"[..] } else { &b"a\nb\n"[..] });
+        // $BANK_EOL only fills in for a missing end_of_line
+        let mut unset = Properties::default();
+        FormatArgs::default().override_properties(&mut unset, || Ok(Some(Eol::Crlf))).unwrap();
+        assert_eq!(unset.get("end_of_line"), Some("crlf"));
+        let mut set = properties(&[("end_of_line", "lf")]);
+        FormatArgs::default().override_properties(&mut set, || panic!("$BANK_EOL read")).unwrap();
+        assert_eq!(set.get("end_of_line"), Some("lf"));
+
+        let latin1 = properties(&[("charset", "latin1")]);
+        assert_eq!(format("café\n", &latin1).unwrap(), b"caf\xe9\n");
+        assert!(format("€\n", &latin1).is_err());
+    }
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand_braces("*.{js,ts}"), ["*.js", "*.ts"]);
+        assert_eq!(expand_braces("v{1..3}"), ["v1", "v2", "v3"]);
+        assert_eq!(expand_braces("{a,{b,c}}d"), ["ad", "bd", "cd"]);
+        assert_eq!(expand_braces("plain"), ["plain"]);
+    }
+}
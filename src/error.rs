@@ -0,0 +1,119 @@
+//! Typed errors for the core create/touch operations, so callers can branch
+//! on what went wrong instead of matching message text
+
+use crate::i18n::{self, tr};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BankError {
+    #[error("{}", tr("error-already-exists", &[("path", &path.display().to_string())]))]
+    AlreadyExists { path: PathBuf },
+
+    #[error("{}", tr("error-not-a-directory", &[("path", &path.display().to_string())]))]
+    NotADirectory { path: PathBuf },
+
+    #[error("{}", tr("error-parent-missing", &[("action", &i18n::action(action)), ("path", &path.display().to_string())]))]
+    ParentMissing {
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("{}", tr("error-permission-denied", &[("action", &i18n::action(action)), ("path", &path.display().to_string())]))]
+    PermissionDenied {
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("{}", tr("error-not-found", &[("path", &path.display().to_string())]))]
+    NotFound { path: PathBuf },
+
+    #[error("{}", tr("error-bad-mode", &[("mode", mode)]))]
+    BadMode { mode: String },
+
+    #[error("{message}")]
+    BadTimestamp { message: String },
+
+    #[error("{}", tr("error-prompt", &[]))]
+    Prompt(#[from] dialoguer::Error),
+
+    #[error("{}", tr("error-io", &[("action", &i18n::action(action)), ("path", &path.display().to_string())]))]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+// `code()` and `path()` are for embedders; the CLI only prints the message
+#[allow(dead_code)]
+impl BankError {
+    /// Classify an I/O failure while performing `action` (e.g. "create file") on `path`
+    pub fn io(action: &'static str, path: &Path, source: io::Error) -> Self {
+        let path = path.to_path_buf();
+        match source.kind() {
+            io::ErrorKind::AlreadyExists => BankError::AlreadyExists { path },
+            io::ErrorKind::PermissionDenied => BankError::PermissionDenied { action, path, source },
+            // Creating something under a missing directory
+            io::ErrorKind::NotFound if action.starts_with("create") => BankError::ParentMissing { action, path, source },
+            _ => BankError::Io { action, path, source },
+        }
+    }
+
+    pub fn bad_timestamp(message: impl Into<String>) -> Self {
+        BankError::BadTimestamp { message: message.into() }
+    }
+
+    /// Stable, machine-usable name of the failure
+    pub fn code(&self) -> &'static str {
+        match self {
+            BankError::AlreadyExists { .. } => "AlreadyExists",
+            BankError::NotADirectory { .. } => "NotADirectory",
+            BankError::ParentMissing { .. } => "ParentMissing",
+            BankError::PermissionDenied { .. } => "PermissionDenied",
+            BankError::NotFound { .. } => "NotFound",
+            BankError::BadMode { .. } => "BadMode",
+            BankError::BadTimestamp { .. } => "BadTimestamp",
+            BankError::Prompt(_) => "Prompt",
+            BankError::Io { .. } => "Io",
+        }
+    }
+
+    /// The path the failure is about, if any
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            BankError::AlreadyExists { path }
+            | BankError::NotADirectory { path }
+            | BankError::ParentMissing { path, .. }
+            | BankError::PermissionDenied { path, .. }
+            | BankError::NotFound { path }
+            | BankError::Io { path, .. } => Some(path),
+            BankError::BadMode { .. } | BankError::BadTimestamp { .. } | BankError::Prompt(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_errors_are_classified() {
+        let path = Path::new("out/a.txt");
+        let missing = BankError::io("create file", path, io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(missing.code(), "ParentMissing");
+        assert_eq!(missing.path(), Some(path));
+
+        let denied = BankError::io("set permissions for", path, io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(denied.code(), "PermissionDenied");
+
+        let read = BankError::io("read metadata for", path, io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(read.code(), "Io");
+        assert_eq!(read.to_string(), "Failed to read metadata for out/a.txt");
+    }
+}
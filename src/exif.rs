@@ -0,0 +1,188 @@
+//! When a photo was taken, from the EXIF `DateTimeOriginal` of JPEG, HEIC
+//! and RAW files (`--time-from-exif`, with the `exif` feature)
+//!
+//! EXIF is a TIFF structure: JPEG keeps it in an APP1 segment, TIFF-based
+//! RAW formats (CR2, NEF, ARW, DNG, ...) are TIFF files themselves, and HEIC
+//! and CR3 embed it in a box, found by its `Exif\0\0` marker or, for CR3,
+//! its `CMT2` box. Cameras write local time; it is read as such unless an
+//! `OffsetTimeOriginal` says which offset it was taken at.
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use std::time::SystemTime;
+
+/// How much of a file is read: the metadata sits in the first few kilobytes
+/// of every supported format, and a RAW file can be tens of megabytes
+pub const HEADER_LIMIT: usize = 256 * 1024;
+
+const EXIF_IFD: u16 = 0x8769;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+const OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+/// The TIFF type of NUL-terminated text
+const ASCII: u16 = 2;
+
+/// The `DateTimeOriginal` of the image in `data`, if it has one
+pub fn date_time_original(data: &[u8]) -> Option<SystemTime> {
+    let tiff = Tiff::find(data)?;
+    let ifd0 = tiff.u32(4)? as usize;
+    // CR3's CMT2 holds the EXIF directory itself; everywhere else IFD0 points to it
+    let (mut date, mut offset) = (tiff.ascii(ifd0, DATE_TIME_ORIGINAL), tiff.ascii(ifd0, OFFSET_TIME_ORIGINAL));
+    if date.is_none() {
+        let exif_ifd = tiff.value(ifd0, EXIF_IFD)? as usize;
+        date = tiff.ascii(exif_ifd, DATE_TIME_ORIGINAL);
+        offset = tiff.ascii(exif_ifd, OFFSET_TIME_ORIGINAL);
+    }
+    parse_time(date?, offset)
+}
+
+/// `2021:06:01 12:00:00`, at `+02:00` if known, else in local time
+fn parse_time(date: &str, offset: Option<&str>) -> Option<SystemTime> {
+    if let Some(offset) = offset.filter(|offset| !offset.trim().is_empty()) {
+        let time = DateTime::parse_from_str(&format!("{} {}", date, offset.trim()), "%Y:%m:%d %H:%M:%S %:z").ok()?;
+        return Some(time.into());
+    }
+    let naive = NaiveDateTime::parse_from_str(date, "%Y:%m:%d %H:%M:%S").ok()?;
+    Local.from_local_datetime(&naive).earliest().map(SystemTime::from)
+}
+
+/// A TIFF structure, with offsets relative to its header
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn find(data: &'a [u8]) -> Option<Self> {
+        if let Some(tiff) = Self::at(data, 0) {
+            return Some(tiff);
+        }
+        if data.starts_with(&[0xFF, 0xD8]) {
+            return Self::in_jpeg(data);
+        }
+        // HEIC and CR3: the structure sits in a box, after a marker
+        for (marker, skip) in [(&b"Exif\0\0"[..], 6), (&b"CMT2"[..], 4)] {
+            let mut from = 0;
+            while let Some(found) = data[from..].windows(marker.len()).position(|window| window == marker) {
+                let start = from + found + skip;
+                if let Some(tiff) = Self::at(data, start) {
+                    return Some(tiff);
+                }
+                from = start;
+            }
+        }
+        None
+    }
+
+    /// The APP1 segment that starts with `Exif\0\0`
+    fn in_jpeg(data: &'a [u8]) -> Option<Self> {
+        let mut position = 2;
+        while position + 4 <= data.len() && data[position] == 0xFF {
+            let marker = data[position + 1];
+            let length = u16::from_be_bytes([data[position + 2], data[position + 3]]) as usize;
+            let segment = data.get(position + 4..position + 2 + length)?;
+            if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+                return Self::at(data, position + 10);
+            }
+            // Start of scan: the image data follows, with no more metadata
+            if marker == 0xDA {
+                return None;
+            }
+            position += 2 + length;
+        }
+        None
+    }
+
+    fn at(data: &'a [u8], start: usize) -> Option<Self> {
+        let data = data.get(start..)?;
+        let little_endian = match data.get(..4)? {
+            [b'I', b'I', 42, 0] => true,
+            [b'M', b'M', 0, 42] => false,
+            _ => return None,
+        };
+        Some(Tiff { data, little_endian })
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    /// The position of the 12-byte entry for `tag` in the directory at `ifd`
+    fn entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16(ifd)? as usize;
+        (0..count).map(|index| ifd + 2 + index * 12).find(|&entry| self.u16(entry) == Some(tag))
+    }
+
+    /// The value of a LONG entry, like the offset of the EXIF directory
+    fn value(&self, ifd: usize, tag: u16) -> Option<u32> {
+        self.u32(self.entry(ifd, tag)? + 8)
+    }
+
+    /// The text of an ASCII entry, stored in the entry itself up to 4 bytes
+    fn ascii(&self, ifd: usize, tag: u16) -> Option<&'a str> {
+        let entry = self.entry(ifd, tag)?;
+        if self.u16(entry + 2)? != ASCII {
+            return None;
+        }
+        let count = self.u32(entry + 4)? as usize;
+        let start = if count <= 4 { entry + 8 } else { self.u32(entry + 8)? as usize };
+        let bytes = self.data.get(start..start.checked_add(count)?)?;
+        std::str::from_utf8(bytes).ok().map(|text| text.trim_end_matches('\0'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// IFD0 pointing to an EXIF directory with DateTimeOriginal and, optionally, its offset
+    fn tiff(little_endian: bool, offset: Option<&str>) -> Vec<u8> {
+        let u16 = |value: u16| if little_endian { value.to_le_bytes() } else { value.to_be_bytes() };
+        let u32 = |value: u32| if little_endian { value.to_le_bytes() } else { value.to_be_bytes() };
+        let mut data = if little_endian { b"II".to_vec() } else { b"MM".to_vec() };
+        data.extend(u16(42));
+        data.extend(u32(8));
+        // IFD0 at 8: one entry, ending at 26
+        data.extend(u16(1));
+        data.extend(u16(EXIF_IFD).into_iter().chain(u16(4)).chain(u32(1)).chain(u32(26)));
+        data.extend(u32(0));
+        // The EXIF directory at 26: two entries, ending at 56
+        let date = b"2021:06:01 12:00:00\0";
+        let offset = offset.map(|offset| format!("{}\0", offset)).unwrap_or_default();
+        data.extend(u16(2));
+        data.extend(u16(DATE_TIME_ORIGINAL).into_iter().chain(u16(ASCII)).chain(u32(date.len() as u32)).chain(u32(56)));
+        data.extend(u16(OFFSET_TIME_ORIGINAL).into_iter().chain(u16(ASCII)).chain(u32(offset.len() as u32)).chain(u32(76)));
+        data.extend(u32(0));
+        data.extend(date);
+        data.extend(offset.as_bytes());
+        data
+    }
+
+    #[test]
+    fn test_date_time_original() {
+        let taken = SystemTime::UNIX_EPOCH + Duration::from_secs(1_622_541_600);
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00];
+        let exif = [&b"Exif\0\0"[..], &tiff(true, Some("+02:00"))].concat();
+        jpeg.extend([0xFF, 0xE1]);
+        jpeg.extend(((exif.len() + 2) as u16).to_be_bytes());
+        jpeg.extend(exif);
+        jpeg.extend([0xFF, 0xD9]);
+        assert_eq!(date_time_original(&jpeg), Some(taken));
+
+        // A big-endian RAW file, and a HEIC-like box with its Exif marker
+        assert_eq!(date_time_original(&tiff(false, Some("+02:00"))), Some(taken));
+        let heic = [&b"\0\0\0\x18ftypheic...."[..], &b"Exif\0\0"[..], &tiff(false, Some("+02:00"))].concat();
+        assert_eq!(date_time_original(&heic), Some(taken));
+
+        let local = Local.with_ymd_and_hms(2021, 6, 1, 12, 0, 0).earliest().map(SystemTime::from);
+        assert_eq!(date_time_original(&tiff(true, None)), local);
+        assert_eq!(date_time_original(b"\xFF\xD8\xFF\xD9"), None);
+        assert_eq!(date_time_original(b"plain text"), None);
+    }
+}
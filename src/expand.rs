@@ -0,0 +1,209 @@
+//! Shell-style `{a,b}`, `~` and `$VAR` expansion for path arguments that never went through a shell
+
+use anyhow::Result;
+use std::ffi::{CStr, CString};
+
+/// Expand `~`, `~user`, `$VAR` and `${VAR}` using the process environment
+pub fn expand_path(input: &str) -> Result<String> {
+    expand_with(input, |name| std::env::var(name).ok())
+}
+
+/// Expand `input`, looking variables (and `HOME`) up through `env`
+pub fn expand_with(input: &str, env: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let rest = match input.strip_prefix('~') {
+        Some(after) => {
+            let end = after.find('/').unwrap_or(after.len());
+            let user = &after[..end];
+            let home = if user.is_empty() {
+                env("HOME").ok_or_else(|| anyhow::anyhow!("Cannot expand ~ in {}: HOME is not set", input))?
+            } else {
+                home_of(user).ok_or_else(|| anyhow::anyhow!("Cannot expand ~{} in {}: no such user", user, input))?
+            };
+            let mut expanded = home.trim_end_matches('/').to_string();
+            if expanded.is_empty() {
+                expanded.push('/');
+            }
+            return Ok(expanded + &expand_variables(&after[end..], input, &env)?);
+        }
+        None => input,
+    };
+
+    expand_variables(rest, input, &env)
+}
+
+fn expand_variables(text: &str, input: &str, env: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+
+        let (name, consumed) = if text[i + 1..].starts_with('{') {
+            match text[i + 2..].find('}') {
+                Some(close) => (&text[i + 2..i + 2 + close], close + 2),
+                None => anyhow::bail!("Unterminated ${{...}} in {}", input),
+            }
+        } else {
+            let len = text[i + 1..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(text.len() - i - 1);
+            (&text[i + 1..i + 1 + len], len)
+        };
+
+        // A lone `$` (e.g. `price$` or `$/`) stays literal
+        if name.is_empty() && consumed == 0 {
+            out.push('$');
+            continue;
+        }
+        if name.is_empty()
+            || name.starts_with(|c: char| c.is_ascii_digit())
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            anyhow::bail!("Invalid variable name in {}", input);
+        }
+
+        let value = env(name).ok_or_else(|| anyhow::anyhow!("Undefined variable ${} in {} (use --no-expand to keep it literally)", name, input))?;
+        out.push_str(&value);
+        for _ in 0..consumed {
+            chars.next();
+        }
+    }
+
+    Ok(out)
+}
+
+/// Most paths one argument may expand to, so a typo cannot fill a disk
+pub const MAX_BRACE_PATHS: usize = 100_000;
+
+/// Expand `{a,b}` groups like bash: every combination, in order, with the
+/// leftmost group varying slowest (`{dev,prod}/{logs,data}` gives dev/logs,
+/// dev/data, prod/logs, prod/data). Groups nest; braces without a comma
+/// (`{n}`), unbalanced ones and `${VAR}` stay as they are.
+pub fn expand_braces(input: &str) -> Result<Vec<String>> {
+    let mut out = Vec::new();
+    expand_braces_into(input, &mut out, input)?;
+    Ok(out)
+}
+
+fn expand_braces_into(text: &str, out: &mut Vec<String>, input: &str) -> Result<()> {
+    let Some((open, commas, close)) = first_group(text) else {
+        if out.len() >= MAX_BRACE_PATHS {
+            anyhow::bail!("{} expands to more than {} paths", input, MAX_BRACE_PATHS);
+        }
+        out.push(text.to_string());
+        return Ok(());
+    };
+    let (prefix, suffix) = (&text[..open], &text[close + 1..]);
+    let mut start = open + 1;
+    for end in commas.into_iter().chain([close]) {
+        expand_braces_into(&format!("{}{}{}", prefix, &text[start..end], suffix), out, input)?;
+        start = end + 1;
+    }
+    Ok(())
+}
+
+/// The first `{...}` with a comma at its own level: its braces and commas
+fn first_group(text: &str) -> Option<(usize, Vec<usize>, usize)> {
+    let bytes = text.as_bytes();
+    let mut search = 0;
+    while let Some(offset) = text[search..].find('{') {
+        let open = search + offset;
+        search = open + 1;
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut close = None;
+        for (i, &byte) in bytes.iter().enumerate().skip(open) {
+            match byte {
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(i);
+                        break;
+                    }
+                }
+                b',' if depth == 1 => commas.push(i),
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            continue;
+        };
+        if open > 0 && bytes[open - 1] == b'$' {
+            // `${VAR}` is left to variable expansion
+            search = close + 1;
+        } else if !commas.is_empty() {
+            return Some((open, commas, close));
+        }
+    }
+    None
+}
+
+/// Home directory of `user` from the password database
+fn home_of(user: &str) -> Option<String> {
+    let name = CString::new(user).ok()?;
+    let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+    // SAFETY: passwd is a plain C struct for which all-zero is valid
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+
+    // SAFETY: every pointer refers to live, correctly sized storage owned by this frame
+    let status = unsafe {
+        libc::getpwnam_r(name.as_ptr(), &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result)
+    };
+    if status != 0 || result.is_null() || entry.pw_dir.is_null() {
+        return None;
+    }
+    // SAFETY: pw_dir points into `buffer`, which is still alive
+    let home = unsafe { CStr::from_ptr(entry.pw_dir) };
+    Some(home.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/dev".to_string()),
+            "OUT" => Some("build/out".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand_variables_and_tilde() {
+        assert_eq!(expand_with("~/notes.txt", env).unwrap(), "/home/dev/notes.txt");
+        assert_eq!(expand_with("~", env).unwrap(), "/home/dev");
+        assert_eq!(expand_with("$OUT/a.log", env).unwrap(), "build/out/a.log");
+        assert_eq!(expand_with("${OUT}_old/", env).unwrap(), "build/out_old/");
+        assert_eq!(expand_with("~/$OUT", env).unwrap(), "/home/dev/build/out");
+        assert_eq!(expand_with("cost$/a~b", env).unwrap(), "cost$/a~b");
+        assert_eq!(expand_with("~root/x", env).unwrap(), format!("{}/x", home_of("root").unwrap().trim_end_matches('/')));
+    }
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(
+            expand_braces("{dev,prod}/{logs,data}/").unwrap(),
+            ["dev/logs/", "dev/data/", "prod/logs/", "prod/data/"]
+        );
+        assert_eq!(expand_braces("src/{a,b{1,2}}.rs").unwrap(), ["src/a.rs", "src/b1.rs", "src/b2.rs"]);
+        assert_eq!(expand_braces("x{,.bak}").unwrap(), ["x", "x.bak"]);
+        assert_eq!(expand_braces("part-{n}.csv").unwrap(), ["part-{n}.csv"]);
+        assert_eq!(expand_braces("${OUT}/{a,b}").unwrap(), ["${OUT}/a", "${OUT}/b"]);
+        assert_eq!(expand_braces("odd{a,b").unwrap(), ["odd{a,b"]);
+        assert_eq!(expand_braces("{x}{a,b}").unwrap(), ["{x}a", "{x}b"]);
+        assert!(expand_braces(&"{0,1,2,3,4,5,6,7,8,9}".repeat(6)).is_err());
+    }
+
+    #[test]
+    fn test_expand_rejects_unknown_names() {
+        assert!(expand_with("$MISSING/x", env).is_err());
+        assert!(expand_with("${OUT", env).is_err());
+        assert!(expand_with("~no-such-user-here/x", env).is_err());
+    }
+}
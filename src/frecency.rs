@@ -0,0 +1,225 @@
+//! Remembered creation places, ranked by frecency, for suggesting where a
+//! relative path was probably meant to go
+//!
+//! Every run adds one to the rank of each directory it created something in.
+//! Ranks are weighted by how recently the directory was used (zoxide's scheme:
+//! four times within the hour, twice within the day, half within the week, a
+//! quarter after that) and aged once their total passes `MAX_TOTAL`. The places
+//! are kept in `$XDG_STATE_HOME/bank/places.tsv` (or `$BANK_PLACES_FILE`) as
+//! `rank<TAB>last use<TAB>directory` lines, with backslashes, tabs and line breaks
+//! in the directory written as `\\`, `\t`, `\n` and `\r`. Only runs given
+//! `--history` (or `history = true` in config.toml) keep them.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the places file
+pub const PLACES_FILE_ENV: &str = "BANK_PLACES_FILE";
+
+/// Once the ranks add up to more than this, they are scaled down and the
+/// places that fall below one are forgotten
+const MAX_TOTAL: f64 = 10_000.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    pub dir: PathBuf,
+    pub rank: f64,
+    /// Seconds since the epoch
+    pub last_used: i64,
+}
+
+impl Place {
+    pub fn score(&self, now: i64) -> f64 {
+        let age = now - self.last_used;
+        let weight = match age {
+            _ if age < 3600 => 4.0,
+            _ if age < 86_400 => 2.0,
+            _ if age < 7 * 86_400 => 0.5,
+            _ => 0.25,
+        };
+        self.rank * weight
+    }
+}
+
+/// `$BANK_PLACES_FILE`, else `$XDG_STATE_HOME/bank/places.tsv`, else
+/// `~/.local/state/bank/places.tsv`
+pub fn places_file() -> Result<PathBuf> {
+    if let Some(file) = std::env::var_os(PLACES_FILE_ENV).filter(|file| !file.is_empty()) {
+        return Ok(PathBuf::from(file));
+    }
+    if let Some(state) = std::env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(state).join("bank/places.tsv"));
+    }
+    match std::env::var_os("HOME") {
+        Some(home) => Ok(PathBuf::from(home).join(".local/state/bank/places.tsv")),
+        None => bail!("Cannot locate the places file: neither {}, XDG_STATE_HOME nor HOME is set", PLACES_FILE_ENV),
+    }
+}
+
+fn parse(text: &str) -> Vec<Place> {
+    // A damaged line only loses that place
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let rank = fields.next()?.parse().ok()?;
+            let last_used = fields.next()?.parse().ok()?;
+            let dir = PathBuf::from(unescape(fields.next()?)?);
+            dir.is_absolute().then_some(Place { dir, rank, last_used })
+        })
+        .collect()
+}
+
+fn format(places: &[Place]) -> String {
+    // A directory that is not UTF-8 could not be read back as itself
+    places
+        .iter()
+        .filter_map(|place| Some(format!("{}\t{}\t{}\n", place.rank, place.last_used, escape(place.dir.to_str()?))))
+        .collect()
+}
+
+fn escape(dir: &str) -> String {
+    dir.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+/// None for an unknown escape
+fn unescape(field: &str) -> Option<String> {
+    let mut dir = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            dir.push(c);
+            continue;
+        }
+        dir.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+    Some(dir)
+}
+
+/// The remembered places; none if nothing was recorded yet
+pub fn load() -> Result<Vec<Place>> {
+    let file = places_file()?;
+    match std::fs::read_to_string(&file) {
+        Ok(text) => Ok(parse(&text)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", file.display())),
+    }
+}
+
+/// Count one more use of each of `dirs` at `now`
+fn add(places: &mut Vec<Place>, dirs: &[PathBuf], now: i64) {
+    for dir in dirs {
+        match places.iter_mut().find(|place| &place.dir == dir) {
+            Some(place) => {
+                place.rank += 1.0;
+                place.last_used = now;
+            }
+            None => places.push(Place { dir: dir.clone(), rank: 1.0, last_used: now }),
+        }
+    }
+    let total: f64 = places.iter().map(|place| place.rank).sum();
+    if total > MAX_TOTAL {
+        let factor = 0.9 * MAX_TOTAL / total;
+        places.iter_mut().for_each(|place| place.rank *= factor);
+        places.retain(|place| place.rank >= 1.0);
+    }
+}
+
+/// Remember that this run created something in each of `dirs` (absolute)
+pub fn record(dirs: &[PathBuf]) -> Result<()> {
+    if dirs.is_empty() {
+        return Ok(());
+    }
+    let mut places = load()?;
+    // One use per run, however many paths went into a directory
+    let mut seen = HashSet::new();
+    let dirs: Vec<PathBuf> = dirs.iter().filter(|dir| seen.insert(*dir)).cloned().collect();
+    add(&mut places, &dirs, chrono::Utc::now().timestamp());
+
+    let file = places_file()?;
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    // Written aside and renamed over, so a concurrent run or a crash never
+    // leaves half a file behind
+    let mut temp = file.clone().into_os_string();
+    temp.push(format!(".{}.tmp", std::process::id()));
+    let temp = PathBuf::from(temp);
+    let written = std::fs::write(&temp, format(&places)).and_then(|()| std::fs::rename(&temp, &file));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    written.with_context(|| format!("Failed to write {}", file.display()))
+}
+
+/// Where `path` (relative, with a parent that does not exist here) most
+/// likely belongs: under the best-scoring place that has its parent
+/// directory, or that is that directory itself
+pub fn suggest(places: &[Place], path: &Path, now: i64, is_dir: &dyn Fn(&Path) -> bool) -> Option<PathBuf> {
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty())?;
+    let name = path.file_name()?;
+    let mut ranked: Vec<&Place> = places.iter().collect();
+    ranked.sort_by(|a, b| b.score(now).total_cmp(&a.score(now)));
+    ranked.into_iter().find_map(|place| {
+        if place.dir.ends_with(parent) && is_dir(&place.dir) {
+            Some(place.dir.join(name))
+        } else {
+            is_dir(&place.dir.join(parent)).then(|| place.dir.join(path))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggest_prefers_frecent_places() {
+        let now = 1_000_000_000;
+        let places = parse(
+            "10\t999000000\t/home/dev/old/src\n\
+             3\t999999000\t/home/dev/work/proj/src\n\
+             garbage line\n\
+             1\t999999999\t/home/dev/work/proj\n",
+        );
+        assert_eq!(places.len(), 3);
+        let existing = ["/home/dev/old/src", "/home/dev/work/proj/src", "/home/dev/work/proj", "/home/dev/work/proj/docs"];
+        let is_dir = |dir: &Path| existing.iter().any(|existing| Path::new(existing) == dir);
+
+        // Recent use outweighs a higher but month-old rank
+        assert_eq!(
+            suggest(&places, Path::new("src/main.rs"), now, &is_dir),
+            Some(PathBuf::from("/home/dev/work/proj/src/main.rs"))
+        );
+        assert_eq!(
+            suggest(&places, Path::new("docs/api/"), now, &is_dir),
+            Some(PathBuf::from("/home/dev/work/proj/docs/api"))
+        );
+        assert_eq!(suggest(&places, Path::new("tests/a.rs"), now, &is_dir), None);
+        assert_eq!(suggest(&places, Path::new("a.rs"), now, &is_dir), None);
+
+        let mut places = places;
+        add(&mut places, &[PathBuf::from("/home/dev/work/proj"), PathBuf::from("/tmp/new")], now);
+        assert_eq!(places[2].rank, 2.0);
+        assert_eq!(places[3], Place { dir: PathBuf::from("/tmp/new"), rank: 1.0, last_used: now });
+        assert_eq!(parse(&format(&places)), places);
+    }
+
+    #[test]
+    fn test_places_with_tabs_and_newlines() {
+        let places = vec![
+            Place { dir: PathBuf::from("/srv/a\tb"), rank: 2.0, last_used: 7 },
+            Place { dir: PathBuf::from("/srv/c\nd\\e"), rank: 1.0, last_used: 8 },
+        ];
+        let text = format(&places);
+        assert_eq!(text, "2\t7\t/srv/a\\tb\n1\t8\t/srv/c\\nd\\\\e\n");
+        assert_eq!(parse(&text), places);
+        assert_eq!(parse("1\t8\t/srv/bad\\x\n"), Vec::new());
+    }
+}
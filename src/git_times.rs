@@ -0,0 +1,159 @@
+//! `bank git-restore-times`: give tracked files the time of their last commit
+//!
+//! A fresh clone or checkout leaves every file with the time it was written,
+//! which defeats reproducible builds and makes static sites report every page
+//! as changed. Each tracked file under the PATHs (the current directory by
+//! default) gets the committer date of the last commit that changed it as
+//! its mtime. The history is read newest first with `git log`, which stops
+//! as soon as every file has its date. Files with uncommitted changes keep
+//! their time, since their contents are not the committed ones. Everything
+//! is asked of the `git` command, which has to be on the PATH. Names are kept
+//! as the bytes git prints, so they need not be UTF-8.
+
+use crate::manifest::format_time;
+use crate::report::outln;
+use crate::vfs::Filesystem;
+use anyhow::{bail, Context, Result};
+use clap::Args as ClapArgs;
+use colored::*;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct GitRestoreTimesArgs {
+    /// Files or directories in the repository (default: the current directory)
+    #[arg(value_name = "PATH")]
+    pub paths: Vec<PathBuf>,
+
+    /// Print the times that would be set, without touching anything
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+/// Run git in `dir`, on `paths` if any, and return its stdout
+fn git(dir: &Path, args: &[&str], paths: &[PathBuf]) -> Result<Vec<u8>> {
+    let mut command = Command::new("git");
+    command.current_dir(dir).args(args);
+    if !paths.is_empty() {
+        command.arg("--").args(paths);
+    }
+    let output = command.output().context("Failed to run git")?;
+    if !output.status.success() {
+        bail!("git {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
+
+/// NUL-separated names, as git prints them with `-z`
+fn split_names(output: &[u8]) -> impl Iterator<Item = Vec<u8>> + '_ {
+    output.split(|byte| *byte == 0).filter(|name| !name.is_empty()).map(<[u8]>::to_vec)
+}
+
+/// The last commit time of each of `files` (relative to the top of the
+/// repository), from `git log --name-only -z` output read newest first
+pub fn last_commit_times(log: impl BufRead, files: &[Vec<u8>]) -> Result<HashMap<Vec<u8>, SystemTime>> {
+    let mut wanted: HashSet<&[u8]> = files.iter().map(Vec::as_slice).collect();
+    let mut times = HashMap::new();
+    let mut commit_time = None;
+    for field in log.split(0) {
+        let field = field.context("Failed to read git log")?;
+        // Each commit is `\x01<time>`, then its files as `\n<name>`, `<name>`, ...
+        if let Some(seconds) = field.strip_prefix(b"\x01") {
+            let seconds: u64 = String::from_utf8_lossy(seconds).trim().parse().context("Unexpected git log output")?;
+            commit_time = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+            continue;
+        }
+        let name = field.strip_prefix(b"\n").unwrap_or(&field);
+        if let (Some(time), true) = (commit_time, wanted.remove(name)) {
+            times.insert(name.to_vec(), time);
+            if wanted.is_empty() {
+                break;
+            }
+        }
+    }
+    Ok(times)
+}
+
+pub fn run(fs: &dyn Filesystem, args: &GitRestoreTimesArgs) -> Result<()> {
+    let top = git(Path::new("."), &["rev-parse", "--show-toplevel"], &[])?;
+    let top = PathBuf::from(OsStr::from_bytes(top.strip_suffix(b"\n").unwrap_or(&top)));
+    let paths = if args.paths.is_empty() { vec![PathBuf::from(".")] } else { args.paths.clone() };
+    let tracked: Vec<Vec<u8>> = split_names(&git(Path::new("."), &["ls-files", "-z", "--full-name"], &paths)?).collect();
+    if tracked.is_empty() {
+        bail!("No tracked files under {}", paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", "));
+    }
+    let modified: HashSet<Vec<u8>> = split_names(&git(&top, &["diff", "--name-only", "-z", "HEAD"], &[])?).collect();
+    let total = tracked.len();
+    let files: Vec<Vec<u8>> = tracked.into_iter().filter(|file| !modified.contains(file)).collect();
+
+    // Newest first; stopped once every file has its time
+    let mut child = Command::new("git")
+        .current_dir(&top)
+        .args(["log", "--format=%x01%ct", "--name-only", "-z", "--no-renames"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run git")?;
+    let log = BufReader::new(child.stdout.take().context("Failed to read git log")?);
+    let times = last_commit_times(log, &files);
+    let _ = child.kill();
+    let _ = child.wait();
+    let times = times?;
+
+    let mut updated = 0;
+    for file in &files {
+        let Some(&time) = times.get(file) else {
+            continue;
+        };
+        let path = top.join(OsStr::from_bytes(file));
+        let metadata = fs.symlink_metadata(&path).with_context(|| format!("Failed to read metadata for {}", path.display()))?;
+        // Symlinks have their own times, which set_times would not reach
+        if metadata.modified == time || metadata.kind != crate::vfs::FileKind::File {
+            continue;
+        }
+        updated += 1;
+        if args.dry_run || args.verbose {
+            outln!("{} {}: {} → {}", "~".yellow(), String::from_utf8_lossy(file), format_time(metadata.modified).red(), format_time(time).green());
+        }
+        if !args.dry_run {
+            fs.set_times(&path, metadata.accessed, time).with_context(|| format!("Failed to set timestamps for {}", path.display()))?;
+        }
+    }
+    if args.dry_run || args.verbose {
+        outln!(
+            "{} to update, {} unchanged, {} with uncommitted changes",
+            updated.to_string().yellow(),
+            (files.len() - updated).to_string().bright_black(),
+            (total - files.len()).to_string().cyan()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_commit_times() {
+        let log = b"\x011100000000\0\na\0\x011000000000\0\na\0d/b c\0caf\xe9\0\x01900000000\0\nold\0";
+        let files = vec![b"a".to_vec(), b"d/b c".to_vec(), b"caf\xe9".to_vec()];
+        let times = last_commit_times(&log[..], &files).unwrap();
+        let at = |seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        assert_eq!(times[&b"a"[..]], at(1_100_000_000));
+        assert_eq!(times[&b"d/b c"[..]], at(1_000_000_000));
+        // Not UTF-8, and still found
+        assert_eq!(times[&b"caf\xe9"[..]], at(1_000_000_000));
+        assert_eq!(times.len(), 3);
+        assert_eq!(split_names(b"a\0d/b c\0").collect::<Vec<_>>(), [b"a".to_vec(), b"d/b c".to_vec()]);
+    }
+}
@@ -0,0 +1,136 @@
+//! Minimal glob matching used by include/exclude filters
+
+/// Match a `/`-separated relative path against a glob pattern.
+///
+/// Supports `*` (any run of characters except `/`), `**` (any run including `/`),
+/// `?` (a single character except `/`) and `[...]` classes with ranges and `!`/`^`
+/// negation. Patterns without a `/` are matched against the final component only,
+/// so `*.log` matches `logs/app.log`.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let subject = if pattern.contains(&'/') {
+        path
+    } else {
+        path.rsplit('/').next().unwrap_or(path)
+    };
+    let subject: Vec<char> = subject.chars().collect();
+    match_from(&pattern, &subject)
+}
+
+/// Returns true if any of the patterns match the path
+pub fn any_match(patterns: &[String], path: &str) -> bool {
+    patterns.iter().any(|pattern| glob_match(pattern, path))
+}
+
+fn match_from(pattern: &[char], subject: &[char]) -> bool {
+    match pattern.first() {
+        None => subject.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            // `**/` may also match zero directories
+            if let Some(after_slash) = rest.strip_prefix(&['/']) {
+                if match_from(after_slash, subject) {
+                    return true;
+                }
+            }
+            (0..=subject.len()).any(|i| match_from(rest, &subject[i..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for i in 0..=subject.len() {
+                if match_from(rest, &subject[i..]) {
+                    return true;
+                }
+                if subject.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => match subject.first() {
+            Some(c) if *c != '/' => match_from(&pattern[1..], &subject[1..]),
+            _ => false,
+        },
+        Some('[') => match parse_class(&pattern[1..]) {
+            Some((class, consumed)) => match subject.first() {
+                Some(c) if *c != '/' && class.matches(*c) => {
+                    match_from(&pattern[1 + consumed..], &subject[1..])
+                }
+                _ => false,
+            },
+            // Unterminated class: treat `[` literally
+            None => subject.first() == Some(&'[') && match_from(&pattern[1..], &subject[1..]),
+        },
+        Some(literal) => {
+            subject.first() == Some(literal) && match_from(&pattern[1..], &subject[1..])
+        }
+    }
+}
+
+struct CharClass {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        let hit = self.ranges.iter().any(|(lo, hi)| *lo <= c && c <= *hi);
+        hit != self.negated
+    }
+}
+
+/// Parse a character class body (after `[`), returning it and the number of chars consumed
+fn parse_class(body: &[char]) -> Option<(CharClass, usize)> {
+    let mut i = 0;
+    let negated = matches!(body.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let start = i;
+    while i < body.len() {
+        let c = body[i];
+        if c == ']' && i > start {
+            return Some((CharClass { negated, ranges }, i + 1));
+        }
+        if body.get(i + 1) == Some(&'-') && body.get(i + 2).is_some_and(|&hi| hi != ']') {
+            ranges.push((c, body[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basename_patterns() {
+        assert!(glob_match("*.log", "app.log"));
+        assert!(glob_match("*.log", "var/logs/app.log"));
+        assert!(!glob_match("*.log", "app.log.1"));
+        assert!(glob_match("cache?", "build/cache1"));
+    }
+
+    #[test]
+    fn test_path_patterns() {
+        assert!(glob_match("src/**", "src/a/b"));
+        assert!(glob_match("**/target", "target"));
+        assert!(glob_match("**/target", "crates/x/target"));
+        assert!(glob_match("a/**/b", "a/b"));
+        assert!(!glob_match("src/*", "src/a/b"));
+        assert!(glob_match("src/*", "src/a"));
+    }
+
+    #[test]
+    fn test_character_classes() {
+        assert!(glob_match("v[0-9]", "v3"));
+        assert!(!glob_match("v[!0-9]", "v3"));
+        assert!(glob_match("[ab]*", "beta"));
+        assert!(glob_match("x[", "x["));
+    }
+}
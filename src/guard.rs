@@ -0,0 +1,281 @@
+//! Guardrails: refuse to create paths outside the locations this user allows
+//!
+//! With `allowed-roots` in config.toml, every local target must be one of the
+//! roots or below one once `..` and the symlinks of its existing part are
+//! resolved, so a malformed variable in shared automation cannot write into
+//! `/`. `--allow-anywhere` lifts the check for one run.
+//!
+//! Whatever the configuration, paths under the system directories are
+//! refused unless `--allow-system` is given.
+//!
+//! Subcommands write through [`GuardFs`], which runs the same checks on every
+//! path they create, modify or remove.
+
+use crate::vfs::{DirEntry, Filesystem, Metadata};
+use anyhow::{bail, Result};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Where a typo does the most damage
+#[cfg(not(windows))]
+const SYSTEM_DIRS: &[&str] = &["/etc", "/boot", "/usr"];
+#[cfg(windows)]
+const SYSTEM_DIRS: &[&str] = &["C:\\Windows", "C:\\Program Files", "C:\\Program Files (x86)", "C:\\ProgramData"];
+
+/// Fail if `path_str` resolves to a system directory or a path below one
+pub fn check_system(path_str: &str) -> Result<()> {
+    let target = resolve(Path::new(path_str));
+    if let Some(dir) = system_dir_containing(&target, SYSTEM_DIRS) {
+        let resolved = if target == Path::new(path_str) { String::new() } else { format!(" ({})", target.display()) };
+        bail!(
+            "Refusing to create or modify {}{} under the system directory {}: a typo there can break the system; pass --allow-system if this is intended",
+            path_str,
+            resolved,
+            dir
+        );
+    }
+    Ok(())
+}
+
+/// The first of `dirs` that `target` (already resolved) is or lies below.
+/// The directories are resolved too, since a system directory may itself be
+/// a symlink (`/etc` is `/private/etc` on macOS)
+fn system_dir_containing<'a>(target: &Path, dirs: &[&'a str]) -> Option<&'a str> {
+    // Windows paths are case-insensitive
+    let fold = |path: &Path| if cfg!(windows) { PathBuf::from(path.to_string_lossy().to_lowercase()) } else { path.to_path_buf() };
+    let folded = fold(target);
+    dirs.iter()
+        .find(|dir| [Path::new(dir).to_path_buf(), resolve(Path::new(dir))].iter().any(|dir| folded.starts_with(fold(dir))))
+        .copied()
+}
+
+/// Fail unless `path_str` resolves to one of `roots` or a path below one
+pub fn check_allowed(roots: &[PathBuf], path_str: &str) -> Result<()> {
+    if roots.is_empty() {
+        return Ok(());
+    }
+    let target = resolve(Path::new(path_str));
+    if roots.iter().any(|root| target.starts_with(resolve(root))) {
+        return Ok(());
+    }
+    let listed: Vec<String> = roots.iter().map(|root| root.display().to_string()).collect();
+    bail!("Refusing to create {} outside the allowed roots ({}); pass --allow-anywhere to override", path_str, listed.join(", "))
+}
+
+/// Wraps another filesystem and refuses every change to a path outside the
+/// allowed roots or, unless `allow_system`, under a system directory.
+/// Reads pass through
+pub struct GuardFs<'a> {
+    inner: &'a dyn Filesystem,
+    roots: Vec<PathBuf>,
+    allow_system: bool,
+}
+
+impl<'a> GuardFs<'a> {
+    pub fn new(inner: &'a dyn Filesystem, roots: Vec<PathBuf>, allow_system: bool) -> Self {
+        Self { inner, roots, allow_system }
+    }
+
+    fn check(&self, path: &Path) -> io::Result<()> {
+        let path_str = path.to_string_lossy();
+        check_allowed(&self.roots, &path_str)
+            .and_then(|()| if self.allow_system { Ok(()) } else { check_system(&path_str) })
+            .map_err(|err| io::Error::new(io::ErrorKind::PermissionDenied, format!("{:#}", err)))
+    }
+}
+
+impl Filesystem for GuardFs<'_> {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.symlink_metadata(path)
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.create_file(path)
+    }
+
+    fn create_file_with_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.create_file_with_times(path, accessed, modified)
+    }
+
+    fn create_file_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.create_file_with_mode(path, mode)
+    }
+
+    fn write_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.write_file(path, contents)
+    }
+
+    fn append_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.append_file(path, contents)
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.inner.read_file(path)
+    }
+
+    fn read_file_prefix(&self, path: &Path, limit: usize) -> io::Result<Vec<u8>> {
+        self.inner.read_file_prefix(path, limit)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.create_dir(path)
+    }
+
+    fn create_dir_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.create_dir_with_mode(path, mode)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.create_dir_all(path)
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.set_permissions(path, mode)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.set_owner(path, uid, gid)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.remove_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.check(from)?;
+        self.check(to)?;
+        self.inner.rename(from, to)
+    }
+
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        self.check(path)?;
+        self.inner.trash(path)
+    }
+
+    fn set_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        self.check(path)?;
+        self.inner.set_times(path, accessed, modified)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        self.inner.read_dir(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+}
+
+/// `path` made absolute without `.` or `..`, with its longest existing
+/// ancestor canonicalized so symlinks cannot lead out of a root
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut lexical = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                lexical.pop();
+            }
+            other => lexical.push(other),
+        }
+    }
+
+    let mut existing = lexical.as_path();
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            return missing.into_iter().rev().fold(canonical, |path, name| path.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return lexical,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::LocalFs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_allowed_roots_and_system_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let work = temp_dir.path().join("work");
+        std::fs::create_dir_all(work.join("src")).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("other")).unwrap();
+        std::os::unix::fs::symlink(temp_dir.path().join("other"), work.join("escape")).unwrap();
+        let roots = [work.clone()];
+        let path = |relative: &str| format!("{}/{}", temp_dir.path().display(), relative);
+
+        assert!(check_allowed(&roots, &path("work/src/new/file.txt")).is_ok());
+        assert!(check_allowed(&roots, &path("work/")).is_ok());
+        assert!(check_allowed(&roots, &path("work/src/../../other/file.txt")).is_err());
+        assert!(check_allowed(&roots, &path("work/escape/file.txt")).is_err());
+        assert!(check_allowed(&roots, &path("workshop/file.txt")).is_err());
+        assert!(check_allowed(&[], "/file.txt").is_ok());
+
+        assert!(check_system(&path("work/app.conf")).is_ok());
+        assert!(check_system("/etc/app/app.conf").is_err());
+        assert!(check_system("/usr/../etc").is_err());
+        assert!(check_system("/etcetera/file").is_ok());
+
+        // A system directory that is a symlink guards where it points
+        let private = temp_dir.path().join("private/etc");
+        std::fs::create_dir_all(&private).unwrap();
+        std::os::unix::fs::symlink(&private, temp_dir.path().join("etc")).unwrap();
+        let link = path("etc");
+        assert_eq!(system_dir_containing(&resolve(&private.join("hosts")), &[&link]), Some(link.as_str()));
+        assert_eq!(system_dir_containing(&resolve(Path::new(&path("etc/new/file"))), &[&link]), Some(link.as_str()));
+        assert_eq!(system_dir_containing(&resolve(Path::new(&path("work/file"))), &[&link]), None);
+    }
+
+    #[test]
+    fn test_guard_fs_refuses_changes_outside_roots() {
+        let temp_dir = TempDir::new().unwrap();
+        let work = temp_dir.path().join("work");
+        std::fs::create_dir(&work).unwrap();
+        std::fs::write(temp_dir.path().join("outside.txt"), "keep").unwrap();
+        let fs = GuardFs::new(&LocalFs, vec![work.clone()], false);
+
+        fs.create_dir_all(&work.join("src")).unwrap();
+        fs.write_file(&work.join("src/main.rs"), b"").unwrap();
+        let err = fs.write_file(&temp_dir.path().join("outside.txt"), b"").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(fs.rename(&work.join("src/main.rs"), &temp_dir.path().join("main.rs")).is_err());
+        assert!(fs.remove_file(&temp_dir.path().join("outside.txt")).is_err());
+        assert!(fs.create_file(Path::new("/etc/bank-guard-test")).is_err());
+        assert_eq!(fs.read_file(&temp_dir.path().join("outside.txt")).unwrap(), b"keep");
+        assert!(work.join("src/main.rs").exists());
+
+        // --allow-system lifts only the system check
+        let fs = GuardFs::new(&LocalFs, Vec::new(), true);
+        assert!(fs.create_dir_all(&temp_dir.path().join("anywhere")).is_ok());
+    }
+}
@@ -0,0 +1,411 @@
+//! Content hashes for manifests: SHA-256 and BLAKE3, both implemented here
+
+use clap::ValueEnum;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+/// Hash a file's contents, formatted as `<algorithm>:<hex digest>`
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut buffer = vec![0u8; 64 * 1024];
+    let digest = match algorithm {
+        HashAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break hasher.finish();
+                }
+                hasher.update(&buffer[..read]);
+            }
+        }
+        HashAlgorithm::Blake3 => {
+            let mut hasher = Blake3::new();
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break hasher.finish();
+                }
+                hasher.update(&buffer[..read]);
+            }
+        }
+    };
+    Ok(format!("{}:{}", algorithm.name(), hex(&digest)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Also BLAKE3's IV
+const SHA256_H: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    block_len: usize,
+    total_len: u64,
+}
+
+impl Sha256 {
+    fn new() -> Self {
+        Self { state: SHA256_H, block: [0; 64], block_len: 0, total_len: 0 }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+        while !data.is_empty() {
+            let take = (64 - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+            if self.block_len == 64 {
+                let block = self.block;
+                self.compress(&block);
+                self.block_len = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let bit_len = self.total_len.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.block_len != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bit_len.to_be_bytes());
+        self.state.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    fn compress(&mut self, block: &[u8; 64]) {
+        let mut w = [0u32; 64];
+        for (i, chunk) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(SHA256_K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+}
+
+const BLAKE3_CHUNK_LEN: usize = 1024;
+const BLAKE3_CHUNK_START: u32 = 1;
+const BLAKE3_CHUNK_END: u32 = 2;
+const BLAKE3_PARENT: u32 = 4;
+const BLAKE3_ROOT: u32 = 8;
+const BLAKE3_MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+fn blake3_g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+    state[d] = (state[d] ^ state[a]).rotate_right(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(12);
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+    state[d] = (state[d] ^ state[a]).rotate_right(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(7);
+}
+
+fn blake3_compress(cv: &[u32; 8], block: &[u32; 16], counter: u64, block_len: u32, flags: u32) -> [u32; 16] {
+    let mut state = [
+        cv[0], cv[1], cv[2], cv[3], cv[4], cv[5], cv[6], cv[7],
+        SHA256_H[0], SHA256_H[1], SHA256_H[2], SHA256_H[3],
+        counter as u32, (counter >> 32) as u32, block_len, flags,
+    ];
+    let mut message = *block;
+    for round in 0..7 {
+        blake3_g(&mut state, 0, 4, 8, 12, message[0], message[1]);
+        blake3_g(&mut state, 1, 5, 9, 13, message[2], message[3]);
+        blake3_g(&mut state, 2, 6, 10, 14, message[4], message[5]);
+        blake3_g(&mut state, 3, 7, 11, 15, message[6], message[7]);
+        blake3_g(&mut state, 0, 5, 10, 15, message[8], message[9]);
+        blake3_g(&mut state, 1, 6, 11, 12, message[10], message[11]);
+        blake3_g(&mut state, 2, 7, 8, 13, message[12], message[13]);
+        blake3_g(&mut state, 3, 4, 9, 14, message[14], message[15]);
+        if round < 6 {
+            let original = message;
+            for (slot, &source) in message.iter_mut().zip(BLAKE3_MSG_PERMUTATION.iter()) {
+                *slot = original[source];
+            }
+        }
+    }
+    for i in 0..8 {
+        state[i] ^= state[i + 8];
+        state[i + 8] ^= cv[i];
+    }
+    state
+}
+
+fn blake3_words(bytes: &[u8; 64]) -> [u32; 16] {
+    let mut words = [0u32; 16];
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    words
+}
+
+fn first_8(words: [u32; 16]) -> [u32; 8] {
+    let mut cv = [0u32; 8];
+    cv.copy_from_slice(&words[..8]);
+    cv
+}
+
+/// Inputs to a pending compression, kept so the root can be finalized with the ROOT flag
+struct Blake3Output {
+    cv: [u32; 8],
+    block: [u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+}
+
+impl Blake3Output {
+    fn chaining_value(&self) -> [u32; 8] {
+        first_8(blake3_compress(&self.cv, &self.block, self.counter, self.block_len, self.flags))
+    }
+
+    fn root_hash(&self) -> Vec<u8> {
+        let words = blake3_compress(&self.cv, &self.block, 0, self.block_len, self.flags | BLAKE3_ROOT);
+        words[..8].iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+}
+
+fn blake3_parent(left: [u32; 8], right: [u32; 8]) -> Blake3Output {
+    let mut block = [0u32; 16];
+    block[..8].copy_from_slice(&left);
+    block[8..].copy_from_slice(&right);
+    Blake3Output { cv: SHA256_H, block, counter: 0, block_len: 64, flags: BLAKE3_PARENT }
+}
+
+struct Blake3Chunk {
+    cv: [u32; 8],
+    counter: u64,
+    block: [u8; 64],
+    block_len: usize,
+    blocks_compressed: usize,
+}
+
+impl Blake3Chunk {
+    fn new(counter: u64) -> Self {
+        Self { cv: SHA256_H, counter, block: [0; 64], block_len: 0, blocks_compressed: 0 }
+    }
+
+    fn len(&self) -> usize {
+        self.blocks_compressed * 64 + self.block_len
+    }
+
+    fn start_flag(&self) -> u32 {
+        if self.blocks_compressed == 0 { BLAKE3_CHUNK_START } else { 0 }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // Only compress a full block once more input arrives: the last block needs CHUNK_END
+            if self.block_len == 64 {
+                let words = blake3_words(&self.block);
+                self.cv = first_8(blake3_compress(&self.cv, &words, self.counter, 64, self.start_flag()));
+                self.blocks_compressed += 1;
+                self.block = [0; 64];
+                self.block_len = 0;
+            }
+            let take = (64 - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+        }
+    }
+
+    fn output(&self) -> Blake3Output {
+        Blake3Output {
+            cv: self.cv,
+            block: blake3_words(&self.block),
+            counter: self.counter,
+            block_len: self.block_len as u32,
+            flags: self.start_flag() | BLAKE3_CHUNK_END,
+        }
+    }
+}
+
+struct Blake3 {
+    chunk: Blake3Chunk,
+    /// Chaining values of completed subtrees, one per set bit of the chunk count
+    stack: Vec<[u32; 8]>,
+}
+
+impl Blake3 {
+    fn new() -> Self {
+        Self { chunk: Blake3Chunk::new(0), stack: Vec::new() }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.chunk.len() == BLAKE3_CHUNK_LEN {
+                let mut cv = self.chunk.output().chaining_value();
+                let total_chunks = self.chunk.counter + 1;
+                // Merge completed subtrees: one merge per trailing zero bit of the chunk count
+                let mut count = total_chunks;
+                while count & 1 == 0 {
+                    cv = blake3_parent(self.stack.pop().expect("subtree on stack"), cv).chaining_value();
+                    count >>= 1;
+                }
+                self.stack.push(cv);
+                self.chunk = Blake3Chunk::new(total_chunks);
+            }
+            let take = (BLAKE3_CHUNK_LEN - self.chunk.len()).min(data.len());
+            self.chunk.update(&data[..take]);
+            data = &data[take..];
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut output = self.chunk.output();
+        for left in self.stack.iter().rev() {
+            output = blake3_parent(*left, output.chaining_value());
+        }
+        output.root_hash()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sha256(data: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(data);
+        hex(&hasher.finish())
+    }
+
+    fn blake3(data: &[u8]) -> String {
+        let mut hasher = Blake3::new();
+        hasher.update(data);
+        hex(&hasher.finish())
+    }
+
+    /// The inputs of BLAKE3's official test vectors: a byte counter mod 251
+    fn counter_bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_sha256_vectors() {
+        // FIPS 180-2 examples and the NIST one-million-'a' message
+        assert_eq!(sha256(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(
+            sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            sha256(b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu"),
+            "cf5b16a778af8380036ce59e7b0492370b249b11e8f07a51afac45037afee9d1"
+        );
+        assert_eq!(sha256(&vec![b'a'; 1_000_000]), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+
+        // Either side of where the length no longer fits in the last block
+        for (len, digest) in [
+            (55, "463eb28e72f82e0a96c0a4cc53690c571281131f672aa229e0d45ae59b598b59"),
+            (56, "da2ae4d6b36748f2a318f23e7ab1dfdf45acdc9d049bd80e59de82a60895f562"),
+            (63, "29af2686fd53374a36b0846694cc342177e428d1647515f078784d69cdb9e488"),
+            (64, "fdeab9acf3710362bd2658cdc9a29e8f9c757fcf9811603a8c447cd1d9151108"),
+            (65, "4bfd2c8b6f1eec7a2afeb48b934ee4b2694182027e6d0fc075074f2fabb31781"),
+        ] {
+            assert_eq!(sha256(&counter_bytes(len)), digest, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn test_blake3_vectors() {
+        assert_eq!(blake3(b""), "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262");
+        assert_eq!(blake3(b"abc"), "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85");
+
+        // From the official test_vectors.json: around chunk and subtree boundaries
+        for (len, digest) in [
+            (1, "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213"),
+            (1023, "10108970eeda3eb932baac1428c7a2163b0e924c9a9e25b35bba72b28f70bd11"),
+            (1024, "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7"),
+            (1025, "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444"),
+            (2048, "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a"),
+            (2049, "5f4d72f40d7a5f82b15ca2b2e44b1de3c2ef86c426c95c1af0b6879522563030"),
+            (3072, "b98cb0ff3623be03326b373de6b9095218513e64f1ee2edd2525c7ad1e5cffd2"),
+            (3073, "7124b49501012f81cc7f11ca069ec9226cecb8a2c850cfe644e327d22d3e1cd3"),
+            (4096, "015094013f57a5277b59d8475c0501042c0b642e531b0a1c8f58d2163229e969"),
+            (4097, "9b4052b38f1c5fc8b1f9ff7ac7b27cd242487b3d890d15c96a1c25b8aa0fb995"),
+            (8192, "aae792484c8efe4f19e2ca7d371d8c467ffb10748d8a5a1ae579948f718a2a63"),
+            (8193, "bab6c09cb8ce8cf459261398d2e7aef35700bf488116ceb94a36d0f5f1b7bc3b"),
+            (102400, "bc3e3d41a1146b069abffad3c0d44860cf664390afce4d9661f7902e7943e085"),
+        ] {
+            assert_eq!(blake3(&counter_bytes(len)), digest, "{} bytes", len);
+        }
+
+        // Streaming in odd pieces must match hashing in one go, across chunk boundaries
+        let data = counter_bytes(5000);
+        let mut hasher = Blake3::new();
+        for piece in data.chunks(333) {
+            hasher.update(piece);
+        }
+        assert_eq!(hex(&hasher.finish()), blake3(&data));
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("seed.txt");
+        std::fs::write(&path, b"abc").unwrap();
+        assert_eq!(
+            hash_file(&path, HashAlgorithm::Sha256).unwrap(),
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(hash_file(&path, HashAlgorithm::Blake3).unwrap().starts_with("blake3:6437b3ac"));
+    }
+}
@@ -0,0 +1,250 @@
+//! `bank hook`: check staged files against a permissions policy on commit
+//!
+//! `bank hook install` writes a git pre-commit hook that runs
+//! `bank hook pre-commit`, which checks the mode of every file added or
+//! changed in the index against the policy (`.bank-policy.toml` at the top of
+//! the repository unless `--policy` says otherwise) and rejects the commit if
+//! any file violates it. Both are taken as they will be committed: the policy
+//! is read from the index, and each file's working-tree mode gets the
+//! executable bit staged for it, the only part of a mode git records. With
+//! `--fix`, the files are given the nearest compliant mode instead, and the
+//! index gets their new executable bit. Owner rules are ignored: git does not
+//! record owners either.
+
+use crate::audit;
+use crate::policy::{Policy, Violation};
+use crate::vfs::{FileKind, Filesystem, Metadata};
+use anyhow::{bail, Context, Result};
+use clap::{Args as ClapArgs, Subcommand};
+use colored::*;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const POLICY_FILE: &str = ".bank-policy.toml";
+
+/// Marks hooks written by `bank hook install`, which it may replace
+const HOOK_MARKER: &str = "# Installed by bank hook install";
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct HookArgs {
+    #[command(subcommand)]
+    pub command: HookCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum HookCommand {
+    /// Write a pre-commit hook running `bank hook pre-commit` into the current repository
+    Install {
+        /// Policy file, relative to the top of the repository
+        #[arg(long = "policy", value_name = "FILE", default_value = POLICY_FILE)]
+        policy: PathBuf,
+        /// Have the hook fix the modes of violating files instead of rejecting the commit
+        #[arg(long = "fix")]
+        fix: bool,
+        /// Replace a pre-commit hook that bank did not write
+        #[arg(long = "force")]
+        force: bool,
+    },
+    /// Check the staged files against the policy, failing if any violates it
+    PreCommit {
+        /// Policy file, relative to the top of the repository
+        #[arg(long = "policy", value_name = "FILE", default_value = POLICY_FILE)]
+        policy: PathBuf,
+        /// Give violating files the nearest compliant mode and stage it
+        #[arg(long = "fix")]
+        fix: bool,
+    },
+}
+
+/// A file added or changed in the index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Staged {
+    /// Relative to the top of the repository
+    pub path: String,
+    /// Staged as 100755 rather than 100644
+    pub executable: bool,
+}
+
+/// What `check` found: the violations left, and the modes it changed
+#[derive(Debug, Default)]
+pub struct Outcome {
+    pub violations: Vec<Violation>,
+    pub fixed: Vec<(String, u32, u32)>,
+}
+
+/// Run git in `dir` and return its stdout
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git").current_dir(dir).args(args).output().context("Failed to run git")?;
+    if !output.status.success() {
+        bail!("git {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
+
+fn top_level() -> Result<PathBuf> {
+    Ok(PathBuf::from(String::from_utf8_lossy(&git(Path::new("."), &["rev-parse", "--show-toplevel"])?).trim()))
+}
+
+/// The regular files of `git diff --cached --raw -z` output, where each
+/// change is `:<old mode> <new mode> <old id> <new id> <status>\0<path>\0`
+pub fn parse_staged(raw: &[u8]) -> Vec<Staged> {
+    let mut fields = raw.split(|byte| *byte == 0);
+    let mut staged = Vec::new();
+    while let (Some(change), Some(path)) = (fields.next(), fields.next()) {
+        // Symlinks (120000) and submodules (160000) have no mode of their own
+        let executable = match String::from_utf8_lossy(change).split(' ').nth(1) {
+            Some("100644") => false,
+            Some("100755") => true,
+            _ => continue,
+        };
+        staged.push(Staged { path: String::from_utf8_lossy(path).into_owned(), executable });
+    }
+    staged
+}
+
+/// `mode` with the execute bits git will record: none, or one for each read
+/// bit when the file is staged as executable
+pub fn staged_mode(mode: u32, executable: bool) -> u32 {
+    let mode = mode & !0o111;
+    if executable {
+        mode | (mode & 0o444) >> 2
+    } else {
+        mode
+    }
+}
+
+/// Check the modes of `staged` files below `top`, as they will be
+/// committed, against `policy`, giving violating files the nearest compliant
+/// mode if `fix` is set
+pub fn check(fs: &dyn Filesystem, top: &Path, staged: &[Staged], policy: &Policy, fix: bool) -> Result<Outcome> {
+    let mut outcome = Outcome::default();
+    for file in staged {
+        let path = top.join(&file.path);
+        let metadata = fs.symlink_metadata(&path).with_context(|| format!("Failed to read metadata for {}", path.display()))?;
+        if metadata.kind != FileKind::File {
+            continue;
+        }
+        let metadata = Metadata { mode: staged_mode(metadata.mode & 0o7777, file.executable), ..metadata };
+        let violations: Vec<Violation> = policy.check(&file.path, &metadata)?.into_iter().filter(|violation| violation.attribute == "mode").collect();
+        if violations.is_empty() {
+            continue;
+        }
+        let mode = metadata.mode & 0o7777;
+        let wanted = policy.compliant_mode(&file.path, mode);
+        // Rules with different exact modes cannot all be met
+        if !fix || policy.check(&file.path, &Metadata { mode: wanted, ..metadata })?.iter().any(|violation| violation.attribute == "mode") {
+            outcome.violations.extend(violations);
+            continue;
+        }
+        fs.set_permissions(&path, wanted).with_context(|| format!("Failed to set permissions on {}", path.display()))?;
+        outcome.fixed.push((file.path.clone(), mode, wanted));
+    }
+    Ok(outcome)
+}
+
+fn pre_commit(fs: &dyn Filesystem, policy_file: &Path, fix: bool) -> Result<()> {
+    let top = top_level()?;
+    // The policy being committed, not whatever the working tree has
+    let text = git(&top, &["show", &format!(":{}", policy_file.display())])
+        .with_context(|| format!("No policy {} in the index; stage one with git add", policy_file.display()))?;
+    let policy = Policy::parse(&String::from_utf8_lossy(&text)).with_context(|| format!("Invalid policy {}", policy_file.display()))?;
+    let staged = parse_staged(&git(&top, &["diff", "--cached", "--raw", "-z", "--no-renames", "--diff-filter=ACMRT"])?);
+    let outcome = check(fs, &top, &staged, &policy, fix)?;
+
+    for (path, from, to) in &outcome.fixed {
+        println!("{} {}: {:04o} → {:04o}", "~".yellow(), path, from, to);
+        let executable = to & 0o100 != 0;
+        if staged.iter().any(|file| &file.path == path && file.executable != executable) {
+            git(&top, &["update-index", if executable { "--chmod=+x" } else { "--chmod=-x" }, "--", path])?;
+        }
+    }
+    if !outcome.violations.is_empty() {
+        let rows: Vec<(String, &Violation)> = outcome.violations.iter().map(|violation| (violation.path.clone(), violation)).collect();
+        audit::print_table(&rows);
+        bail!(
+            "Commit rejected: {} violation(s) of {}{}",
+            rows.len(),
+            policy_file.display(),
+            if fix { "" } else { "; run `bank hook pre-commit --fix` to fix the modes" }
+        );
+    }
+    Ok(())
+}
+
+/// The hook script, run by git from the top of the work tree
+pub fn hook_script(policy: &Path, fix: bool) -> String {
+    let policy = policy.display().to_string().replace('\'', "'\\''");
+    format!("#!/bin/sh\n{}\nexec bank hook pre-commit{} --policy '{}'\n", HOOK_MARKER, if fix { " --fix" } else { "" }, policy)
+}
+
+fn install(fs: &dyn Filesystem, policy: &Path, fix: bool, force: bool) -> Result<()> {
+    let top = top_level()?;
+    // Honours core.hooksPath and linked work trees
+    let hook = top.join(String::from_utf8_lossy(&git(&top, &["rev-parse", "--git-path", "hooks/pre-commit"])?).trim());
+    if let Ok(existing) = fs.read_file(&hook) {
+        if !force && !String::from_utf8_lossy(&existing).contains(HOOK_MARKER) {
+            bail!("{} already exists; use --force to replace it", hook.display());
+        }
+    }
+    if !top.join(policy).is_file() {
+        eprintln!("{} No policy at {} yet; commits will fail until there is one", "⚠".yellow(), top.join(policy).display());
+    }
+    if let Some(dir) = hook.parent() {
+        fs.create_dir_all(dir).with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    fs.write_file(&hook, hook_script(policy, fix).as_bytes()).with_context(|| format!("Failed to write {}", hook.display()))?;
+    fs.set_permissions(&hook, 0o755).with_context(|| format!("Failed to set permissions on {}", hook.display()))?;
+    println!("{} Installed {}", "✓".bright_green(), hook.display());
+    Ok(())
+}
+
+pub fn run(fs: &dyn Filesystem, args: &HookArgs) -> Result<()> {
+    match &args.command {
+        HookCommand::Install { policy, fix, force } => install(fs, policy, *fix, *force),
+        HookCommand::PreCommit { policy, fix } => pre_commit(fs, policy, *fix),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[test]
+    fn test_pre_commit_checks_staged_modes() {
+        let raw = b":000000 100644 0000000 1111111 A\0bin/run\0:100644 100755 2222222 3333333 M\0site.key\0:000000 120000 0000000 4444444 A\0link\0";
+        let staged = parse_staged(raw);
+        assert_eq!(staged, [Staged { path: "bin/run".to_string(), executable: false }, Staged { path: "site.key".to_string(), executable: true }]);
+
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("repo/bin")).unwrap();
+        for (path, mode) in [("repo/bin/run", 0o644), ("repo/site.key", 0o777)] {
+            memory.write_file(Path::new(path), b"").unwrap();
+            memory.set_permissions(Path::new(path), mode).unwrap();
+        }
+        let policy = Policy::parse("[rules.\"bin/*\"]\nmode = \"0755\"\n\n[rules.\"**\"]\nmax-mode = \"0775\"\nowner = \"root\"\n").unwrap();
+        let outcome = check(&memory, Path::new("repo"), &staged, &policy, false).unwrap();
+        assert_eq!(outcome.violations.iter().map(|violation| violation.path.as_str()).collect::<Vec<_>>(), ["bin/run", "site.key"]);
+        assert_eq!(memory.metadata(Path::new("repo/bin/run")).unwrap().mode & 0o7777, 0o644);
+
+        let outcome = check(&memory, Path::new("repo"), &staged, &policy, true).unwrap();
+        assert!(outcome.violations.is_empty());
+        assert_eq!(outcome.fixed, [("bin/run".to_string(), 0o644, 0o755), ("site.key".to_string(), 0o777, 0o775)]);
+        assert_eq!(memory.metadata(Path::new("repo/site.key")).unwrap().mode & 0o7777, 0o775);
+
+        let conflicting = Policy::parse("[rules.\"*.key\"]\nmode = \"0600\"\n\n[rules.\"site.*\"]\nmode = \"0640\"\n").unwrap();
+        assert_eq!(check(&memory, Path::new("repo"), &staged, &conflicting, true).unwrap().violations.len(), 2);
+        assert_eq!(memory.metadata(Path::new("repo/site.key")).unwrap().mode & 0o7777, 0o775);
+        // The staged executable bit counts, whatever the working tree says
+        assert_eq!(staged_mode(0o640, true), 0o750);
+        assert_eq!(staged_mode(0o755, false), 0o644);
+        let run = [Staged { path: "scripts/run".to_string(), executable: true }];
+        memory.create_dir_all(Path::new("repo/scripts")).unwrap();
+        memory.write_file(Path::new("repo/scripts/run"), b"").unwrap();
+        memory.set_permissions(Path::new("repo/scripts/run"), 0o644).unwrap();
+        let plain = Policy::parse("[rules.\"**\"]\nmode = \"0644\"\n").unwrap();
+        assert_eq!(check(&memory, Path::new("repo"), &run, &plain, false).unwrap().violations.len(), 1);
+        assert_eq!(check(&memory, Path::new("repo"), &run, &plain, true).unwrap().fixed, [("scripts/run".to_string(), 0o755, 0o644)]);
+        assert_eq!(hook_script(Path::new("it's.toml"), true).lines().last(), Some("exec bank hook pre-commit --fix --policy 'it'\\''s.toml'"));
+    }
+}
@@ -0,0 +1,291 @@
+//! Message catalog for user-facing output, with locale detection and `--lang`
+//!
+//! Messages use `{name}` placeholders. Languages: English (fallback), Spanish, German.
+
+use clap::ValueEnum;
+use std::sync::OnceLock;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lang {
+    En,
+    Es,
+    De,
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+/// Message key followed by its English, Spanish and German text
+const CATALOG: &[(&str, [&str; 3])] = &[
+    ("processing-paths", ["Processing {count} paths...", "Procesando {count} rutas...", "Verarbeite {count} Pfade..."]),
+    ("streamed-paths", ["Processed {count} paths", "Procesadas {count} rutas", "{count} Pfade verarbeitet"]),
+    ("warning", ["Warning:", "Advertencia:", "Warnung:"]),
+    ("no-exif-date", [
+        "{path}: no EXIF DateTimeOriginal",
+        "{path}: sin DateTimeOriginal de EXIF",
+        "{path}: kein EXIF-DateTimeOriginal",
+    ]),
+    ("suggest-destination", [
+        "{parent} does not exist here; create {path} instead?",
+        "{parent} no existe aquí; ¿crear {path} en su lugar?",
+        "{parent} existe hier nicht; stattdessen {path} erstellen?",
+    ]),
+    ("no-paths-selected", ["No paths selected", "No se seleccionó ninguna ruta", "Keine Pfade ausgewählt"]),
+    ("select-paths", [
+        "Select the paths to create (space to toggle, enter to confirm)",
+        "Seleccione las rutas a crear (espacio para marcar, intro para confirmar)",
+        "Zu erstellende Pfade auswählen (Leertaste zum Umschalten, Eingabe zum Bestätigen)",
+    ]),
+    ("prompt-kind", ["What should '{path}' be?", "¿Qué debe ser '{path}'?", "Was soll '{path}' sein?"]),
+    ("choice-file", ["File", "Archivo", "Datei"]),
+    ("choice-directory", ["Directory", "Directorio", "Verzeichnis"]),
+    ("skipping-missing", [
+        "Skipping non-existent path in no-create mode: {path}",
+        "Omitiendo ruta inexistente en modo sin creación: {path}",
+        "Überspringe nicht vorhandenen Pfad im No-Create-Modus: {path}",
+    ]),
+    ("updated-timestamps", ["Updated timestamps: {path}", "Marcas de tiempo actualizadas: {path}", "Zeitstempel aktualisiert: {path}"]),
+    ("updated-timestamps-for", ["Updated timestamps for: {path}", "Marcas de tiempo actualizadas para: {path}", "Zeitstempel aktualisiert für: {path}"]),
+    ("updated-tree", [
+        "Updated timestamps of {count} entries under {path}",
+        "Marcas de tiempo actualizadas de {count} entradas en {path}",
+        "Zeitstempel von {count} Einträgen unter {path} aktualisiert",
+    ]),
+    ("filesystem-loop", [
+        "File system loop detected: {path} leads back to a directory already being walked",
+        "Bucle en el sistema de archivos: {path} lleva a un directorio que ya se está recorriendo",
+        "Dateisystemschleife erkannt: {path} führt zurück zu einem bereits durchlaufenen Verzeichnis",
+    ]),
+    ("creating-file", ["Creating file: {path}", "Creando archivo: {path}", "Erstelle Datei: {path}"]),
+    ("creating-directory", ["Creating directory: {path}", "Creando directorio: {path}", "Erstelle Verzeichnis: {path}"]),
+    ("under-root-done", ["{root}: done (paths: {count})", "{root}: hecho (rutas: {count})", "{root}: erledigt (Pfade: {count})"]),
+    ("under-root-missing", [
+        "not a directory (roots are never created)",
+        "no es un directorio (las raíces nunca se crean)",
+        "kein Verzeichnis (Wurzelverzeichnisse werden nie erstellt)",
+    ]),
+    ("under-root-failed", ["{root}: {error}", "{root}: {error}", "{root}: {error}"]),
+    ("under-roots-failed", [
+        "{failed} of {total} roots failed",
+        "Fallaron {failed} de {total} raíces",
+        "{failed} von {total} Wurzelverzeichnissen fehlgeschlagen",
+    ]),
+    ("created-placeholder", ["Created placeholder {path}", "Marcador creado: {path}", "Platzhalter erstellt: {path}"]),
+    ("created-parent", [
+        "Created parent directory: {path}",
+        "Directorio padre creado: {path}",
+        "Übergeordnetes Verzeichnis erstellt: {path}",
+    ]),
+    ("created", ["Created: {path}", "Creado: {path}", "Erstellt: {path}"]),
+    ("file-exists", ["File already exists: {path}", "El archivo ya existe: {path}", "Datei existiert bereits: {path}"]),
+    ("appended", ["Appended to {path}", "Añadido a {path}", "Angehängt an {path}"]),
+    ("line-present", ["Line already present in {path}", "La línea ya está en {path}", "Zeile bereits vorhanden in {path}"]),
+    ("truncated", ["Truncated {path}", "Vaciado {path}", "Geleert: {path}"]),
+    ("replacing", ["Replacing existing {path}", "Reemplazando {path} existente", "Ersetze vorhandenes {path}"]),
+    ("trashed", [
+        "Moved existing {path} to the trash: {trash}",
+        "{path} existente movido a la papelera: {trash}",
+        "Vorhandenes {path} in den Papierkorb verschoben: {trash}",
+    ]),
+    ("confirm-summary", ["About to create {count} paths:", "Se van a crear {count} rutas:", "Es werden {count} Pfade erstellt:"]),
+    ("confirm-more", ["... and {count} more", "... y {count} más", "... und {count} weitere"]),
+    ("confirm-outside", [
+        "{count} of them are outside the current directory:",
+        "{count} de ellas están fuera del directorio actual:",
+        "{count} davon liegen außerhalb des aktuellen Verzeichnisses:",
+    ]),
+    ("confirm-foreign-owner", [
+        "{count} paths go into directories owned by root or another user, where mistakes are hard to undo:",
+        "{count} rutas van a directorios de root o de otro usuario, donde los errores son difíciles de deshacer:",
+        "{count} Pfade liegen in Verzeichnissen von root oder einem anderen Benutzer, wo sich Fehler schwer rückgängig machen lassen:",
+    ]),
+    ("confirm-create", ["Create {path}?", "¿Crear {path}?", "{path} erstellen?"]),
+    ("confirm-update", ["Update {path}?", "¿Actualizar {path}?", "{path} aktualisieren?"]),
+    ("confirm-answers", [
+        "Answer y (yes), n (no), a (all remaining) or q (quit)",
+        "Responda y (sí), n (no), a (todas las restantes) o q (salir)",
+        "Antworten Sie y (ja), n (nein), a (alle restlichen) oder q (beenden)",
+    ]),
+    ("confirm-proceed", ["Proceed?", "¿Continuar?", "Fortfahren?"]),
+    ("directory-exists", ["Directory already exists: {path}", "El directorio ya existe: {path}", "Verzeichnis existiert bereits: {path}"]),
+    ("set-permissions", [
+        "Set permissions to {mode} for {path}",
+        "Permisos establecidos a {mode} para {path}",
+        "Berechtigungen für {path} auf {mode} gesetzt",
+    ]),
+    ("running-as", [
+        "Acting as {user} (umask {umask})",
+        "Actuando como {user} (umask {umask})",
+        "Handle als {user} (umask {umask})",
+    ]),
+    ("set-owner", [
+        "Set owner to {owner} for {path}",
+        "Propietario establecido a {owner} para {path}",
+        "Eigentümer von {path} auf {owner} gesetzt",
+    ]),
+    ("wrote-manifest", [
+        "Recorded {count} paths in {path}",
+        "{count} rutas registradas en {path}",
+        "{count} Pfade in {path} festgehalten",
+    ]),
+    ("wrote-script", ["Wrote the shell script {path}", "Script de shell escrito en {path}", "Shell-Skript {path} geschrieben"]),
+    ("symlink-times", [
+        "Setting timestamps on symlink: {path}",
+        "Estableciendo marcas de tiempo en el enlace simbólico: {path}",
+        "Setze Zeitstempel auf symbolischem Link: {path}",
+    ]),
+    ("symlink-times-unsupported", [
+        "Warning: Symlink timestamp modification not fully supported on this platform",
+        "Advertencia: la modificación de marcas de tiempo de enlaces simbólicos no está totalmente soportada en esta plataforma",
+        "Warnung: Das Ändern von Zeitstempeln symbolischer Links wird auf dieser Plattform nicht vollständig unterstützt",
+    ]),
+    ("remote-running", [
+        "Running {count} operations on {target}",
+        "Ejecutando {count} operaciones en {target}",
+        "Führe {count} Operationen auf {target} aus",
+    ]),
+    ("remote-running-container", [
+        "Running {count} operations in {engine} container {container}",
+        "Ejecutando {count} operaciones en el contenedor {engine} {container}",
+        "Führe {count} Operationen im {engine}-Container {container} aus",
+    ]),
+    ("remote-creating", ["Creating {path}", "Creando {path}", "Erstelle {path}"]),
+    ("remote-updating", ["Updating {path}", "Actualizando {path}", "Aktualisiere {path}"]),
+    ("remote-skipping", ["Skipping {path}", "Omitiendo {path}", "Überspringe {path}"]),
+    ("remote-times-refused", [
+        "Server did not accept timestamps for {path} (HTTP {status})",
+        "El servidor no aceptó las marcas de tiempo de {path} (HTTP {status})",
+        "Server hat die Zeitstempel für {path} nicht übernommen (HTTP {status})",
+    ]),
+    ("archive-adding", ["Adding entry: {name}", "Añadiendo entrada: {name}", "Füge Eintrag hinzu: {name}"]),
+    ("archive-added", [
+        "Added {count} entries to {path}",
+        "{count} entradas añadidas a {path}",
+        "{count} Einträge zu {path} hinzugefügt",
+    ]),
+    ("error-already-exists", ["Path already exists: {path}", "La ruta ya existe: {path}", "Pfad existiert bereits: {path}"]),
+    ("error-not-a-directory", [
+        "Path exists but is not a directory: {path}",
+        "La ruta existe pero no es un directorio: {path}",
+        "Pfad existiert, ist aber kein Verzeichnis: {path}",
+    ]),
+    ("error-parent-missing", [
+        "Failed to {action} {path}: parent directory does not exist (use -p to create it)",
+        "No se pudo {action} {path}: el directorio padre no existe (use -p para crearlo)",
+        "Fehler beim {action} {path}: übergeordnetes Verzeichnis existiert nicht (mit -p anlegen)",
+    ]),
+    ("error-permission-denied", [
+        "Failed to {action} {path}: permission denied",
+        "No se pudo {action} {path}: permiso denegado",
+        "Fehler beim {action} {path}: Zugriff verweigert",
+    ]),
+    ("error-not-found", ["Path does not exist: {path}", "La ruta no existe: {path}", "Pfad existiert nicht: {path}"]),
+    ("error-bad-mode", ["Invalid mode format: {mode}", "Formato de modo no válido: {mode}", "Ungültiges Modusformat: {mode}"]),
+    ("error-prompt", ["Interactive prompt failed", "Falló la solicitud interactiva", "Interaktive Abfrage fehlgeschlagen"]),
+    ("error-io", ["Failed to {action} {path}", "No se pudo {action} {path}", "Fehler beim {action} {path}"]),
+    // Actions spliced into the error messages above
+    ("action:create file", ["create file", "crear el archivo", "Erstellen der Datei"]),
+    ("action:create directory", ["create directory", "crear el directorio", "Erstellen des Verzeichnisses"]),
+    ("action:create parent directories for", [
+        "create parent directories for",
+        "crear los directorios padre de",
+        "Erstellen der übergeordneten Verzeichnisse für",
+    ]),
+    ("action:change owner of", ["change owner of", "cambiar el propietario de", "Ändern des Eigentümers von"]),
+    ("action:set permissions for", ["set permissions for", "establecer los permisos de", "Setzen der Berechtigungen für"]),
+    ("action:read current timestamps for", [
+        "read current timestamps for",
+        "leer las marcas de tiempo actuales de",
+        "Lesen der aktuellen Zeitstempel von",
+    ]),
+    ("action:set timestamps for", ["set timestamps for", "establecer las marcas de tiempo de", "Setzen der Zeitstempel für"]),
+    ("action:read metadata from reference file", [
+        "read metadata from reference file",
+        "leer los metadatos del archivo de referencia",
+        "Lesen der Metadaten der Referenzdatei",
+    ]),
+];
+
+/// Use `lang` for the rest of the process (from `--lang`); first call wins
+pub fn set_lang(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+/// The active language: `--lang`, else the locale environment, else English
+pub fn current() -> Lang {
+    *LANG.get_or_init(|| {
+        // Tests compare English messages whatever the developer's locale
+        if cfg!(test) {
+            return Lang::En;
+        }
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .map(|value| parse_locale(&value))
+            .unwrap_or(Lang::En)
+    })
+}
+
+/// Map a POSIX locale name like `de_DE.UTF-8` or `es` to a supported language
+pub fn parse_locale(locale: &str) -> Lang {
+    let language = locale.split(['_', '.', '@', '-']).next().unwrap_or("").to_ascii_lowercase();
+    match language.as_str() {
+        "es" => Lang::Es,
+        "de" => Lang::De,
+        _ => Lang::En,
+    }
+}
+
+/// Look up `key` in the active language and fill in `{name}` placeholders
+pub fn tr(key: &str, args: &[(&str, &str)]) -> String {
+    translate(current(), key, args)
+}
+
+/// Translate an error action like "create file"; unknown actions stay in English
+pub fn action(phrase: &str) -> String {
+    let key = format!("action:{}", phrase);
+    match CATALOG.iter().any(|(name, _)| *name == key) {
+        true => tr(&key, &[]),
+        false => phrase.to_string(),
+    }
+}
+
+pub fn translate(lang: Lang, key: &str, args: &[(&str, &str)]) -> String {
+    let Some((_, texts)) = CATALOG.iter().find(|(name, _)| *name == key) else {
+        return key.to_string();
+    };
+    let mut text = texts[lang as usize].to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders(text: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = text.split('{').skip(1).filter_map(|rest| rest.split('}').next()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_catalog_placeholders_match_across_languages() {
+        for (key, texts) in CATALOG {
+            for text in &texts[1..] {
+                assert_eq!(placeholders(texts[0]), placeholders(text), "placeholders differ for {}", key);
+            }
+        }
+    }
+
+    #[test]
+    fn test_locale_detection_and_translation() {
+        assert_eq!(parse_locale("de_DE.UTF-8"), Lang::De);
+        assert_eq!(parse_locale("es"), Lang::Es);
+        assert_eq!(parse_locale("C.UTF-8"), Lang::En);
+        assert_eq!(translate(Lang::Es, "created", &[("path", "a.txt")]), "Creado: a.txt");
+        assert_eq!(translate(Lang::De, "unknown-key", &[]), "unknown-key");
+    }
+}
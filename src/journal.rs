@@ -0,0 +1,633 @@
+//! The undo journal: every run that changes the filesystem is recorded as one
+//! JSON file, optionally labelled with `--session`, so `bank undo` can revert
+//! it later and `bank redo` can replay what was reverted
+//!
+//! Recording a run also forgets the oldest: runs not saved (run, undone or
+//! redone) for `MAX_AGE`, and all but the newest `MAX_RUNS`.
+
+use crate::json::{self, Value};
+use crate::manifest::{format_time, kind_name, parse_kind};
+use crate::trash;
+use crate::vfs::{Change, FileKind, Filesystem, RecordingFs};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::Args as ClapArgs;
+use colored::*;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Runs not saved for this long are forgotten
+const MAX_AGE: Duration = Duration::from_secs(90 * 86_400);
+
+/// At most this many runs are kept, the newest
+const MAX_RUNS: usize = 1000;
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct UndoArgs {
+    /// Revert every run recorded under NAME instead of only the most recent run
+    #[arg(long = "session", value_name = "NAME")]
+    pub session: Option<String>,
+
+    /// List the recorded sessions with their path counts instead of undoing anything
+    #[arg(long = "list", conflicts_with = "session")]
+    pub list: bool,
+
+    /// Also remove created files that were modified after the run
+    #[arg(long = "force")]
+    pub force: bool,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct RedoArgs {
+    /// Replay every reverted run recorded under NAME instead of only the most recent one
+    #[arg(long = "session", value_name = "NAME")]
+    pub session: Option<String>,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+/// Mode and timestamps of a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attributes {
+    pub mode: u32,
+    pub accessed: SystemTime,
+    pub modified: SystemTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Absolute path
+    pub path: PathBuf,
+    pub kind: FileKind,
+    pub change: Change,
+    /// The attributes the run left the path with
+    pub after: Attributes,
+    /// The attributes a modified path had before the run, or a replaced one
+    /// before it was trashed
+    pub before: Option<Attributes>,
+    /// Where `--trash` moved the file a created one replaced
+    pub trashed: Option<PathBuf>,
+}
+
+/// One recorded run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub file: PathBuf,
+    pub session: Option<String>,
+    pub time: DateTime<Utc>,
+    pub undone: bool,
+    pub entries: Vec<Entry>,
+}
+
+/// `$BANK_JOURNAL_DIR`, else `$XDG_STATE_HOME/bank/journal`, else
+/// `~/.local/state/bank/journal`
+pub fn journal_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("BANK_JOURNAL_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Some(state) = std::env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(state).join("bank/journal"));
+    }
+    match std::env::var_os("HOME") {
+        Some(home) => Ok(PathBuf::from(home).join(".local/state/bank/journal")),
+        None => bail!("Cannot locate the undo journal: neither BANK_JOURNAL_DIR, XDG_STATE_HOME nor HOME is set"),
+    }
+}
+
+impl Run {
+    /// Build a run from what `fs` recorded; relative paths are resolved against `cwd`
+    pub fn from_recording(fs: &RecordingFs, session: Option<&str>, cwd: &Path, dir: &Path) -> Self {
+        let time = Utc::now();
+        let entries = fs
+            .changes()
+            .into_iter()
+            .filter_map(|(path, change)| {
+                let metadata = fs.symlink_metadata(&path).ok()?;
+                let before = fs.before(&path).map(|before| Attributes {
+                    mode: before.mode,
+                    accessed: before.accessed,
+                    modified: before.modified,
+                });
+                Some(Entry {
+                    path: cwd.join(&path),
+                    kind: metadata.kind,
+                    change,
+                    after: Attributes { mode: metadata.mode, accessed: metadata.accessed, modified: metadata.modified },
+                    before,
+                    trashed: fs.trashed(&path).map(|trashed| cwd.join(trashed)),
+                })
+            })
+            .collect();
+        let name = format!("{}-{}.json", time.format("%Y%m%dT%H%M%S%.9fZ"), std::process::id());
+        Self { file: dir.join(name), session: session.map(str::to_string), time, undone: false, entries }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.file.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create journal directory {}", dir.display()))?;
+        }
+        std::fs::write(&self.file, self.to_json())
+            .with_context(|| format!("Failed to write journal {}", self.file.display()))
+    }
+
+    fn to_json(&self) -> String {
+        let attributes = |attrs: &Attributes| {
+            format!(
+                "\"mode\": {}, \"atime\": {}, \"mtime\": {}",
+                json::quote(&format!("{:04o}", attrs.mode)),
+                json::quote(&format_time(attrs.accessed)),
+                json::quote(&format_time(attrs.modified))
+            )
+        };
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let change = match entry.change {
+                    Change::Created => "created",
+                    Change::Modified => "modified",
+                };
+                let mut line = format!(
+                    "    {{\"path\": {}, \"type\": {}, \"change\": {}, {}",
+                    json::quote(&entry.path.to_string_lossy()),
+                    json::quote(kind_name(entry.kind)),
+                    json::quote(change),
+                    attributes(&entry.after)
+                );
+                if let Some(before) = &entry.before {
+                    line.push_str(&format!(", \"before\": {{{}}}", attributes(before)));
+                }
+                if let Some(trashed) = &entry.trashed {
+                    line.push_str(&format!(", \"trashed\": {}", json::quote(&trashed.to_string_lossy())));
+                }
+                line.push('}');
+                line
+            })
+            .collect();
+        let session = self.session.as_deref().map_or("null".to_string(), json::quote);
+        format!(
+            "{{\n  \"version\": 1,\n  \"session\": {},\n  \"time\": {},\n  \"state\": {},\n  \"entries\": [\n{}\n  ]\n}}\n",
+            session,
+            json::quote(&format_time(self.time.into())),
+            json::quote(if self.undone { "undone" } else { "applied" }),
+            entries.join(",\n")
+        )
+    }
+
+    fn parse(file: &Path, text: &str) -> Result<Self> {
+        let document = json::parse(text)?;
+        let string = |value: &Value, key: &str| -> Result<String> {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .with_context(|| format!("missing or invalid '{}'", key))
+        };
+        let time = |value: &Value, key: &str| -> Result<SystemTime> {
+            let text = string(value, key)?;
+            Ok(DateTime::parse_from_rfc3339(&text).with_context(|| format!("invalid time '{}'", text))?.into())
+        };
+        let attributes = |value: &Value| -> Result<Attributes> {
+            let mode = string(value, "mode")?;
+            Ok(Attributes {
+                mode: u32::from_str_radix(&mode, 8).with_context(|| format!("invalid mode '{}'", mode))?,
+                accessed: time(value, "atime")?,
+                modified: time(value, "mtime")?,
+            })
+        };
+
+        let Some(Value::Array(items)) = document.get("entries") else {
+            bail!("missing 'entries'");
+        };
+        let mut entries = Vec::new();
+        for item in items {
+            let kind = string(item, "type")?;
+            let change = match string(item, "change")?.as_str() {
+                "created" => Change::Created,
+                "modified" => Change::Modified,
+                other => bail!("unknown change '{}'", other),
+            };
+            entries.push(Entry {
+                path: PathBuf::from(string(item, "path")?),
+                kind: parse_kind(&kind).with_context(|| format!("unknown type '{}'", kind))?,
+                change,
+                after: attributes(item)?,
+                before: item.get("before").map(attributes).transpose()?,
+                trashed: item.get("trashed").and_then(Value::as_str).map(PathBuf::from),
+            });
+        }
+
+        Ok(Self {
+            file: file.to_path_buf(),
+            session: document.get("session").and_then(Value::as_str).map(str::to_string),
+            time: time(&document, "time")?.into(),
+            undone: string(&document, "state")? == "undone",
+            entries,
+        })
+    }
+}
+
+/// Every recorded run, oldest first
+pub fn load_runs(dir: &Path) -> Result<Vec<Run>> {
+    let listing = match std::fs::read_dir(dir) {
+        Ok(listing) => listing,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read journal directory {}", dir.display())),
+    };
+    let mut files: Vec<PathBuf> = listing
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+
+    files
+        .iter()
+        .map(|file| {
+            let text = std::fs::read_to_string(file).with_context(|| format!("Failed to read journal {}", file.display()))?;
+            Run::parse(file, &text).with_context(|| format!("Invalid journal {}", file.display()))
+        })
+        .collect()
+}
+
+/// Record a finished run in the journal, if it changed anything
+pub fn record(fs: &RecordingFs, session: Option<&str>) -> Result<Option<PathBuf>> {
+    let cwd = std::env::current_dir().context("Failed to determine the current directory")?;
+    let run = Run::from_recording(fs, session, &cwd, &journal_dir()?);
+    if run.entries.is_empty() {
+        return Ok(None);
+    }
+    run.save()?;
+    prune(run.file.parent().unwrap_or(Path::new(".")), SystemTime::now())?;
+    Ok(Some(run.file))
+}
+
+/// Forget the runs beyond `MAX_RUNS` and those not saved within `MAX_AGE`;
+/// returns how many were removed
+fn prune(dir: &Path, now: SystemTime) -> Result<usize> {
+    let listing = std::fs::read_dir(dir).with_context(|| format!("Failed to read journal directory {}", dir.display()))?;
+    let mut files: Vec<PathBuf> = listing
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    // Named by the time they ran, so newest last
+    files.sort();
+
+    let excess = files.len().saturating_sub(MAX_RUNS);
+    let mut removed = 0;
+    for (index, file) in files.iter().enumerate() {
+        let stale = std::fs::metadata(file)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|saved| now.duration_since(saved).is_ok_and(|age| age > MAX_AGE));
+        if index < excess || stale {
+            std::fs::remove_file(file).with_context(|| format!("Failed to remove journal {}", file.display()))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+pub fn run_undo(fs: &dyn Filesystem, args: &UndoArgs) -> Result<()> {
+    let runs = load_runs(&journal_dir()?)?;
+    if args.list {
+        list_sessions(&runs);
+        return Ok(());
+    }
+
+    let selected: Vec<&Run> = match &args.session {
+        Some(name) => runs.iter().rev().filter(|run| !run.undone && run.session.as_deref() == Some(name)).collect(),
+        None => runs.iter().rev().filter(|run| !run.undone).take(1).collect(),
+    };
+    if selected.is_empty() {
+        match &args.session {
+            Some(name) => bail!("Nothing to undo for session '{}'", name),
+            None => bail!("Nothing to undo"),
+        }
+    }
+
+    let mut reverted = 0;
+    for run in selected {
+        reverted += undo_run(fs, run, args)?;
+        let mut run = run.clone();
+        run.undone = true;
+        run.save()?;
+    }
+    let label = args.session.as_deref().map_or(String::new(), |name| format!(" from session {}", name.cyan()));
+    println!("{} Reverted {} paths{}", "✓".bright_green(), reverted.to_string().cyan(), label);
+    Ok(())
+}
+
+/// Revert one run, newest change first; returns how many paths were reverted
+fn undo_run(fs: &dyn Filesystem, run: &Run, args: &UndoArgs) -> Result<usize> {
+    let mut reverted = 0;
+    for entry in run.entries.iter().rev() {
+        let path = &entry.path;
+        let current = match fs.symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            // Already gone: nothing left to revert
+            Err(_) => continue,
+        };
+
+        match (entry.change, &entry.before) {
+            (Change::Created, _) if current.kind == FileKind::Directory => match fs.remove_dir(path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::DirectoryNotEmpty => {
+                    println!("{} Keeping {}: it is not empty", "Warning:".yellow(), path.display());
+                    continue;
+                }
+                Err(err) => return Err(err).with_context(|| format!("Failed to remove {}", path.display())),
+            },
+            (Change::Created, _) => {
+                if current.modified != entry.after.modified && !args.force {
+                    println!("{} Keeping {}: it changed after it was created (use --force)", "Warning:".yellow(), path.display());
+                    continue;
+                }
+                fs.remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+                // The file it replaced comes back out of the trash
+                if let Some(trashed) = &entry.trashed {
+                    if let Err(err) = trash::restore(trashed, path) {
+                        println!("{} Could not restore {} from {}: {}", "Warning:".yellow(), path.display(), trashed.display(), err);
+                    }
+                }
+            }
+            (Change::Modified, Some(before)) => {
+                fs.set_permissions(path, before.mode)
+                    .with_context(|| format!("Failed to restore permissions of {}", path.display()))?;
+                fs.set_times(path, before.accessed, before.modified)
+                    .with_context(|| format!("Failed to restore timestamps of {}", path.display()))?;
+            }
+            (Change::Modified, None) => continue,
+        }
+
+        reverted += 1;
+        if args.verbose {
+            let action = if entry.change == Change::Created { "Removed" } else { "Restored" };
+            println!("{}: {}", action, path.display().to_string().yellow());
+        }
+    }
+    Ok(reverted)
+}
+
+pub fn run_redo(fs: &dyn Filesystem, args: &RedoArgs) -> Result<()> {
+    let runs = load_runs(&journal_dir()?)?;
+    // Replayed in the order they originally ran
+    let selected: Vec<&Run> = match &args.session {
+        Some(name) => runs.iter().filter(|run| run.undone && run.session.as_deref() == Some(name)).collect(),
+        None => runs.iter().rev().filter(|run| run.undone).take(1).collect(),
+    };
+    if selected.is_empty() {
+        match &args.session {
+            Some(name) => bail!("Nothing to redo for session '{}'", name),
+            None => bail!("Nothing to redo"),
+        }
+    }
+
+    let mut replayed = 0;
+    for run in selected {
+        let mut run = run.clone();
+        replayed += redo_run(fs, &mut run, args.verbose)?;
+        run.undone = false;
+        run.save()?;
+    }
+    let label = args.session.as_deref().map_or(String::new(), |name| format!(" from session {}", name.cyan()));
+    println!("{} Replayed {} paths{}", "✓".bright_green(), replayed.to_string().cyan(), label);
+    Ok(())
+}
+
+/// Recreate what a run created and reapply the attributes it left behind;
+/// returns how many paths were replayed. Paths that changed after the undo
+/// (a created path that exists again, a modified one whose restored mode or
+/// mtime is gone) are someone else's now and are left alone. A file the run
+/// replaced is trashed again, and where it went is kept in `run`.
+fn redo_run(fs: &dyn Filesystem, run: &mut Run, verbose: bool) -> Result<usize> {
+    // Judged before anything is recreated, which moves its parent's mtime
+    let mut replay = Vec::with_capacity(run.entries.len());
+    for entry in &run.entries {
+        let current = fs.symlink_metadata(&entry.path).ok();
+        replay.push(match (entry.change, current, &entry.before) {
+            (Change::Created, None, _) => entry.kind != FileKind::Symlink,
+            // The replaced file undo brought back, as it was
+            (Change::Created, Some(current), Some(before)) if entry.trashed.is_some() && current.modified == before.modified => true,
+            (Change::Created, Some(_), _) => {
+                println!("{} Keeping {}: it was created again after the undo", "Warning:".yellow(), entry.path.display());
+                false
+            }
+            (Change::Modified, Some(current), Some(before)) => {
+                let restored = current.mode == before.mode && current.modified == before.modified;
+                if !restored {
+                    println!("{} Keeping {}: it changed after the undo", "Warning:".yellow(), entry.path.display());
+                }
+                restored
+            }
+            // Gone, or nothing recorded to compare with
+            (Change::Modified, _, _) => false,
+        });
+    }
+
+    for (entry, _) in run.entries.iter_mut().zip(&replay).filter(|(entry, replay)| **replay && entry.change == Change::Created) {
+        if entry.trashed.is_some() && fs.symlink_metadata(&entry.path).is_ok() {
+            let trashed = fs.trash(&entry.path).with_context(|| format!("Failed to move {} to the trash", entry.path.display()))?;
+            entry.trashed = Some(trashed);
+        }
+        let result = match entry.kind {
+            FileKind::Directory => fs.create_dir(&entry.path),
+            // bank never creates symlinks
+            _ => fs.create_file(&entry.path),
+        };
+        result.with_context(|| format!("Failed to recreate {}", entry.path.display()))?;
+        if verbose {
+            println!("Recreated: {}", entry.path.display().to_string().green());
+        }
+    }
+
+    // Deepest first, so creating children cannot move a directory's timestamps again
+    let mut replayed = 0;
+    for (entry, _) in run.entries.iter().zip(&replay).rev().filter(|(_, replay)| **replay) {
+        fs.set_permissions(&entry.path, entry.after.mode)
+            .with_context(|| format!("Failed to set permissions of {}", entry.path.display()))?;
+        fs.set_times(&entry.path, entry.after.accessed, entry.after.modified)
+            .with_context(|| format!("Failed to set timestamps of {}", entry.path.display()))?;
+        replayed += 1;
+    }
+    Ok(replayed)
+}
+
+fn list_sessions(runs: &[Run]) {
+    if runs.is_empty() {
+        println!("The journal is empty");
+        return;
+    }
+
+    // Sessions in order of their latest run
+    let mut sessions: Vec<Option<&str>> = Vec::new();
+    for run in runs.iter().rev() {
+        if !sessions.contains(&run.session.as_deref()) {
+            sessions.push(run.session.as_deref());
+        }
+    }
+
+    println!("{:<24} {:>5} {:>7}  {:<20} STATE", "SESSION", "RUNS", "PATHS", "LAST RUN");
+    for session in sessions {
+        let runs: Vec<&Run> = runs.iter().filter(|run| run.session.as_deref() == session).collect();
+        let paths: usize = runs.iter().map(|run| run.entries.len()).sum();
+        let undone = runs.iter().filter(|run| run.undone).count();
+        let state = match undone {
+            0 => "applied",
+            n if n == runs.len() => "undone",
+            _ => "partly undone",
+        };
+        let last = runs.last().map(|run| run.time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string());
+        println!(
+            "{:<24} {:>5} {:>7}  {:<20} {}",
+            session.unwrap_or("(unnamed)"),
+            runs.len(),
+            paths,
+            last.unwrap_or_default(),
+            state
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{LocalFs, MemoryFs};
+    use tempfile::TempDir;
+
+    fn undo_args() -> UndoArgs {
+        UndoArgs { session: None, list: false, force: false, verbose: false }
+    }
+
+    #[test]
+    fn test_journal_round_trip() {
+        let journal = TempDir::new().unwrap();
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("/work")).unwrap();
+        memory.create_file(Path::new("/work/existing.txt")).unwrap();
+        let fs = RecordingFs::new(&memory);
+        fs.create_dir(Path::new("/work/out")).unwrap();
+        fs.create_file(Path::new("/work/out/new.txt")).unwrap();
+        fs.set_permissions(Path::new("/work/existing.txt"), 0o600).unwrap();
+
+        let run = Run::from_recording(&fs, Some("release \"prep\""), Path::new("/"), journal.path());
+        run.save().unwrap();
+        let runs = load_runs(journal.path()).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].session.as_deref(), Some("release \"prep\""));
+        assert_eq!(runs[0].entries, run.entries);
+        assert_eq!(runs[0].entries[2].before.map(|before| before.mode), Some(0o644));
+        assert!(!runs[0].undone);
+    }
+
+    #[test]
+    fn test_undo_reverts_creations_and_modifications() {
+        let journal = TempDir::new().unwrap();
+        let memory = MemoryFs::new();
+        memory.create_file(Path::new("kept.txt")).unwrap();
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        memory.set_times(Path::new("kept.txt"), old, old).unwrap();
+
+        let fs = RecordingFs::new(&memory);
+        fs.create_dir_all(Path::new("a/b")).unwrap();
+        fs.create_file(Path::new("a/b/c.txt")).unwrap();
+        fs.create_file(Path::new("a/touched.txt")).unwrap();
+        fs.set_times(Path::new("kept.txt"), SystemTime::now(), SystemTime::now()).unwrap();
+        let run = Run::from_recording(&fs, None, Path::new(""), journal.path());
+
+        // Modified after the run: kept unless forced, and so is its directory
+        memory.set_times(Path::new("a/touched.txt"), old, old).unwrap();
+        let reverted = undo_run(&memory, &run, &undo_args()).unwrap();
+        assert_eq!(reverted, 3);
+        assert!(!memory.exists(Path::new("a/b")));
+        assert!(memory.exists(Path::new("a/touched.txt")));
+        assert_eq!(memory.metadata(Path::new("kept.txt")).unwrap().modified, old);
+
+        let args = UndoArgs { force: true, ..undo_args() };
+        assert_eq!(undo_run(&memory, &run, &args).unwrap(), 3);
+        assert!(!memory.exists(Path::new("a")));
+    }
+
+    #[test]
+    fn test_redo_replays_an_undone_run() {
+        let journal = TempDir::new().unwrap();
+        let memory = MemoryFs::new();
+        memory.create_file(Path::new("kept.txt")).unwrap();
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        let fs = RecordingFs::new(&memory);
+        fs.create_dir_all(Path::new("a/b")).unwrap();
+        fs.create_file(Path::new("a/b/c.txt")).unwrap();
+        fs.set_permissions(Path::new("a/b/c.txt"), 0o600).unwrap();
+        fs.set_times(Path::new("a"), old, old).unwrap();
+        fs.set_permissions(Path::new("kept.txt"), 0o640).unwrap();
+        let run = Run::from_recording(&fs, None, Path::new(""), journal.path());
+
+        undo_run(&memory, &run, &undo_args()).unwrap();
+        assert!(!memory.exists(Path::new("a")));
+        assert_eq!(memory.metadata(Path::new("kept.txt")).unwrap().mode, 0o644);
+
+        assert_eq!(redo_run(&memory, &mut run.clone(), false).unwrap(), 4);
+        assert_eq!(memory.metadata(Path::new("a/b/c.txt")).unwrap().mode, 0o600);
+        assert_eq!(memory.metadata(Path::new("a")).unwrap().modified, old);
+        assert_eq!(memory.metadata(Path::new("kept.txt")).unwrap().mode, 0o640);
+
+        // Changed after the undo: left as they are
+        undo_run(&memory, &run, &undo_args()).unwrap();
+        memory.set_permissions(Path::new("kept.txt"), 0o600).unwrap();
+        memory.create_dir(Path::new("a")).unwrap();
+        memory.set_permissions(Path::new("a"), 0o700).unwrap();
+        assert_eq!(redo_run(&memory, &mut run.clone(), false).unwrap(), 2);
+        assert_eq!(memory.metadata(Path::new("kept.txt")).unwrap().mode, 0o600);
+        assert_eq!(memory.metadata(Path::new("a")).unwrap().mode, 0o700);
+        assert_eq!(memory.metadata(Path::new("a/b/c.txt")).unwrap().mode, 0o600);
+    }
+
+    #[test]
+    fn test_prune_forgets_old_and_excess_runs() {
+        let journal = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        for index in 0..MAX_RUNS + 2 {
+            std::fs::write(journal.path().join(format!("{:05}.json", index)), "{}").unwrap();
+        }
+        // Not saved for longer than MAX_AGE; only journals count
+        let old = filetime::FileTime::from_system_time(now - MAX_AGE - Duration::from_secs(60));
+        filetime::set_file_mtime(journal.path().join("00005.json"), old).unwrap();
+        std::fs::write(journal.path().join("notes.txt"), "").unwrap();
+        filetime::set_file_mtime(journal.path().join("notes.txt"), old).unwrap();
+
+        assert_eq!(prune(journal.path(), now).unwrap(), 3);
+        assert_eq!(std::fs::read_dir(journal.path()).unwrap().count(), MAX_RUNS);
+        assert!(!journal.path().join("00001.json").exists());
+        assert!(!journal.path().join("00005.json").exists());
+        assert!(journal.path().join("00002.json").exists());
+        assert!(journal.path().join("notes.txt").exists());
+        assert_eq!(prune(journal.path(), now).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_undo_restores_a_trashed_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("build.log");
+        std::fs::write(&path, b"old").unwrap();
+        let attributes = |path: &Path| {
+            let metadata = LocalFs.symlink_metadata(path).unwrap();
+            Attributes { mode: metadata.mode, accessed: metadata.accessed, modified: metadata.modified }
+        };
+        let before = attributes(&path);
+        let trashed = trash::move_to(&path, &temp_dir.path().join("Trash")).unwrap();
+        std::fs::write(&path, b"").unwrap();
+        let entry = Entry { path: path.clone(), kind: FileKind::File, change: Change::Created, after: attributes(&path), before: Some(before), trashed: Some(trashed.clone()) };
+        let run = Run { file: temp_dir.path().join("run.json"), session: None, time: Utc::now(), undone: false, entries: vec![entry] };
+        run.save().unwrap();
+        assert_eq!(load_runs(temp_dir.path()).unwrap()[0].entries, run.entries);
+
+        assert_eq!(undo_run(&LocalFs, &run, &undo_args()).unwrap(), 1);
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+        assert!(!trashed.exists());
+    }
+}
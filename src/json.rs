@@ -0,0 +1,364 @@
+//! Minimal JSON support: quoting for bank's JSON output and a reader for the
+//! structured inputs it accepts (batch files)
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in document order
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Member of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Name of the value's type, for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse a complete JSON document
+pub fn parse(text: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser { chars: text.chars().collect(), pos: 0 };
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+    Ok(value)
+}
+
+/// Where each item of the array under `key` in the top-level object starts,
+/// as `(line, column)`; empty if there is no such array
+pub fn item_positions(text: &str, key: &str) -> Result<Vec<(usize, usize)>, ParseError> {
+    let mut parser = Parser { chars: text.chars().collect(), pos: 0 };
+    let mut positions = Vec::new();
+    parser.skip_whitespace();
+    if parser.peek() != Some('{') {
+        return Ok(positions);
+    }
+    parser.pos += 1;
+    loop {
+        parser.skip_whitespace();
+        if parser.peek() != Some('"') {
+            return Ok(positions);
+        }
+        let name = parser.string()?;
+        parser.skip_whitespace();
+        parser.expect(':')?;
+        parser.skip_whitespace();
+        if name == key && parser.peek() == Some('[') {
+            parser.pos += 1;
+            loop {
+                parser.skip_whitespace();
+                if parser.peek() == Some(']') {
+                    break;
+                }
+                positions.push(parser.position());
+                parser.value()?;
+                parser.skip_whitespace();
+                if parser.peek() != Some(',') {
+                    break;
+                }
+                parser.pos += 1;
+            }
+            return Ok(positions);
+        }
+        parser.value()?;
+        parser.skip_whitespace();
+        if parser.peek() != Some(',') {
+            return Ok(positions);
+        }
+        parser.pos += 1;
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    /// The current `(line, column)`
+    fn position(&self) -> (usize, usize) {
+        let before = &self.chars[..self.pos.min(self.chars.len())];
+        let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        (line, column)
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        let (line, column) = self.position();
+        ParseError { line, column, message: message.to_string() }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Value::String),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) => {
+                for (word, value) in [("true", Value::Bool(true)), ("false", Value::Bool(false)), ("null", Value::Null)] {
+                    if self.chars[self.pos..].starts_with(&word.chars().collect::<Vec<_>>()) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("expected a value"))
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, ParseError> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a member name"));
+            }
+            let name = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            members.push((name, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            self.skip_whitespace();
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            self.pos += 1;
+                            let high = self.hex4()?;
+                            // Surrogate pairs encode characters outside the BMP
+                            let code = if (0xD800..0xDC00).contains(&high) {
+                                if self.peek() != Some('\\') || self.chars.get(self.pos + 1) != Some(&'u') {
+                                    return Err(self.error("unpaired surrogate in \\u escape"));
+                                }
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+                            } else {
+                                high
+                            };
+                            text.push(char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))?);
+                            continue;
+                        }
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    text.push(escaped);
+                    self.pos += 1;
+                }
+                Some(c) if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+                Some(c) => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let digits: String = self.chars.iter().skip(self.pos).take(4).collect();
+        if digits.len() != 4 {
+            return Err(self.error("truncated \\u escape"));
+        }
+        let code = u32::from_str_radix(&digits, 16).map_err(|_| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        while matches!(self.peek(), Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse().map(Value::Number).map_err(|_| {
+            self.pos = start;
+            self.error(&format!("invalid number '{}'", text))
+        })
+    }
+}
+
+/// Quote `text` as a JSON string
+pub fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_documents() {
+        let value = parse(r#" {"path": "a\/b\n", "n": -1.5e1, "ok": true, "list": [null, "é😀"]} "#).unwrap();
+        assert_eq!(
+            value,
+            Value::Object(vec![
+                ("path".to_string(), Value::String("a/b\n".to_string())),
+                ("n".to_string(), Value::Number(-15.0)),
+                ("ok".to_string(), Value::Bool(true)),
+                ("list".to_string(), Value::Array(vec![Value::Null, Value::String("é😀".to_string())])),
+            ])
+        );
+        assert_eq!(parse("[]").unwrap(), Value::Array(vec![]));
+    }
+
+    #[test]
+    fn test_parse_errors_report_position() {
+        let err = parse("[\n  \"a\",\n  oops\n]").unwrap_err();
+        assert_eq!((err.line, err.column), (3, 3));
+        assert!(parse("{\"a\": 1,}").is_err());
+        assert!(parse("\"open").is_err());
+        assert!(parse("[1] 2").is_err());
+    }
+
+    #[test]
+    fn test_item_positions() {
+        let text = "{\"version\": 1,\n \"entries\": [\n  {\"path\": \"a\"},\n  {\"path\": \"b\"}]}";
+        assert_eq!(item_positions(text, "entries").unwrap(), [(3, 3), (4, 3)]);
+        assert!(item_positions("{\"entries\": []}", "entries").unwrap().is_empty());
+        assert!(item_positions("[1]", "entries").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("src/a.rs"), "\"src/a.rs\"");
+        assert_eq!(quote("a\"b\\c\n"), "\"a\\\"b\\\\c\\n\"");
+        assert_eq!(quote("\u{1}"), "\"\\u0001\"");
+    }
+}
@@ -0,0 +1,71 @@
+//! Placeholder files for empty directories, so version control keeps them
+//!
+//! `--keep-file .gitkeep` (or `$BANK_KEEP_FILE`) puts an empty file with that
+//! name into every directory bank creates that is still empty afterwards;
+//! `--no-keep` switches it off for one run.
+
+use crate::vfs::Filesystem;
+use anyhow::{bail, Result};
+use clap::Args as ClapArgs;
+use std::io;
+use std::path::Path;
+
+/// Environment variable naming the placeholder when `--keep-file` is not given
+pub const KEEP_FILE_ENV: &str = "BANK_KEEP_FILE";
+
+#[derive(ClapArgs, Debug, Clone, Default)]
+pub struct KeepArgs {
+    /// Put an empty file NAME (e.g. .gitkeep) into every empty directory created (default: $BANK_KEEP_FILE)
+    #[arg(long = "keep-file", value_name = "NAME")]
+    pub keep_file: Option<String>,
+
+    /// Don't add placeholder files, even with $BANK_KEEP_FILE set
+    #[arg(long = "no-keep", conflicts_with = "keep_file")]
+    pub no_keep: bool,
+}
+
+impl KeepArgs {
+    /// The placeholder name in effect, if any
+    pub fn resolved(&self) -> Result<Option<String>> {
+        if self.no_keep {
+            return Ok(None);
+        }
+        let Some(name) = self.keep_file.clone().or_else(|| std::env::var(KEEP_FILE_ENV).ok().filter(|name| !name.is_empty())) else {
+            return Ok(None);
+        };
+        if name.contains('/') || name == "." || name == ".." {
+            bail!("Placeholder file name must be a plain file name: {}", name);
+        }
+        Ok(Some(name))
+    }
+}
+
+/// Create `dir/name` if `dir` is empty; whether it did
+pub fn fill(fs: &dyn Filesystem, dir: &Path, name: &str) -> io::Result<bool> {
+    if !fs.read_dir(dir)?.is_empty() {
+        return Ok(false);
+    }
+    fs.create_file(&dir.join(name))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[test]
+    fn test_fill_only_empty_directories() {
+        let fs = MemoryFs::new();
+        fs.create_dir_all(Path::new("a/b")).unwrap();
+        assert!(!fill(&fs, Path::new("a"), ".gitkeep").unwrap());
+        assert!(fill(&fs, Path::new("a/b"), ".gitkeep").unwrap());
+        assert!(fs.exists(Path::new("a/b/.gitkeep")));
+        assert!(!fill(&fs, Path::new("a/b"), ".gitkeep").unwrap());
+
+        let args = KeepArgs { keep_file: Some("x/y".to_string()), no_keep: false };
+        assert!(args.resolved().is_err());
+        let args = KeepArgs { keep_file: Some(".keep".to_string()), no_keep: false };
+        assert_eq!(args.resolved().unwrap().as_deref(), Some(".keep"));
+    }
+}
@@ -0,0 +1,170 @@
+//! `bank license`: create a LICENSE file from a bundled SPDX license text
+//!
+//! The texts are templates rendered with `year` and `author`, so the ones
+//! with a copyright line get it filled in. A file named after the license in
+//! `$XDG_CONFIG_HOME/bank/licenses` (optionally with `.tmpl`) replaces the
+//! bundled text, and can include the shared partials like any scaffold file.
+
+use crate::editorconfig::FormatArgs;
+use crate::journal;
+use crate::scaffold::{self, Item};
+use crate::template::{Loader, Vars};
+use crate::vfs::{Filesystem, RecordingFs};
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use clap::Args as ClapArgs;
+use colored::*;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// SPDX identifier and text of every bundled license
+const LICENSES: &[(&str, &str)] = &[
+    ("Apache-2.0", include_str!("licenses/Apache-2.0")),
+    ("BSD-2-Clause", include_str!("licenses/BSD-2-Clause")),
+    ("BSD-3-Clause", include_str!("licenses/BSD-3-Clause")),
+    ("GPL-3.0-only", include_str!("licenses/GPL-3.0")),
+    ("GPL-3.0-or-later", include_str!("licenses/GPL-3.0")),
+    ("ISC", include_str!("licenses/ISC")),
+    ("MIT", include_str!("licenses/MIT")),
+    ("MPL-2.0", include_str!("licenses/MPL-2.0")),
+    ("Unlicense", include_str!("licenses/Unlicense")),
+];
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct LicenseArgs {
+    /// SPDX identifier of the license, e.g. MIT or Apache-2.0 (case-insensitive)
+    #[arg(value_name = "SPDX-ID", required_unless_present = "list")]
+    pub id: Option<String>,
+
+    /// Copyright holder (defaults to git's user.name)
+    #[arg(long = "author", value_name = "NAME")]
+    pub author: Option<String>,
+
+    /// Copyright year or range (defaults to the current year)
+    #[arg(long = "year", value_name = "YEAR")]
+    pub year: Option<String>,
+
+    /// File to create
+    #[arg(short = 'o', long = "output", value_name = "FILE", default_value = "LICENSE")]
+    pub output: PathBuf,
+
+    #[command(flatten)]
+    pub format: FormatArgs,
+
+    /// List the available licenses
+    #[arg(long = "list", conflicts_with = "id")]
+    pub list: bool,
+
+    /// Do not record this run in the undo journal
+    #[arg(long = "no-journal")]
+    pub no_journal: bool,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+/// The canonical identifier and template text for `id`, preferring a user
+/// override from `config`'s `licenses` directory
+fn lookup(id: &str, config: Option<&Path>) -> Result<(&'static str, String, PathBuf)> {
+    let Some(&(canonical, bundled)) = LICENSES.iter().find(|(name, _)| name.eq_ignore_ascii_case(id)) else {
+        let known: Vec<&str> = LICENSES.iter().map(|(name, _)| *name).collect();
+        bail!("Unknown license '{}' (available: {})", id, known.join(", "));
+    };
+    let overrides = config.map(|dir| dir.join("licenses"));
+    for candidate in overrides.iter().flat_map(|dir| [dir.join(canonical), dir.join(format!("{}.tmpl", canonical))]) {
+        if candidate.is_file() {
+            let text = std::fs::read_to_string(&candidate).with_context(|| format!("Failed to read {}", candidate.display()))?;
+            return Ok((canonical, text, candidate));
+        }
+    }
+    Ok((canonical, bundled.to_string(), PathBuf::from(canonical)))
+}
+
+fn git_user_name() -> Option<String> {
+    let output = Command::new("git").args(["config", "user.name"]).output().ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !name.is_empty()).then_some(name)
+}
+
+/// Render the license text for `id`, with overrides and partials from `config`
+pub fn render(id: &str, author: Option<&str>, year: &str, config: Option<&Path>) -> Result<(&'static str, String)> {
+    let (canonical, text, origin) = lookup(id, config)?;
+    let mut vars = Vars::from([("year".to_string(), year.to_string())]);
+    match author {
+        Some(author) => {
+            vars.insert("author".to_string(), author.to_string());
+        }
+        None if text.contains("{{ author }}") => {
+            bail!("{} names the copyright holder; pass --author (git's user.name is not set)", canonical)
+        }
+        None => {}
+    }
+    let partials = config.map(|dir| dir.join("partials"));
+    let rendered = Loader::new(partials.into_iter().collect()).render(&origin, &text, &vars)?;
+    Ok((canonical, rendered))
+}
+
+pub fn run(fs: &dyn Filesystem, args: &LicenseArgs) -> Result<()> {
+    if args.list {
+        for (name, _) in LICENSES {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+    let id = args.id.as_deref().expect("clap requires SPDX-ID without --list");
+    let author = args.author.clone().or_else(git_user_name);
+    let year = args.year.clone().unwrap_or_else(|| Local::now().format("%Y").to_string());
+    let (canonical, text) = render(id, author.as_deref(), &year, scaffold::config_dir().as_deref())?;
+
+    let directory = args.output.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = args.output.file_name().with_context(|| format!("{} is not a file name", args.output.display()))?;
+    let item = Item { path: PathBuf::from(name), contents: Some(text.into_bytes()), mode: 0o644 };
+
+    let recording = RecordingFs::new(fs);
+    let result = scaffold::write_tree(&recording, directory, &[item], &args.format, args.verbose);
+    if !args.no_journal {
+        if let Err(err) = journal::record(&recording, None) {
+            println!("{} {:#}", "Warning:".yellow(), err);
+        }
+    }
+    result?;
+    println!("{} Created {} ({})", "✓".bright_green(), args.output.display().to_string().green(), canonical.cyan());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render_bundled_licenses() {
+        let (canonical, text) = render("mit", Some("Jane Doe"), "2026", None).unwrap();
+        assert_eq!(canonical, "MIT");
+        assert!(text.starts_with("MIT License\n\nCopyright (c) 2026 Jane Doe\n"));
+
+        // Licenses without a copyright line need no author
+        let (canonical, text) = render("apache-2.0", None, "2026", None).unwrap();
+        assert_eq!(canonical, "Apache-2.0");
+        assert!(text.contains("Apache License"));
+
+        assert!(render("BSD-3-Clause", None, "2026", None).is_err());
+        assert!(render("WTFPL", Some("Jane"), "2026", None).is_err());
+        for (name, _) in LICENSES {
+            assert!(render(name, Some("Jane"), "2026", None).is_ok(), "{} does not render", name);
+        }
+    }
+
+    #[test]
+    fn test_render_user_override() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("licenses")).unwrap();
+        std::fs::write(temp_dir.path().join("licenses/MIT.tmpl"), "Ours, {{ year }}\n").unwrap();
+
+        let (canonical, text) = render("MIT", None, "2026", Some(temp_dir.path())).unwrap();
+        assert_eq!(canonical, "MIT");
+        assert_eq!(text, "Ours, 2026\n");
+        assert!(render("ISC", None, "2026", Some(temp_dir.path())).is_err());
+    }
+}
@@ -1,19 +1,94 @@
+mod apply;
+mod archive;
+mod audit;
+mod batch;
+mod bench;
+mod bookmark;
+mod cargo_generate;
+mod clone;
+mod compat;
+mod config;
+mod confine;
+mod conflict;
+mod cookiecutter;
+mod editorconfig;
+mod error;
+#[cfg(feature = "exif")]
+mod exif;
+mod expand;
+mod frecency;
+mod git_times;
+mod glob;
+mod guard;
+mod hash;
+mod hook;
+mod i18n;
+mod journal;
+mod json;
+mod keep;
+mod license;
+mod manifest;
+mod mode;
+mod naming;
+mod normalize;
+mod note;
+mod outline;
+mod owner;
+mod policy;
+mod recurse;
+mod remote;
+mod report;
+mod rotate;
+mod scaffold;
+mod schedule;
+mod schema;
+mod secure;
+mod sequence;
+mod skel;
+mod snapshot;
+mod stream;
+mod sudo;
+mod sync_times;
+mod template;
+mod toml;
+mod trash;
+mod tree;
+mod vfs;
+mod watch;
+mod xdg;
+
 use anyhow::{Context, Result};
 use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::*;
-use dialoguer::{theme::ColorfulTheme, Select};
-use std::fs;
-use std::os::unix::fs::PermissionsExt;
+use dialoguer::{theme::ColorfulTheme, Confirm, MultiSelect, Select};
+use error::BankError;
+use i18n::tr;
+use report::outln;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufRead, IsTerminal, Read};
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH};
+use vfs::{Filesystem, LocalFs};
 
 /// Bank: A comprehensive command-line utility combining mkdir, touch, and advanced filesystem operations
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+#[cfg_attr(
+    feature = "exif",
+    command(group(clap::ArgGroup::new("walk").args(["recursive", "existing_only", "shift", "clamp_future", "fix_epoch", "time_from_exif"]).multiple(true)))
+)]
+#[cfg_attr(
+    not(feature = "exif"),
+    command(group(clap::ArgGroup::new("walk").args(["recursive", "existing_only", "shift", "clamp_future", "fix_epoch"]).multiple(true)))
+)]
 struct Args {
-    /// The paths to create (files or directories)
-    #[arg(value_name = "PATH", required = true)]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The paths to create (files or directories); `-` reads one path per line from stdin
+    #[arg(value_name = "PATH", required_unless_present_any = ["from_file", "from_file0", "from_archive", "from_tree", "from_outline", "batch_file", "specs", "existing_only"])]
     paths: Vec<String>,
 
     /// Force creation as directory (mkdir mode)
@@ -28,13 +103,56 @@ struct Args {
     #[arg(short = 'p', long = "parents")]
     parents: bool,
 
+    /// Permissions for directories created by -p (octal or symbolic, e.g. 750 or u=rwx,g=rx)
+    #[arg(long = "parents-mode", value_name = "MODE")]
+    parents_mode: Option<String>,
+
+    #[command(flatten)]
+    keep: keep::KeepArgs,
+
+    /// Create N numbered copies of each path containing {n}, e.g. shard-{n}.csv
+    #[arg(long = "count", value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    count: Option<u64>,
+
+    /// First number of a --count series
+    #[arg(long = "seq-start", value_name = "N", default_value_t = 1, requires = "count")]
+    seq_start: u64,
+
+    /// How --count numbers are written: decimal, hex, lower, upper or alphabet:CHARS
+    #[arg(long = "seq-format", value_name = "FORMAT", default_value = "decimal", requires = "count")]
+    seq_format: sequence::SeqFormat,
+
     /// Set file/directory permissions (octal format, e.g., 755)
     #[arg(short = 'm', long = "mode")]
     mode: Option<String>,
 
-    /// Interactive mode for ambiguous paths
-    #[arg(short = 'i', long = "interactive")]
-    interactive: bool,
+    /// Make files executable wherever they are readable, like chmod +x
+    #[arg(short = 'x', long = "executable", conflicts_with = "directory")]
+    executable: bool,
+
+    /// Only the owner may read or write: 0700 for directories (including -p parents), 0600 for files
+    #[arg(long = "private", conflicts_with_all = ["mode", "public_read"])]
+    private: bool,
+
+    /// Anyone may read, only the owner write: 0755 for directories (including -p parents), 0644 for files
+    #[arg(long = "public-read", conflicts_with = "mode")]
+    public_read: bool,
+
+    /// Give created paths (and -p parents) the owner and group of the directory they are created in, rather than yours
+    #[arg(long = "inherit-owner")]
+    inherit_owner: bool,
+
+    /// Policy whose [modes] and exact rule modes give new paths their mode when no --mode is given (on top of config.toml)
+    #[arg(long = "policy", value_name = "FILE")]
+    policy: Option<PathBuf>,
+
+    /// Default modes by glob, from config.toml and --policy
+    #[arg(skip)]
+    default_modes: Vec<(String, u32)>,
+
+    /// Ask whether ambiguous paths are files or directories; with =always, confirm every operation instead (y/n/a/q)
+    #[arg(short = 'i', long = "interactive", value_name = "WHEN", value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "ambiguous")]
+    interactive: Option<Interactive>,
 
     /// Verbose output
     #[arg(short = 'v', long = "verbose")]
@@ -44,6 +162,18 @@ struct Args {
     #[arg(short = 'c', long = "no-create")]
     no_create: bool,
 
+    /// Append a line of text to each file, creating it unless -c (a missing final newline is added first)
+    #[arg(long = "append", value_name = "TEXT")]
+    append: Option<String>,
+
+    /// With --append, leave files that already contain the exact line alone
+    #[arg(long = "ensure-line", requires = "append")]
+    ensure_line: bool,
+
+    /// Empty existing files (before --append, so the file ends up holding just that line)
+    #[arg(long = "truncate")]
+    truncate: bool,
+
     /// Parse date string and use it instead of current time
     #[arg(long = "date", value_name = "STRING")]
     date: Option<String>,
@@ -64,9 +194,393 @@ struct Args {
     #[arg(long = "mtime")]
     modification_time_only: bool,
 
+    /// Use this date for the access time (same formats as --date)
+    #[arg(long = "atime-date", value_name = "STRING", conflicts_with = "modification_time_only")]
+    atime_date: Option<String>,
+
+    /// Use this date for the modification time (same formats as --date)
+    #[arg(long = "mtime-date", value_name = "STRING", conflicts_with = "access_time_only")]
+    mtime_date: Option<String>,
+
+    /// Shift existing timestamps by a relative offset (BSD touch); missing files are ignored
+    #[arg(
+        short = 'A',
+        long = "adjust",
+        value_name = "[-][[hh]mm]SS",
+        allow_hyphen_values = true,
+        value_parser = parse_adjustment,
+        conflicts_with_all = ["date", "timestamp", "reference", "atime_date", "mtime_date"]
+    )]
+    adjust: Option<i64>,
+
+    /// Shift existing timestamps by OFFSET, like -A, and everything below directories like -R (e.g. +2h, -30m, -1h30m)
+    #[arg(
+        long = "shift",
+        value_name = "OFFSET",
+        allow_hyphen_values = true,
+        value_parser = parse_shift,
+        conflicts_with_all = ["adjust", "date", "timestamp", "reference", "atime_date", "mtime_date"]
+    )]
+    shift: Option<i64>,
+
+    /// Set timestamps later than now back to now, walking directories like -R (missing files are ignored)
+    #[arg(long = "clamp-future", conflicts_with_all = ["adjust", "shift", "date", "timestamp", "reference", "atime_date", "mtime_date"])]
+    clamp_future: bool,
+
+    /// Replace timestamps on or before 1970-01-01 with DATE (same formats as --date), walking directories like -R
+    #[arg(
+        long = "fix-epoch",
+        value_name = "DATE",
+        value_parser = parse_fix_epoch,
+        conflicts_with_all = ["adjust", "shift", "date", "timestamp", "reference", "atime_date", "mtime_date"]
+    )]
+    fix_epoch: Option<SystemTime>,
+
+    /// Set the mtime of photos to their EXIF DateTimeOriginal (JPEG, HEIC, RAW), walking directories like -R
+    #[cfg(feature = "exif")]
+    #[arg(
+        long = "time-from-exif",
+        conflicts_with_all = ["adjust", "shift", "clamp_future", "fix_epoch", "date", "timestamp", "reference", "atime_date", "mtime_date"]
+    )]
+    time_from_exif: bool,
+
+    /// Stands in for --time-from-exif in builds without the `exif` feature, to say so
+    #[cfg(not(feature = "exif"))]
+    #[arg(long = "time-from-exif", hide = true)]
+    time_from_exif_unavailable: bool,
+
+    /// Update timestamps of everything below existing directories (nothing is created)
+    #[arg(short = 'R', long = "recursive")]
+    recursive: bool,
+
+    /// Update timestamps of everything under the existing directory DIR, creating nothing, like touch -c over the tree (-R -c DIR; repeatable)
+    #[arg(
+        long = "existing-only",
+        value_name = "DIR",
+        conflicts_with_all = ["paths", "from_file", "from_file0", "from_archive", "from_tree", "from_outline", "batch_file", "specs"]
+    )]
+    existing_only: Vec<String>,
+
+    /// With -R, only update entries whose path below the directory matches GLOB (repeatable)
+    #[arg(long = "include", value_name = "GLOB", requires = "walk")]
+    include: Vec<String>,
+
+    /// With -R, skip entries matching GLOB, and everything below matching directories (repeatable)
+    #[arg(long = "exclude", value_name = "GLOB", requires = "walk")]
+    exclude: Vec<String>,
+
+    /// With -R, only update entries of these types: f (file), d (directory), l (symlink)
+    #[arg(long = "type", value_name = "TYPE", value_enum, value_delimiter = ',', requires = "walk")]
+    entry_types: Vec<recurse::EntryType>,
+
+    /// With -R, only update entries last modified more than DURATION ago (e.g. 7d, 12h)
+    #[arg(long = "older-than", value_name = "DURATION", value_parser = watch::parse_duration, requires = "walk")]
+    older_than: Option<std::time::Duration>,
+
+    /// With -R, only update entries modified within the last DURATION
+    #[arg(long = "newer-than", value_name = "DURATION", value_parser = watch::parse_duration, requires = "walk")]
+    newer_than: Option<std::time::Duration>,
+
+    /// With -R, only update entries of at least SIZE bytes (k, M, G suffixes)
+    #[arg(long = "min-size", value_name = "SIZE", value_parser = recurse::parse_size, requires = "walk")]
+    min_size: Option<u64>,
+
+    /// With -R, only update entries of at most SIZE bytes
+    #[arg(long = "max-size", value_name = "SIZE", value_parser = recurse::parse_size, requires = "walk")]
+    max_size: Option<u64>,
+
+    /// With -R, do not descend more than N levels below the directory (0 = only the directory)
+    #[arg(long = "max-depth", value_name = "N", requires = "walk")]
+    max_depth: Option<usize>,
+
+    /// With -R, skip entries less than N levels below the directory
+    #[arg(long = "min-depth", value_name = "N", requires = "walk")]
+    min_depth: Option<usize>,
+
+    /// With -R, follow symbolic links and descend into linked directories (loops are detected and skipped)
+    #[arg(long = "follow-symlinks", requires = "walk", overrides_with = "no_follow_symlinks")]
+    follow_symlinks: bool,
+
+    /// With -R, treat symbolic links as entries of their own (the default)
+    #[arg(long = "no-follow-symlinks", requires = "walk", overrides_with = "follow_symlinks")]
+    no_follow_symlinks: bool,
+
     /// Affect symbolic links instead of referenced files
     #[arg(long = "no-dereference")]
     no_dereference: bool,
+
+    /// Also create the paths listed in FILE, one per line; blank lines and lines starting with # are skipped (- reads stdin)
+    #[arg(long = "from-file", value_name = "FILE", conflicts_with = "from_file0")]
+    from_file: Option<PathBuf>,
+
+    /// Like --from-file for NUL-separated lists, as `find -print0` writes them
+    #[arg(long = "from-file0", value_name = "FILE")]
+    from_file0: Option<PathBuf>,
+
+    /// Create the empty skeleton an archive (tar or zip) would extract to; implies --parents
+    #[arg(long = "from-archive", value_name = "ARCHIVE")]
+    from_archive: Option<PathBuf>,
+
+    /// Recreate the hierarchy drawn in FILE by the tree command; entries with children or a trailing / are directories
+    #[arg(long = "from-tree", value_name = "FILE")]
+    from_tree: Option<PathBuf>,
+
+    /// Build the tree an indented outline or Markdown list in FILE describes; entries with an extension or a trailing (file) are files
+    #[arg(long = "from-outline", value_name = "FILE")]
+    from_outline: Option<PathBuf>,
+
+    /// Create the paths listed in a CSV file (with a header row) or a .json array, each row with its own type, mode, owner and timestamp
+    #[arg(long = "batch-file", value_name = "FILE")]
+    batch_file: Option<PathBuf>,
+
+    /// Create PATH with its own attributes, e.g. logs/::mode=750,owner=app,mtime=2024-01-01 (repeatable)
+    #[arg(long = "spec", value_name = "PATH::ATTRS")]
+    specs: Vec<String>,
+
+    /// Record every path this run creates or modifies in a manifest (YAML, or JSON for .json files)
+    #[arg(long = "emit-manifest", value_name = "FILE")]
+    emit_manifest: Option<PathBuf>,
+
+    /// Write the planned operations as a POSIX shell script of mkdir/touch/chmod/chown commands (- for stdout) instead of performing them
+    #[arg(long = "emit-script", value_name = "FILE")]
+    emit_script: Option<PathBuf>,
+
+    /// Print the planned operations for another tool instead of performing them (ansible: a task list for the file module)
+    #[arg(long = "emit", value_name = "FORMAT", value_enum, conflicts_with = "emit_script")]
+    emit: Option<manifest::EmitFormat>,
+
+    /// With --emit-manifest, also record a hash of each file's contents (sha256 by default, or blake3)
+    #[arg(long = "hash", value_name = "ALGORITHM", value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "sha256", requires = "emit_manifest")]
+    hash: Option<hash::HashAlgorithm>,
+
+    /// What to do with paths that already exist: skip (reuse them) or overwrite (replace existing files)
+    #[arg(long = "on-exists", value_name = "ACTION", value_enum, default_value = "skip")]
+    on_exists: OnExists,
+
+    /// Move files replaced by --on-exists overwrite to the trash instead of deleting them
+    #[arg(long = "trash")]
+    trash: bool,
+
+    /// Show a summary and ask before creating more than N paths or touching paths outside the current directory (default: $BANK_CONFIRM_OVER)
+    #[arg(long = "confirm-over", value_name = "N")]
+    confirm_over: Option<usize>,
+
+    /// Answer yes to the --confirm-over prompt and to writing into directories owned by root or another user
+    #[arg(long = "yes")]
+    yes: bool,
+
+    /// Print what would be created or changed, without touching anything
+    #[arg(long = "dry-run")]
+    dry_run: bool,
+
+    /// How --dry-run prints the plan: text, or JSON for other tools
+    #[arg(long = "output", value_name = "FORMAT", value_enum, default_value = "text", requires = "dry_run")]
+    output: apply::PlanFormat,
+
+    /// Exit with status 10 instead of 0 when nothing had to be changed
+    #[arg(long = "changed-exit-code")]
+    changed_exit_code: bool,
+
+    /// Label this run in the undo journal, so `bank undo --session NAME` can revert it later
+    #[arg(long = "session", value_name = "NAME", conflicts_with = "no_journal")]
+    session: Option<String>,
+
+    /// Do not record this run in the undo journal
+    #[arg(long = "no-journal")]
+    no_journal: bool,
+
+    /// Remember where this run creates things, and suggest remembered places for missing parents
+    #[arg(long = "history")]
+    history: bool,
+
+    /// Keep no places history even if config.toml has history = true
+    #[arg(long = "no-history", conflicts_with = "history")]
+    no_history: bool,
+
+    /// Process and report the paths in the order given, instead of sorted with parents before their children
+    #[arg(long = "unordered")]
+    unordered: bool,
+
+    /// Create the paths from -, --from-file, --from-file0 or a CSV --batch-file as they are read, in input order, without holding the whole list in memory; needs --no-journal
+    #[arg(
+        long = "stream",
+        requires = "no_journal",
+        conflicts_with_all = ["history", "count", "specs", "from_archive", "from_tree", "from_outline", "under", "interactive", "check", "detect_case_collisions", "confirm_over", "dry_run", "emit_script", "emit", "into_archive", "remote", "container", "emit_manifest", "changed_exit_code"]
+    )]
+    stream: bool,
+
+    /// Process the paths on N threads; a path waits for the earlier ones it shares directories with, so parents are made before their children
+    #[arg(long = "jobs", short = 'j', value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["stream", "under", "interactive", "sudo"])]
+    jobs: u64,
+
+    /// Add the paths as empty entries to a .tar or .zip archive instead of creating them on disk
+    #[arg(long = "into-archive", value_name = "ARCHIVE")]
+    into_archive: Option<PathBuf>,
+
+    /// Perform the operations on a remote target instead of locally (ssh://user@host/base, s3://bucket/prefix, davs://host/path)
+    #[arg(long = "remote", value_name = "URL")]
+    remote: Option<String>,
+
+    /// Perform the operations inside a running container through `docker exec` or `podman exec` (ID, or docker:ID / podman:ID)
+    #[arg(long = "container", value_name = "[ENGINE:]ID", conflicts_with = "remote")]
+    container: Option<String>,
+
+    /// Reject names that would break on other platforms (portable, windows, posix)
+    #[arg(long = "check", value_name = "RULES")]
+    check: Option<naming::NameCheck>,
+
+    /// Report --check violations as warnings instead of failing
+    #[arg(long = "check-warn", requires = "check")]
+    check_warn: bool,
+
+    /// Replace characters and names that are unsafe on common filesystems instead of failing
+    #[arg(long = "sanitize")]
+    sanitize: bool,
+
+    /// Like --sanitize, and also transliterate non-ASCII characters
+    #[arg(long = "sanitize-ascii")]
+    sanitize_ascii: bool,
+
+    /// What to do when a path is requested more than once with different type, mode, owner or timestamp: merge (later values win), warn (merge and report) or error
+    #[arg(long = "on-conflict", value_name = "ACTION", value_enum, default_value_t = conflict::OnConflict::Warn)]
+    on_conflict: conflict::OnConflict,
+
+    /// Detect names that differ only in case from existing siblings or other requested paths
+    #[arg(
+        long = "detect-case-collisions",
+        value_name = "ACTION",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "error"
+    )]
+    detect_case_collisions: Option<naming::CollisionAction>,
+
+    /// Interpret path arguments relative to DIR instead of the current directory
+    #[arg(short = 'C', long = "relative-to", value_name = "DIR")]
+    relative_to: Option<PathBuf>,
+
+    /// Create the same relative paths beneath each ROOT, reporting every root separately (repeatable)
+    #[arg(long = "under", value_name = "ROOT", conflicts_with_all = ["relative_to", "root"])]
+    under: Vec<PathBuf>,
+
+    /// Confine every target strictly inside DIR, rejecting `..` and symlink escapes
+    #[arg(long = "root", value_name = "DIR")]
+    root: Option<PathBuf>,
+
+    /// Create paths outside the allowed-roots of config.toml
+    #[arg(long = "allow-anywhere", global = true)]
+    allow_anywhere: bool,
+
+    /// Create or modify paths under /etc, /boot, /usr (or the Windows system directories)
+    #[arg(long = "allow-system", global = true)]
+    allow_system: bool,
+
+    /// The allowed-roots of config.toml, unless --allow-anywhere is given
+    #[arg(skip)]
+    allowed_roots: Vec<PathBuf>,
+
+    /// Don't expand `~`, `~user`, `$VAR` or `${VAR}` in path arguments
+    #[arg(long = "no-expand")]
+    no_expand: bool,
+
+    /// How `.` and `..` are handled: left to the OS, collapsed textually, or resolved on disk
+    #[arg(long = "normalize-path", value_name = "MODE", value_enum, default_value = "none")]
+    normalize_path: normalize::NormalizeMode,
+
+    /// Refuse paths whose existing parent directories are reached through a symlink
+    #[arg(long = "no-symlinked-parents")]
+    no_symlinked_parents: bool,
+
+    /// Never follow symlinks, in parent directories or as the final component (openat2 with RESOLVE_NO_SYMLINKS)
+    #[arg(long = "secure")]
+    secure: bool,
+
+    /// Re-run operations refused with permission denied through sudo (or pkexec) instead of aborting
+    #[arg(long = "sudo")]
+    sudo: bool,
+
+    /// When run as root, create everything as USER: their uid, gid, groups and login umask
+    #[arg(long = "as-user", value_name = "USER")]
+    as_user: Option<String>,
+
+    /// Retry operations failing with transient errors (EINTR, EAGAIN, ESTALE) up to N times
+    #[arg(long = "retries", value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// Delay before the first retry; doubled after each attempt
+    #[arg(long = "retry-delay", value_name = "DURATION", default_value = "200ms", value_parser = watch::parse_duration)]
+    retry_delay: std::time::Duration,
+
+    /// Language for messages (defaults to the locale from LC_ALL/LC_MESSAGES/LANG)
+    #[arg(long = "lang", value_name = "LANG", value_enum)]
+    lang: Option<i18n::Lang>,
+
+    /// Behave exactly like coreutils: `bank --compat gnu|posix touch|mkdir ARGS...`
+    /// (also enabled when invoked as `touch` or `mkdir`)
+    #[arg(long = "compat", value_name = "STANDARD", value_enum)]
+    compat: Option<compat::Standard>,
+}
+
+#[derive(Subcommand, Clone)]
+enum Command {
+    /// Create or update paths to match a manifest, after showing the planned changes
+    Apply(apply::ApplyArgs),
+    /// Check a tree against a permissions policy and report violations, changing nothing
+    Audit(audit::AuditArgs),
+    /// Time the creation of a synthetic workload in a few configurations, then remove it
+    Bench(bench::BenchArgs),
+    /// Manage directory bookmarks, used as `@name/path` in path arguments
+    Bookmark(bookmark::BookmarkArgs),
+    /// Recreate the directory hierarchy of SRC under DEST without copying any files
+    CloneStructure(clone::CloneStructureArgs),
+    /// Set each tracked file's mtime to the date of the last commit that changed it
+    GitRestoreTimes(git_times::GitRestoreTimesArgs),
+    /// Install or run a git pre-commit hook that checks staged files against a permissions policy
+    Hook(hook::HookArgs),
+    /// Keep updating FILE's timestamps on an interval until interrupted
+    Keepalive(watch::KeepaliveArgs),
+    /// Create a LICENSE file from a bundled SPDX license text
+    License(license::LicenseArgs),
+    /// Create a project from a scaffold: a directory of templates with {{ variables }}, partials and inheritance
+    New(scaffold::NewArgs),
+    /// Create today's note from a template at a dated location, or print its path if it exists
+    Note(note::NoteArgs),
+    /// Rotate log files: FILE.1 becomes FILE.2 and so on, and FILE starts over empty with its old mode and owner
+    Rotate(rotate::RotateArgs),
+    /// Print the JSON Schema of the manifest format, for editors and tools that write manifests
+    Schema(schema::SchemaArgs),
+    /// Populate a user's home directory from a skeleton like /etc/skel, owned by them and with their umask
+    Skel(skel::SkelArgs),
+    /// Describe an existing tree as a manifest that bank apply can lay out again
+    Snapshot(snapshot::SnapshotArgs),
+    /// Copy each file's timestamps from its counterpart at the same relative path in another tree
+    SyncTimes(sync_times::SyncTimesArgs),
+    /// Revert the most recent run, or every run of a --session, using the undo journal
+    Undo(journal::UndoArgs),
+    /// Replay the most recently reverted run, or every reverted run of a --session
+    Redo(journal::RedoArgs),
+    /// Touch a target file whenever watched paths change
+    Trigger(watch::TriggerArgs),
+    /// Create an application's config, data, cache and state directories in the platform's standard locations
+    Xdg(xdg::XdgArgs),
+}
+
+/// When `-i` prompts
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Interactive {
+    /// Only ask whether ambiguous paths are files or directories
+    Ambiguous,
+    /// Confirm each creation or update, like `rm -i`
+    Always,
+}
+
+/// What to do with a path that already exists
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OnExists {
+    /// Keep it and only apply the requested attributes
+    Skip,
+    /// Replace an existing file (or symlink) with a fresh one; directories are never removed
+    Overwrite,
 }
 
 #[derive(Debug)]
@@ -81,261 +595,2126 @@ struct TimeSpec {
     modification_time: Option<SystemTime>,
 }
 
+impl TimeSpec {
+    /// The access and modification times to set; `current` is only asked for
+    /// when one of them is kept
+    fn resolve(&self, current: impl FnOnce() -> Result<vfs::Metadata, BankError>) -> Result<(SystemTime, SystemTime), BankError> {
+        match (self.access_time, self.modification_time) {
+            (Some(accessed), Some(modified)) => Ok((accessed, modified)),
+            (accessed, modified) => {
+                let current = current()?;
+                Ok((accessed.unwrap_or(current.accessed), modified.unwrap_or(current.modified)))
+            }
+        }
+    }
+}
+
+/// What is at a path before bank acts on it. Looked up once per path (one
+/// `statx` on Linux, two for symlinks) and handed to each step, instead of
+/// every step asking the filesystem again
+#[derive(Debug, Clone, Default)]
+struct Existing {
+    /// What `stat` reports, following symlinks; `None` if nothing resolves there
+    target: Option<vfs::Metadata>,
+    /// Whether the path itself is a symlink, possibly a dangling one
+    symlink: bool,
+}
+
+impl Existing {
+    fn lookup(fs: &dyn Filesystem, path: &Path) -> Self {
+        match fs.symlink_metadata(path) {
+            Ok(metadata) if metadata.kind == vfs::FileKind::Symlink => Existing { target: fs.metadata(path).ok(), symlink: true },
+            Ok(metadata) => Existing { target: Some(metadata), symlink: false },
+            Err(_) => Existing::default(),
+        }
+    }
+}
+
+/// Exit status for `--changed-exit-code` runs that found everything up to date
+const UNCHANGED_EXIT_CODE: i32 = 10;
+
 fn main() -> Result<()> {
-    let args = Args::parse();
-    
-    // Validate argument combinations
-    validate_arguments(&args)?;
+    // Compatibility mode has its own, tool-specific flag semantics
+    let argv: Vec<String> = std::env::args_os().map(|arg| arg.to_string_lossy().into_owned()).collect();
+    if let Some(status) = compat::dispatch(&argv) {
+        std::process::exit(status);
+    }
 
-    if args.verbose {
-        println!("{} {}", "Bank".bright_green().bold(), "v0.2.0".cyan());
-        if args.paths.len() > 1 {
-            println!("Processing {} paths...", args.paths.len().to_string().cyan());
+    let mut args = Args::parse();
+    check_features(&args)?;
+    if args.compat.is_some() {
+        anyhow::bail!("--compat must be the first argument: bank --compat gnu|posix touch|mkdir ARGS...");
+    }
+    if let Some(lang) = args.lang {
+        i18n::set_lang(lang);
+    }
+    // A container is one more remote target
+    if let Some(container) = &args.container {
+        args.remote = Some(remote::container_url(container)?);
+    }
+
+    let config = load_config(config::Config::load(), args.allow_anywhere)?;
+    args.default_modes = config.modes;
+    if !args.allow_anywhere {
+        args.allowed_roots = config.allowed_roots;
+    }
+    // A stream keeps no entry per change, so it cannot feed the history
+    if config.history && !args.no_history && !args.stream {
+        args.history = true;
+    }
+
+    if let Some(command) = &args.command {
+        // Subcommands write through the same guardrails as plain paths
+        return run_command(command, &guard::GuardFs::new(&LocalFs, args.allowed_roots.clone(), args.allow_system));
+    }
+
+    if !args.existing_only.is_empty() {
+        expand_existing_only(&LocalFs, &mut args)?;
+    }
+    // --shift is -A with friendlier offsets, over whole trees
+    if let Some(offset) = args.shift {
+        args.adjust = Some(offset);
+        args.recursive = true;
+    }
+    if repairs_times(&args) || times_from_exif(&args) {
+        args.recursive = true;
+    }
+
+    if let Some(file) = &args.policy {
+        args.default_modes.extend(policy::Policy::load(file)?.default_modes());
+    }
+
+    // Streamed paths are read one at a time while processing; until then
+    // the steps below see no paths
+    let streamed = if args.stream { Some(stream::open(&mut args)?) } else { None };
+
+    let from_stdin = args.paths.iter().any(|p| p == "-");
+    if from_stdin {
+        read_stdin_paths(&mut args)?;
+    }
+    // A list file adds paths as if they had been given as arguments, except
+    // that a NUL-separated one holds names as find printed them, which are
+    // added after the expansions below
+    let list_file = args.from_file.clone().map(|file| (file, false)).or_else(|| args.from_file0.clone().map(|file| (file, true)));
+    let mut literal = Vec::new();
+    if let Some((file, nul)) = list_file {
+        if from_stdin && file == Path::new("-") {
+            anyhow::bail!("Cannot read both a - path argument and the --from-file list from stdin");
+        }
+        let listed = read_list_file(&file, nul)?;
+        match nul {
+            true => literal = listed,
+            false => args.paths.extend(listed),
         }
     }
 
-    // Process each path
-    for path_str in &args.paths {
-        process_single_path(path_str, &args)?;
+    // Like a shell: braces before --count numbers and variables
+    if !args.no_expand {
+        args.paths = args.paths.iter().map(|path_str| expand::expand_braces(path_str)).collect::<Result<Vec<_>>>()?.concat();
     }
 
-    Ok(())
-}
+    if let Some(count) = args.count {
+        args.paths = sequence::expand(&args.paths, &args.seq_format, args.seq_start, count).map_err(anyhow::Error::msg)?;
+    }
 
-/// Validate argument combinations
-fn validate_arguments(args: &Args) -> Result<()> {
-    // Check for conflicting directory/file flags
-    if args.directory && args.file {
-        anyhow::bail!("Cannot specify both --directory and --file flags");
+    // Batch rows follow the positional paths; their attributes are looked up by position
+    let mut batch_start = args.paths.len();
+    let mut batch_rows = match &args.batch_file {
+        Some(file) if from_stdin => anyhow::bail!("Cannot read paths from stdin together with --batch-file {}", file.display()),
+        Some(file) => batch::load(file)?,
+        None => Vec::new(),
+    };
+    // Inline specs are batch rows given on the command line
+    for spec in &args.specs {
+        batch_rows.push(batch::parse_spec(spec)?);
     }
-    
-    // Check for conflicting time specification flags  
-    let time_sources = [args.date.is_some(), args.timestamp.is_some(), args.reference.is_some()];
-    let time_source_count = time_sources.iter().filter(|&&x| x).count();
-    if time_source_count > 1 {
-        anyhow::bail!("Cannot specify multiple time sources (--date, --timestamp, --reference)");
+    args.paths.extend(batch_rows.iter().map(|row| row.path.clone()));
+
+    if !args.no_expand {
+        expand_arguments(&mut args)?;
     }
-    
-    // Check for conflicting access/modification time flags
-    if args.access_time_only && args.modification_time_only {
-        anyhow::bail!("Cannot specify both --atime and --mtime flags");
+    args.paths.extend(literal);
+
+    // Archive entries are processed like extra path arguments
+    if let Some(archive_path) = args.from_archive.clone() {
+        args.paths.extend(archive::list_entries(&archive_path)?);
+        args.parents = true;
     }
-    
-    Ok(())
-}
 
-fn process_single_path(path_str: &str, args: &Args) -> Result<()> {
-    let path = PathBuf::from(path_str);
-    
-    // Parse custom timestamp if provided
-    let custom_time = parse_timestamp(args)?;
-    
-    // Check no-create mode
-    if args.no_create {
-        if !path.exists() {
-            if args.verbose {
-                println!("Skipping non-existent path in no-create mode: {}", path.display().to_string().yellow());
-            }
-            return Ok(());
+    // So are the entries of a tree drawing, which lists every parent itself
+    if let Some(tree_file) = &args.from_tree {
+        args.paths.extend(tree::load(tree_file)?);
+    }
+    if let Some(outline_file) = &args.from_outline {
+        args.paths.extend(outline::load(outline_file)?);
+    }
+
+    if let Some(base) = args.relative_to.clone() {
+        apply_base_directory(&LocalFs, &base, &mut args)?;
+    }
+
+    // Each target once, before --under multiplies them
+    let requests = conflict::Requests { paths: &mut args.paths, rows: &mut batch_rows, batch_start: &mut batch_start };
+    for warning in conflict::resolve(requests, args.on_conflict)? {
+        outln!("{} {}", tr("warning", &[]).yellow(), warning);
+    }
+
+    // Which --under root each path belongs to
+    let root_of = if args.under.is_empty() {
+        Vec::new()
+    } else {
+        replicate_under(&mut args, &mut batch_rows, &mut batch_start)?
+    };
+
+    if args.normalize_path != normalize::NormalizeMode::None {
+        for path_str in args.paths.iter_mut() {
+            *path_str = normalize::normalize_path(&LocalFs, path_str, args.normalize_path)?;
         }
-        
-        // Only update timestamps for existing files/directories
-        let time_spec = get_time_spec(args, custom_time)?;
-        set_file_times(&path, &time_spec, args)?;
-        
-        if args.verbose {
-            println!("{} Updated timestamps: {}", "✓".bright_green(), path.display().to_string().green());
-        } else if args.paths.len() > 1 {
-            println!("{} {}", "✓".bright_green(), path.display().to_string().green());
+    }
+
+    if args.no_symlinked_parents {
+        for path_str in &args.paths {
+            normalize::reject_symlinked_parents(&LocalFs, path_str)?;
         }
-        return Ok(());
+    }
+
+    if let Some(root) = args.root.clone() {
+        confine_paths(&root, &mut args)?;
     }
     
-    // Determine what to create
-    let creation_type = determine_creation_type(args, &path, path_str)?;
-    
-    if args.verbose {
-        match creation_type {
-            CreationType::File => println!("Creating file: {}", path.display().to_string().yellow()),
-            CreationType::Directory => println!("Creating directory: {}", path.display().to_string().yellow()),
-        }
+    // Validate argument combinations
+    validate_arguments(&args)?;
+
+    // Offer remembered places for relative paths whose parent is missing here
+    if !args.parents && args.history && args.root.is_none() && args.remote.is_none() && args.into_archive.is_none() {
+        suggest_destinations(&LocalFs, &mut args)?;
     }
 
-    // Create parents if needed
-    if args.parents {
-        if let Some(parent) = path.parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent)
-                    .with_context(|| format!("Failed to create parent directories for {}", path.display()))?;
-                if args.verbose {
-                    println!("Created parent directories: {}", parent.display().to_string().green());
-                }
-            }
+    if args.verbose {
+        outln!("{} {}", "Bank".bright_green().bold(), "v0.2.0".cyan());
+        if args.paths.len() > 1 {
+            outln!("{}", tr("processing-paths", &[("count", &args.paths.len().to_string().cyan().to_string())]));
         }
     }
 
-    // Create the target
-    match creation_type {
-        CreationType::File => create_file(&path, args)?,
-        CreationType::Directory => create_directory(&path, args)?,
+    // Let the user pick from a piped list instead of accepting all of it
+    if from_stdin && args.interactive == Some(Interactive::Ambiguous) && !args.paths.is_empty() {
+        select_paths(&mut args)?;
     }
 
-    // Set custom timestamps if specified
-    if custom_time.is_some() || args.access_time_only || args.modification_time_only {
-        let time_spec = get_time_spec(args, custom_time)?;
-        set_file_times(&path, &time_spec, args)?;
+    if args.sanitize || args.sanitize_ascii {
+        sanitize_paths(&LocalFs, &mut args);
     }
 
-    // Set permissions if specified
-    if let Some(mode_str) = &args.mode {
-        set_permissions(&path, mode_str, args.verbose)?;
+    for path_str in &args.paths {
+        check_target(&args, path_str)?;
     }
 
-    if args.verbose {
-        println!("{} Created: {}", "✓".bright_green(), path.display().to_string().green());
-    } else if args.paths.len() > 1 {
-        // Show minimal progress for multiple files when not verbose
-        println!("{} {}", "✓".bright_green(), path.display().to_string().green());
+    // Check names before anything is created
+    if let Some(check) = args.check {
+        check_names(&LocalFs, &args, check)?;
     }
 
-    Ok(())
-}
+    if let Some(action) = args.detect_case_collisions {
+        check_case_collisions(&LocalFs, &args, action)?;
+    }
 
-fn determine_creation_type(args: &Args, path: &Path, path_str: &str) -> Result<CreationType> {
-    // Explicit flags take precedence
-    if args.directory {
-        return Ok(CreationType::Directory);
+    if args.dry_run {
+        return dry_run(&LocalFs, &args, &batch_rows, batch_start);
     }
 
-    if args.file {
-        return Ok(CreationType::File);
+    if let Some(script_path) = &args.emit_script {
+        return emit_script(script_path, &args, &batch_rows, batch_start);
     }
 
-    // Check if path already exists
-    if path.exists() {
-        if path.is_dir() {
-            return Ok(CreationType::Directory);
-        } else {
-            return Ok(CreationType::File);
-        }
+    if let Some(manifest::EmitFormat::Ansible) = args.emit {
+        print!("{}", planned_manifest(&LocalFs, &args, &batch_rows, batch_start)?.to_ansible());
+        return Ok(());
     }
 
-    // Heuristics for ambiguous paths
-    if let Some(extension) = path.extension() {
-        if !extension.is_empty() {
-            return Ok(CreationType::File);
+    if let Some(threshold) = confirm_threshold(&args)? {
+        confirm_batch(&args, threshold)?;
+    }
+    if args.remote.is_none() && args.into_archive.is_none() {
+        if let Some(invoker) = invoking_uid() {
+            confirm_foreign_parents(&LocalFs, &args.paths, &args, invoker)?;
         }
     }
 
-    // Path ends with separator -> directory
-    if path_str.ends_with('/') || path_str.ends_with('\\') {
-        return Ok(CreationType::Directory);
+    if let Some(archive_path) = &args.into_archive {
+        return write_into_archive(archive_path, &args);
     }
 
-    // Interactive mode or auto-detection
-    if args.interactive {
-        let choices = vec!["File", "Directory"];
-        let selection = Select::with_theme(&ColorfulTheme::default())
-            .with_prompt(format!("What should '{}' be?", path.display()))
-            .items(&choices)
-            .default(0)
-            .interact()?;
+    if let Some(url) = &args.remote {
+        return remote::run(url, &args, parse_timestamp(&LocalFs, &args)?);
+    }
 
-        match selection {
-            0 => Ok(CreationType::File),
-            1 => Ok(CreationType::Directory),
-            _ => unreachable!(),
+    // Process each path
+    let direct: &(dyn Filesystem + Sync) = if args.secure { &secure::SecureFs } else { &LocalFs };
+    let local: &dyn Filesystem = direct;
+    // Offered interactively even without --sudo; --secure never hands paths to other tools
+    let sudo_fs = sudo::SudoFs::new(local, args.sudo, args.verbose);
+    let local: &dyn Filesystem = if args.secure { local } else { &sudo_fs };
+    let retry_fs = vfs::RetryFs::new(local, args.retries, args.retry_delay);
+    let fs = vfs::RecordingFs::new(&retry_fs);
+    // Recording costs memory per change, which a long stream should not pay for nothing
+    let keeps = matches!(args.keep.resolved(), Ok(Some(_)));
+    let records = !args.no_journal || args.history || args.emit_manifest.is_some() || args.changed_exit_code || keeps;
+    let target: &dyn Filesystem = if records { &fs } else { &retry_fs };
+    let mut prompt = OperationPrompt::new(args.interactive == Some(Interactive::Always));
+    let as_user = args.as_user.as_deref().map(owner::become_user).transpose()?;
+    if let (Some(identity), true) = (&as_user, args.verbose) {
+        let umask = format!("{:04o}", identity.umask);
+        outln!("{}", tr("running-as", &[("user", &owner::describe(identity.uid, identity.gid).cyan().to_string()), ("umask", &umask)]));
+    }
+    // A stream's paths are only known as they are read: the first that goes
+    // into someone else's directory asks for all of them
+    let mut unconfirmed_foreign = if args.stream { invoking_uid() } else { None };
+    let mut process = |path_str: &str, row: Option<&batch::BatchRow>| -> Result<()> {
+        if let Some(invoker) = unconfirmed_foreign {
+            if confirm_foreign_parents(target, &[path_str.to_string()], &args, invoker)? {
+                unconfirmed_foreign = None;
+            }
+        }
+        if !prompt.confirm(target, path_str)? {
+            return Ok(());
         }
+        if let Some(row) = row {
+            process_batch_row(target, path_str, row, &args)
+        } else if args.recursive && target.is_dir(Path::new(path_str)) {
+            touch_tree(target, path_str, &args)
+        } else {
+            Ok(process_single_path(target, path_str, &args)?)
+        }
+    };
+    let batch_row = |index: usize| index.checked_sub(batch_start).and_then(|row| batch_rows.get(row));
+    let order = processing_order(&args);
+    report::start_buffering();
+    let result = if let Some(entries) = streamed {
+        stream::process(&LocalFs, entries, &args, &mut process)
+    } else if args.jobs > 1 {
+        process_parallel(direct, &fs, &order, &args, &batch_row)
+    } else if root_of.is_empty() {
+        order.iter().try_for_each(|&index| process(&args.paths[index], batch_row(index)))
     } else {
-        // Default to file for ambiguous cases
-        Ok(CreationType::File)
-    }
-}
+        process_under(target, &args.under, &root_of, &order, &args.paths, &mut |index, path_str| process(path_str, batch_row(index)))
+    };
+    // Once every path exists, so a directory that got children later in the run gets none
+    let result = result.and_then(|()| Ok(add_placeholders(&fs, &args)?));
+    report::stop_buffering();
 
-fn create_file(path: &Path, args: &Args) -> Result<()> {
-    if path.exists() {
+    // Back to root, so the manifest and journal belong to whoever ran bank
+    drop(as_user);
+
+    // Written even after a failure, so the manifest shows how far the run got
+    if let Some(manifest_path) = &args.emit_manifest {
+        let mut manifest = manifest::Manifest::from_changes(&fs, &fs.changes());
+        if let Some(algorithm) = args.hash {
+            manifest.add_hashes(algorithm)?;
+        }
+        manifest.write(manifest_path)?;
         if args.verbose {
-            println!("File already exists: {}", path.display().to_string().yellow());
+            let count = manifest.entries.len().to_string().cyan().to_string();
+            outln!("{}", tr("wrote-manifest", &[("count", &count), ("path", &manifest_path.display().to_string().green().to_string())]));
         }
-        // Don't update timestamps here - will be handled by set_file_times if needed
-    } else {
-        fs::File::create(path)
-            .with_context(|| format!("Failed to create file {}", path.display()))?;
+    }
+
+    if !args.no_journal {
+        if let Err(err) = journal::record(&fs, args.session.as_deref()) {
+            outln!("{} {:#}", tr("warning", &[]).yellow(), err);
+        }
+    }
+
+    if args.history {
+        remember_places(&fs);
+    }
+
+    result?;
+    if args.changed_exit_code && !fs.changed() {
+        std::process::exit(UNCHANGED_EXIT_CODE);
+    }
+    Ok(())
+}
+
+/// The configuration to run with. A broken config file stops the run, since
+/// ignoring it would also drop its `allowed-roots`; with --allow-anywhere
+/// those do not apply, so then it is only pointed out.
+fn load_config(loaded: Result<config::Config>, allow_anywhere: bool) -> Result<config::Config> {
+    match loaded {
+        Ok(config) => Ok(config),
+        Err(err) if allow_anywhere => {
+            outln!("{} {:#}; ignoring it", tr("warning", &[]).yellow(), err);
+            Ok(config::Config::default())
+        }
+        Err(err) => Err(err).context("Refusing to run without the allowed-roots of a broken config file; fix it or pass --allow-anywhere"),
+    }
+}
+
+/// Dispatch a subcommand; `guarded` is for the ones that change paths
+fn run_command(command: &Command, guarded: &dyn Filesystem) -> Result<()> {
+    match command {
+        Command::Apply(apply_args) => {
+            let changed = apply::run(guarded, apply_args)?;
+            if apply_args.changed_exit_code && !changed {
+                std::process::exit(UNCHANGED_EXIT_CODE);
+            }
+            Ok(())
+        }
+        Command::Audit(audit_args) => audit::run(&LocalFs, audit_args),
+        Command::Bench(bench_args) => bench::run(&LocalFs, bench_args),
+        Command::Bookmark(bookmark_args) => bookmark::run(&LocalFs, bookmark_args),
+        Command::CloneStructure(clone_args) => clone::run(guarded, clone_args),
+        Command::GitRestoreTimes(git_args) => git_times::run(guarded, git_args),
+        Command::Hook(hook_args) => hook::run(guarded, hook_args),
+        Command::Keepalive(keepalive_args) => watch::run_keepalive(guarded, keepalive_args),
+        Command::License(license_args) => license::run(guarded, license_args),
+        Command::New(new_args) => scaffold::run(guarded, new_args),
+        Command::Note(note_args) => note::run(guarded, note_args),
+        Command::Rotate(rotate_args) => rotate::run(guarded, rotate_args),
+        Command::Schema(schema_args) => schema::run(schema_args),
+        Command::Skel(skel_args) => skel::run(guarded, skel_args),
+        Command::Snapshot(snapshot_args) => snapshot::run(&LocalFs, snapshot_args),
+        Command::SyncTimes(sync_args) => sync_times::run(guarded, sync_args),
+        Command::Trigger(trigger_args) => watch::run_trigger(guarded, trigger_args),
+        Command::Undo(undo_args) => journal::run_undo(guarded, undo_args),
+        Command::Redo(redo_args) => journal::run_redo(guarded, redo_args),
+        Command::Xdg(xdg_args) => xdg::run(guarded, xdg_args),
+    }
+}
+
+/// Validate argument combinations
+fn validate_arguments(args: &Args) -> Result<()> {
+    // Check for conflicting directory/file flags
+    if args.directory && args.file {
+        anyhow::bail!("Cannot specify both --directory and --file flags");
+    }
+    
+    // Check for conflicting time specification flags  
+    let time_sources = [args.date.is_some(), args.timestamp.is_some(), args.reference.is_some()];
+    let time_source_count = time_sources.iter().filter(|&&x| x).count();
+    if time_source_count > 1 {
+        anyhow::bail!("Cannot specify multiple time sources (--date, --timestamp, --reference)");
+    }
+    
+    // Check for conflicting access/modification time flags
+    if args.access_time_only && args.modification_time_only {
+        anyhow::bail!("Cannot specify both --atime and --mtime flags");
+    }
+
+    if args.into_archive.is_some() && args.no_create {
+        anyhow::bail!("Cannot combine --into-archive with --no-create");
+    }
+
+    if args.parents_mode.is_some() && !args.parents {
+        anyhow::bail!("--parents-mode requires --parents");
+    }
+    if args.recursive && (args.remote.is_some() || args.into_archive.is_some() || args.mode.is_some() || args.executable || args.private || args.public_read) {
+        anyhow::bail!("--recursive only updates timestamps of local files and cannot be combined with --remote, --into-archive, --mode, --executable, --private or --public-read");
+    }
+    if args.inherit_owner && (args.remote.is_some() || args.into_archive.is_some() || args.emit_script.is_some()) {
+        anyhow::bail!("--inherit-owner only works on local paths and cannot be combined with --remote, --into-archive or --emit-script");
+    }
+    if (args.private || args.public_read) && args.parents && (args.remote.is_some() || args.emit_script.is_some()) {
+        anyhow::bail!("--private and --public-read cannot set the mode of -p parents with --remote or --emit-script, whose commands only find out which parents are missing when they run");
+    }
+    if args.adjust.is_some() && (args.remote.is_some() || args.into_archive.is_some()) {
+        anyhow::bail!("--adjust only works on existing local files");
+    }
+    if (args.atime_date.is_some() || args.mtime_date.is_some()) && args.remote.is_some() {
+        anyhow::bail!("--atime-date and --mtime-date are not supported with --remote");
+    }
+    if args.parents_mode.is_some() && args.remote.is_some() {
+        anyhow::bail!("--parents-mode is not supported with --remote");
+    }
+
+    if args.trash && args.on_exists != OnExists::Overwrite {
+        anyhow::bail!("--trash only applies to files replaced by --on-exists overwrite");
+    }
+    if args.on_exists == OnExists::Overwrite && (args.no_create || args.remote.is_some() || args.into_archive.is_some()) {
+        anyhow::bail!("--on-exists overwrite replaces local files and cannot be combined with --no-create, --remote or --into-archive");
+    }
+    if args.emit_manifest.is_some() && (args.remote.is_some() || args.into_archive.is_some()) {
+        anyhow::bail!("--emit-manifest records local changes and cannot be combined with --remote or --into-archive");
+    }
+    if args.dry_run
+        && (args.recursive
+            || args.adjust.is_some()
+            || args.on_exists == OnExists::Overwrite
+            || args.interactive.is_some()
+            || args.emit_manifest.is_some()
+            || args.remote.is_some()
+            || args.into_archive.is_some())
+    {
+        anyhow::bail!("--dry-run plans local creation and cannot be combined with -R, -A, --on-exists overwrite, -i, --emit-manifest, --remote or --into-archive");
+    }
+    if (args.emit_script.is_some() || args.emit.is_some())
+        && (args.dry_run
+            || args.recursive
+            || args.adjust.is_some()
+            || args.append.is_some()
+            || args.truncate
+            || args.on_exists == OnExists::Overwrite
+            || args.parents_mode.is_some()
+            || args.atime_date.is_some()
+            || args.mtime_date.is_some()
+            || args.emit_manifest.is_some()
+            || args.remote.is_some()
+            || args.into_archive.is_some())
+    {
+        anyhow::bail!("--emit-script and --emit describe the run for other tools and cannot be combined with --dry-run, -R, -A, --append, --truncate, --on-exists overwrite, --parents-mode, --atime-date, --mtime-date, --emit-manifest, --remote or --into-archive");
+    }
+    if args.changed_exit_code && (args.remote.is_some() || args.into_archive.is_some()) {
+        anyhow::bail!("--changed-exit-code tracks local changes and cannot be combined with --remote or --into-archive");
+    }
+    if !args.under.is_empty() && (args.remote.is_some() || args.into_archive.is_some()) {
+        anyhow::bail!("--under creates local paths and cannot be combined with --remote or --into-archive");
+    }
+    if (args.batch_file.is_some() || !args.specs.is_empty()) && (args.remote.is_some() || args.into_archive.is_some()) {
+        anyhow::bail!("--batch-file and --spec only create local paths and cannot be combined with --remote or --into-archive");
+    }
+
+    if args.append.is_some()
+        && (args.directory || args.recursive || args.adjust.is_some() || args.dry_run || args.remote.is_some() || args.into_archive.is_some())
+    {
+        anyhow::bail!("--append writes to local files and cannot be combined with -d, -R, -A, --dry-run, --remote or --into-archive");
+    }
+    if args.truncate
+        && (args.directory || args.recursive || args.adjust.is_some() || args.dry_run || args.remote.is_some() || args.into_archive.is_some())
+    {
+        anyhow::bail!("--truncate empties local files and cannot be combined with -d, -R, -A, --dry-run, --remote or --into-archive");
+    }
+
+    if args.secure && (args.follow_symlinks || args.remote.is_some() || args.into_archive.is_some()) {
+        anyhow::bail!("--secure never follows symlinks on local paths and cannot be combined with --follow-symlinks, --remote or --into-archive");
+    }
+
+    if args.sudo && (args.secure || args.remote.is_some() || args.into_archive.is_some()) {
+        anyhow::bail!("--sudo escalates local operations and cannot be combined with --secure, --remote or --into-archive");
+    }
+
+    if args.as_user.is_some() && (args.sudo || args.remote.is_some() || args.into_archive.is_some()) {
+        anyhow::bail!("--as-user switches the local user and cannot be combined with --sudo, --remote or --into-archive");
+    }
+
+    args.keep.resolved()?;
+    if args.keep.keep_file.is_some() && (args.remote.is_some() || args.into_archive.is_some()) {
+        anyhow::bail!("--keep-file adds files to local directories and cannot be combined with --remote or --into-archive");
+    }
+
+    if args.interactive == Some(Interactive::Always) && args.into_archive.is_some() {
+        anyhow::bail!("--interactive=always cannot be combined with --into-archive");
+    }
+    if args.remote.is_some() && (args.into_archive.is_some() || args.interactive.is_some()) {
+        anyhow::bail!("--remote cannot be combined with --into-archive or --interactive");
+    }
+    
+    Ok(())
+}
+
+/// Resolve path arguments against the `--relative-to` base without changing the process cwd
+fn apply_base_directory(fs: &dyn Filesystem, base: &Path, args: &mut Args) -> Result<()> {
+    let local = args.remote.is_none() && args.into_archive.is_none();
+    if local && !args.parents && !fs.is_dir(base) {
+        anyhow::bail!("Base directory does not exist: {}", base.display());
+    }
+
+    for path_str in args.paths.iter_mut() {
+        *path_str = join_base(base, path_str);
+    }
+    Ok(())
+}
+
+/// Join a path argument onto a base directory, keeping a trailing separator
+/// (which marks directories); absolute arguments are left as they are
+fn join_base(base: &Path, path_str: &str) -> String {
+    let mut joined = base.join(path_str).to_string_lossy().into_owned();
+    if path_str.ends_with('/') && !joined.ends_with('/') {
+        joined.push('/');
+    }
+    joined
+}
+
+/// Repeat every path beneath each `--under` root, keeping batch rows in one
+/// block after the other paths; returns the root index of each path
+fn replicate_under(args: &mut Args, batch_rows: &mut Vec<batch::BatchRow>, batch_start: &mut usize) -> Result<Vec<usize>> {
+    if let Some(path_str) = args.paths.iter().find(|path_str| Path::new(path_str).is_absolute()) {
+        anyhow::bail!("--under creates the same relative paths beneath each root; {} is absolute", path_str);
+    }
+    let row_paths = &args.paths[*batch_start..*batch_start + batch_rows.len()];
+    // Archive entries follow the batch rows but are plain paths
+    let plain: Vec<&String> = args.paths[..*batch_start].iter().chain(&args.paths[*batch_start + batch_rows.len()..]).collect();
+
+    let (mut paths, mut rows, mut root_of) = (Vec::new(), Vec::new(), Vec::new());
+    for (index, root) in args.under.iter().enumerate() {
+        paths.extend(plain.iter().map(|path_str| join_base(root, path_str)));
+        root_of.extend(std::iter::repeat_n(index, plain.len()));
+    }
+    for (index, root) in args.under.iter().enumerate() {
+        for (row, path_str) in batch_rows.iter().zip(row_paths) {
+            let path = join_base(root, path_str);
+            paths.push(path.clone());
+            rows.push(batch::BatchRow { path, ..row.clone() });
+            root_of.push(index);
+        }
+    }
+    *batch_start = paths.len() - rows.len();
+    *batch_rows = rows;
+    args.paths = paths;
+    Ok(root_of)
+}
+
+/// Process the paths root by root, carrying on past a failed root and
+/// reporting how each one went. Roots are never created, even with `-p`: an
+/// unmounted mount point must not quietly receive the files
+fn process_under(
+    fs: &dyn Filesystem,
+    roots: &[PathBuf],
+    root_of: &[usize],
+    order: &[usize],
+    paths: &[String],
+    process: &mut dyn FnMut(usize, &String) -> Result<()>,
+) -> Result<()> {
+    let mut failed = 0;
+    for (root_index, root) in roots.iter().enumerate() {
+        let mut done = 0;
+        let result = if !fs.is_dir(root) {
+            Err(anyhow::anyhow!(tr("under-root-missing", &[])))
+        } else {
+            order.iter().filter(|&&index| root_of[index] == root_index).try_for_each(|&index| {
+                process(index, &paths[index])?;
+                done += 1;
+                Ok(())
+            })
+        };
+        let root_str = root.display().to_string();
+        match result {
+            Ok(()) => {
+                let count = done.to_string().cyan().to_string();
+                outln!("{} {}", "✓".bright_green(), tr("under-root-done", &[("root", &root_str.green().to_string()), ("count", &count)]));
+            }
+            Err(err) => {
+                failed += 1;
+                let error = format!("{:#}", err);
+                outln!("{} {}", "✗".red(), tr("under-root-failed", &[("root", &root_str.red().to_string()), ("error", &error)]));
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!(tr("under-roots-failed", &[("failed", &failed.to_string()), ("total", &roots.len().to_string())]));
+    }
+    Ok(())
+}
+
+/// Process the paths on `--jobs` threads (see `schedule`). Each path is
+/// recorded on its own and added to `fs` in processing order, which puts
+/// parents before their children as the journal expects. Nothing is offered
+/// to sudo, which would prompt from several threads at once.
+fn process_parallel<'a>(
+    direct: &(dyn Filesystem + Sync),
+    fs: &vfs::RecordingFs,
+    order: &[usize],
+    args: &Args,
+    batch_row: &(dyn Fn(usize) -> Option<&'a batch::BatchRow> + Sync),
+) -> Result<()> {
+    let dependencies = schedule::dependencies(order.iter().map(|&index| args.paths[index].as_str()));
+    let logs = std::sync::Mutex::new(Vec::new());
+    // Each path's report lines wait for those of the paths before it, so the
+    // output matches a run without -j
+    let reported = std::sync::Mutex::new((0, BTreeMap::<usize, Vec<String>>::new()));
+    let result = schedule::run(&dependencies, args.jobs as usize, &|position| {
+        let index = order[position];
+        let path_str = &args.paths[index];
+        let retry_fs = vfs::RetryFs::new(direct, args.retries, args.retry_delay);
+        let recording = vfs::RecordingFs::new(&retry_fs);
+        let (result, lines) = report::capture(|| {
+            if let Some(row) = batch_row(index) {
+                process_batch_row(&recording, path_str, row, args)
+            } else if args.recursive && recording.is_dir(Path::new(path_str)) {
+                touch_tree(&recording, path_str, args)
+            } else {
+                Ok(process_single_path(&recording, path_str, args)?)
+            }
+        });
+        logs.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push((position, recording.into_log()));
+        let mut reported = reported.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (next, pending) = &mut *reported;
+        pending.insert(position, lines);
+        while let Some(lines) = pending.remove(next) {
+            report::lines(&lines);
+            *next += 1;
+        }
+        result
+    });
+    // A failure stops the run and leaves gaps; print what did run
+    let (_, pending) = reported.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for lines in pending.values() {
+        report::lines(lines);
+    }
+    let mut logs = logs.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+    logs.sort_by_key(|(position, _)| *position);
+    for (_, log) in logs {
+        fs.absorb(log);
+    }
+    result
+}
+
+/// The order in which the paths are processed and reported: sorted component
+/// by component, so each parent comes before its children and the output is
+/// the same from run to run whatever order the paths arrived in. The sort is
+/// stable, so repeated paths keep their relative order; `--unordered` keeps
+/// the order they were given in
+fn processing_order(args: &Args) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..args.paths.len()).collect();
+    if !args.unordered {
+        order.sort_by(|&a, &b| Path::new(&args.paths[a]).cmp(Path::new(&args.paths[b])));
+    }
+    order
+}
+
+/// Replace a `-` argument with the paths piped in on stdin
+fn read_stdin_paths(args: &mut Args) -> Result<()> {
+    let piped = read_path_list(std::io::stdin().lock())?;
+    let mut paths = Vec::with_capacity(args.paths.len() + piped.len());
+    for path_str in args.paths.drain(..) {
+        if path_str == "-" {
+            paths.extend(piped.iter().cloned());
+        } else {
+            paths.push(path_str);
+        }
+    }
+    args.paths = paths;
+    Ok(())
+}
+
+/// One path per line; blank lines are skipped and CRLF endings tolerated
+fn read_path_list(reader: impl BufRead) -> Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for line in reader.lines() {
+        let line = line.context("Failed to read paths from stdin")?;
+        let line = line.trim_end_matches('\r');
+        if !line.trim().is_empty() {
+            paths.push(line.to_string());
+        }
+    }
+    Ok(paths)
+}
+
+/// The paths listed in `file` (`-` for stdin): one per line with blank lines
+/// and `#` comments skipped, or NUL-separated
+fn read_list_file(file: &Path, nul: bool) -> Result<Vec<String>> {
+    let bytes = if file == Path::new("-") {
+        let mut bytes = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut bytes).context("Failed to read paths from stdin")?;
+        bytes
+    } else {
+        std::fs::read(file).map_err(|err| BankError::io("read", file, err))?
+    };
+    let text = String::from_utf8(bytes).map_err(|_| anyhow::anyhow!("{} is not valid UTF-8", file.display()))?;
+    Ok(parse_list(&text, nul))
+}
+
+fn parse_list(text: &str, nul: bool) -> Vec<String> {
+    if nul {
+        // Names may start with # or hold newlines here, so only empty ones are dropped
+        return text.split('\0').filter(|path| !path.is_empty()).map(str::to_string).collect();
+    }
+    text.lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Show the candidate paths as a checklist and keep only the ticked ones
+fn select_paths(args: &mut Args) -> Result<()> {
+    let defaults = vec![true; args.paths.len()];
+    // dialoguer reads keys from the terminal, not from the (piped) stdin
+    let chosen = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(tr("select-paths", &[]))
+        .items(&args.paths)
+        .defaults(&defaults)
+        .interact()
+        .context("Interactive selection needs a terminal")?;
+
+    let selected: HashSet<usize> = chosen.into_iter().collect();
+    args.paths = std::mem::take(&mut args.paths)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, path)| selected.contains(&i).then_some(path))
+        .collect();
+    if args.paths.is_empty() && args.verbose {
+        outln!("{}", tr("no-paths-selected", &[]).yellow());
+    }
+    Ok(())
+}
+
+/// Environment variable with the default `--confirm-over` threshold
+const CONFIRM_OVER_ENV: &str = "BANK_CONFIRM_OVER";
+
+/// How many paths `confirm_batch` lists before summarising the rest
+const CONFIRM_PREVIEW: usize = 10;
+
+/// `--confirm-over`, falling back to `$BANK_CONFIRM_OVER`
+fn confirm_threshold(args: &Args) -> Result<Option<usize>> {
+    if args.confirm_over.is_some() {
+        return Ok(args.confirm_over);
+    }
+    match std::env::var(CONFIRM_OVER_ENV) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .with_context(|| format!("Invalid {}: {}", CONFIRM_OVER_ENV, value)),
+        _ => Ok(None),
+    }
+}
+
+/// The paths that lead outside `cwd` once `.` and `..` are collapsed
+fn paths_outside(cwd: &Path, paths: &[String]) -> Vec<String> {
+    paths
+        .iter()
+        .filter(|path_str| !normalize::lexical(&cwd.join(path_str)).starts_with(cwd))
+        .cloned()
+        .collect()
+}
+
+/// Ask before creating more than `threshold` paths or any path outside the current directory
+fn confirm_batch(args: &Args, threshold: usize) -> Result<()> {
+    let outside = if args.remote.is_none() && args.into_archive.is_none() {
+        let cwd = std::env::current_dir().context("Failed to read the current directory")?;
+        paths_outside(&cwd, &args.paths)
+    } else {
+        Vec::new()
+    };
+    if args.paths.len() <= threshold && outside.is_empty() {
+        return Ok(());
+    }
+
+    outln!("{}", tr("confirm-summary", &[("count", &args.paths.len().to_string().cyan().to_string())]));
+    print_preview(&args.paths);
+    if !outside.is_empty() {
+        let count = outside.len().to_string().cyan().to_string();
+        outln!("{} {}", tr("warning", &[]).yellow(), tr("confirm-outside", &[("count", &count)]));
+        print_preview(&outside);
+    }
+    if args.yes {
+        return Ok(());
+    }
+
+    let proceed = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(tr("confirm-proceed", &[]))
+        .default(false)
+        .interact()
+        .context("Confirmation needs a terminal; pass --yes to skip it")?;
+    if !proceed {
+        anyhow::bail!("Aborted; nothing was created");
+    }
+    Ok(())
+}
+
+/// The user bank runs for: the effective user, or the one who ran sudo;
+/// `None` for root itself, which owns what matters anyway
+fn invoking_uid() -> Option<libc::uid_t> {
+    // SAFETY: geteuid has no preconditions
+    match unsafe { libc::geteuid() } {
+        0 => std::env::var("SUDO_UID").ok()?.parse().ok().filter(|uid| *uid != 0),
+        uid => Some(uid),
+    }
+}
+
+/// The paths whose nearest existing parent belongs to someone other than
+/// `invoker`, with that parent and its owner. Sticky directories like /tmp
+/// are meant to be shared and left out.
+fn foreign_parents(fs: &dyn Filesystem, paths: &[String], invoker: libc::uid_t) -> Vec<(String, PathBuf, String)> {
+    paths
+        .iter()
+        .filter_map(|path_str| {
+            let (dir, metadata) = nearest_existing_parent(fs, path_str)?;
+            (metadata.uid != invoker && metadata.mode & 0o1000 == 0).then(|| (path_str.clone(), dir, owner::describe(metadata.uid, metadata.gid)))
+        })
+        .collect()
+}
+
+/// The closest directory above `path_str` that exists, with its metadata
+fn nearest_existing_parent(fs: &dyn Filesystem, path_str: &str) -> Option<(PathBuf, vfs::Metadata)> {
+    let mut parent = Path::new(path_str.trim_end_matches('/')).parent();
+    while let Some(dir) = parent {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        if let Ok(metadata) = fs.metadata(dir) {
+            return Some((dir.to_path_buf(), metadata));
+        }
+        parent = dir.parent();
+    }
+    None
+}
+
+/// `--inherit-owner`: the ids a path created at `path_str` gets, from the
+/// directory it is created in
+fn inherited_owner(fs: &dyn Filesystem, path_str: &str) -> Option<(libc::uid_t, libc::gid_t)> {
+    nearest_existing_parent(fs, path_str).map(|(_, metadata)| (metadata.uid, metadata.gid))
+}
+
+/// Give a path bank just created the ids `--inherit-owner` found
+fn set_inherited_owner(fs: &dyn Filesystem, path: &Path, (uid, gid): (libc::uid_t, libc::gid_t), verbose: bool) -> Result<(), BankError> {
+    let metadata = fs.symlink_metadata(path).map_err(|err| BankError::io("read metadata for", path, err))?;
+    if (metadata.uid, metadata.gid) == (uid, gid) {
+        return Ok(());
+    }
+    fs.set_owner(path, Some(uid), Some(gid)).map_err(|err| BankError::io("change owner of", path, err))?;
+    if verbose {
+        outln!("{}", tr("set-owner", &[("owner", &owner::describe(uid, gid).green().to_string()), ("path", &path.display().to_string())]));
+    }
+    Ok(())
+}
+
+/// Ask before writing into directories owned by root or another user, where
+/// mistakes are the hardest to undo. Returns whether any of `paths` does
+fn confirm_foreign_parents(fs: &dyn Filesystem, paths: &[String], args: &Args, invoker: libc::uid_t) -> Result<bool> {
+    let foreign = foreign_parents(fs, paths, invoker);
+    if foreign.is_empty() || args.yes {
+        return Ok(!foreign.is_empty());
+    }
+    let count = foreign.len().to_string().cyan().to_string();
+    outln!("{} {}", tr("warning", &[]).yellow(), tr("confirm-foreign-owner", &[("count", &count)]));
+    let listed: Vec<String> = foreign.iter().map(|(path_str, dir, owner)| format!("{} ({}: {})", path_str, dir.display(), owner)).collect();
+    print_preview(&listed);
+    // Scripts and pipelines get the warning; only a terminal can be asked
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return Ok(true);
+    }
+    report::flush();
+    let proceed = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(tr("confirm-proceed", &[]))
+        .default(false)
+        .interact()
+        .context("Confirmation needs a terminal; pass --yes to skip it")?;
+    if !proceed {
+        anyhow::bail!("Aborted; nothing was created");
+    }
+    Ok(true)
+}
+
+/// Offer to move relative paths whose parent does not exist here to the most
+/// frecent remembered place that has it; only asked on a terminal
+fn suggest_destinations(fs: &dyn Filesystem, args: &mut Args) -> Result<()> {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return Ok(());
+    }
+    let mut places = None;
+    let now = Utc::now().timestamp();
+    for path_str in args.paths.iter_mut() {
+        let path = Path::new(path_str.as_str());
+        let Some(parent) = path.parent().filter(|parent| path.is_relative() && !parent.as_os_str().is_empty()) else {
+            continue;
+        };
+        if fs.exists(parent) {
+            continue;
+        }
+        // Only read once a path needs it
+        let places = places.get_or_insert_with(|| {
+            frecency::load().unwrap_or_else(|err| {
+                outln!("{} {:#}", tr("warning", &[]).yellow(), err);
+                Vec::new()
+            })
+        });
+        let Some(suggestion) = frecency::suggest(places, path, now, &|dir| fs.is_dir(dir)) else {
+            continue;
+        };
+        let mut suggestion = suggestion.to_string_lossy().into_owned();
+        if path_str.ends_with('/') && !suggestion.ends_with('/') {
+            suggestion.push('/');
+        }
+        let accept = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(tr("suggest-destination", &[("parent", &parent.display().to_string()), ("path", &suggestion)]))
+            .default(false)
+            .interact()
+            .context("Confirmation needs a terminal")?;
+        if accept {
+            *path_str = suggestion;
+        }
+    }
+    Ok(())
+}
+
+/// Remember the directories this run created something in, for later suggestions
+fn remember_places(fs: &vfs::RecordingFs) {
+    let result = std::env::current_dir().context("Failed to determine the current directory").and_then(|cwd| {
+        let dirs: Vec<PathBuf> = fs
+            .changes()
+            .into_iter()
+            .filter(|(_, change)| *change == vfs::Change::Created)
+            .filter_map(|(path, _)| path.parent().map(|parent| normalize::lexical(&cwd.join(parent))))
+            .collect();
+        frecency::record(&dirs)
+    });
+    if let Err(err) = result {
+        outln!("{} {:#}", tr("warning", &[]).yellow(), err);
+    }
+}
+
+/// An answer to the `--interactive=always` question
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Answer {
+    Yes,
+    No,
+    /// Yes to this and every remaining path
+    All,
+    /// No to this and every remaining path
+    Quit,
+}
+
+/// Asks before each path with `--interactive=always`, remembering "all" and "quit"
+struct OperationPrompt {
+    enabled: bool,
+    standing: Option<Answer>,
+    tty: Option<std::io::BufReader<std::fs::File>>,
+}
+
+impl OperationPrompt {
+    fn new(enabled: bool) -> Self {
+        Self { enabled, standing: None, tty: None }
+    }
+
+    /// Whether to go ahead with `path_str`
+    fn confirm(&mut self, fs: &dyn Filesystem, path_str: &str) -> Result<bool> {
+        if !self.enabled {
+            return Ok(true);
+        }
+        let answer = match self.standing {
+            Some(answer) => answer,
+            None => {
+                let key = if fs.exists(Path::new(path_str)) { "confirm-update" } else { "confirm-create" };
+                let question = tr(key, &[("path", &path_str.yellow().to_string())]);
+                // Paths may be arriving on stdin, so answers come from the terminal itself
+                let tty = match &mut self.tty {
+                    Some(tty) => tty,
+                    None => {
+                        let tty = std::fs::File::open("/dev/tty").context("--interactive=always needs a terminal")?;
+                        self.tty.insert(std::io::BufReader::new(tty))
+                    }
+                };
+                read_answer(&question, tty)?
+            }
+        };
+        if matches!(answer, Answer::All | Answer::Quit) {
+            self.standing = Some(answer);
+        }
+        Ok(matches!(answer, Answer::Yes | Answer::All))
+    }
+}
+
+/// Ask `question` until a valid answer is given; a blank line means no, end of input quit
+fn read_answer(question: &str, input: &mut dyn BufRead) -> Result<Answer> {
+    report::flush();
+    loop {
+        eprint!("{} [y/N/a/q] ", question);
+        let mut line = String::new();
+        if input.read_line(&mut line).context("Failed to read the answer")? == 0 {
+            eprintln!();
+            return Ok(Answer::Quit);
+        }
+        match line.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => return Ok(Answer::Yes),
+            "" | "n" | "no" => return Ok(Answer::No),
+            "a" | "all" => return Ok(Answer::All),
+            "q" | "quit" => return Ok(Answer::Quit),
+            _ => eprintln!("{}", tr("confirm-answers", &[])),
+        }
+    }
+}
+
+fn print_preview(paths: &[String]) {
+    for path_str in paths.iter().take(CONFIRM_PREVIEW) {
+        outln!("  {}", path_str);
+    }
+    if paths.len() > CONFIRM_PREVIEW {
+        let count = (paths.len() - CONFIRM_PREVIEW).to_string();
+        outln!("  {}", tr("confirm-more", &[("count", &count)]).dimmed());
+    }
+}
+
+/// Expand `~` and environment variables in path arguments that never reached a shell
+fn expand_arguments(args: &mut Args) -> Result<()> {
+    let mut bookmarks = None;
+    for path_str in args.paths.iter_mut() {
+        *path_str = expand_argument(path_str, &mut bookmarks)?;
+    }
+    for dir in [&mut args.relative_to, &mut args.root].into_iter().flatten().chain(args.under.iter_mut()) {
+        *dir = PathBuf::from(expand_argument(&dir.to_string_lossy(), &mut bookmarks)?);
+    }
+    Ok(())
+}
+
+/// Expand `~`, variables and a leading `@bookmark` in one argument
+fn expand_argument(text: &str, bookmarks: &mut Option<bookmark::Bookmarks>) -> Result<String> {
+    if !text.starts_with('@') {
+        return expand::expand_path(text);
+    }
+    // Bookmarks are only read once an argument names one
+    if bookmarks.is_none() {
+        *bookmarks = Some(bookmark::load(&LocalFs)?);
+    }
+    bookmark::expand(bookmarks.as_ref().expect("loaded above"), text)
+}
+
+/// Resolve every path inside the `--root` directory, failing if any would escape it
+fn confine_paths(root: &Path, args: &mut Args) -> Result<()> {
+    let local = args.remote.is_none() && args.into_archive.is_none();
+    for path_str in args.paths.iter_mut() {
+        *path_str = confine_path(root, path_str, local)?;
+    }
+    Ok(())
+}
+
+fn confine_path(root: &Path, path_str: &str, local: bool) -> Result<String> {
+    let confined = confine::confine_lexically(root, path_str)?;
+    if local {
+        confine::verify_beneath(root, &confined)?;
+    }
+    Ok(confined)
+}
+
+/// Rewrite the requested paths with `--sanitize`, reporting every changed name
+fn sanitize_paths(fs: &dyn Filesystem, args: &mut Args) {
+    for path_str in args.paths.iter_mut() {
+        *path_str = sanitize_path(fs, path_str, args.sanitize_ascii);
+    }
+}
+
+fn sanitize_path(fs: &dyn Filesystem, path_str: &str, ascii: bool) -> String {
+    let sanitized = naming::sanitize_path(fs, path_str, ascii);
+    if sanitized != path_str {
+        outln!("{} {} {}", path_str.yellow(), "→".cyan(), sanitized.green());
+    }
+    sanitized
+}
+
+/// Apply the `--check` naming rules to every requested path
+fn check_names(fs: &dyn Filesystem, args: &Args, check: naming::NameCheck) -> Result<()> {
+    let violations: Vec<String> = args
+        .paths
+        .iter()
+        .flat_map(|path_str| naming::check_path(fs, path_str, check))
+        .collect();
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    if args.check_warn {
+        for violation in &violations {
+            outln!("{} {}", tr("warning", &[]).yellow(), violation);
+        }
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "{} name(s) fail the --check {} rules:\n  {}",
+        violations.len(),
+        check.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default(),
+        violations.join("\n  ")
+    );
+}
+
+/// Report requested paths that collide by case with existing entries or each other
+fn check_case_collisions(fs: &dyn Filesystem, args: &Args, action: naming::CollisionAction) -> Result<()> {
+    let mut collisions = naming::batch_case_collisions(&args.paths);
+    for path_str in &args.paths {
+        collisions.extend(naming::case_collisions(fs, path_str));
+    }
+
+    if collisions.is_empty() {
+        return Ok(());
+    }
+
+    if action == naming::CollisionAction::Warn {
+        for collision in &collisions {
+            outln!("{} {}", tr("warning", &[]).yellow(), collision);
+        }
+        return Ok(());
+    }
+
+    anyhow::bail!("{} case collision(s) found:\n  {}", collisions.len(), collisions.join("\n  "));
+}
+
+/// Whether each path gets its own report line, as it does when there are
+/// several (and a stream may have any number)
+fn reports_each_path(args: &Args) -> bool {
+    args.stream || args.paths.len() > 1
+}
+
+fn process_single_path(fs: &dyn Filesystem, path_str: &str, args: &Args) -> Result<(), BankError> {
+    let path = PathBuf::from(path_str);
+    
+    // -A shifts existing times and, like BSD touch, implies -c
+    if let Some(offset) = args.adjust {
+        if fs.exists(&path) {
+            adjust_file_times(fs, &path, offset, args)?;
+            if args.verbose || reports_each_path(args) {
+                outln!("{} {}", "✓".bright_green(), tr("updated-timestamps", &[("path", &path.display().to_string().green().to_string())]));
+            }
+        }
+        return Ok(());
+    }
+
+    // So do repairs, which leave plausible times alone, and --time-from-exif
+    #[cfg(feature = "exif")]
+    if args.time_from_exif {
+        if fs.exists(&path) && exif_file_times(fs, &path, args)? && (args.verbose || reports_each_path(args)) {
+            outln!("{} {}", "✓".bright_green(), tr("updated-timestamps", &[("path", &path.display().to_string().green().to_string())]));
+        }
+        return Ok(());
+    }
+    if repairs_times(args) {
+        if fs.exists(&path) && repair_file_times(fs, &path, args, SystemTime::now())? && (args.verbose || reports_each_path(args)) {
+            outln!("{} {}", "✓".bright_green(), tr("updated-timestamps", &[("path", &path.display().to_string().green().to_string())]));
+        }
+        return Ok(());
+    }
+
+    // Parse custom timestamp if provided
+    let custom_time = parse_timestamp(fs, args)?;
+    let mut existing = Existing::lookup(fs, &path);
+
+    // Check no-create mode
+    if args.no_create {
+        let Some(metadata) = &existing.target else {
+            if args.verbose {
+                outln!("{}", tr("skipping-missing", &[("path", &path.display().to_string().yellow().to_string())]));
+            }
+            return Ok(());
+        };
+        if args.truncate {
+            truncate_file(fs, &path, metadata, args)?;
+        }
+        if let Some(line) = &args.append {
+            append_line(fs, &path, line, args)?;
+        }
+        // Only update timestamps for existing files/directories
+        // Still what the file looks like unless its contents just changed
+        let time_spec = get_time_spec(args, custom_time)?;
+        let current = (!args.truncate && args.append.is_none()).then_some(&existing);
+        set_file_times(fs, &path, &time_spec, current, args)?;
+        
+        if args.verbose {
+            outln!("{} {}", "✓".bright_green(), tr("updated-timestamps", &[("path", &path.display().to_string().green().to_string())]));
+        } else if reports_each_path(args) {
+            outln!("{} {}", "✓".bright_green(), path.display().to_string().green());
+        }
+        return Ok(());
+    }
+    
+    // Determine what to create
+    let creation_type = determine_creation_type(args, &path, path_str, existing.target.as_ref())?;
+    
+    if args.verbose {
+        match creation_type {
+            CreationType::File => outln!("{}", tr("creating-file", &[("path", &path.display().to_string().yellow().to_string())])),
+            CreationType::Directory => outln!("{}", tr("creating-directory", &[("path", &path.display().to_string().yellow().to_string())])),
+        }
+    }
+
+    // Looked up before anything is created, in the directory the path lands in
+    let inherited = (args.inherit_owner && existing.target.is_none() && !existing.symlink).then(|| inherited_owner(fs, path_str)).flatten();
+
+    // Create parents if needed, each no more open than the mode it ends up with
+    let parents_mode = args.parents_mode.as_deref().map(parse_parents_mode).transpose()?.or_else(|| preset_mode(args, true));
+    let mut created_parents = Vec::new();
+    // An existing path has all its parents
+    if args.parents && existing.target.is_none() && !existing.symlink {
+        let mode = |dir: &Path| parents_mode.or_else(|| policy::default_mode(&args.default_modes, &dir.to_string_lossy()));
+        created_parents = create_parent_directories(fs, &path, &mode)?;
+        for created in &created_parents {
+            if args.verbose {
+                outln!("{}", tr("created-parent", &[("path", &created.display().to_string().green().to_string())]));
+            }
+        }
+    }
+
+    if args.on_exists == OnExists::Overwrite && remove_existing(fs, &path, args)? {
+        existing = Existing::default();
+    }
+
+    let custom_times = custom_time.is_some()
+        || args.access_time_only
+        || args.modification_time_only
+        || args.atime_date.is_some()
+        || args.mtime_date.is_some();
+
+    // Create the target; a new file gets its custom times through the
+    // descriptor that created it, unless --append is about to change it again
+    // or it has to be created with a mode
+    let is_dir = matches!(creation_type, CreationType::Directory);
+    let mode = creation_mode(args, is_dir, path_str).filter(|_| !existing.symlink);
+    let mut times_set = false;
+    match creation_type {
+        CreationType::File if custom_times && args.append.is_none() && mode.is_none() => {
+            times_set = create_file_with_times(fs, &path, &get_time_spec(args, custom_time)?, &existing, args)?;
+        }
+        CreationType::File => create_file(fs, &path, existing.target.as_ref(), mode, args)?,
+        CreationType::Directory => {
+            create_directory(fs, &path, existing.target.as_ref(), mode, args)?;
+        }
+    }
+    if let Some(line) = &args.append {
+        append_line(fs, &path, line, args)?;
+    }
+
+    // Set custom timestamps if specified
+    if custom_times && !times_set {
+        // What was there before still holds unless it was just created or its contents changed
+        let time_spec = get_time_spec(args, custom_time)?;
+        let current = (existing.target.is_some() && !args.truncate && args.append.is_none()).then_some(&existing);
+        set_file_times(fs, &path, &time_spec, current, args)?;
+    }
+
+    // Set permissions if specified, else a new path takes its default mode
+    if let Some(mode_str) = &args.mode {
+        set_permissions(fs, &path, mode_str, args.verbose)?;
+    } else if let Some(mode) = preset_mode(args, matches!(creation_type, CreationType::Directory)) {
+        set_permissions(fs, &path, &format!("{:o}", mode), args.verbose)?;
+    } else if existing.target.is_none() && !existing.symlink {
+        if let Some(mode) = policy::default_mode(&args.default_modes, path_str) {
+            set_permissions(fs, &path, &format!("{:o}", mode), args.verbose)?;
+        }
+    }
+    if args.executable {
+        make_executable(fs, &path, args.verbose)?;
+    }
+
+    // Innermost first, so a restrictive mode cannot lock us out of the rest
+    for dir in created_parents.iter().rev() {
+        if let Some(mode) = parents_mode.or_else(|| policy::default_mode(&args.default_modes, &dir.to_string_lossy())) {
+            fs.set_permissions(dir, mode)
+                .map_err(|err| BankError::io("set permissions for", dir, err))?;
+        }
+    }
+    if let Some(ids) = inherited {
+        for created in created_parents.iter().chain([&path]) {
+            set_inherited_owner(fs, created, ids, args.verbose)?;
+        }
+    }
+
+    if args.verbose {
+        outln!("{} {}", "✓".bright_green(), tr("created", &[("path", &path.display().to_string().green().to_string())]));
+    } else if reports_each_path(args) {
+        // Show minimal progress for multiple files when not verbose
+        outln!("{} {}", "✓".bright_green(), path.display().to_string().green());
+    }
+
+    Ok(())
+}
+
+/// `--on-exists overwrite`: get an existing file or symlink out of the way,
+/// into the trash with `--trash`; whether there was one
+fn remove_existing(fs: &dyn Filesystem, path: &Path, args: &Args) -> Result<bool, BankError> {
+    match fs.symlink_metadata(path) {
+        Ok(metadata) if metadata.kind != vfs::FileKind::Directory => {}
+        _ => return Ok(false),
+    }
+
+    if args.trash {
+        let trashed = fs.trash(path).map_err(|err| BankError::io("move to the trash", path, err))?;
+        if args.verbose {
+            outln!("{}", tr("trashed", &[("path", &path.display().to_string().yellow().to_string()), ("trash", &trashed.display().to_string())]));
+        }
+    } else {
+        fs.remove_file(path).map_err(|err| BankError::io("remove", path, err))?;
+        if args.verbose {
+            outln!("{}", tr("replacing", &[("path", &path.display().to_string().yellow().to_string())]));
+        }
+    }
+    Ok(true)
+}
+
+/// `--append`: add `line` at the end of the file, after a newline if the file
+/// does not end with one; with `--ensure-line`, only if no line matches it
+fn append_line(fs: &dyn Filesystem, path: &Path, line: &str, args: &Args) -> Result<(), BankError> {
+    let existing = match fs.read_file(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(BankError::io("read", path, err)),
+    };
+    let present = existing
+        .split(|byte| *byte == b'\n')
+        .any(|existing_line| existing_line.strip_suffix(b"\r").unwrap_or(existing_line) == line.as_bytes());
+    if args.ensure_line && present {
+        if args.verbose {
+            outln!("{}", tr("line-present", &[("path", &path.display().to_string().yellow().to_string())]));
+        }
+        return Ok(());
+    }
+
+    let mut text = Vec::with_capacity(line.len() + 2);
+    if !existing.is_empty() && !existing.ends_with(b"\n") {
+        text.push(b'\n');
+    }
+    text.extend_from_slice(line.as_bytes());
+    text.push(b'\n');
+    fs.append_file(path, &text).map_err(|err| BankError::io("append to", path, err))?;
+    if args.verbose {
+        outln!("{}", tr("appended", &[("path", &path.display().to_string().green().to_string())]));
+    }
+    Ok(())
+}
+
+/// Process a `--batch-file` row: the attributes it sets replace the command-line ones
+fn process_batch_row(fs: &dyn Filesystem, path_str: &str, row: &batch::BatchRow, args: &Args) -> Result<()> {
+    process_single_path(fs, path_str, &batch_row_args(row, args))?;
+
+    let path = Path::new(path_str);
+    if let Some(owner) = &row.owner {
+        if fs.exists(path) || fs.is_symlink(path) {
+            owner::set_owner(fs, path, owner, args.no_dereference)?;
+            if args.verbose {
+                outln!("{}", tr("set-owner", &[("owner", &owner.green().to_string()), ("path", &path.display().to_string())]));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The command line with a batch row's type, mode and timestamp applied on top
+fn batch_row_args(row: &batch::BatchRow, args: &Args) -> Args {
+    let mut row_args = args.clone();
+    match row.kind {
+        Some(batch::RowKind::File) => (row_args.file, row_args.directory) = (true, false),
+        Some(batch::RowKind::Directory) => (row_args.file, row_args.directory) = (false, true),
+        None => {}
+    }
+    if let Some(mode) = &row.mode {
+        row_args.mode = Some(mode.clone());
+    }
+    if let Some(timestamp) = &row.timestamp {
+        row_args.date = Some(timestamp.clone());
+        row_args.timestamp = None;
+        row_args.reference = None;
+    }
+    row_args
+}
+
+/// `--dry-run`: print what the run would create or change, and change nothing
+fn dry_run(fs: &dyn Filesystem, args: &Args, batch_rows: &[batch::BatchRow], batch_start: usize) -> Result<()> {
+    let manifest = planned_manifest(fs, args, batch_rows, batch_start)?;
+    let mut plan = apply::Plan::new(fs, &manifest)?;
+
+    // Without -p, a path whose parent is missing would fail
+    let planned: HashSet<&str> = manifest.entries.iter().map(|entry| plan_key(&entry.path)).collect();
+    for step in plan.steps.iter_mut().filter(|step| step.action == apply::Action::Create) {
+        let parent = Path::new(&step.entry.path).parent().filter(|parent| !parent.as_os_str().is_empty());
+        if parent.is_some_and(|parent| !fs.exists(parent) && !planned.contains(parent.to_string_lossy().as_ref())) {
+            step.action = apply::Action::Conflict("its parent directory does not exist (use -p)".to_string());
+        }
+    }
+
+    plan.show(args.output)?;
+    if args.changed_exit_code && plan.changes() == 0 {
+        std::process::exit(UNCHANGED_EXIT_CODE);
+    }
+    Ok(())
+}
+
+/// The state a run would leave each path in, as manifest entries: missing
+/// parents first with `-p`, then every requested path
+fn planned_manifest(fs: &dyn Filesystem, args: &Args, batch_rows: &[batch::BatchRow], batch_start: usize) -> Result<manifest::Manifest> {
+    let mut entries: Vec<manifest::Entry> = Vec::new();
+    let mut planned = HashSet::new();
+    for index in processing_order(args) {
+        let path_str = &args.paths[index];
+        let row = index.checked_sub(batch_start).and_then(|row| batch_rows.get(row));
+        let row_args = row.map_or_else(|| args.clone(), |row| batch_row_args(row, args));
+        let path = Path::new(path_str);
+        if row_args.no_create && !fs.exists(path) {
+            continue;
+        }
+
+        if row_args.parents {
+            let parents_mode = row_args.parents_mode.as_deref().map(parse_parents_mode).transpose()?.or_else(|| preset_mode(&row_args, true));
+            let missing: Vec<&Path> = path
+                .ancestors()
+                .skip(1)
+                .filter(|ancestor| !ancestor.as_os_str().is_empty())
+                .take_while(|ancestor| !fs.exists(ancestor))
+                .collect();
+            for dir in missing.into_iter().rev() {
+                let dir = dir.to_string_lossy().into_owned();
+                let mode = parents_mode.or_else(|| policy::default_mode(&row_args.default_modes, &dir));
+                if planned.insert(dir.clone()) {
+                    let mut entry = planned_entry(dir, vfs::FileKind::Directory, mode, None);
+                    entry.owner = inherited_owner_spec(fs, &row_args, path_str);
+                    entries.push(entry);
+                }
+            }
+        }
+
+        let kind = match determine_creation_type(&row_args, path, path_str, fs.metadata(path).ok().as_ref())? {
+            CreationType::File => vfs::FileKind::File,
+            CreationType::Directory => vfs::FileKind::Directory,
+        };
+        let mode = row_args
+            .mode
+            .as_deref()
+            .map(|mode_str| u32::from_str_radix(mode_str, 8).map_err(|_| BankError::BadMode { mode: mode_str.to_string() }))
+            .transpose()?
+            .or_else(|| preset_mode(&row_args, kind == vfs::FileKind::Directory))
+            .or_else(|| policy::default_mode(&row_args.default_modes, path_str).filter(|_| fs.symlink_metadata(path).is_err()));
+        let mode = match (row_args.executable, kind) {
+            (true, vfs::FileKind::File) => {
+                let current = fs.metadata(path).map(|metadata| metadata.mode & 0o7777).unwrap_or(0o666 & !mode::current_umask());
+                Some(executable_mode(mode.unwrap_or(current)))
+            }
+            _ => mode,
+        };
+        // Only an explicit time is part of the desired state; a plain run leaves existing times
+        // alone, but -c touches what exists and moves its mtime to now unless only -a is given
+        let custom_time = parse_timestamp(fs, &row_args)?;
+        let touched = row_args.no_create && !row_args.access_time_only;
+        let mtime = if custom_time.is_some() || row_args.modification_time_only || row_args.mtime_date.is_some() || touched {
+            get_time_spec(&row_args, custom_time)?.modification_time
+        } else {
+            None
+        };
+        if planned.insert(plan_key(path_str).to_string()) {
+            let mut entry = planned_entry(path_str.clone(), kind, mode, mtime);
+            entry.owner = row
+                .and_then(|row| row.owner.clone())
+                .or_else(|| inherited_owner_spec(fs, &row_args, path_str).filter(|_| fs.symlink_metadata(path).is_err()));
+            entries.push(entry);
+        }
+    }
+    Ok(manifest::Manifest { entries })
+}
+
+/// The owner `--inherit-owner` plans for what is created at `path_str`
+fn inherited_owner_spec(fs: &dyn Filesystem, args: &Args, path_str: &str) -> Option<String> {
+    let (uid, gid) = inherited_owner(fs, path_str).filter(|_| args.inherit_owner)?;
+    Some(owner::describe(uid, gid))
+}
+
+/// `srv/x/` and `srv/x` are the same directory
+fn plan_key(path_str: &str) -> &str {
+    match path_str.trim_end_matches('/') {
+        "" => path_str,
+        trimmed => trimmed,
+    }
+}
+
+fn planned_entry(path: String, kind: vfs::FileKind, mode: Option<u32>, mtime: Option<SystemTime>) -> manifest::Entry {
+    manifest::Entry { path, kind, mode, owner: None, mtime, hash: None, change: None, when: None, content: None }
+}
+
+/// `--existing-only DIR` is `-R -c DIR`, for directories that must already exist
+fn expand_existing_only(fs: &dyn Filesystem, args: &mut Args) -> Result<()> {
+    for dir in &args.existing_only {
+        if !fs.is_dir(Path::new(dir)) {
+            anyhow::bail!("--existing-only needs an existing directory: {}", dir);
+        }
+    }
+    args.paths.append(&mut args.existing_only);
+    args.recursive = true;
+    args.no_create = true;
+    Ok(())
+}
+
+/// `-R`: update the timestamps of every selected entry of an existing tree
+fn touch_tree(fs: &dyn Filesystem, path_str: &str, args: &Args) -> Result<()> {
+    let time_spec = get_time_spec(args, parse_timestamp(fs, args)?)?;
+    let filter = recurse::Filter::from_args(args, SystemTime::now());
+    let entries = recurse::walk(fs, Path::new(path_str), &filter)?;
+
+    let now = SystemTime::now();
+    let mut updated = 0;
+    for entry in &entries {
+        #[cfg(feature = "exif")]
+        if args.time_from_exif {
+            updated += usize::from(exif_file_times(fs, entry, args)?);
+            continue;
+        }
+        match args.adjust {
+            Some(offset) => adjust_file_times(fs, entry, offset, args)?,
+            None if repairs_times(args) => {
+                if !repair_file_times(fs, entry, args, now)? {
+                    continue;
+                }
+            }
+            None => set_file_times(fs, entry, &time_spec, None, args)?,
+        }
+        updated += 1;
+    }
+
+    if args.verbose || reports_each_path(args) {
+        let count = updated.to_string().cyan().to_string();
+        outln!("{} {}", "✓".bright_green(), tr("updated-tree", &[("count", &count), ("path", &path_str.green().to_string())]));
+    }
+    Ok(())
+}
+
+/// The `--emit-script` script: the commands for each path in order, with
+/// batch rows' own type, mode, timestamp and owner
+fn shell_script(args: &Args, batch_rows: &[batch::BatchRow], batch_start: usize) -> Result<String> {
+    let mut script = format!(
+        "#!/bin/sh\n# Written by bank {}; run it from the directory the paths are relative to\nset -e\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    let mut created_parents = HashSet::new();
+    for index in processing_order(args) {
+        let path_str = &args.paths[index];
+        let row = index.checked_sub(batch_start).and_then(|row| batch_rows.get(row));
+        let mut path_args = match row {
+            Some(row) => batch_row_args(row, args),
+            None => args.clone(),
+        };
+        path_args.paths = vec![path_str.clone()];
+        let commands = remote::shell_commands(&path_args, parse_timestamp(&LocalFs, &path_args)?)?;
+        // Siblings share their `mkdir -p` line
+        for line in commands.lines().filter(|line| !line.starts_with("mkdir -p ") || created_parents.insert(line.to_string())) {
+            script.push_str(line);
+            script.push('\n');
+        }
+        if let Some(owner) = row.and_then(|row| row.owner.as_deref()) {
+            let flag = if args.no_dereference { "-h " } else { "" };
+            let path = remote::shell_quote(path_str.trim_end_matches('/'));
+            script.push_str(&format!("chown {}-- {} {}\n", flag, remote::shell_quote(owner), path));
+        }
+    }
+    Ok(script)
+}
+
+/// `--emit-script`: write the script instead of creating anything
+fn emit_script(script_path: &Path, args: &Args, batch_rows: &[batch::BatchRow], batch_start: usize) -> Result<()> {
+    let script = shell_script(args, batch_rows, batch_start)?;
+    if script_path.as_os_str() == "-" {
+        print!("{}", script);
+        return Ok(());
+    }
+    LocalFs.write_file(script_path, script.as_bytes()).map_err(|err| BankError::io("write", script_path, err))?;
+    LocalFs.set_permissions(script_path, 0o755).map_err(|err| BankError::io("set permissions for", script_path, err))?;
+    if args.verbose {
+        outln!("{} {}", "✓".bright_green(), tr("wrote-script", &[("path", &script_path.display().to_string().green().to_string())]));
+    }
+    Ok(())
+}
+
+/// Write the requested paths as empty entries into an archive instead of the filesystem
+fn write_into_archive(archive_path: &Path, args: &Args) -> Result<()> {
+    let mtime = match &args.mtime_date {
+        Some(date) => parse_date_string(date)?,
+        None => parse_timestamp(&LocalFs, args)?,
+    };
+    let mtime = mtime.unwrap_or_else(SystemTime::now);
+    let mtime = mtime
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let mode = args
+        .mode
+        .as_deref()
+        .map(|mode_str| {
+            u32::from_str_radix(mode_str, 8).with_context(|| format!("Invalid mode format: {}", mode_str))
+        })
+        .transpose()?;
+    let parents_mode = args.parents_mode.as_deref().map(parse_parents_mode).transpose()?.or_else(|| preset_mode(args, true));
+
+    let mut entries = Vec::new();
+    let mut seen = HashSet::new();
+    for path_str in &args.paths {
+        let name = archive::entry_name(path_str)?;
+        let is_dir = matches!(determine_creation_type_offline(args, path_str), CreationType::Directory);
+
+        // Archives don't need parent entries, but -p adds them for extractors that expect them
+        if args.parents {
+            let components: Vec<&str> = name.split('/').collect();
+            for depth in 1..components.len() {
+                let parent = components[..depth].join("/");
+                if seen.insert(parent.clone()) {
+                    entries.push(archive::ArchiveEntry {
+                        name: parent,
+                        is_dir: true,
+                        mode: parents_mode.unwrap_or(0o755),
+                        mtime,
+                    });
+                }
+            }
+        }
+
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        if args.verbose {
+            outln!("{}", tr("archive-adding", &[("name", &name.yellow().to_string())]));
+        }
+        entries.push(archive::ArchiveEntry {
+            name,
+            is_dir,
+            mode: match mode.or_else(|| preset_mode(args, is_dir)).unwrap_or(if is_dir { 0o755 } else { 0o644 }) {
+                mode if args.executable && !is_dir => executable_mode(mode),
+                mode => mode,
+            },
+            mtime,
+        });
+    }
+
+    archive::append_entries(archive_path, &entries)?;
+
+    if args.verbose {
+        let count = entries.len().to_string().cyan().to_string();
+        outln!("{} {}", "✓".bright_green(), tr("archive-added", &[("count", &count), ("path", &archive_path.display().to_string().green().to_string())]));
+    }
+    Ok(())
+}
+
+fn parse_parents_mode(mode_str: &str) -> Result<u32, BankError> {
+    mode::parse_mode(mode_str, mode::current_umask()).ok_or_else(|| BankError::BadMode { mode: mode_str.to_string() })
+}
+
+/// Create the missing ancestors of `path` one level at a time (like `mkdir -p`),
+/// returning each directory that was actually created, outermost first.
+/// `mode` gives the mode a directory will end up with, if it is set later.
+fn create_parent_directories(fs: &dyn Filesystem, path: &Path, mode: &dyn Fn(&Path) -> Option<u32>) -> Result<Vec<PathBuf>, BankError> {
+    let missing: Vec<&Path> = path
+        .ancestors()
+        .skip(1)
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .take_while(|ancestor| !fs.exists(ancestor))
+        .collect();
+
+    let mut created = Vec::new();
+    for dir in missing.into_iter().rev() {
+        let result = match mode(dir) {
+            Some(mode) => fs.create_dir_with_mode(dir, creation_bits(mode, true)),
+            None => fs.create_dir(dir),
+        };
+        match result {
+            Ok(()) => created.push(dir.to_path_buf()),
+            // Someone else created it in the meantime
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists && fs.is_dir(dir) => {}
+            Err(err) => return Err(BankError::io("create parent directories for", path, err)),
+        }
+    }
+    Ok(created)
+}
+
+/// Whether to create `path` as a file or a directory; `existing` is what `stat`
+/// reports for it, if anything
+fn determine_creation_type(args: &Args, path: &Path, path_str: &str, existing: Option<&vfs::Metadata>) -> Result<CreationType, BankError> {
+    // Explicit flags take precedence
+    if args.directory {
+        return Ok(CreationType::Directory);
+    }
+
+    if args.file {
+        return Ok(CreationType::File);
+    }
+
+    // Check if path already exists
+    if let Some(metadata) = existing {
+        if metadata.is_dir() {
+            return Ok(CreationType::Directory);
+        } else {
+            return Ok(CreationType::File);
+        }
+    }
+
+    // Heuristics for ambiguous paths
+    if let Some(extension) = path.extension() {
+        if !extension.is_empty() {
+            return Ok(CreationType::File);
+        }
+    }
+
+    // Path ends with separator -> directory
+    if path_str.ends_with('/') || path_str.ends_with('\\') {
+        return Ok(CreationType::Directory);
+    }
+
+    // Interactive mode or auto-detection
+    if args.interactive == Some(Interactive::Ambiguous) {
+        let choices = vec![tr("choice-file", &[]), tr("choice-directory", &[])];
+        report::flush();
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(tr("prompt-kind", &[("path", &path.display().to_string())]))
+            .items(&choices)
+            .default(0)
+            .interact()?;
+
+        match selection {
+            0 => Ok(CreationType::File),
+            1 => Ok(CreationType::Directory),
+            _ => unreachable!(),
+        }
+    } else {
+        // Default to file for ambiguous cases
+        Ok(CreationType::File)
+    }
+}
+
+/// Determine the creation type for targets that are not on the local filesystem
+/// (archives, remotes): only the flags and the trailing separator are considered
+fn determine_creation_type_offline(args: &Args, path_str: &str) -> CreationType {
+    if args.directory || (!args.file && (path_str.ends_with('/') || path_str.ends_with('\\'))) {
+        CreationType::Directory
+    } else {
+        CreationType::File
+    }
+}
+
+/// The mode a path being created at `path_str` ends up with, if `--mode`, a
+/// preset or a default mode sets one
+fn creation_mode(args: &Args, is_dir: bool, path_str: &str) -> Option<u32> {
+    args.mode
+        .as_deref()
+        .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+        .or_else(|| preset_mode(args, is_dir))
+        .or_else(|| policy::default_mode(&args.default_modes, path_str))
+}
+
+/// The bits to create a path with that will end up with `mode`: never more
+/// open than it, but letting bank fill a directory or write a file until
+/// the final chmod
+fn creation_bits(mode: u32, is_dir: bool) -> u32 {
+    mode & 0o777 | if is_dir { 0o700 } else { 0o600 }
+}
+
+/// Create a missing file, no more open than `mode` if one is given
+fn create_file(fs: &dyn Filesystem, path: &Path, existing: Option<&vfs::Metadata>, mode: Option<u32>, args: &Args) -> Result<(), BankError> {
+    if let Some(metadata) = existing {
+        if args.verbose {
+            outln!("{}", tr("file-exists", &[("path", &path.display().to_string().yellow().to_string())]));
+        }
+        if args.truncate {
+            truncate_file(fs, path, metadata, args)?;
+        }
+        // Don't update timestamps here - will be handled by set_file_times if needed
+    } else {
+        match mode {
+            Some(mode) => fs.create_file_with_mode(path, creation_bits(mode, false)),
+            None => fs.create_file(path),
+        }
+        .map_err(|err| BankError::io("create file", path, err))?;
+    }
+    Ok(())
+}
+
+/// Create a missing file with `time_spec` already applied (times it leaves
+/// unset are the creation time). Returns false if the file already existed,
+/// or is a dangling symlink to create through, so the caller sets its times
+/// the usual way.
+fn create_file_with_times(fs: &dyn Filesystem, path: &Path, time_spec: &TimeSpec, existing: &Existing, args: &Args) -> Result<bool, BankError> {
+    if existing.target.is_some() || existing.symlink {
+        create_file(fs, path, existing.target.as_ref(), None, args)?;
+        return Ok(false);
+    }
+    let now = SystemTime::now();
+    let accessed = time_spec.access_time.unwrap_or(now);
+    let modified = time_spec.modification_time.unwrap_or(now);
+    match fs.create_file_with_times(path, accessed, modified) {
+        Ok(()) => {
+            if args.verbose {
+                outln!("{}", tr("updated-timestamps-for", &[("path", &path.display().to_string().cyan().to_string())]));
+            }
+            Ok(true)
+        }
+        // Another process created the file since the check; a symlink that
+        // appeared in the meantime is refused rather than followed
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists && !fs.is_symlink(path) => Ok(false),
+        Err(err) => Err(BankError::io("create file", path, err)),
+    }
+}
+
+/// `--truncate`: empty an existing file (`metadata` is its current state);
+/// directories are left alone
+fn truncate_file(fs: &dyn Filesystem, path: &Path, metadata: &vfs::Metadata, args: &Args) -> Result<(), BankError> {
+    if metadata.kind != vfs::FileKind::File || metadata.len == 0 {
+        return Ok(());
+    }
+    fs.write_file(path, &[]).map_err(|err| BankError::io("truncate", path, err))?;
+    if args.verbose {
+        outln!("{}", tr("truncated", &[("path", &path.display().to_string().yellow().to_string())]));
     }
     Ok(())
 }
 
-fn create_directory(path: &Path, args: &Args) -> Result<()> {
-    if path.exists() {
-        if path.is_dir() {
+/// Create a directory unless it already exists, no more open than `mode` if
+/// one is given; whether it was created
+fn create_directory(fs: &dyn Filesystem, path: &Path, existing: Option<&vfs::Metadata>, mode: Option<u32>, args: &Args) -> Result<bool, BankError> {
+    if let Some(metadata) = existing {
+        if metadata.is_dir() {
             if args.verbose {
-                println!("Directory already exists: {}", path.display().to_string().yellow());
+                outln!("{}", tr("directory-exists", &[("path", &path.display().to_string().yellow().to_string())]));
             }
         } else {
-            anyhow::bail!("Path exists but is not a directory: {}", path.display());
+            return Err(BankError::NotADirectory { path: path.to_path_buf() });
         }
     } else {
-        fs::create_dir(path)
-            .with_context(|| format!("Failed to create directory {}", path.display()))?;
+        match mode {
+            Some(mode) => fs.create_dir_with_mode(path, creation_bits(mode, true)),
+            None => fs.create_dir(path),
+        }
+        .map_err(|err| BankError::io("create directory", path, err))?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// `--keep-file`: put the placeholder into every directory this run created
+/// that is still empty at its end
+fn add_placeholders(fs: &vfs::RecordingFs, args: &Args) -> Result<(), BankError> {
+    // The name was validated with the other arguments
+    let Ok(Some(name)) = args.keep.resolved() else {
+        return Ok(());
+    };
+    for (dir, change) in fs.changes() {
+        if change != vfs::Change::Created || !fs.is_dir(&dir) {
+            continue;
+        }
+        let placeholder = dir.join(&name);
+        // --mode may have taken away write permission by now; lend it back
+        let mode = fs.metadata(&dir).map_err(|err| BankError::io("read metadata for", &dir, err))?.mode;
+        if mode & 0o200 == 0 {
+            fs.set_permissions(&dir, mode | 0o200).map_err(|err| BankError::io("set permissions for", &dir, err))?;
+        }
+        let created = keep::fill(fs, &dir, &name).map_err(|err| BankError::io("create file", &placeholder, err));
+        if mode & 0o200 == 0 {
+            fs.set_permissions(&dir, mode).map_err(|err| BankError::io("set permissions for", &dir, err))?;
+        }
+        if created? && args.verbose {
+            outln!("{}", tr("created-placeholder", &[("path", &placeholder.display().to_string().green().to_string())]));
+        }
     }
     Ok(())
 }
 
-fn set_permissions(path: &Path, mode_str: &str, verbose: bool) -> Result<()> {
+fn set_permissions(fs: &dyn Filesystem, path: &Path, mode_str: &str, verbose: bool) -> Result<(), BankError> {
     let mode = u32::from_str_radix(mode_str, 8)
-        .with_context(|| format!("Invalid mode format: {}", mode_str))?;
+        .map_err(|_| BankError::BadMode { mode: mode_str.to_string() })?;
 
-    let permissions = fs::Permissions::from_mode(mode);
-    fs::set_permissions(path, permissions)
-        .with_context(|| format!("Failed to set permissions for {}", path.display()))?;
+    fs.set_permissions(path, mode)
+        .map_err(|err| BankError::io("set permissions for", path, err))?;
 
     if verbose {
-        println!("Set permissions to {} for {}", mode_str.green(), path.display());
+        outln!("{}", tr("set-permissions", &[("mode", &mode_str.green().to_string()), ("path", &path.display().to_string())]));
     }
 
     Ok(())
 }
 
-/// Set file timestamps with symlink handling support
-fn set_file_times(path: &Path, time_spec: &TimeSpec, args: &Args) -> Result<()> {
+/// `--private` and `--public-read`: the mode they give a directory or a file
+fn preset_mode(args: &Args, is_dir: bool) -> Option<u32> {
+    let (dir_mode, file_mode) = match (args.private, args.public_read) {
+        (true, _) => (0o700, 0o600),
+        (_, true) => (0o755, 0o644),
+        _ => return None,
+    };
+    Some(if is_dir { dir_mode } else { file_mode })
+}
+
+/// `--executable`: an execute bit for every read bit, like `chmod +x`
+fn executable_mode(mode: u32) -> u32 {
+    mode | (mode & 0o444) >> 2
+}
+
+/// `--executable`: make the file at `path` runnable by whoever can read it;
+/// directories are left alone
+fn make_executable(fs: &dyn Filesystem, path: &Path, verbose: bool) -> Result<(), BankError> {
+    let metadata = fs.metadata(path).map_err(|err| BankError::io("read metadata for", path, err))?;
+    let mode = metadata.mode & 0o7777;
+    if metadata.kind != vfs::FileKind::File || executable_mode(mode) == mode {
+        return Ok(());
+    }
+    set_permissions(fs, path, &format!("{:o}", executable_mode(mode)), verbose)
+}
+
+/// Set the times `time_spec` asks for, leaving symlinks alone with
+/// `--no-dereference`. The path is only looked up if `current` does not
+/// already say what is there, and its times only when one of them is kept
+fn set_file_times(fs: &dyn Filesystem, path: &Path, time_spec: &TimeSpec, current: Option<&Existing>, args: &Args) -> Result<(), BankError> {
     // Handle symlinks if --no-dereference is specified
-    if args.no_dereference && path.is_symlink() {
+    if args.no_dereference && current.map_or_else(|| fs.is_symlink(path), |current| current.symlink) {
         if args.verbose {
-            println!("Setting timestamps on symlink: {}", path.display().to_string().cyan());
-            println!("Warning: Symlink timestamp modification not fully supported on this platform");
+            outln!("{}", tr("symlink-times", &[("path", &path.display().to_string().cyan().to_string())]));
+            outln!("{}", tr("symlink-times-unsupported", &[]));
         }
         return Ok(());
     }
     
-    // Get current times if we only want to modify one
-    let current_metadata = path.metadata()
-        .with_context(|| format!("Failed to read current timestamps for {}", path.display()))?;
-    
-    let current_access = current_metadata.accessed()?;
-    let current_modified = current_metadata.modified()?;
-    
-    // Use specified times or keep current ones
-    let access_time = time_spec.access_time.unwrap_or(current_access);
-    let modification_time = time_spec.modification_time.unwrap_or(current_modified);
-    
-    filetime::set_file_times(
-        path,
-        filetime::FileTime::from_system_time(access_time),
-        filetime::FileTime::from_system_time(modification_time)
-    ).with_context(|| format!("Failed to set timestamps for {}", path.display()))?;
+    let (access_time, modification_time) = time_spec.resolve(|| match current.and_then(|current| current.target.clone()) {
+        Some(metadata) => Ok(metadata),
+        None => fs.metadata(path).map_err(|err| BankError::io("read current timestamps for", path, err)),
+    })?;
+
+    fs.set_times(path, access_time, modification_time).map_err(|err| BankError::io("set timestamps for", path, err))?;
     
     if args.verbose {
-        println!("Updated timestamps for: {}", path.display().to_string().cyan());
+        outln!("{}", tr("updated-timestamps-for", &[("path", &path.display().to_string().cyan().to_string())]));
     }
     
     Ok(())
 }
 
+/// Shift the selected timestamps of `path` by `offset` seconds
+fn adjust_file_times(fs: &dyn Filesystem, path: &Path, offset: i64, args: &Args) -> Result<(), BankError> {
+    let metadata = fs.metadata(path)
+        .map_err(|err| BankError::io("read current timestamps for", path, err))?;
+    let shift = |time: SystemTime| {
+        let delta = std::time::Duration::from_secs(offset.unsigned_abs());
+        if offset < 0 { time - delta } else { time + delta }
+    };
+
+    let access_time = if args.modification_time_only { metadata.accessed } else { shift(metadata.accessed) };
+    let modification_time = if args.access_time_only { metadata.modified } else { shift(metadata.modified) };
+    fs.set_times(path, access_time, modification_time)
+        .map_err(|err| BankError::io("set timestamps for", path, err))
+}
+
+/// Whether `--clamp-future` or `--fix-epoch` replace implausible times
+fn repairs_times(args: &Args) -> bool {
+    args.clamp_future || args.fix_epoch.is_some()
+}
+
+/// Move times later than `now` back to it with `--clamp-future`, and replace
+/// those on or before 1970-01-01 with `--fix-epoch`'s date, honoring -a and
+/// -m; returns whether anything had to change
+fn repair_file_times(fs: &dyn Filesystem, path: &Path, args: &Args, now: SystemTime) -> Result<bool, BankError> {
+    let metadata = fs.metadata(path)
+        .map_err(|err| BankError::io("read current timestamps for", path, err))?;
+    let end_of_epoch_day = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(86_400);
+    let repair = |time: SystemTime| match args.fix_epoch {
+        _ if args.clamp_future && time > now => now,
+        Some(replacement) if time < end_of_epoch_day => replacement,
+        _ => time,
+    };
+
+    let access_time = if args.modification_time_only { metadata.accessed } else { repair(metadata.accessed) };
+    let modification_time = if args.access_time_only { metadata.modified } else { repair(metadata.modified) };
+    if (access_time, modification_time) == (metadata.accessed, metadata.modified) {
+        return Ok(false);
+    }
+    fs.set_times(path, access_time, modification_time)
+        .map_err(|err| BankError::io("set timestamps for", path, err))?;
+    Ok(true)
+}
+
+/// The guardrails for a local target: the `allowed-roots` of config.toml and,
+/// without --allow-system, the system directories. Remote and archive
+/// targets are not checked.
+fn check_target(args: &Args, path_str: &str) -> Result<()> {
+    if args.remote.is_some() || args.into_archive.is_some() {
+        return Ok(());
+    }
+    guard::check_allowed(&args.allowed_roots, path_str)?;
+    if !args.allow_system {
+        guard::check_system(path_str)?;
+    }
+    Ok(())
+}
+
+/// Refuse flags whose feature this build was made without
+fn check_features(args: &Args) -> Result<()> {
+    #[cfg(not(feature = "exif"))]
+    if args.time_from_exif_unavailable {
+        anyhow::bail!("--time-from-exif is not available: this bank was built without the exif feature (build it with --features exif)");
+    }
+    let _ = args;
+    Ok(())
+}
+
+/// Whether `--time-from-exif` was given (never without the `exif` feature)
+fn times_from_exif(args: &Args) -> bool {
+    #[cfg(feature = "exif")]
+    return args.time_from_exif;
+    #[cfg(not(feature = "exif"))]
+    return {
+        let _ = args;
+        false
+    };
+}
+
+/// `--time-from-exif`: set a photo's mtime to when it was taken; files
+/// without a DateTimeOriginal are left alone. Returns whether anything changed
+#[cfg(feature = "exif")]
+fn exif_file_times(fs: &dyn Filesystem, path: &Path, args: &Args) -> Result<bool, BankError> {
+    let metadata = fs.metadata(path)
+        .map_err(|err| BankError::io("read current timestamps for", path, err))?;
+    if metadata.is_dir() {
+        return Ok(false);
+    }
+    let data = fs.read_file_prefix(path, exif::HEADER_LIMIT).map_err(|err| BankError::io("read", path, err))?;
+    let Some(taken) = exif::date_time_original(&data) else {
+        if args.verbose {
+            outln!("{} {}", tr("warning", &[]).yellow(), tr("no-exif-date", &[("path", &path.display().to_string())]));
+        }
+        return Ok(false);
+    };
+    if taken == metadata.modified {
+        return Ok(false);
+    }
+    fs.set_times(path, metadata.accessed, taken)
+        .map_err(|err| BankError::io("set timestamps for", path, err))?;
+    Ok(true)
+}
+
+/// The replacement date of `--fix-epoch`
+fn parse_fix_epoch(text: &str) -> Result<SystemTime, String> {
+    match parse_date_string(text) {
+        Ok(Some(time)) => Ok(time),
+        Ok(None) => Err(format!("Unable to parse date string: {}", text)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Parse a BSD `-A` offset `[-][[hh]mm]SS` into seconds
+fn parse_adjustment(text: &str) -> Result<i64, String> {
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, text),
+    };
+    if !matches!(digits.len(), 2 | 4 | 6) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("invalid adjustment '{}' (expected [-][[hh]mm]SS)", text));
+    }
+
+    // Rightmost pair is seconds, then minutes, then hours
+    let mut seconds = 0;
+    for (index, pair) in digits.as_bytes().rchunks(2).enumerate() {
+        let value = i64::from((pair[0] - b'0') * 10 + (pair[1] - b'0'));
+        let (limit, unit) = match index {
+            0 => (59, 1),
+            1 => (59, 60),
+            _ => (99, 3600),
+        };
+        if value > limit {
+            return Err(format!("invalid adjustment '{}': field out of range", text));
+        }
+        seconds += value * unit;
+    }
+    Ok(sign * seconds)
+}
+
+/// Parse a `--shift` offset: a sign and durations like `2h`, `-30m` or `+1h30m`, in whole seconds
+fn parse_shift(text: &str) -> Result<i64, String> {
+    let (sign, mut rest) = match (text.strip_prefix('-'), text.strip_prefix('+')) {
+        (Some(rest), _) => (-1, rest),
+        (None, Some(rest)) => (1, rest),
+        (None, None) => (1, text),
+    };
+    let mut seconds = 0.0;
+    while !rest.is_empty() {
+        // Each part is a number followed by its unit, up to the next digit
+        let unit_start = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+        let end = rest[unit_start..].find(|c: char| c.is_ascii_digit()).map_or(rest.len(), |offset| unit_start + offset);
+        seconds += watch::parse_duration(&rest[..end]).map_err(|err| format!("invalid offset '{}': {}", text, err))?.as_secs_f64();
+        rest = &rest[end..];
+    }
+    if seconds == 0.0 || seconds.fract() != 0.0 {
+        return Err(format!("invalid offset '{}' (expected whole seconds, e.g. +2h, -30m, -1h30m)", text));
+    }
+    Ok(sign * seconds as i64)
+}
+
 /// Parse timestamp from various formats
-fn parse_timestamp(args: &Args) -> Result<Option<SystemTime>> {
+fn parse_timestamp(fs: &dyn Filesystem, args: &Args) -> Result<Option<SystemTime>, BankError> {
     // Priority: reference file > date string > timestamp format
     if let Some(ref_file) = &args.reference {
-        return parse_reference_time(ref_file);
+        return parse_reference_time(fs, ref_file);
     }
     
     if let Some(date_str) = &args.date {
@@ -350,21 +2729,21 @@ fn parse_timestamp(args: &Args) -> Result<Option<SystemTime>> {
 }
 
 /// Parse reference file timestamps
-fn parse_reference_time(reference_path: &str) -> Result<Option<SystemTime>> {
+fn parse_reference_time(fs: &dyn Filesystem, reference_path: &str) -> Result<Option<SystemTime>, BankError> {
     let path = Path::new(reference_path);
-    if !path.exists() {
-        anyhow::bail!("Reference file does not exist: {}", reference_path);
+    if !fs.exists(path) {
+        return Err(BankError::NotFound { path: path.to_path_buf() });
     }
     
-    let metadata = path.metadata()
-        .with_context(|| format!("Failed to read metadata from reference file: {}", reference_path))?;
+    let metadata = fs.metadata(path)
+        .map_err(|err| BankError::io("read metadata from reference file", path, err))?;
     
     // For reference files, we use the modification time as the base
-    Ok(Some(metadata.modified()?))
+    Ok(Some(metadata.modified))
 }
 
 /// Parse date string like "2023-12-25 15:30:45" or "2023-12-25"
-fn parse_date_string(date_str: &str) -> Result<Option<SystemTime>> {
+fn parse_date_string(date_str: &str) -> Result<Option<SystemTime>, BankError> {
     // Try different common formats
     let formats = [
         "%Y-%m-%d %H:%M:%S",
@@ -391,21 +2770,27 @@ fn parse_date_string(date_str: &str) -> Result<Option<SystemTime>> {
         }
     }
     
-    anyhow::bail!("Unable to parse date string: {}", date_str);
+    Err(BankError::bad_timestamp(format!("Unable to parse date string: {}", date_str)))
 }
 
 /// Parse timestamp format [[CC]YY]MMDDhhmm[.ss]
-fn parse_timestamp_format(timestamp_str: &str) -> Result<Option<SystemTime>> {
+fn parse_timestamp_format(timestamp_str: &str) -> Result<Option<SystemTime>, BankError> {
+    let invalid = || BankError::bad_timestamp(format!("Invalid timestamp format: {}", timestamp_str));
+    let number = |digits: &str| digits.parse::<u32>().map_err(|_| invalid());
+
     // Remove optional seconds part
     let (base, seconds) = if timestamp_str.contains('.') {
         let parts: Vec<&str> = timestamp_str.split('.').collect();
         if parts.len() != 2 {
-            anyhow::bail!("Invalid timestamp format: {}", timestamp_str);
+            return Err(invalid());
         }
-        (parts[0], Some(parts[1].parse::<u32>()?))
+        (parts[0], Some(number(parts[1])?))
     } else {
         (timestamp_str, None)
     };
+    if !base.is_ascii() {
+        return Err(invalid());
+    }
     
     let base_len = base.len();
     
@@ -413,33 +2798,37 @@ fn parse_timestamp_format(timestamp_str: &str) -> Result<Option<SystemTime>> {
     let (year, month, day, hour, minute) = match base_len {
         8 => { // MMDDHHMM (current year assumed)
             let current_year = chrono::Utc::now().year();
-            (current_year, base[0..2].parse()?, base[2..4].parse()?, base[4..6].parse()?, base[6..8].parse()?)
+            (current_year, number(&base[0..2])?, number(&base[2..4])?, number(&base[4..6])?, number(&base[6..8])?)
         },
         10 => { // YYMMDDHHMM
-            let yy: i32 = base[0..2].parse()?;
+            let yy = number(&base[0..2])? as i32;
             let year = if yy >= 70 { 1900 + yy } else { 2000 + yy };
-            (year, base[2..4].parse()?, base[4..6].parse()?, base[6..8].parse()?, base[8..10].parse()?)
+            (year, number(&base[2..4])?, number(&base[4..6])?, number(&base[6..8])?, number(&base[8..10])?)
         },
         12 => { // CCYYMMDDHHMM  
-            let cc: i32 = base[0..2].parse()?;
-            let yy: i32 = base[2..4].parse()?;
-            (cc * 100 + yy, base[4..6].parse()?, base[6..8].parse()?, base[8..10].parse()?, base[10..12].parse()?)
+            let cc = number(&base[0..2])? as i32;
+            let yy = number(&base[2..4])? as i32;
+            (cc * 100 + yy, number(&base[4..6])?, number(&base[6..8])?, number(&base[8..10])?, number(&base[10..12])?)
         },
-        _ => anyhow::bail!("Invalid timestamp format length: {} (expected 8, 10, or 12 digits)", base_len)
+        _ => return Err(BankError::bad_timestamp(format!(
+            "Invalid timestamp format length: {} (expected 8, 10, or 12 digits)", base_len
+        ))),
     };
     
     let seconds = seconds.unwrap_or(0);
     
     let naive_dt = chrono::NaiveDate::from_ymd_opt(year, month, day)
         .and_then(|d| d.and_hms_opt(hour, minute, seconds))
-        .ok_or_else(|| anyhow::anyhow!("Invalid timestamp values: {}-{}-{} {}:{}:{}", year, month, day, hour, minute, seconds))?;
+        .ok_or_else(|| BankError::bad_timestamp(format!(
+            "Invalid timestamp values: {}-{}-{} {}:{}:{}", year, month, day, hour, minute, seconds
+        )))?;
     
     let dt = DateTime::<Utc>::from_naive_utc_and_offset(naive_dt, Utc);
     Ok(Some(SystemTime::from(dt)))
 }
 
 /// Determine which timestamps to set based on flags
-fn get_time_spec(args: &Args, custom_time: Option<SystemTime>) -> Result<TimeSpec> {
+fn get_time_spec(args: &Args, custom_time: Option<SystemTime>) -> Result<TimeSpec, BankError> {
     let now = custom_time.unwrap_or_else(SystemTime::now);
     
     let (access_time, modification_time) = if args.access_time_only {
@@ -450,6 +2839,16 @@ fn get_time_spec(args: &Args, custom_time: Option<SystemTime>) -> Result<TimeSpe
         // Default: set both times
         (Some(now), Some(now))
     };
+
+    // Per-field dates override the shared time
+    let access_time = match &args.atime_date {
+        Some(date) => parse_date_string(date)?,
+        None => access_time,
+    };
+    let modification_time = match &args.mtime_date {
+        Some(date) => parse_date_string(date)?,
+        None => modification_time,
+    };
     
     Ok(TimeSpec {
         access_time,
@@ -461,23 +2860,112 @@ fn get_time_spec(args: &Args, custom_time: Option<SystemTime>) -> Result<TimeSpe
 mod tests {
     use super::*;
     use tempfile::TempDir;
+    use vfs::MemoryFs;
     
-    fn create_test_args(paths: Vec<String>) -> Args {
+    pub(crate) fn create_test_args(paths: Vec<String>) -> Args {
         Args {
+            command: None,
             paths,
             directory: false,
             file: false,
             parents: false,
             mode: None,
-            interactive: false,
+            executable: false,
+            private: false,
+            public_read: false,
+            inherit_owner: false,
+            policy: None,
+            default_modes: Vec::new(),
+            parents_mode: None,
+            keep: keep::KeepArgs::default(),
+            count: None,
+            seq_start: 1,
+            seq_format: sequence::SeqFormat::Decimal,
+            interactive: None,
             verbose: false,
             no_create: false,
+            append: None,
+            ensure_line: false,
+            truncate: false,
             date: None,
             timestamp: None,
             reference: None,
             access_time_only: false,
             modification_time_only: false,
+            atime_date: None,
+            mtime_date: None,
+            adjust: None,
+            shift: None,
+            clamp_future: false,
+            fix_epoch: None,
+            #[cfg(feature = "exif")]
+            time_from_exif: false,
+            #[cfg(not(feature = "exif"))]
+            time_from_exif_unavailable: false,
+            recursive: false,
+            existing_only: Vec::new(),
+            batch_file: None,
+            specs: Vec::new(),
+            emit_manifest: None,
+            emit_script: None,
+            emit: None,
+            hash: None,
+            on_exists: OnExists::Skip,
+            trash: false,
+            dry_run: false,
+            output: apply::PlanFormat::Text,
+            changed_exit_code: false,
+            confirm_over: None,
+            yes: false,
+            session: None,
+            no_journal: false,
+            history: false,
+            no_history: false,
+            unordered: false,
+            stream: false,
+            jobs: 1,
+            on_conflict: conflict::OnConflict::Warn,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            entry_types: Vec::new(),
+            older_than: None,
+            newer_than: None,
+            min_size: None,
+            max_size: None,
+            max_depth: None,
+            min_depth: None,
+            follow_symlinks: false,
+            no_follow_symlinks: false,
             no_dereference: false,
+            from_archive: None,
+            from_tree: None,
+            from_outline: None,
+            from_file: None,
+            from_file0: None,
+            into_archive: None,
+            remote: None,
+            container: None,
+            check: None,
+            check_warn: false,
+            sanitize: false,
+            sanitize_ascii: false,
+            detect_case_collisions: None,
+            relative_to: None,
+            under: Vec::new(),
+            root: None,
+            allow_anywhere: false,
+            allow_system: false,
+            allowed_roots: Vec::new(),
+            no_expand: false,
+            normalize_path: normalize::NormalizeMode::None,
+            no_symlinked_parents: false,
+            secure: false,
+            sudo: false,
+            as_user: None,
+            retries: 0,
+            retry_delay: std::time::Duration::from_millis(200),
+            lang: None,
+            compat: None,
         }
     }
 
@@ -489,7 +2977,7 @@ mod tests {
         let mut args = create_test_args(vec![file_path.to_str().unwrap().to_string()]);
         args.file = true;
 
-        create_file(&file_path, &args).unwrap();
+        create_file(&LocalFs, &file_path, None, None, &args).unwrap();
         assert!(file_path.exists());
         assert!(file_path.is_file());
     }
@@ -502,7 +2990,7 @@ mod tests {
         let mut args = create_test_args(vec![dir_path.to_str().unwrap().to_string()]);
         args.directory = true;
 
-        create_directory(&dir_path, &args).unwrap();
+        create_directory(&LocalFs, &dir_path, None, None, &args).unwrap();
         assert!(dir_path.exists());
         assert!(dir_path.is_dir());
     }
@@ -512,7 +3000,7 @@ mod tests {
         let args = create_test_args(vec!["test.txt".to_string()]);
 
         let path = PathBuf::from("test.txt");
-        let creation_type = determine_creation_type(&args, &path, "test.txt").unwrap();
+        let creation_type = determine_creation_type(&args, &path, "test.txt", None).unwrap();
         
         match creation_type {
             CreationType::File => (),
@@ -525,7 +3013,7 @@ mod tests {
         let args = create_test_args(vec!["test_dir/".to_string()]);
 
         let path = PathBuf::from("test_dir");
-        let creation_type = determine_creation_type(&args, &path, "test_dir/").unwrap();
+        let creation_type = determine_creation_type(&args, &path, "test_dir/", None).unwrap();
         
         match creation_type {
             CreationType::Directory => (),
@@ -545,8 +3033,8 @@ mod tests {
         ]);
         args.file = true;
 
-        process_single_path(&args.paths[0], &args).unwrap();
-        process_single_path(&args.paths[1], &args).unwrap();
+        process_single_path(&LocalFs, &args.paths[0], &args).unwrap();
+        process_single_path(&LocalFs, &args.paths[1], &args).unwrap();
         
         assert!(file1_path.exists());
         assert!(file1_path.is_file());
@@ -567,16 +3055,399 @@ mod tests {
         args.no_create = true;
         
         // Should succeed for existing file
-        process_single_path(file_path.to_str().unwrap(), &args).unwrap();
+        process_single_path(&LocalFs, file_path.to_str().unwrap(), &args).unwrap();
         
         // Should not create nonexistent file
         let mut args2 = create_test_args(vec![nonexistent_path.to_str().unwrap().to_string()]);
         args2.no_create = true;
-        process_single_path(nonexistent_path.to_str().unwrap(), &args2).unwrap();
+        process_single_path(&LocalFs, nonexistent_path.to_str().unwrap(), &args2).unwrap();
         
         assert!(!nonexistent_path.exists());
     }
 
+    #[test]
+    fn test_each_path_is_looked_up_once() {
+        let memory = MemoryFs::new();
+        memory.write_file(Path::new("stamp"), b"").unwrap();
+        let mut args = create_test_args(vec!["stamp".to_string()]);
+        args.no_create = true;
+        let counting = vfs::CountingFs::new(&memory);
+        process_single_path(&counting, "stamp", &args).unwrap();
+        // The existence check and the new times
+        assert_eq!(counting.calls(), 2);
+
+        // Keeping the modification time reuses that metadata
+        args.access_time_only = true;
+        let before = memory.metadata(Path::new("stamp")).unwrap().modified;
+        let counting = vfs::CountingFs::new(&memory);
+        process_single_path(&counting, "stamp", &args).unwrap();
+        assert_eq!(counting.calls(), 2);
+        assert_eq!(memory.metadata(Path::new("stamp")).unwrap().modified, before);
+
+        // A new file: one lookup, then the creation itself
+        let args = create_test_args(vec!["new.txt".to_string()]);
+        let counting = vfs::CountingFs::new(&memory);
+        process_single_path(&counting, "new.txt", &args).unwrap();
+        assert_eq!(counting.calls(), 2);
+
+        // An existing file given a mode keeps its times: the lookup and the chmod
+        let mut args = create_test_args(vec!["stamp".to_string()]);
+        args.mode = Some("600".to_string());
+        let counting = vfs::CountingFs::new(&memory);
+        process_single_path(&counting, "stamp", &args).unwrap();
+        assert_eq!(counting.calls(), 2);
+        assert_eq!(memory.metadata(Path::new("stamp")).unwrap().mode, 0o600);
+
+        // A directory is looked up like a file, then gets its new times
+        memory.create_dir(Path::new("logs")).unwrap();
+        let mut args = create_test_args(vec!["logs/".to_string()]);
+        args.no_create = true;
+        let counting = vfs::CountingFs::new(&memory);
+        process_single_path(&counting, "logs/", &args).unwrap();
+        assert_eq!(counting.calls(), 2);
+    }
+
+    #[test]
+    fn test_append_and_ensure_line() {
+        let fs = MemoryFs::new();
+        fs.write_file(Path::new(".gitignore"), b"target").unwrap();
+        let mut args = create_test_args(vec![".gitignore".to_string(), "new.txt".to_string()]);
+        args.append = Some("*.log".to_string());
+        args.ensure_line = true;
+        for path in args.paths.clone() {
+            process_single_path(&fs, &path, &args).unwrap();
+            process_single_path(&fs, &path, &args).unwrap();
+        }
+        assert_eq!(fs.contents(".gitignore").unwrap(), b"target\n*.log\n");
+        assert_eq!(fs.contents("new.txt").unwrap(), b"*.log\n");
+
+        // Without --ensure-line every run appends; -c leaves missing files alone
+        args.ensure_line = false;
+        args.no_create = true;
+        args.paths = vec![".gitignore".to_string(), "missing.txt".to_string()];
+        for path in args.paths.clone() {
+            process_single_path(&fs, &path, &args).unwrap();
+        }
+        assert_eq!(fs.contents(".gitignore").unwrap(), b"target\n*.log\n*.log\n");
+        assert!(!fs.exists(Path::new("missing.txt")));
+    }
+
+    #[test]
+    fn test_truncate() {
+        let fs = MemoryFs::new();
+        fs.write_file(Path::new("app.log"), b"old entries\n").unwrap();
+        fs.write_file(Path::new("marker"), b"stale").unwrap();
+        let mut args = create_test_args(vec!["app.log".to_string()]);
+        args.truncate = true;
+        process_single_path(&fs, "app.log", &args).unwrap();
+        assert_eq!(fs.contents("app.log").unwrap(), b"");
+
+        // With --append the file is reset to just that line, also under -c
+        args.append = Some("ready".to_string());
+        args.no_create = true;
+        process_single_path(&fs, "marker", &args).unwrap();
+        assert_eq!(fs.contents("marker").unwrap(), b"ready\n");
+    }
+
+    #[test]
+    fn test_keep_file_in_new_empty_directories() {
+        let memory = MemoryFs::new();
+        memory.create_dir(Path::new("existing")).unwrap();
+        let fs = vfs::RecordingFs::new(&memory);
+        let mut args = create_test_args(vec!["skel/empty/".to_string(), "existing/".to_string()]);
+        args.parents = true;
+        args.keep.keep_file = Some(".gitkeep".to_string());
+        for path_str in ["skel/empty/", "existing/", "a/", "a/b/"] {
+            process_single_path(&fs, path_str, &args).unwrap();
+        }
+        // A directory that is empty when created but not when the run ends gets none
+        assert!(!fs.exists(Path::new("a/.gitkeep")));
+        add_placeholders(&fs, &args).unwrap();
+        assert!(fs.exists(Path::new("skel/empty/.gitkeep")));
+        assert!(!fs.exists(Path::new("skel/.gitkeep")));
+        assert!(!fs.exists(Path::new("existing/.gitkeep")));
+        assert!(fs.exists(Path::new("a/b/.gitkeep")));
+        assert!(!fs.exists(Path::new("a/.gitkeep")));
+
+        args.keep = keep::KeepArgs { keep_file: None, no_keep: true };
+        process_single_path(&fs, "skel/other/", &args).unwrap();
+        add_placeholders(&fs, &args).unwrap();
+        assert!(!fs.exists(Path::new("skel/other/.gitkeep")));
+    }
+
+    #[test]
+    fn test_shell_script_applies_batch_rows() {
+        let mut args = create_test_args(vec!["srv/".to_string(), "srv/app.conf".to_string()]);
+        args.mode = Some("750".to_string());
+        let rows = vec![batch::parse_spec("srv/app.conf::mode=600,owner=app:app,type=file").unwrap()];
+        let script = shell_script(&args, &rows, 1).unwrap();
+        let commands: Vec<&str> = script.lines().skip(3).collect();
+        assert_eq!(
+            commands,
+            [
+                "[ -d 'srv' ] || mkdir -- 'srv'",
+                "chmod 750 -- 'srv'",
+                "[ -e 'srv/app.conf' ] || touch -- 'srv/app.conf'",
+                "chmod 600 -- 'srv/app.conf'",
+                "chown -- 'app:app' 'srv/app.conf'",
+            ]
+        );
+        assert!(script.starts_with("#!/bin/sh\n"));
+    }
+
+    #[test]
+    fn test_under_replicates_paths_per_root() {
+        let mut args = create_test_args(vec!["shared/in/".to_string(), "shared/out/".to_string(), "shared/app.conf".to_string()]);
+        args.under = vec![PathBuf::from("hostA"), PathBuf::from("hostB")];
+        let mut rows = vec![batch::parse_spec("shared/app.conf::mode=600").unwrap()];
+        let mut batch_start = 2;
+        let root_of = replicate_under(&mut args, &mut rows, &mut batch_start).unwrap();
+        assert_eq!(
+            args.paths,
+            ["hostA/shared/in/", "hostA/shared/out/", "hostB/shared/in/", "hostB/shared/out/", "hostA/shared/app.conf", "hostB/shared/app.conf"]
+        );
+        assert_eq!(root_of, [0, 0, 1, 1, 0, 1]);
+        assert_eq!(batch_start, 4);
+        assert_eq!(rows[1].path, "hostB/shared/app.conf");
+        assert_eq!(rows[1].mode.as_deref(), Some("600"));
+
+        // A failing root does not stop the others, and a missing one is not created
+        let fs = MemoryFs::new();
+        fs.create_dir(Path::new("hostA")).unwrap();
+        fs.create_dir(Path::new("hostB")).unwrap();
+        let order: Vec<usize> = (0..args.paths.len()).collect();
+        let mut seen = Vec::new();
+        let result = process_under(&fs, &args.under, &root_of, &order, &args.paths, &mut |index, path_str| {
+            seen.push(index);
+            if path_str.starts_with("hostA/shared/out") {
+                anyhow::bail!("refused");
+            }
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(seen, [0, 1, 2, 3, 5]);
+        let roots = [PathBuf::from("hostA"), PathBuf::from("missing")];
+        let mut seen = Vec::new();
+        let result = process_under(&fs, &roots, &root_of, &order, &args.paths, &mut |index, _| {
+            seen.push(index);
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(seen, [0, 1, 4]);
+
+        let mut args = create_test_args(vec!["/abs".to_string()]);
+        args.under = vec![PathBuf::from("hostA")];
+        assert!(replicate_under(&mut args, &mut Vec::new(), &mut 1).is_err());
+    }
+
+    #[test]
+    fn test_new_files_get_times_at_creation() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("stamp");
+        let mut args = create_test_args(vec![path.display().to_string()]);
+        args.date = Some("2020-01-02 03:04:05".to_string());
+        process_single_path(&LocalFs, &path.display().to_string(), &args).unwrap();
+        let expected = parse_date_string("2020-01-02 03:04:05").unwrap().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), expected);
+
+        // A symlink planted where a new file was expected is not followed
+        let target = temp_dir.path().join("target");
+        let planted = temp_dir.path().join("planted");
+        std::os::unix::fs::symlink(&target, &planted).unwrap();
+        assert!(LocalFs.create_file_with_times(&planted, expected, expected).is_err());
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_confirm_over() {
+        let cwd = Path::new("/work/project");
+        let paths = vec!["src/a.rs".to_string(), "src/../../other".to_string(), "/etc/x".to_string(), "/work/project/ok".to_string()];
+        assert_eq!(paths_outside(cwd, &paths), vec!["src/../../other".to_string(), "/etc/x".to_string()]);
+
+        let mut args = create_test_args(vec!["a".to_string(), "b".to_string()]);
+        args.confirm_over = Some(2);
+        assert_eq!(confirm_threshold(&args).unwrap(), Some(2));
+        // At or under the threshold, inside the current directory: no prompt
+        confirm_batch(&args, 2).unwrap();
+        // Over it, --yes answers the prompt
+        args.yes = true;
+        confirm_batch(&args, 1).unwrap();
+    }
+
+    #[test]
+    fn test_foreign_parents() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("/srv/app")).unwrap();
+        memory.create_dir_all(Path::new("/tmp")).unwrap();
+        memory.set_permissions(Path::new("/tmp"), 0o1777).unwrap();
+        let mine = memory.metadata(Path::new("/srv/app")).unwrap().uid;
+        let paths = vec!["/srv/app/new/deep.txt".to_string(), "/tmp/scratch".to_string()];
+        assert!(foreign_parents(&memory, &paths, mine).is_empty());
+
+        let foreign = foreign_parents(&memory, &paths, mine + 1);
+        assert_eq!(foreign.len(), 1);
+        assert_eq!((foreign[0].0.as_str(), foreign[0].1.as_path()), ("/srv/app/new/deep.txt", Path::new("/srv/app")));
+        let mut args = create_test_args(paths);
+        args.yes = true;
+        assert!(confirm_foreign_parents(&memory, &args.paths, &args, mine + 1).unwrap());
+        assert!(!confirm_foreign_parents(&memory, &args.paths, &args, mine).unwrap());
+    }
+
+    #[test]
+    fn test_inherit_owner() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("/srv/app")).unwrap();
+        memory.create_file(Path::new("/srv/app/existing.conf")).unwrap();
+        memory.set_owner(Path::new("/srv/app"), Some(1000), Some(1001)).unwrap();
+        let app = memory.metadata(Path::new("/srv/app")).unwrap();
+        assert_eq!(inherited_owner(&memory, "/srv/app/logs/deep/today.log"), Some((1000, 1001)));
+
+        let mut args = create_test_args(vec!["/srv/app/logs/today.log".to_string(), "/srv/app/existing.conf".to_string()]);
+        args.parents = true;
+        args.inherit_owner = true;
+        process_single_path(&memory, "/srv/app/logs/today.log", &args).unwrap();
+        for created in ["/srv/app/logs", "/srv/app/logs/today.log"] {
+            let metadata = memory.metadata(Path::new(created)).unwrap();
+            assert_eq!((metadata.uid, metadata.gid), (1000, 1001));
+        }
+        assert_eq!(memory.metadata(Path::new("/srv/app/existing.conf")).unwrap().uid, 0);
+
+        // Planned owners are only for what gets created
+        memory.remove_file(Path::new("/srv/app/logs/today.log")).unwrap();
+        memory.remove_dir(Path::new("/srv/app/logs")).unwrap();
+        let manifest = planned_manifest(&memory, &args, &[], args.paths.len()).unwrap();
+        let owners: Vec<Option<&str>> = manifest.entries.iter().map(|entry| entry.owner.as_deref()).collect();
+        let owner = owner::describe(app.uid, app.gid);
+        assert_eq!(owners, [None, Some(owner.as_str()), Some(owner.as_str())]);
+    }
+
+    #[test]
+    fn test_interactive_always_answers() {
+        let mut input = std::io::Cursor::new("maybe\ny\n\nn\na\nq\n");
+        let answers: Vec<Answer> = (0..5).map(|_| read_answer("Create x?", &mut input).unwrap()).collect();
+        assert_eq!(answers, [Answer::Yes, Answer::No, Answer::No, Answer::All, Answer::Quit]);
+        assert_eq!(read_answer("Create x?", &mut input).unwrap(), Answer::Quit);
+
+        // A standing "all" or "quit" answers every later path without asking
+        let memory = MemoryFs::new();
+        let mut prompt = OperationPrompt::new(true);
+        prompt.standing = Some(Answer::All);
+        assert!(prompt.confirm(&memory, "a").unwrap());
+        prompt.standing = Some(Answer::Quit);
+        assert!(!prompt.confirm(&memory, "b").unwrap());
+        assert!(OperationPrompt::new(false).confirm(&memory, "c").unwrap());
+
+        let args = Args::try_parse_from(["bank", "-i", "a"]).unwrap();
+        assert_eq!(args.interactive, Some(Interactive::Ambiguous));
+        let args = Args::try_parse_from(["bank", "--interactive=always", "a"]).unwrap();
+        assert_eq!(args.interactive, Some(Interactive::Always));
+    }
+
+    #[test]
+    fn test_existing_only_touches_the_tree() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("site/assets")).unwrap();
+        memory.write_file(Path::new("site/assets/app.css"), b"").unwrap();
+        memory.write_file(Path::new("site/index.html"), b"").unwrap();
+        for path in ["site", "site/assets", "site/assets/app.css", "site/index.html"] {
+            memory.set_times(Path::new(path), SystemTime::UNIX_EPOCH, SystemTime::UNIX_EPOCH).unwrap();
+        }
+        let mut args = Args::try_parse_from(["bank", "--existing-only", "site", "--include", "*.css"]).unwrap();
+        expand_existing_only(&memory, &mut args).unwrap();
+        assert!(args.recursive && args.no_create);
+        assert_eq!(args.paths, ["site"]);
+
+        touch_tree(&memory, "site", &args).unwrap();
+        let moved = |path: &str| memory.metadata(Path::new(path)).unwrap().modified != SystemTime::UNIX_EPOCH;
+        assert!(moved("site/assets/app.css"));
+        assert!(!moved("site/index.html"));
+
+        let mut args = Args::try_parse_from(["bank", "--existing-only", "missing"]).unwrap();
+        assert!(expand_existing_only(&memory, &mut args).is_err());
+        assert!(Args::try_parse_from(["bank", "--include", "*.css", "site"]).is_err());
+    }
+
+    #[test]
+    fn test_processing_order_puts_parents_first() {
+        let mut args = create_test_args(["b.txt", "a/b/c.txt", "a.txt", "a/", "a/b/", "a/b/c.txt"].map(String::from).to_vec());
+        assert_eq!(processing_order(&args), [3, 4, 1, 5, 2, 0]);
+        args.unordered = true;
+        assert_eq!(processing_order(&args), [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_dry_run_plans_without_changes() {
+        let memory = MemoryFs::new();
+        memory.create_dir(Path::new("etc")).unwrap();
+        let mut args = create_test_args(vec!["srv/data/seed.txt".to_string(), "etc".to_string(), "srv/data/".to_string()]);
+        args.parents = true;
+        args.mode = Some("750".to_string());
+        args.dry_run = true;
+        args.unordered = true;
+
+        let manifest = planned_manifest(&memory, &args, &[], args.paths.len()).unwrap();
+        let planned: Vec<(&str, vfs::FileKind)> = manifest.entries.iter().map(|entry| (entry.path.as_str(), entry.kind)).collect();
+        assert_eq!(
+            planned,
+            [("srv", vfs::FileKind::Directory), ("srv/data", vfs::FileKind::Directory), ("srv/data/seed.txt", vfs::FileKind::File), ("etc", vfs::FileKind::Directory)]
+        );
+        assert_eq!(manifest.entries[2].mode, Some(0o750));
+        let plan = apply::Plan::new(&memory, &manifest).unwrap();
+        assert_eq!(plan.steps[3].action.name(), "update");
+
+        dry_run(&memory, &args, &[], args.paths.len()).unwrap();
+        assert!(!memory.exists(Path::new("srv")));
+
+        // Without -p the missing parent is reported as a conflict
+        args.parents = false;
+        assert!(dry_run(&memory, &args, &[], args.paths.len()).is_err());
+    }
+
+    #[test]
+    fn test_dry_run_reports_drift_of_existing_paths() {
+        let memory = MemoryFs::new();
+        memory.create_dir(Path::new("stamps")).unwrap();
+        memory.write_file(Path::new("stamps/built"), b"").unwrap();
+        memory.set_permissions(Path::new("stamps/built"), 0o644).unwrap();
+        memory.set_times(Path::new("stamps/built"), SystemTime::UNIX_EPOCH, SystemTime::UNIX_EPOCH).unwrap();
+        let mut args = create_test_args(vec!["stamps/built".to_string(), "stamps/missing".to_string()]);
+        args.no_create = true;
+        args.mode = Some("640".to_string());
+
+        let plan = apply::Plan::new(&memory, &planned_manifest(&memory, &args, &[], args.paths.len()).unwrap()).unwrap();
+        assert_eq!(plan.steps.len(), 1);
+        let apply::Action::Update(differences) = &plan.steps[0].action else { panic!("expected an update") };
+        let attributes: Vec<&str> = differences.iter().map(|difference| difference.attribute).collect();
+        assert_eq!(attributes, ["mode", "mtime"]);
+
+        // Touching only the access time leaves the mtime where it is
+        args.access_time_only = true;
+        args.mode = None;
+        let plan = apply::Plan::new(&memory, &planned_manifest(&memory, &args, &[], args.paths.len()).unwrap()).unwrap();
+        assert_eq!(plan.steps[0].action, apply::Action::Unchanged);
+    }
+
+    #[test]
+    fn test_on_exists_overwrite() {
+        let memory = MemoryFs::new();
+        memory.create_file(Path::new("marker")).unwrap();
+        memory.set_permissions(Path::new("marker"), 0o600).unwrap();
+        memory.create_dir(Path::new("dir.d")).unwrap();
+
+        let mut args = create_test_args(vec!["marker".to_string()]);
+        process_single_path(&memory, "marker", &args).unwrap();
+        assert_eq!(memory.metadata(Path::new("marker")).unwrap().mode, 0o600);
+
+        args.on_exists = OnExists::Overwrite;
+        process_single_path(&memory, "marker", &args).unwrap();
+        assert_eq!(memory.metadata(Path::new("marker")).unwrap().mode, 0o644);
+
+        // Directories are never removed to make room
+        args.file = true;
+        process_single_path(&memory, "dir.d", &args).unwrap();
+        assert!(memory.is_dir(Path::new("dir.d")));
+    }
+
     #[test]
     fn test_date_parsing() {
         let result = parse_date_string("2023-12-25 15:30:00");
@@ -629,4 +3500,329 @@ mod tests {
         args.timestamp = Some("202301011200".to_string());
         assert!(validate_arguments(&args).is_err());
     }
+
+    #[test]
+    fn test_allow_system() {
+        let mut args = create_test_args(vec!["/etc/app/app.conf".to_string()]);
+        assert!(check_target(&args, "/etc/app/app.conf").is_err());
+        assert!(check_target(&args, "/usr/local/../lib/x").is_err());
+        assert!(check_target(&args, "notes.txt").is_ok());
+
+        args.allow_system = true;
+        assert!(check_target(&args, "/etc/app/app.conf").is_ok());
+        // Allowed roots still apply
+        args.allowed_roots = vec![PathBuf::from("/srv/data")];
+        assert!(check_target(&args, "/etc/app/app.conf").is_err());
+
+        // Nothing local is written for a remote target
+        let mut args = create_test_args(vec!["/etc/app/app.conf".to_string()]);
+        args.remote = Some("ssh://web1/".to_string());
+        assert!(check_target(&args, "/etc/app/app.conf").is_ok());
+
+        // Subcommands get the same checks from GuardFs
+        let memory = MemoryFs::new();
+        let refusing = guard::GuardFs::new(&memory, Vec::new(), false);
+        assert!(refusing.create_dir_all(Path::new("/etc/app")).is_err());
+        assert!(!memory.exists(Path::new("/etc/app")));
+        let allowing = guard::GuardFs::new(&memory, Vec::new(), true);
+        allowing.create_dir_all(Path::new("/etc/app")).unwrap();
+        allowing.create_file(Path::new("/etc/app/app.conf")).unwrap();
+        assert!(memory.exists(Path::new("/etc/app/app.conf")));
+    }
+
+    #[test]
+    fn test_time_from_exif_needs_its_feature() {
+        let args = Args::try_parse_from(["bank", "--time-from-exif", "photos"]).unwrap();
+        assert_eq!(check_features(&args).is_ok(), cfg!(feature = "exif"));
+        assert!(check_features(&create_test_args(vec!["photos".to_string()])).is_ok());
+    }
+
+    #[test]
+    fn test_process_path_in_memory() {
+        let memory = MemoryFs::new();
+        let mut args = create_test_args(vec!["deep/nested/file.txt".to_string()]);
+        args.parents = true;
+        args.mode = Some("600".to_string());
+
+        process_single_path(&memory, "deep/nested/file.txt", &args).unwrap();
+
+        assert!(memory.is_dir(Path::new("deep/nested")));
+        assert_eq!(memory.metadata(Path::new("deep/nested/file.txt")).unwrap().mode, 0o600);
+    }
+
+    #[test]
+    fn test_default_modes_apply_to_new_paths() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("app")).unwrap();
+        memory.write_file(Path::new("app/old.key"), b"").unwrap();
+        memory.set_permissions(Path::new("app/old.key"), 0o644).unwrap();
+        let mut args = create_test_args(Vec::new());
+        args.parents = true;
+        args.default_modes = vec![("**/bin".to_string(), 0o755), ("**/*.key".to_string(), 0o600)];
+
+        for path_str in ["app/bin/tool.key", "app/old.key", "app/notes.txt"] {
+            process_single_path(&memory, path_str, &args).unwrap();
+        }
+        assert_eq!(memory.metadata(Path::new("app/bin")).unwrap().mode, 0o755);
+        assert_eq!(memory.metadata(Path::new("app/bin/tool.key")).unwrap().mode, 0o600);
+        // New paths are created with the default mode rather than chmodded to it
+        assert_eq!(creation_mode(&args, false, "app/other.key"), Some(0o600));
+        assert_eq!(creation_bits(0o600, false), 0o600);
+        assert_eq!(creation_bits(0o444, true), 0o744);
+        // Existing paths keep their mode, and --mode wins over the defaults
+        assert_eq!(memory.metadata(Path::new("app/old.key")).unwrap().mode, 0o644);
+        args.mode = Some("640".to_string());
+        process_single_path(&memory, "app/new.key", &args).unwrap();
+        assert_eq!(memory.metadata(Path::new("app/new.key")).unwrap().mode, 0o640);
+    }
+
+    #[test]
+    fn test_private_and_public_read_presets() {
+        let memory = MemoryFs::new();
+        let mut args = create_test_args(Vec::new());
+        args.parents = true;
+        args.private = true;
+        for path_str in ["vault/keys/", "vault/notes/todo.txt"] {
+            process_single_path(&memory, path_str, &args).unwrap();
+        }
+        for (path, mode) in [("vault", 0o700), ("vault/keys", 0o700), ("vault/notes", 0o700), ("vault/notes/todo.txt", 0o600)] {
+            assert_eq!(memory.metadata(Path::new(path)).unwrap().mode, mode, "{}", path);
+        }
+
+        args.private = false;
+        args.public_read = true;
+        args.paths = vec!["www/index.html".to_string()];
+        let manifest = planned_manifest(&memory, &args, &[], args.paths.len()).unwrap();
+        let modes: Vec<Option<u32>> = manifest.entries.iter().map(|entry| entry.mode).collect();
+        assert_eq!(modes, [Some(0o755), Some(0o644)]);
+        assert!(Args::try_parse_from(["bank", "--private", "-m", "600", "a"]).is_err());
+    }
+
+    #[test]
+    fn test_executable_follows_read_bits() {
+        let memory = MemoryFs::new();
+        let mut args = create_test_args(Vec::new());
+        args.executable = true;
+        process_single_path(&memory, "run.sh", &args).unwrap();
+        assert_eq!(memory.metadata(Path::new("run.sh")).unwrap().mode, 0o755);
+        args.mode = Some("640".to_string());
+        process_single_path(&memory, "deploy.sh", &args).unwrap();
+        assert_eq!(memory.metadata(Path::new("deploy.sh")).unwrap().mode, 0o750);
+        process_single_path(&memory, "bin/", &create_test_args(Vec::new())).unwrap();
+        args.mode = None;
+        process_single_path(&memory, "bin/", &args).unwrap();
+        assert_eq!(memory.metadata(Path::new("bin")).unwrap().mode, 0o755);
+        assert_eq!(executable_mode(0o604), 0o705);
+    }
+
+    #[test]
+    fn test_process_path_reports_injected_failures() {
+        let memory = MemoryFs::new();
+        memory.fail_on("full.txt", std::io::ErrorKind::StorageFull);
+        memory.fail_on("locked", std::io::ErrorKind::PermissionDenied);
+        let args = create_test_args(vec!["full.txt".to_string()]);
+
+        let error = process_single_path(&memory, "full.txt", &args).unwrap_err();
+        assert!(error.to_string().contains("Failed to create file full.txt"));
+        assert_eq!(error.code(), "Io");
+
+        let mut args = create_test_args(vec!["locked/".to_string()]);
+        args.directory = true;
+        let error = process_single_path(&memory, "locked/", &args).unwrap_err();
+        assert_eq!(error.code(), "PermissionDenied");
+        assert_eq!(error.path(), Some(Path::new("locked/")));
+
+        let args = create_test_args(vec!["missing/file.txt".to_string()]);
+        let error = process_single_path(&memory, "missing/file.txt", &args).unwrap_err();
+        assert_eq!(error.code(), "ParentMissing");
+        assert!(!memory.exists(Path::new("locked")));
+    }
+
+    #[test]
+    fn test_parents_are_reported_individually() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("a")).unwrap();
+
+        let created = create_parent_directories(&memory, Path::new("a/b/c/file.txt"), &|_| None).unwrap();
+        assert_eq!(created, vec![PathBuf::from("a/b"), PathBuf::from("a/b/c")]);
+        assert!(create_parent_directories(&memory, Path::new("a/b/c/other.txt"), &|_| None).unwrap().is_empty());
+        create_parent_directories(&memory, Path::new("a/d/file.txt"), &|_| Some(0o750)).unwrap();
+        assert_eq!(memory.metadata(Path::new("a/d")).unwrap().mode, 0o750);
+
+        let mut args = create_test_args(vec!["x/y/file.txt".to_string()]);
+        args.parents = true;
+        args.parents_mode = Some("u=rwx,g=rx,o=".to_string());
+        process_single_path(&memory, "x/y/file.txt", &args).unwrap();
+        assert_eq!(memory.metadata(Path::new("x")).unwrap().mode, 0o750);
+        assert_eq!(memory.metadata(Path::new("x/y")).unwrap().mode, 0o750);
+        assert_eq!(memory.metadata(Path::new("x/y/file.txt")).unwrap().mode, 0o644);
+    }
+
+    #[test]
+    fn test_independent_atime_and_mtime_dates() {
+        let memory = MemoryFs::new();
+        let mut args = create_test_args(vec!["log.txt".to_string()]);
+        args.atime_date = Some("2024-01-01".to_string());
+        args.mtime_date = Some("2024-06-01 12:00:00".to_string());
+        process_single_path(&memory, "log.txt", &args).unwrap();
+
+        let metadata = memory.metadata(Path::new("log.txt")).unwrap();
+        assert_eq!(metadata.accessed, UNIX_EPOCH + std::time::Duration::from_secs(1_704_067_200));
+        assert_eq!(metadata.modified, UNIX_EPOCH + std::time::Duration::from_secs(1_717_243_200));
+
+        args.mtime_date = Some("not a date".to_string());
+        assert_eq!(process_single_path(&memory, "log.txt", &args).unwrap_err().code(), "BadTimestamp");
+    }
+
+    #[test]
+    fn test_adjust_shifts_existing_times() {
+        assert_eq!(parse_adjustment("30"), Ok(30));
+        assert_eq!(parse_adjustment("-0130"), Ok(-90));
+        assert_eq!(parse_adjustment("010000"), Ok(3600));
+        assert!(parse_adjustment("0160").is_err());
+        assert!(parse_adjustment("123").is_err());
+
+        let memory = MemoryFs::new();
+        memory.create_file(Path::new("skewed.txt")).unwrap();
+        let original = UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        memory.set_times(Path::new("skewed.txt"), original, original).unwrap();
+
+        let mut args = create_test_args(vec!["skewed.txt".to_string(), "missing.txt".to_string()]);
+        args.adjust = Some(-3600);
+        args.modification_time_only = true;
+        process_single_path(&memory, "skewed.txt", &args).unwrap();
+        process_single_path(&memory, "missing.txt", &args).unwrap();
+
+        let metadata = memory.metadata(Path::new("skewed.txt")).unwrap();
+        assert_eq!(metadata.accessed, original);
+        assert_eq!(metadata.modified, original - std::time::Duration::from_secs(3600));
+        assert!(!memory.exists(Path::new("missing.txt")));
+    }
+
+    #[test]
+    fn test_shift_moves_a_tree() {
+        assert_eq!(parse_shift("+2h"), Ok(7200));
+        assert_eq!(parse_shift("-1h30m"), Ok(-5400));
+        assert_eq!(parse_shift("90s"), Ok(90));
+        assert!(parse_shift("+2x").is_err());
+        assert!(parse_shift("1.5s").is_err());
+
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("photos/day1")).unwrap();
+        memory.create_file(Path::new("photos/day1/a.jpg")).unwrap();
+        let original = UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        memory.set_times(Path::new("photos/day1/a.jpg"), original, original).unwrap();
+
+        let args = Args::try_parse_from(["bank", "--shift", "-2h", "--type", "f", "photos"]).unwrap();
+        assert_eq!(args.shift, Some(-7200));
+        let args = Args { adjust: args.shift, recursive: true, ..args };
+        touch_tree(&memory, "photos", &args).unwrap();
+        let metadata = memory.metadata(Path::new("photos/day1/a.jpg")).unwrap();
+        assert_eq!((metadata.accessed, metadata.modified), (original - std::time::Duration::from_secs(7200), original - std::time::Duration::from_secs(7200)));
+    }
+
+    #[test]
+    fn test_repair_implausible_times() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("build/obj")).unwrap();
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let future = now + std::time::Duration::from_secs(86_400 * 365);
+        let fine = now - std::time::Duration::from_secs(3600);
+        for (name, time) in [("future.o", future), ("epoch.o", UNIX_EPOCH), ("fine.o", fine)] {
+            let path = Path::new("build/obj").join(name);
+            memory.create_file(&path).unwrap();
+            memory.set_times(&path, time, time).unwrap();
+        }
+
+        let mut args = Args::try_parse_from(["bank", "--clamp-future", "--fix-epoch", "2020-01-01", "build"]).unwrap();
+        assert!(repairs_times(&args));
+        let replacement = args.fix_epoch.unwrap();
+        let modified = |name: &str| memory.metadata(&Path::new("build/obj").join(name)).unwrap().modified;
+        for name in ["future.o", "epoch.o", "fine.o"] {
+            repair_file_times(&memory, &Path::new("build/obj").join(name), &args, now).unwrap();
+        }
+        assert_eq!((modified("future.o"), modified("epoch.o"), modified("fine.o")), (now, replacement, fine));
+        assert!(!repair_file_times(&memory, Path::new("build/obj/fine.o"), &args, now).unwrap());
+
+        args.clamp_future = false;
+        args.recursive = true;
+        memory.set_times(Path::new("build/obj/future.o"), future, future).unwrap();
+        touch_tree(&memory, "build", &args).unwrap();
+        assert_eq!(modified("future.o"), future);
+        assert!(Args::try_parse_from(["bank", "--fix-epoch", "someday", "build"]).is_err());
+    }
+
+    #[test]
+    fn test_read_path_list() {
+        let input = "src/main.rs\r\n\n  \nlogs/\nname with spaces.txt\n";
+        assert_eq!(
+            read_path_list(input.as_bytes()).unwrap(),
+            vec!["src/main.rs", "logs/", "name with spaces.txt"]
+        );
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let list = "# Service layout\r\nsrv/app/\n\n  # logs come later\nsrv/app/config.toml\n";
+        assert_eq!(parse_list(list, false), vec!["srv/app/", "srv/app/config.toml"]);
+        assert_eq!(parse_list("./#draft.md\0./a\nb\0", true), vec!["./#draft.md", "./a\nb"]);
+    }
+
+    #[test]
+    fn test_relative_to_base_directory() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("project")).unwrap();
+        let mut args = create_test_args(vec!["logs/".to_string(), "/abs/file.txt".to_string(), "a.txt".to_string()]);
+
+        apply_base_directory(&memory, Path::new("project"), &mut args).unwrap();
+        assert_eq!(args.paths, vec!["project/logs/", "/abs/file.txt", "project/a.txt"]);
+
+        assert!(apply_base_directory(&memory, Path::new("missing"), &mut args).is_err());
+        args.parents = true;
+        assert!(apply_base_directory(&memory, Path::new("missing"), &mut args).is_ok());
+    }
+
+    #[test]
+    fn test_broken_config_keeps_its_roots() {
+        let temp_dir = TempDir::new().unwrap();
+        let roots = format!("allowed-roots = [\"{}\"]\n", temp_dir.path().join("work").display());
+        let config_file = temp_dir.path().join("config.toml");
+        std::fs::write(&config_file, format!("{}colour = \"red\"\n", roots)).unwrap();
+        let outside = temp_dir.path().join("elsewhere/file.txt");
+
+        // Ignoring the file would drop its roots, so the run stops instead
+        let err = load_config(config::Config::load_from(&config_file), false).unwrap_err();
+        assert!(format!("{:#}", err).contains("--allow-anywhere"));
+        assert_eq!(load_config(config::Config::load_from(&config_file), true).unwrap(), config::Config::default());
+
+        std::fs::write(&config_file, roots).unwrap();
+        let config = load_config(config::Config::load_from(&config_file), false).unwrap();
+        assert!(guard::check_allowed(&config.allowed_roots, &outside.to_string_lossy()).is_err());
+    }
+
+    #[test]
+    fn test_check_names() {
+        let memory = MemoryFs::new();
+        let mut args = create_test_args(vec!["ok.txt".to_string(), "nul".to_string(), "a:b".to_string()]);
+        assert!(check_names(&memory, &args, naming::NameCheck::Windows).is_err());
+
+        args.check_warn = true;
+        assert!(check_names(&memory, &args, naming::NameCheck::Windows).is_ok());
+    }
+
+    #[test]
+    fn test_into_archive_does_not_touch_disk() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_path = temp_dir.path().join("out.tar");
+        let target = temp_dir.path().join("placeholder.txt");
+
+        let mut args = create_test_args(vec!["data/_SUCCESS".to_string(), target.to_str().unwrap().to_string()]);
+        args.parents = true;
+        args.timestamp = Some("202401010000".to_string());
+        write_into_archive(&archive_path, &args).unwrap();
+
+        assert!(archive_path.exists());
+        assert!(!target.exists());
+        assert!(!PathBuf::from("data").exists());
+    }
 }
@@ -0,0 +1,942 @@
+//! Manifests: a declarative list of paths with their type and attributes,
+//! written as YAML (or JSON for `.json` files), or as Ansible tasks
+//!
+//! The YAML reader understands the subset bank writes, which is also what
+//! people write by hand: `key: value` pairs, a list of flat mappings under
+//! `entries`, plain or quoted scalars and `#` comments.
+
+use crate::hash::{self, HashAlgorithm};
+use crate::json::{self, Value};
+use crate::normalize;
+use crate::owner;
+use crate::vfs::{Change, FileKind, Filesystem, Metadata};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Version of the manifest format: 2 added `when`, `content` and doubled `$`
+pub const VERSION: u32 = 2;
+
+/// Formats `--emit` can translate a plan into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EmitFormat {
+    /// A task list for Ansible's `file` module
+    Ansible,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: String,
+    pub kind: FileKind,
+    /// Permission bits
+    pub mode: Option<u32>,
+    /// `user`, `user:group` or `:group`
+    pub owner: Option<String>,
+    pub mtime: Option<SystemTime>,
+    /// Content hash of files, `<algorithm>:<hex digest>`
+    pub hash: Option<String>,
+    /// What the run that wrote the manifest did to the path
+    pub change: Option<Change>,
+    /// Conditions under which the entry applies (see `check_when`)
+    pub when: Option<String>,
+    /// Contents of a file, written when it is created
+    pub content: Option<String>,
+}
+
+impl Entry {
+    /// Describe a path as it is on disk
+    pub fn from_metadata(path: &str, metadata: &Metadata) -> Self {
+        Entry {
+            path: path.to_string(),
+            kind: metadata.kind,
+            mode: Some(metadata.mode),
+            owner: Some(owner::describe(metadata.uid, metadata.gid)),
+            mtime: Some(metadata.modified),
+            hash: None,
+            change: None,
+            when: None,
+            content: None,
+        }
+    }
+
+    /// `(key, value)` pairs in output order, values already quoted for YAML and JSON
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("path", json::quote(&self.path)), ("type", json::quote(kind_name(self.kind)))];
+        if let Some(mode) = self.mode {
+            fields.push(("mode", json::quote(&format!("{:04o}", mode))));
+        }
+        if let Some(owner) = &self.owner {
+            fields.push(("owner", json::quote(owner)));
+        }
+        if let Some(mtime) = self.mtime {
+            fields.push(("mtime", json::quote(&format_time(mtime))));
+        }
+        if let Some(hash) = &self.hash {
+            fields.push(("hash", json::quote(hash)));
+        }
+        if let Some(change) = self.change {
+            let change = match change {
+                Change::Created => "created",
+                Change::Modified => "modified",
+            };
+            fields.push(("change", json::quote(change)));
+        }
+        if let Some(when) = &self.when {
+            fields.push(("when", json::quote(when)));
+        }
+        if let Some(content) = &self.content {
+            fields.push(("content", json::quote(content)));
+        }
+        fields
+    }
+
+    /// `fields` as a manifest file records them: a `$` in the path, owner or
+    /// content is doubled so `apply` does not take it for a placeholder
+    fn written_fields(&self) -> Vec<(&'static str, String)> {
+        let escape = |text: &str| text.replace('$', "$$");
+        let escaped = Entry {
+            path: escape(&self.path),
+            owner: self.owner.as_deref().map(escape),
+            content: self.content.as_deref().map(escape),
+            ..self.clone()
+        };
+        escaped.fields()
+    }
+
+    /// Build an entry from its `(key, value)` pairs, as read from YAML or JSON
+    fn from_fields(fields: &[(String, Value)]) -> Result<Self> {
+        let mut path = None;
+        let mut kind = None;
+        let mut entry = Entry {
+            path: String::new(),
+            kind: FileKind::File,
+            mode: None,
+            owner: None,
+            mtime: None,
+            hash: None,
+            change: None,
+            when: None,
+            content: None,
+        };
+        for (key, value) in fields {
+            let text = match value {
+                Value::Null => continue,
+                Value::String(text) => text.clone(),
+                // Modes are octal digits, so a bare 644 means "644"
+                Value::Number(number) if key == "mode" && number.fract() == 0.0 && *number >= 0.0 => {
+                    format!("{}", *number as u64)
+                }
+                other => bail!("'{}' must be a string, found {}", key, other.type_name()),
+            };
+            match key.as_str() {
+                "path" => path = Some(text),
+                "type" => kind = Some(parse_kind(&text).with_context(|| format!("unknown type '{}' (expected file, dir or symlink)", text))?),
+                "mode" => {
+                    entry.mode = Some(u32::from_str_radix(&text, 8).ok().filter(|mode| *mode <= 0o7777).with_context(|| format!("invalid mode '{}'", text))?)
+                }
+                "owner" => entry.owner = Some(text),
+                "mtime" => entry.mtime = Some(DateTime::parse_from_rfc3339(&text).with_context(|| format!("invalid mtime '{}'", text))?.into()),
+                "hash" => entry.hash = Some(text),
+                "change" => {
+                    entry.change = Some(match text.as_str() {
+                        "created" => Change::Created,
+                        "modified" => Change::Modified,
+                        other => bail!("unknown change '{}'", other),
+                    })
+                }
+                "when" => {
+                    parse_when(&text).with_context(|| format!("invalid condition '{}'", text))?;
+                    entry.when = Some(text);
+                }
+                "content" => entry.content = Some(text),
+                other => bail!("unknown key '{}'", other),
+            }
+        }
+        entry.path = path.filter(|path| !path.is_empty()).context("missing 'path'")?;
+        // Without a type, a trailing slash marks a directory, as on the command line
+        entry.kind = kind.unwrap_or(if entry.path.ends_with('/') { FileKind::Directory } else { FileKind::File });
+        if entry.content.is_some() && entry.kind != FileKind::File {
+            bail!("'content' is only for files");
+        }
+        Ok(entry)
+    }
+}
+
+/// Where an entry starts in the manifest text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Describe the current state of every changed path; paths that are gone
+    /// again are left out
+    pub fn from_changes(fs: &dyn Filesystem, changes: &[(PathBuf, Change)]) -> Self {
+        let entries = changes
+            .iter()
+            .filter_map(|(path, change)| {
+                let metadata = fs.symlink_metadata(path).ok()?;
+                let entry = Entry::from_metadata(&path.to_string_lossy(), &metadata);
+                Some(Entry { change: Some(*change), ..entry })
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Record the content hash of every regular file
+    pub fn add_hashes(&mut self, algorithm: HashAlgorithm) -> Result<()> {
+        for entry in self.entries.iter_mut().filter(|entry| entry.kind == FileKind::File) {
+            let digest = hash::hash_file(Path::new(&entry.path), algorithm)
+                .with_context(|| format!("Failed to hash {}", entry.path))?;
+            entry.hash = Some(digest);
+        }
+        Ok(())
+    }
+
+    pub fn to_yaml(&self) -> String {
+        let mut out = format!("# bank manifest\nversion: {}\n", VERSION);
+        if self.entries.is_empty() {
+            out.push_str("entries: []\n");
+            return out;
+        }
+        out.push_str("entries:\n");
+        for entry in &self.entries {
+            for (index, (key, value)) in entry.written_fields().into_iter().enumerate() {
+                let lead = if index == 0 { "  - " } else { "    " };
+                out.push_str(&format!("{}{}: {}\n", lead, key, value));
+            }
+        }
+        out
+    }
+
+    /// One `ansible.builtin.file` task per entry. Files use `state: touch`
+    /// with preserved times unless the entry has an mtime, so existing files
+    /// are left as they are; times are in the local time of the host writing
+    /// the tasks, which is how Ansible reads them on the target.
+    pub fn to_ansible(&self) -> String {
+        let mut out = String::from("# Ansible tasks written by bank\n");
+        if self.entries.is_empty() {
+            out.push_str("[]\n");
+            return out;
+        }
+        for entry in &self.entries {
+            let state = match entry.kind {
+                FileKind::Directory => "directory",
+                FileKind::File => "touch",
+                // A link needs its target, which manifests do not record
+                FileKind::Symlink => {
+                    out.push_str(&format!("# Skipped symlink {}\n", entry.path));
+                    continue;
+                }
+            };
+            let path = entry.path.trim_end_matches('/');
+            let path = if path.is_empty() { entry.path.as_str() } else { path };
+            out.push_str(&format!("- name: {}\n", json::quote(&format!("Create {} {}", kind_name(entry.kind), path))));
+            out.push_str("  ansible.builtin.file:\n");
+            out.push_str(&format!("    path: {}\n    state: {}\n", json::quote(path), state));
+            if let Some(mode) = entry.mode {
+                out.push_str(&format!("    mode: {}\n", json::quote(&format!("{:04o}", mode))));
+            }
+            if let Some(owner) = &entry.owner {
+                let (user, group) = owner.split_once(':').unwrap_or((owner, ""));
+                if !user.is_empty() {
+                    out.push_str(&format!("    owner: {}\n", json::quote(user)));
+                }
+                if !group.is_empty() {
+                    out.push_str(&format!("    group: {}\n", json::quote(group)));
+                }
+            }
+            match entry.mtime {
+                Some(mtime) => {
+                    let stamp = DateTime::<Local>::from(mtime).format("%Y%m%d%H%M.%S").to_string();
+                    out.push_str(&format!("    modification_time: {}\n", json::quote(&stamp)));
+                }
+                None if entry.kind == FileKind::File => {
+                    out.push_str("    modification_time: preserve\n    access_time: preserve\n");
+                }
+                None => {}
+            }
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let fields: Vec<String> = entry
+                    .written_fields()
+                    .into_iter()
+                    .map(|(key, value)| format!("{}: {}", json::quote(key), value))
+                    .collect();
+                format!("    {{{}}}", fields.join(", "))
+            })
+            .collect();
+        if entries.is_empty() {
+            return format!("{{\n  \"version\": {},\n  \"entries\": []\n}}\n", VERSION);
+        }
+        format!("{{\n  \"version\": {},\n  \"entries\": [\n{}\n  ]\n}}\n", VERSION, entries.join(",\n"))
+    }
+
+    /// Read a manifest, as JSON if the file ends in `.json` and YAML otherwise,
+    /// with where each of its entries starts
+    pub fn load(path: &Path) -> Result<(Self, Vec<Position>)> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read manifest {}", path.display()))?;
+        let parsed = if is_json(path) { Self::parse_json_located(&text) } else { Self::parse_yaml_located(&text) };
+        parsed.with_context(|| format!("Invalid manifest {}", path.display()))
+    }
+
+    #[cfg(test)]
+    pub fn parse_json(text: &str) -> Result<Self> {
+        Ok(Self::parse_json_located(text)?.0)
+    }
+
+    fn parse_json_located(text: &str) -> Result<(Self, Vec<Position>)> {
+        let manifest = Self::parse_json_entries(text)?;
+        let positions = json::item_positions(text, "entries")?.into_iter().map(|(line, column)| Position { line, column }).collect();
+        Ok((manifest, positions))
+    }
+
+    fn parse_json_entries(text: &str) -> Result<Self> {
+        let document = json::parse(text)?;
+        let version = check_version(document.get("version"))?;
+        let items = match document.get("entries") {
+            Some(Value::Array(items)) => items,
+            Some(other) => bail!("'entries' must be an array, found {}", other.type_name()),
+            None => bail!("missing 'entries'"),
+        };
+        let entries = items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| match item {
+                Value::Object(fields) => Entry::from_fields(fields).with_context(|| format!("entry {}", index + 1)),
+                other => bail!("entry {}: expected an object, found {}", index + 1, other.type_name()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        check_fields(version, &entries)?;
+        Ok(Self { entries })
+    }
+
+    #[cfg(test)]
+    pub fn parse_yaml(text: &str) -> Result<Self> {
+        Ok(Self::parse_yaml_located(text)?.0)
+    }
+
+    fn parse_yaml_located(text: &str) -> Result<(Self, Vec<Position>)> {
+        let mut version = None;
+        let mut in_entries = false;
+        // Each entry's fields, with where it started for error messages
+        let mut items: Vec<(Position, Vec<(String, Value)>)> = Vec::new();
+        for (index, raw) in text.lines().enumerate() {
+            let line = index + 1;
+            let content = strip_comment(raw).trim_end();
+            if content.trim().is_empty() {
+                continue;
+            }
+            let indent = content.len() - content.trim_start().len();
+            let content = content.trim_start();
+
+            if indent == 0 {
+                let (key, value) = split_pair(content).with_context(|| format!("line {}: expected 'key: value'", line))?;
+                match key {
+                    "version" => version = Some(parse_scalar(value).with_context(|| format!("line {}", line))?),
+                    "entries" if value.is_empty() || value == "[]" => in_entries = value.is_empty(),
+                    "entries" => bail!("line {}: 'entries' must be a list", line),
+                    other => bail!("line {}: unknown key '{}'", line, other),
+                }
+                continue;
+            }
+            if !in_entries {
+                bail!("line {}: unexpected indentation", line);
+            }
+            let pair = match content.strip_prefix("- ").or_else(|| (content == "-").then_some("")) {
+                Some(rest) => {
+                    items.push((Position { line, column: indent + 1 }, Vec::new()));
+                    rest.trim_start()
+                }
+                None => content,
+            };
+            if pair.is_empty() {
+                continue;
+            }
+            let Some((_, fields)) = items.last_mut() else {
+                bail!("line {}: expected a list item ('- path: ...')", line);
+            };
+            let (key, value) = split_pair(pair).with_context(|| format!("line {}: expected 'key: value'", line))?;
+            fields.push((key.to_string(), parse_scalar(value).with_context(|| format!("line {}", line))?));
+        }
+
+        let version = check_version(version.as_ref())?;
+        let entries = items
+            .iter()
+            .map(|(position, fields)| Entry::from_fields(fields).with_context(|| format!("entry at line {}", position.line)))
+            .collect::<Result<Vec<_>>>()?;
+        check_fields(version, &entries)?;
+        Ok((Self { entries }, items.into_iter().map(|(position, _)| position).collect()))
+    }
+
+    /// Drop the entries whose `when:` does not hold, with their positions
+    pub fn retain_when(&mut self, positions: &mut Vec<Position>, facts: &Facts) -> Result<()> {
+        let mut keep = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            keep.push(match &entry.when {
+                Some(when) => check_when(when, facts).with_context(|| format!("in the condition of {}", entry.path))?,
+                None => true,
+            });
+        }
+        let mut flags = keep.iter();
+        self.entries.retain(|_| *flags.next().expect("one flag per entry"));
+        if positions.len() == keep.len() {
+            let mut flags = keep.iter();
+            positions.retain(|_| *flags.next().expect("one flag per entry"));
+        }
+        Ok(())
+    }
+
+    /// The `${name}` placeholders in the entries' paths, owners and contents,
+    /// in the order they first appear
+    pub fn variables(&self) -> Result<Vec<String>> {
+        let mut names: Vec<String> = Vec::new();
+        for text in self.entries.iter().flat_map(|entry| std::iter::once(&entry.path).chain(&entry.owner).chain(&entry.content)) {
+            expand_variables(text, &mut |name| {
+                if !names.iter().any(|known| known == name) {
+                    names.push(name.to_string());
+                }
+                Some(String::new())
+            })?;
+        }
+        Ok(names)
+    }
+
+    /// Replace every `${name}` placeholder with its value, and `$$` with `$`
+    pub fn substitute(&mut self, vars: &HashMap<String, String>) -> Result<()> {
+        for entry in &mut self.entries {
+            let mut lookup = |name: &str| vars.get(name).cloned();
+            entry.path = expand_variables(&entry.path, &mut lookup).with_context(|| format!("in path {}", entry.path))?;
+            if let Some(owner) = &entry.owner {
+                entry.owner = Some(expand_variables(owner, &mut lookup).with_context(|| format!("in the owner of {}", entry.path))?);
+            }
+            if let Some(content) = &entry.content {
+                entry.content = Some(expand_variables(content, &mut lookup).with_context(|| format!("in the content of {}", entry.path))?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything that makes the manifest unsafe to apply in `base`, where
+    /// relative entries are resolved: entries repeating a path, entries below
+    /// one declared as a file, relative entries leaving `base` (through `..`
+    /// or an existing symlink) and paths running into a symlink cycle. Each
+    /// problem starts with the position of its entry, when it is known.
+    pub fn validate(&self, fs: &dyn Filesystem, base: &Path, positions: &[Position]) -> Vec<String> {
+        let at = |index: usize| match positions.get(index) {
+            Some(position) => format!("line {}, column {}", position.line, position.column),
+            None => format!("entry {}", index + 1),
+        };
+        let targets: Vec<PathBuf> = self.entries.iter().map(|entry| normalize::lexical(Path::new(&entry.path))).collect();
+        let mut first: HashMap<&Path, usize> = HashMap::new();
+        for (index, target) in targets.iter().enumerate() {
+            first.entry(target).or_insert(index);
+        }
+        let real_base = fs.canonicalize(base).ok();
+
+        let mut problems = Vec::new();
+        for (index, (entry, target)) in self.entries.iter().zip(&targets).enumerate() {
+            let mut problem = |text: String| problems.push(format!("{}: {}: {}", at(index), entry.path, text));
+            if let Some(&earlier) = first.get(target.as_path()).filter(|&&earlier| earlier != index) {
+                problem(format!("repeats the entry at {}", at(earlier)));
+            }
+            if let Some((ancestor, owner)) = target
+                .ancestors()
+                .skip(1)
+                .find_map(|ancestor| first.get(ancestor).map(|&owner| (ancestor, owner)))
+                .filter(|&(_, owner)| self.entries[owner].kind == FileKind::File)
+            {
+                problem(format!("lies under {}, which {} declares as a file", ancestor.display(), at(owner)));
+            }
+            if target.has_root() {
+                problem("is absolute, but entries are relative to the directory the manifest is applied in".to_string());
+                continue;
+            }
+            if target.components().next() == Some(Component::ParentDir) {
+                problem("leaves the directory the manifest is applied in".to_string());
+                continue;
+            }
+
+            // The deepest part that exists already; a symlink the entry
+            // declares is looked at from its parent
+            let full = base.join(target);
+            let Some(existing) = full.ancestors().find(|prefix| fs.symlink_metadata(prefix).is_ok()) else {
+                continue;
+            };
+            let existing = match existing.parent() {
+                Some(parent) if existing == full && entry.kind == FileKind::Symlink => parent,
+                _ => existing,
+            };
+            let shown = existing.strip_prefix(base).unwrap_or(existing).display();
+            match fs.canonicalize(existing) {
+                Err(err) if err.raw_os_error() == Some(libc::ELOOP) => {
+                    problem(format!("runs into a symlink cycle at {}", shown));
+                }
+                Ok(real) if target.is_relative() && real_base.as_ref().is_some_and(|real_base| !real.starts_with(real_base)) => {
+                    problem(format!("leaves the directory the manifest is applied in through a symlink at {}", shown));
+                }
+                _ => {}
+            }
+        }
+        problems
+    }
+
+    /// Write the manifest to `path`, as JSON if it ends in `.json` and YAML otherwise
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let text = if is_json(path) { self.to_json() } else { self.to_yaml() };
+        std::fs::write(path, text).with_context(|| format!("Failed to write manifest {}", path.display()))
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Manifests without a version are read as the current one
+/// The version a manifest declares; one without is read as the current version
+fn check_version(version: Option<&Value>) -> Result<u32> {
+    let version = match version {
+        None | Some(Value::Null) => return Ok(VERSION),
+        Some(Value::Number(number)) => *number,
+        Some(Value::String(text)) => text.parse().with_context(|| format!("invalid version '{}'", text))?,
+        Some(other) => bail!("'version' must be a number, found {}", other.type_name()),
+    };
+    if version.fract() != 0.0 || version < 1.0 || version > VERSION as f64 {
+        bail!("unsupported manifest version {} (this bank reads version {})", version, VERSION);
+    }
+    Ok(version as u32)
+}
+
+/// Fail on the fields a manifest's version does not have yet
+fn check_fields(version: u32, entries: &[Entry]) -> Result<()> {
+    if version < 2 {
+        if let Some(entry) = entries.iter().find(|entry| entry.when.is_some() || entry.content.is_some()) {
+            bail!("{}: 'when' and 'content' need manifest version 2", entry.path);
+        }
+    }
+    Ok(())
+}
+
+/// Drop a `#` comment that starts the line or follows whitespace, outside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut previous = ' ';
+    for (index, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            // Quotes only open a scalar at its start, so `it's` stays plain text
+            None if (c == '"' || c == '\'') && previous.is_whitespace() => quote = Some(c),
+            None if c == '#' && previous.is_whitespace() => return &line[..index],
+            None => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+/// Split `key: value` at the first `: ` (or a trailing `:`)
+fn split_pair(text: &str) -> Option<(&str, &str)> {
+    let (key, value) = match text.split_once(": ") {
+        Some(pair) => pair,
+        None => (text.strip_suffix(':')?, ""),
+    };
+    let key = key.trim();
+    (!key.is_empty()).then_some((key, value.trim()))
+}
+
+/// A YAML scalar: double-quoted (JSON escapes), single-quoted, null, or plain text
+fn parse_scalar(text: &str) -> Result<Value> {
+    if text.starts_with('"') {
+        return match json::parse(text)? {
+            Value::String(value) => Ok(Value::String(value)),
+            _ => bail!("invalid quoted string {}", text),
+        };
+    }
+    if let Some(inner) = text.strip_prefix('\'') {
+        let inner = inner.strip_suffix('\'').with_context(|| format!("unterminated string {}", text))?;
+        return Ok(Value::String(inner.replace("''", "'")));
+    }
+    Ok(match text {
+        "" | "~" | "null" => Value::Null,
+        _ => Value::String(text.to_string()),
+    })
+}
+
+/// What `when:` conditions are checked against
+pub struct Facts<'a> {
+    /// As in `std::env::consts::OS`: linux, macos, windows, ...
+    pub os: &'a str,
+    pub profile: Option<&'a str>,
+    pub env: &'a dyn Fn(&str) -> Option<String>,
+}
+
+/// One clause of a `when:`
+#[derive(Debug, PartialEq, Eq)]
+enum Clause<'a> {
+    /// `os=linux|macos`, `profile!=prod`, `env.NAME=value`; the values are alternatives
+    Compare { key: &'a str, negated: bool, values: Vec<&'a str> },
+    /// `env.NAME` (set and not empty) or `!env.NAME`
+    EnvSet { name: &'a str, negated: bool },
+}
+
+/// Parse a `when:`: clauses separated by commas, all of which must hold
+fn parse_when(when: &str) -> Result<Vec<Clause<'_>>> {
+    when.split(',')
+        .map(str::trim)
+        .map(|clause| {
+            if clause.is_empty() {
+                bail!("empty condition");
+            }
+            let (key, negated, value) = match clause.find('=') {
+                Some(at) if clause[..at].ends_with('!') => (&clause[..at - 1], true, Some(&clause[at + 1..])),
+                Some(at) => (&clause[..at], false, Some(&clause[at + 1..])),
+                None => match clause.strip_prefix('!') {
+                    Some(rest) => (rest, true, None),
+                    None => (clause, false, None),
+                },
+            };
+            let key = key.trim();
+            match (key, value) {
+                ("os" | "profile", Some(value)) => Ok(Clause::Compare { key, negated, values: value.split('|').map(str::trim).collect() }),
+                (key, value) if key.starts_with("env.") && key.len() > 4 => match value {
+                    Some(value) => Ok(Clause::Compare { key, negated, values: value.split('|').map(str::trim).collect() }),
+                    None => Ok(Clause::EnvSet { name: &key[4..], negated }),
+                },
+                ("os" | "profile", None) => bail!("'{}' needs a value, like {}=...", key, key),
+                _ => bail!("unknown condition '{}' (expected os, profile or env.NAME)", clause),
+            }
+        })
+        .collect()
+}
+
+/// Whether every clause of `when` holds for `facts`
+pub fn check_when(when: &str, facts: &Facts) -> Result<bool> {
+    for clause in parse_when(when)? {
+        let holds = match clause {
+            Clause::Compare { key, negated, values } => {
+                let actual = match key {
+                    "os" => Some(facts.os.to_string()),
+                    "profile" => facts.profile.map(str::to_string),
+                    env => (facts.env)(&env[4..]),
+                };
+                actual.is_some_and(|actual| values.contains(&actual.as_str())) != negated
+            }
+            Clause::EnvSet { name, negated } => (facts.env)(name).is_some_and(|value| !value.is_empty()) != negated,
+        };
+        if !holds {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Expand `${name}` with `lookup`, which returns `None` for unknown names;
+/// `$$` stands for a literal `$` and any other `$` is kept as it is
+fn expand_variables(text: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+        } else if let Some(inner) = after.strip_prefix('{') {
+            let end = inner.find('}').with_context(|| format!("unterminated '${{' in '{}'", text))?;
+            let name = &inner[..end];
+            let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if !valid {
+                bail!("invalid variable name '{}' in '{}'", name, text);
+            }
+            out.push_str(&lookup(name).with_context(|| format!("no value for variable '{}'", name))?);
+            rest = &inner[end + 1..];
+        } else {
+            out.push('$');
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+pub fn kind_name(kind: FileKind) -> &'static str {
+    match kind {
+        FileKind::File => "file",
+        FileKind::Directory => "dir",
+        FileKind::Symlink => "symlink",
+    }
+}
+
+pub fn parse_kind(name: &str) -> Option<FileKind> {
+    match name {
+        "file" => Some(FileKind::File),
+        "dir" => Some(FileKind::Directory),
+        "symlink" => Some(FileKind::Symlink),
+        _ => None,
+    }
+}
+
+/// RFC 3339 in UTC, with as much sub-second precision as the time has
+pub fn format_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{LocalFs, MemoryFs, RecordingFs};
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_manifest_from_recorded_changes() {
+        let memory = MemoryFs::new();
+        memory.create_dir(Path::new("srv")).unwrap();
+        let fs = RecordingFs::new(&memory);
+        fs.create_dir(Path::new("srv/data")).unwrap();
+        fs.set_permissions(Path::new("srv/data"), 0o750).unwrap();
+        fs.create_file(Path::new("srv/data/app \"1\".conf")).unwrap();
+        let stamp = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        fs.set_times(Path::new("srv"), stamp, stamp).unwrap();
+
+        let manifest = Manifest::from_changes(&fs, &fs.changes());
+        assert_eq!(manifest.entries.len(), 3);
+        assert_eq!(manifest.entries[0].mode, Some(0o750));
+
+        let yaml = manifest.to_yaml();
+        assert!(yaml.starts_with("# bank manifest\nversion: 2\nentries:\n  - path: \"srv/data\"\n    type: \"dir\"\n    mode: \"0750\"\n"));
+        assert!(yaml.contains("  - path: \"srv/data/app \\\"1\\\".conf\"\n    type: \"file\"\n"));
+        assert!(yaml.contains("    mtime: \"2023-11-14T22:13:20.500Z\"\n    change: \"modified\"\n"));
+
+        let json = manifest.to_json();
+        assert!(json.contains("{\"path\": \"srv\", \"type\": \"dir\", \"mode\": \"0755\""));
+        assert!(crate::json::parse(&json).is_ok());
+        assert_eq!(Manifest::default().to_yaml(), "# bank manifest\nversion: 2\nentries: []\n");
+        assert!(crate::json::parse(&Manifest::default().to_json()).is_ok());
+    }
+
+    #[test]
+    fn test_ansible_tasks() {
+        let manifest = Manifest::parse_yaml(
+            "version: 1\n\
+             entries:\n\
+             \x20 - path: srv/data/\n\
+             \x20   type: dir\n\
+             \x20   mode: \"0750\"\n\
+             \x20   owner: app:app\n\
+             \x20 - path: srv/data/app.conf\n\
+             \x20   type: file\n\
+             \x20   owner: :app\n",
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.to_ansible(),
+            "# Ansible tasks written by bank\n\
+             - name: \"Create dir srv/data\"\n\
+             \x20 ansible.builtin.file:\n\
+             \x20   path: \"srv/data\"\n\
+             \x20   state: directory\n\
+             \x20   mode: \"0750\"\n\
+             \x20   owner: \"app\"\n\
+             \x20   group: \"app\"\n\
+             - name: \"Create file srv/data/app.conf\"\n\
+             \x20 ansible.builtin.file:\n\
+             \x20   path: \"srv/data/app.conf\"\n\
+             \x20   state: touch\n\
+             \x20   group: \"app\"\n\
+             \x20   modification_time: preserve\n\
+             \x20   access_time: preserve\n"
+        );
+    }
+
+    #[test]
+    fn test_manifest_round_trips() {
+        let memory = MemoryFs::new();
+        let fs = RecordingFs::new(&memory);
+        fs.create_dir(Path::new("srv")).unwrap();
+        fs.create_file(Path::new("srv/it's \"here\" # not a comment")).unwrap();
+        let manifest = Manifest::from_changes(&fs, &fs.changes());
+
+        assert_eq!(Manifest::parse_yaml(&manifest.to_yaml()).unwrap(), manifest);
+        assert_eq!(Manifest::parse_json(&manifest.to_json()).unwrap(), manifest);
+        assert_eq!(Manifest::parse_yaml(&Manifest::default().to_yaml()).unwrap(), Manifest::default());
+    }
+
+    #[test]
+    fn test_parse_hand_written_yaml() {
+        let manifest = Manifest::parse_yaml(
+            "# provisioning for the app\n\
+             entries:\n\
+             \x20 - path: etc/app/   # a directory\n\
+             \x20   mode: 0750\n\
+             \x20 - path: 'etc/app/app''s.conf'\n\
+             \x20   owner: root\n\
+             \x20   mtime: 2024-01-01T00:00:00Z\n\
+             \x20 -\n\
+             \x20   path: var/log/app\n\
+             \x20   type: dir\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.entries.len(), 3);
+        assert_eq!(manifest.entries[0].kind, FileKind::Directory);
+        assert_eq!(manifest.entries[0].mode, Some(0o750));
+        assert_eq!(manifest.entries[1].path, "etc/app/app's.conf");
+        assert_eq!(manifest.entries[1].kind, FileKind::File);
+        assert_eq!(manifest.entries[1].owner.as_deref(), Some("root"));
+        assert_eq!(manifest.entries[1].mtime, Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200)));
+        assert_eq!(manifest.entries[2].kind, FileKind::Directory);
+
+        assert!(Manifest::parse_yaml("entries:\n  - mode: 0644\n").is_err());
+        assert!(Manifest::parse_yaml("entries:\n  - path: x\n    colour: red\n").is_err());
+        assert!(Manifest::parse_yaml("version: 3\nentries: []\n").is_err());
+        assert!(Manifest::parse_yaml("version: 1\nentries:\n  - path: x\n    when: os=linux\n").is_err());
+        assert!(Manifest::parse_yaml("version: 2\nentries:\n  - path: x\n    when: os=linux\n").is_ok());
+        assert!(Manifest::parse_yaml("entries:\n    path: x\n").is_err());
+        assert!(Manifest::parse_json(r#"{"entries": [{"path": "x", "mode": 644}]}"#).unwrap().entries[0].mode == Some(0o644));
+        assert!(Manifest::parse_json(r#"{"entries": [{"path": "x", "mode": true}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem_with_its_position() {
+        let temp_dir = TempDir::new().unwrap();
+        let base = temp_dir.path();
+        std::os::unix::fs::symlink("loop", base.join("loop")).unwrap();
+        std::os::unix::fs::symlink("/", base.join("out")).unwrap();
+        let text = "entries:\n\
+                    \x20 - path: srv/\n\
+                    \x20 - path: srv/app.conf\n\
+                    \x20 - path: ./srv\n\
+                    \x20 - path: srv/app.conf/x\n\
+                    \x20 - path: ../up\n\
+                    \x20 - path: loop/x\n\
+                    \x20 - path: out/etc\n\
+                    \x20   type: dir\n\
+                    \x20 - path: /etc/app.conf\n";
+        let (manifest, positions) = Manifest::parse_yaml_located(text).unwrap();
+        assert_eq!(positions[1], Position { line: 3, column: 3 });
+        assert_eq!(
+            manifest.validate(&LocalFs, base, &positions),
+            [
+                "line 4, column 3: ./srv: repeats the entry at line 2, column 3",
+                "line 5, column 3: srv/app.conf/x: lies under srv/app.conf, which line 3, column 3 declares as a file",
+                "line 6, column 3: ../up: leaves the directory the manifest is applied in",
+                "line 7, column 3: loop/x: runs into a symlink cycle at loop",
+                "line 8, column 3: out/etc: leaves the directory the manifest is applied in through a symlink at out/etc",
+                "line 10, column 3: /etc/app.conf: is absolute, but entries are relative to the directory the manifest is applied in",
+            ]
+        );
+
+        let (manifest, positions) = Manifest::parse_json_located("{\"entries\": [{\"path\": \"a\"},\n  {\"path\": \"a/\"}]}").unwrap();
+        assert_eq!(manifest.validate(&LocalFs, base, &positions), ["line 2, column 3: a/: repeats the entry at line 1, column 14"]);
+    }
+
+    #[test]
+    fn test_variables_in_paths_and_owners() {
+        let mut manifest = Manifest::parse_yaml(
+            "entries:\n\
+             \x20 - path: services/${service}/\n\
+             \x20   owner: ${service}:${group}\n\
+             \x20 - path: services/${service}/$$HOME-$x.env\n\
+             \x20   content: \"name = ${name}\"\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.variables().unwrap(), ["service", "group", "name"]);
+        let vars = HashMap::from([
+            ("service".to_string(), "billing".to_string()),
+            ("group".to_string(), "svc".to_string()),
+            ("name".to_string(), "${billing}".to_string()),
+        ]);
+        manifest.substitute(&vars).unwrap();
+        assert_eq!(manifest.entries[0].path, "services/billing/");
+        assert_eq!(manifest.entries[0].owner.as_deref(), Some("billing:svc"));
+        assert_eq!(manifest.entries[1].path, "services/billing/$HOME-$x.env");
+        assert_eq!(manifest.entries[1].content.as_deref(), Some("name = ${billing}"));
+
+        // A written manifest reads back with its dollars intact
+        let mut reread = Manifest::parse_yaml(&manifest.to_yaml()).unwrap();
+        assert!(reread.variables().unwrap().is_empty());
+        reread.substitute(&HashMap::new()).unwrap();
+        assert_eq!(reread.entries, manifest.entries);
+        let mut reread = Manifest::parse_json(&manifest.to_json()).unwrap();
+        reread.substitute(&HashMap::new()).unwrap();
+        assert_eq!(reread.entries, manifest.entries);
+
+        assert!(Manifest::parse_yaml("entries:\n  - path: ${open\n").unwrap().variables().is_err());
+        assert!(Manifest::parse_yaml("entries:\n  - path: ${1st}\n").unwrap().variables().is_err());
+        let mut missing = Manifest::parse_yaml("entries:\n  - path: ${service}\n").unwrap();
+        assert!(missing.substitute(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_when_conditions() {
+        let env = |name: &str| (name == "CI").then(|| "true".to_string());
+        let facts = Facts { os: "linux", profile: Some("dev"), env: &env };
+        assert!(check_when("os=linux", &facts).unwrap());
+        assert!(check_when("os=macos|linux, profile!=prod", &facts).unwrap());
+        assert!(!check_when("os=windows", &facts).unwrap());
+        assert!(check_when("env.CI, !env.HOME", &facts).unwrap());
+        assert!(check_when("env.CI=true", &facts).unwrap());
+        assert!(!check_when("env.CI!=true", &facts).unwrap());
+        assert!(!check_when("profile=prod", &Facts { profile: None, ..facts }).unwrap());
+        assert!(parse_when("arch=x86").is_err());
+        assert!(parse_when("os").is_err());
+        assert!(parse_when("os=linux,").is_err());
+
+        let (mut manifest, mut positions) = Manifest::parse_yaml_located(
+            "entries:\n\
+             \x20 - path: Library/\n\
+             \x20   when: os=macos\n\
+             \x20 - path: .config/\n\
+             \x20   when: os=linux\n\
+             \x20 - path: notes.txt\n",
+        )
+        .unwrap();
+        assert!(Manifest::parse_yaml("entries:\n  - path: x\n    when: cpu=arm\n").is_err());
+        manifest.retain_when(&mut positions, &Facts { os: "linux", profile: None, env: &env }).unwrap();
+        let paths: Vec<&str> = manifest.entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, [".config/", "notes.txt"]);
+        assert_eq!(positions.iter().map(|position| position.line).collect::<Vec<_>>(), [4, 6]);
+        assert!(manifest.to_yaml().contains("    when: \"os=linux\"\n"));
+    }
+
+    #[test]
+    fn test_manifest_hashes_files_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("seed");
+        let file = dir.join("config.ini");
+        let fs = RecordingFs::new(&LocalFs);
+        fs.create_dir(&dir).unwrap();
+        fs.create_file(&file).unwrap();
+        std::fs::write(&file, b"abc").unwrap();
+
+        let mut manifest = Manifest::from_changes(&fs, &fs.changes());
+        manifest.add_hashes(HashAlgorithm::Sha256).unwrap();
+        assert_eq!(manifest.entries[0].hash, None);
+        assert_eq!(
+            manifest.entries[1].hash.as_deref(),
+            Some("sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert!(manifest.to_yaml().contains("    hash: \"sha256:ba7816bf"));
+    }
+}
@@ -0,0 +1,98 @@
+//! Octal and symbolic (`u=rwx,go=rx`) permission modes for new directories
+
+/// The process umask
+pub fn current_umask() -> u32 {
+    // Read without changing it where the kernel reports it, so threads
+    // creating files meanwhile (`--jobs`) never see a umask of 0
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    if let Some(mask) = status.lines().find_map(|line| line.strip_prefix("Umask:")) {
+        if let Ok(mask) = u32::from_str_radix(mask.trim(), 8) {
+            return mask;
+        }
+    }
+    // SAFETY: umask has no failure modes; the previous value is restored right away
+    unsafe {
+        let mask = libc::umask(0);
+        libc::umask(mask);
+        mask as u32
+    }
+}
+
+/// Parse an octal or chmod-style symbolic mode for a new directory, starting
+/// from `a=rwx` as `mkdir -m` does. Clauses without `ugoa` honor the umask,
+/// like chmod.
+pub fn parse_mode(text: &str, umask: u32) -> Option<u32> {
+    if !text.is_empty() && text.bytes().all(|b| (b'0'..=b'7').contains(&b)) {
+        let mode = u32::from_str_radix(text, 8).ok()?;
+        return (mode <= 0o7777).then_some(mode);
+    }
+
+    let mut mode = 0o777;
+    for clause in text.split(',') {
+        let who_end = clause.find(|c: char| !"ugoa".contains(c)).unwrap_or(clause.len());
+        let (who, mut actions) = clause.split_at(who_end);
+        let who_mask = if who.is_empty() {
+            0o7777 & !umask
+        } else {
+            who.chars().fold(0, |mask, c| {
+                mask | match c {
+                    'u' => 0o4700,
+                    'g' => 0o2070,
+                    'o' => 0o1007,
+                    _ => 0o7777,
+                }
+            })
+        };
+        let clear_mask = if who.is_empty() { 0o7777 } else { who_mask };
+
+        if actions.is_empty() {
+            return None;
+        }
+        while let Some(op) = actions.chars().next().filter(|c| "+-=".contains(*c)) {
+            actions = &actions[1..];
+            let perm_end = actions.find(|c: char| "+-=".contains(c)).unwrap_or(actions.len());
+            let (perms, rest) = actions.split_at(perm_end);
+            actions = rest;
+
+            let mut bits = 0;
+            for c in perms.chars() {
+                bits |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    // Directories are always searchable targets for X
+                    'x' | 'X' => 0o111,
+                    's' => 0o6000,
+                    't' => 0o1000,
+                    _ => return None,
+                };
+            }
+            let bits = bits & who_mask;
+            match op {
+                '+' => mode |= bits,
+                '-' => mode &= !bits,
+                _ => mode = (mode & !clear_mask) | bits,
+            }
+        }
+        if !actions.is_empty() {
+            return None;
+        }
+    }
+    Some(mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("755", 0o022), Some(0o755));
+        assert_eq!(parse_mode("u=rwx,go=rx", 0o022), Some(0o755));
+        assert_eq!(parse_mode("a-w", 0o022), Some(0o555));
+        assert_eq!(parse_mode("+t", 0o022), Some(0o1777));
+        assert_eq!(parse_mode("g+s", 0o022), Some(0o2777));
+        assert_eq!(parse_mode("o=", 0o022), Some(0o770));
+        assert_eq!(parse_mode("u+q", 0o022), None);
+        assert_eq!(parse_mode("99", 0o022), None);
+    }
+}
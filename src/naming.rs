@@ -0,0 +1,338 @@
+//! Filename portability checks and sanitization
+
+use crate::vfs::Filesystem;
+use clap::ValueEnum;
+use std::path::{Component, Path};
+
+/// Which platforms' naming rules `--check` enforces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NameCheck {
+    /// Both the Windows and POSIX rules
+    Portable,
+    /// Reserved device names, illegal characters, trailing dots/spaces, MAX_PATH
+    Windows,
+    /// The POSIX portable filename character set `[A-Za-z0-9._-]`
+    Posix,
+}
+
+/// What to do when a differently-cased sibling is found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CollisionAction {
+    Warn,
+    Error,
+}
+
+const WINDOWS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+const WINDOWS_ILLEGAL: [char; 8] = ['<', '>', ':', '"', '\\', '|', '?', '*'];
+const WINDOWS_MAX_PATH: usize = 260;
+const NAME_MAX: usize = 255;
+
+/// Problems with a single path component, empty if it is acceptable
+pub fn component_problems(name: &str, check: NameCheck) -> Vec<String> {
+    let mut problems = Vec::new();
+    let windows = matches!(check, NameCheck::Windows | NameCheck::Portable);
+    let posix = matches!(check, NameCheck::Posix | NameCheck::Portable);
+
+    if name.len() > NAME_MAX {
+        problems.push(format!("component is longer than {} bytes", NAME_MAX));
+    }
+
+    if windows {
+        let stem = name.split('.').next().unwrap_or(name).trim_end();
+        if WINDOWS_RESERVED.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+            problems.push(format!("'{}' is a reserved device name on Windows", stem));
+        }
+        if let Some(c) = name.chars().find(|c| WINDOWS_ILLEGAL.contains(c)) {
+            problems.push(format!("contains '{}', which is not allowed on Windows", c));
+        }
+        if name.chars().any(|c| c.is_control()) {
+            problems.push("contains control characters".to_string());
+        }
+        if name.ends_with('.') || name.ends_with(' ') {
+            problems.push("ends with a dot or space, which Windows strips".to_string());
+        }
+    }
+
+    if posix {
+        if let Some(c) = name
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')))
+        {
+            problems.push(format!("contains '{}', outside the POSIX portable character set", c.escape_default()));
+        }
+        if name.starts_with('-') {
+            problems.push("starts with '-', which tools mistake for an option".to_string());
+        }
+    }
+
+    problems
+}
+
+/// Check the components of `path` that do not exist yet, i.e. the ones bank would create,
+/// returning a description of every violation
+pub fn check_path(fs: &dyn Filesystem, path_str: &str, check: NameCheck) -> Vec<String> {
+    let path = Path::new(path_str);
+    let mut violations = Vec::new();
+
+    if matches!(check, NameCheck::Windows | NameCheck::Portable) && path_str.len() > WINDOWS_MAX_PATH {
+        violations.push(format!("{}: path is longer than {} characters (Windows MAX_PATH)", path_str, WINDOWS_MAX_PATH));
+    }
+
+    let mut current = std::path::PathBuf::new();
+    let mut missing = false;
+    for component in path.components() {
+        current.push(component);
+        let Component::Normal(name) = component else {
+            continue;
+        };
+        missing = missing || !fs.exists(&current);
+        if !missing {
+            continue;
+        }
+        for problem in component_problems(&name.to_string_lossy(), check) {
+            violations.push(format!("{}: {}", path_str, problem));
+        }
+    }
+
+    violations
+}
+
+/// Turn a single component into a name that passes the Windows rules: illegal and
+/// control characters become `_`, surrounding whitespace and trailing dots are
+/// trimmed and reserved device names get a `_` suffix. With `ascii`, non-ASCII
+/// characters are transliterated where a common equivalent exists.
+pub fn sanitize_component(name: &str, ascii: bool) -> String {
+    let mut sanitized = String::new();
+    for c in name.chars() {
+        if WINDOWS_ILLEGAL.contains(&c) || c.is_control() {
+            sanitized.push('_');
+        } else if ascii && !c.is_ascii() {
+            sanitized.push_str(transliterate(c));
+        } else {
+            sanitized.push(c);
+        }
+    }
+
+    let mut sanitized = sanitized.trim().trim_end_matches(['.', ' ']).to_string();
+    if sanitized.is_empty() {
+        sanitized.push('_');
+    }
+
+    let stem = sanitized.split('.').next().unwrap_or(&sanitized);
+    if WINDOWS_RESERVED.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+        sanitized.insert(stem.len(), '_');
+    }
+
+    // Truncate on a character boundary
+    while sanitized.len() > NAME_MAX {
+        sanitized.pop();
+    }
+    sanitized
+}
+
+/// ASCII replacement for common accented Latin letters; anything else becomes `_`
+fn transliterate(c: char) -> &'static str {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' | 'Ă' | 'Ą' => "A",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'ç' | 'ć' | 'č' => "c",
+        'Ç' | 'Ć' | 'Č' => "C",
+        'ď' | 'đ' | 'ð' => "d",
+        'Ď' | 'Đ' | 'Ð' => "D",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' => "e",
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ę' | 'Ě' => "E",
+        'ğ' => "g",
+        'Ğ' => "G",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'ı' => "i",
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ī' | 'İ' => "I",
+        'ł' => "l",
+        'Ł' => "L",
+        'ñ' | 'ń' | 'ň' => "n",
+        'Ñ' | 'Ń' | 'Ň' => "N",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' | 'Ő' => "O",
+        'œ' => "oe",
+        'Œ' => "OE",
+        'ř' => "r",
+        'Ř' => "R",
+        'ś' | 'š' | 'ş' => "s",
+        'Ś' | 'Š' | 'Ş' => "S",
+        'ß' => "ss",
+        'ť' | 'ţ' => "t",
+        'Ť' | 'Ţ' => "T",
+        'þ' => "th",
+        'Þ' => "TH",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => "u",
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ū' | 'Ů' | 'Ű' => "U",
+        'ý' | 'ÿ' => "y",
+        'Ý' | 'Ÿ' => "Y",
+        'ź' | 'ż' | 'ž' => "z",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        '–' | '—' => "-",
+        '‘' | '’' => "'",
+        _ => "_",
+    }
+}
+
+/// Sanitize the components of `path` that do not exist yet, keeping existing
+/// parents and any trailing separator intact
+pub fn sanitize_path(fs: &dyn Filesystem, path_str: &str, ascii: bool) -> String {
+    let mut current = std::path::PathBuf::new();
+    let mut sanitized = std::path::PathBuf::new();
+    let mut missing = false;
+    for component in Path::new(path_str).components() {
+        current.push(component);
+        match component {
+            Component::Normal(name) => {
+                missing = missing || !fs.exists(&current);
+                if missing {
+                    sanitized.push(sanitize_component(&name.to_string_lossy(), ascii));
+                } else {
+                    sanitized.push(name);
+                }
+            }
+            other => sanitized.push(other),
+        }
+    }
+
+    let mut result = sanitized.to_string_lossy().into_owned();
+    if path_str.ends_with('/') && !result.ends_with('/') {
+        result.push('/');
+    }
+    result
+}
+
+/// Find existing entries that differ from a component of `path` only by case.
+///
+/// On case-insensitive filesystems such a creation silently lands on the
+/// existing entry; on case-sensitive ones it produces two names that cannot
+/// both be checked out elsewhere.
+pub fn case_collisions(fs: &dyn Filesystem, path_str: &str) -> Vec<String> {
+    let mut collisions = Vec::new();
+    let mut parent = std::path::PathBuf::new();
+    for component in Path::new(path_str).components() {
+        let Component::Normal(name) = component else {
+            parent.push(component);
+            continue;
+        };
+        let name = name.to_string_lossy();
+
+        let dir = if parent.as_os_str().is_empty() { Path::new(".") } else { parent.as_path() };
+        let Ok(entries) = fs.read_dir(dir) else {
+            break;
+        };
+        if entries.iter().any(|entry| entry.name == name) {
+            parent.push(name.as_ref());
+            continue;
+        }
+
+        let lowered = name.to_lowercase();
+        for entry in entries.iter().filter(|entry| entry.name.to_lowercase() == lowered) {
+            collisions.push(format!(
+                "{}: '{}' differs only in case from existing '{}'",
+                path_str,
+                name,
+                parent.join(&entry.name).display()
+            ));
+        }
+        // Nothing below a missing component exists yet
+        break;
+    }
+    collisions
+}
+
+/// Find requested paths that differ from each other only by case
+pub fn batch_case_collisions(paths: &[String]) -> Vec<String> {
+    let mut seen: std::collections::HashMap<String, &str> = std::collections::HashMap::new();
+    let mut collisions = Vec::new();
+    for path_str in paths {
+        let trimmed = path_str.trim_end_matches('/');
+        match seen.get(&trimmed.to_lowercase()) {
+            Some(previous) if *previous != trimmed => collisions.push(format!(
+                "{}: differs only in case from '{}' in the same batch",
+                path_str, previous
+            )),
+            Some(_) => {}
+            None => {
+                seen.insert(trimmed.to_lowercase(), trimmed);
+            }
+        }
+    }
+    collisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[test]
+    fn test_windows_rules() {
+        assert!(component_problems("report.txt", NameCheck::Windows).is_empty());
+        assert_eq!(component_problems("con.txt", NameCheck::Windows).len(), 1);
+        assert_eq!(component_problems("LPT1", NameCheck::Windows).len(), 1);
+        assert_eq!(component_problems("what?.md", NameCheck::Windows).len(), 1);
+        assert_eq!(component_problems("trailing. ", NameCheck::Windows).len(), 1);
+        assert!(component_problems("console.log", NameCheck::Windows).is_empty());
+    }
+
+    #[test]
+    fn test_posix_and_portable_rules() {
+        assert!(component_problems("build-1.0_rc", NameCheck::Posix).is_empty());
+        assert_eq!(component_problems("my file", NameCheck::Posix).len(), 1);
+        assert_eq!(component_problems("-rf", NameCheck::Posix).len(), 1);
+        assert_eq!(component_problems("aux", NameCheck::Posix).len(), 0);
+        assert_eq!(component_problems("aux", NameCheck::Portable).len(), 1);
+        assert_eq!(component_problems(&"x".repeat(300), NameCheck::Posix).len(), 1);
+    }
+
+    #[test]
+    fn test_sanitize_component() {
+        assert_eq!(sanitize_component("  Q3: plan|review?  ", false), "Q3_ plan_review_");
+        assert_eq!(sanitize_component("con.txt", false), "con_.txt");
+        assert_eq!(sanitize_component("notes...", false), "notes");
+        assert_eq!(sanitize_component("Café Müller", true), "Cafe Muller");
+        assert_eq!(sanitize_component("Café", false), "Café");
+        assert_eq!(sanitize_component("???", false), "___");
+        assert_eq!(sanitize_component(" ", false), "_");
+        assert!(component_problems(&sanitize_component("AUX: <draft>. ", true), NameCheck::Windows).is_empty());
+    }
+
+    #[test]
+    fn test_sanitize_path_keeps_existing_parents() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("Old: stuff")).unwrap();
+
+        assert_eq!(sanitize_path(&memory, "Old: stuff/new: file?.txt", false), "Old: stuff/new_ file_.txt");
+        assert_eq!(sanitize_path(&memory, "a|b/", false), "a_b/");
+    }
+
+    #[test]
+    fn test_case_collisions() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("docs/Images")).unwrap();
+        memory.create_file(Path::new("docs/README.md")).unwrap();
+
+        assert!(case_collisions(&memory, "docs/Images/logo.png").is_empty());
+        assert_eq!(case_collisions(&memory, "docs/readme.md").len(), 1);
+        assert_eq!(case_collisions(&memory, "Docs/new.txt").len(), 1);
+        assert!(case_collisions(&memory, "other/README.md").is_empty());
+
+        let batch = vec!["src/Util.rs".to_string(), "src/util.rs".to_string(), "src/Util.rs".to_string()];
+        assert_eq!(batch_case_collisions(&batch).len(), 1);
+    }
+
+    #[test]
+    fn test_only_missing_components_are_checked() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("My Documents")).unwrap();
+
+        assert!(check_path(&memory, "My Documents/notes.txt", NameCheck::Posix).is_empty());
+        assert_eq!(check_path(&memory, "My Documents/new dir/notes.txt", NameCheck::Posix).len(), 1);
+    }
+}
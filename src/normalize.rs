@@ -0,0 +1,160 @@
+//! `--normalize-path`: make `.`/`..` handling and symlinked parents explicit
+
+use crate::vfs::{FileKind, Filesystem};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalizeMode {
+    /// Pass paths to the OS untouched (`a/link/..` follows `link`)
+    None,
+    /// Collapse `.` and `..` textually, like `cd -L` (`a/link/..` is `a`)
+    Lexical,
+    /// Resolve the existing part through the filesystem, like `cd -P`/`realpath`
+    Physical,
+}
+
+/// Rewrite `path_str` according to `mode`, keeping a trailing separator
+pub fn normalize_path(fs: &dyn Filesystem, path_str: &str, mode: NormalizeMode) -> Result<String> {
+    let normalized = match mode {
+        NormalizeMode::None => return Ok(path_str.to_string()),
+        NormalizeMode::Lexical => lexical(Path::new(path_str)),
+        NormalizeMode::Physical => physical(fs, Path::new(path_str))?,
+    };
+
+    let mut normalized = normalized.to_string_lossy().into_owned();
+    if normalized.is_empty() {
+        normalized.push('.');
+    }
+    if (path_str.ends_with('/') || path_str.ends_with('\\')) && !normalized.ends_with('/') {
+        normalized.push('/');
+    }
+    Ok(normalized)
+}
+
+/// Collapse `.` and `..` without touching the filesystem. Leading `..` of a
+/// relative path are kept; `..` at the root is dropped.
+pub fn lexical(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match out.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => out.push(".."),
+            },
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Apply each component the way the kernel would: `..` after an existing
+/// directory goes to its physical parent. The existing prefix ends up canonical.
+fn physical(fs: &dyn Filesystem, path: &Path) -> Result<PathBuf> {
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                let current = if resolved.as_os_str().is_empty() { Path::new(".") } else { &resolved };
+                if fs.exists(current) {
+                    resolved = canonical(fs, current)?;
+                    resolved.pop();
+                } else {
+                    resolved = lexical(&resolved.join(".."));
+                }
+            }
+            other => resolved.push(other),
+        }
+    }
+
+    // Canonicalize the longest existing prefix, keep the rest as written
+    let mut prefix = resolved.clone();
+    let mut rest = Vec::new();
+    while !prefix.as_os_str().is_empty() && !fs.exists(&prefix) {
+        match prefix.file_name() {
+            Some(name) => rest.push(name.to_os_string()),
+            None => break,
+        }
+        prefix.pop();
+    }
+    let mut out = if prefix.as_os_str().is_empty() {
+        canonical(fs, Path::new("."))?
+    } else {
+        canonical(fs, &prefix)?
+    };
+    out.extend(rest.iter().rev());
+    Ok(out)
+}
+
+fn canonical(fs: &dyn Filesystem, path: &Path) -> Result<PathBuf> {
+    fs.canonicalize(path)
+        .with_context(|| format!("Failed to resolve {}", path.display()))
+}
+
+/// Fail if an existing parent directory of `path_str` is reached through a symlink
+pub fn reject_symlinked_parents(fs: &dyn Filesystem, path_str: &str) -> Result<()> {
+    let path = Path::new(path_str.trim_end_matches('/'));
+    let mut current = PathBuf::new();
+    let components: Vec<_> = path.components().collect();
+    for component in components.iter().take(components.len().saturating_sub(1)) {
+        current.push(component);
+        if let Component::Normal(_) = component {
+            match fs.symlink_metadata(&current) {
+                Ok(metadata) if metadata.kind == FileKind::Symlink => {
+                    anyhow::bail!("Parent {} of {} is a symlink (--no-symlinked-parents)", current.display(), path_str)
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::LocalFs;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lexical_normalization() {
+        let memory = crate::vfs::MemoryFs::new();
+        let lexical = |p| normalize_path(&memory, p, NormalizeMode::Lexical).unwrap();
+        assert_eq!(lexical("a/./b/../c.txt"), "a/c.txt");
+        assert_eq!(lexical("../x/../y/"), "../y/");
+        assert_eq!(lexical("/../etc"), "/etc");
+        assert_eq!(lexical("a/.."), ".");
+        assert_eq!(normalize_path(&memory, "a/../b", NormalizeMode::None).unwrap(), "a/../b");
+    }
+
+    #[test]
+    fn test_physical_normalization_and_symlinked_parents() {
+        let temp_dir = TempDir::new().unwrap();
+        let base = fs::canonicalize(temp_dir.path()).unwrap();
+        fs::create_dir_all(base.join("real/deep")).unwrap();
+        std::os::unix::fs::symlink(base.join("real/deep"), base.join("link")).unwrap();
+
+        // Physically, link/.. is real/, not the directory containing link
+        let path = format!("{}/link/../new.txt", base.display());
+        assert_eq!(
+            normalize_path(&LocalFs, &path, NormalizeMode::Physical).unwrap(),
+            format!("{}/real/new.txt", base.display())
+        );
+        assert_eq!(
+            normalize_path(&LocalFs, &path, NormalizeMode::Lexical).unwrap(),
+            format!("{}/new.txt", base.display())
+        );
+
+        let through_link = format!("{}/link/file.txt", base.display());
+        assert!(reject_symlinked_parents(&LocalFs, &through_link).is_err());
+        assert!(reject_symlinked_parents(&LocalFs, &format!("{}/real/deep/x/y", base.display())).is_ok());
+    }
+}
@@ -0,0 +1,197 @@
+//! `bank note`: create (or find) the note for a day from a location pattern
+//! and a note template
+//!
+//! The location is `--location`, else `$BANK_NOTE_PATH`, else
+//! `~/notes/%Y/%m/%d-{title}.md`: `~` and `$VAR` are expanded, `%` codes are
+//! strftime codes for the note's date and `{title}` is the title as a slug.
+//! Without a title, `{title}` goes together with the separator in front of
+//! it, so the default gives `~/notes/2026/10/14.md`.
+
+use crate::editorconfig::FormatArgs;
+use crate::expand;
+use crate::journal;
+use crate::scaffold::{self, Item};
+use crate::template::{Loader, Vars};
+use crate::vfs::{Filesystem, RecordingFs};
+use anyhow::{bail, Context, Result};
+use chrono::format::{Item as FormatItem, StrftimeItems};
+use chrono::{Local, NaiveDate};
+use clap::Args as ClapArgs;
+use colored::*;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Location pattern used when neither `--location` nor the variable is set
+pub const DEFAULT_LOCATION: &str = "~/notes/%Y/%m/%d-{title}.md";
+
+/// Environment variable holding the location pattern
+pub const LOCATION_ENV: &str = "BANK_NOTE_PATH";
+
+/// Template used when no `note.md` is configured
+const DEFAULT_TEMPLATE: &str = "# {{ title }}\n\n";
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct NoteArgs {
+    /// Title of the note; also names the file through `{title}`
+    #[arg(value_name = "TITLE")]
+    pub title: Vec<String>,
+
+    /// Where notes go: strftime codes, `{title}`, `~` and `$VAR` (default: $BANK_NOTE_PATH, else ~/notes/%Y/%m/%d-{title}.md)
+    #[arg(long = "location", value_name = "PATTERN")]
+    pub location: Option<String>,
+
+    /// Template for new notes (default: ~/.config/bank/note.md or note.md.tmpl)
+    #[arg(long = "template", value_name = "FILE")]
+    pub template: Option<PathBuf>,
+
+    /// Date of the note instead of today (YYYY-MM-DD)
+    #[arg(long = "date", value_name = "DATE")]
+    pub date: Option<NaiveDate>,
+
+    /// Open the note in $VISUAL or $EDITOR afterwards
+    #[arg(short = 'e', long = "edit")]
+    pub edit: bool,
+
+    #[command(flatten)]
+    pub format: FormatArgs,
+
+    /// Do not record this run in the undo journal
+    #[arg(long = "no-journal")]
+    pub no_journal: bool,
+}
+
+/// Lowercase words joined by `-`, e.g. "Sprint #4: retro" -> "sprint-4-retro"
+pub fn slug(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The note's path for `pattern`, with `~` and variables already expanded
+pub fn note_path(pattern: &str, date: NaiveDate, title: &str) -> Result<PathBuf> {
+    let items: Vec<FormatItem> = StrftimeItems::new(pattern).collect();
+    if items.contains(&FormatItem::Error) {
+        bail!("Invalid date format in note location: {}", pattern);
+    }
+    let dated = date.format_with_items(items.into_iter()).to_string();
+    let slug = slug(title);
+    let path = if slug.is_empty() {
+        let mut path = dated.replace("{title}", "\u{0}");
+        while let Some(index) = path.find('\u{0}') {
+            let before = path[..index].chars().next_back().filter(|c| ['-', '_', ' ', '.'].contains(c));
+            let start = index - before.map_or(0, char::len_utf8);
+            path.replace_range(start..index + 1, "");
+        }
+        // A whole `/{title}/` component
+        path.replace("//", "/")
+    } else {
+        dated.replace("{title}", &slug)
+    };
+    if path.ends_with('/') || Path::new(&path).file_name().is_none() {
+        bail!("Note location {} does not name a file", pattern);
+    }
+    Ok(PathBuf::from(path))
+}
+
+/// The note template and the path it was read from
+fn template(args: &NoteArgs) -> Result<(String, PathBuf)> {
+    let candidates = match &args.template {
+        Some(file) => vec![file.clone()],
+        None => scaffold::config_dir().map(|dir| vec![dir.join("note.md"), dir.join("note.md.tmpl")]).unwrap_or_default(),
+    };
+    for candidate in &candidates {
+        if args.template.is_some() || candidate.is_file() {
+            let text = std::fs::read_to_string(candidate).with_context(|| format!("Failed to read {}", candidate.display()))?;
+            return Ok((text, candidate.clone()));
+        }
+    }
+    Ok((DEFAULT_TEMPLATE.to_string(), PathBuf::from("note.md")))
+}
+
+fn open_in_editor(path: &Path) -> Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|key| std::env::var(key).ok().filter(|value| !value.trim().is_empty()))
+        .unwrap_or_else(|| "vi".to_string());
+    // Through the shell, so EDITOR can carry arguments ("code --wait")
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to run {}", editor))?;
+    if !status.success() {
+        bail!("{} exited with {}", editor, status);
+    }
+    Ok(())
+}
+
+pub fn run(fs: &dyn Filesystem, args: &NoteArgs) -> Result<()> {
+    let title = args.title.join(" ");
+    let date = args.date.unwrap_or_else(|| Local::now().date_naive());
+    let pattern = match &args.location {
+        Some(location) => location.clone(),
+        None => std::env::var(LOCATION_ENV).ok().filter(|value| !value.is_empty()).unwrap_or_else(|| DEFAULT_LOCATION.to_string()),
+    };
+    let path = note_path(&expand::expand_path(&pattern)?, date, &title)?;
+
+    if fs.exists(&path) || fs.is_symlink(&path) {
+        println!("{}", path.display());
+    } else {
+        let (text, origin) = template(args)?;
+        let vars = Vars::from([
+            ("title".to_string(), if title.is_empty() { date.format("%Y-%m-%d").to_string() } else { title.clone() }),
+            ("slug".to_string(), slug(&title)),
+            ("date".to_string(), date.format("%Y-%m-%d").to_string()),
+            ("year".to_string(), date.format("%Y").to_string()),
+            ("weekday".to_string(), date.format("%A").to_string()),
+            ("time".to_string(), Local::now().format("%H:%M").to_string()),
+        ]);
+        let partials = scaffold::config_dir().map(|dir| dir.join("partials"));
+        let contents = Loader::new(partials.into_iter().collect()).render(&origin, &text, &vars)?;
+
+        let directory = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let name = path.file_name().expect("note_path returns a file name");
+        let item = Item { path: PathBuf::from(name), contents: Some(contents.into_bytes()), mode: 0o644 };
+        let recording = RecordingFs::new(fs);
+        let result = scaffold::write_tree(&recording, directory, &[item], &args.format, false);
+        if !args.no_journal {
+            if let Err(err) = journal::record(&recording, None) {
+                println!("{} {:#}", "Warning:".yellow(), err);
+            }
+        }
+        result?;
+        println!("{} Created {}", "✓".bright_green(), path.display().to_string().green());
+    }
+
+    if args.edit {
+        open_in_editor(&path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_path() {
+        let date = NaiveDate::from_ymd_opt(2026, 3, 9).unwrap();
+        assert_eq!(note_path("/n/%Y/%m/%d-{title}.md", date, "Sprint #4: Retro").unwrap(), PathBuf::from("/n/2026/03/09-sprint-4-retro.md"));
+        assert_eq!(note_path("/n/%Y/%m/%d-{title}.md", date, "").unwrap(), PathBuf::from("/n/2026/03/09.md"));
+        assert_eq!(note_path("/n/{title}/%F.md", date, "").unwrap().to_str(), Some("/n/2026-03-09.md"));
+        assert!(note_path("/n/%Q.md", date, "x").is_err());
+        assert!(note_path("/n/{title}", date, "").is_err());
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("  Hello, World!  "), "hello-world");
+        assert_eq!(slug("Café 2"), "café-2");
+        assert_eq!(slug("---"), "");
+    }
+}
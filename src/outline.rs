@@ -0,0 +1,182 @@
+//! `--from-outline`: build the tree an indented outline describes
+//!
+//! The outline is plain text indented with spaces or tabs, or a Markdown list
+//! (`-`, `*`, `+` or `1.` items; then only the items count, so headings, prose
+//! and code blocks around them are skipped). An entry belongs to the closest
+//! entry above it that is indented less. Entries with children or a trailing
+//! `/` are directories; the others are files if their name has an extension
+//! or ends with `(file)` (for `Makefile` or `.env`), directories if not.
+//! Backticks and bold around a name are dropped, and so is a description
+//! after ` — `, ` – ` or ` # `. A name may hold several components
+//! (`src/bin/tool.rs`), whose directories are created first.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::path::{Component, Path};
+
+/// Marks an entry without an extension as a file
+const FILE_MARKER: &str = "(file)";
+
+const TAB_WIDTH: usize = 4;
+
+const DESCRIPTION_SEPARATORS: [&str; 3] = [" — ", " – ", " # "];
+
+struct Node {
+    path: String,
+    column: usize,
+    /// `Some(true)` for a trailing `/`, `Some(false)` for the file marker;
+    /// directories only named as a parent are known by their children
+    marked_dir: Option<bool>,
+    has_children: bool,
+}
+
+impl Node {
+    fn is_dir(&self) -> bool {
+        self.has_children || self.marked_dir.unwrap_or_else(|| Path::new(&self.path).extension().is_none())
+    }
+}
+
+/// The indentation width of `line` and the text after it
+fn indent(line: &str) -> (usize, &str) {
+    let text = line.trim_start_matches([' ', '\t']);
+    let column = line[..line.len() - text.len()].chars().map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum();
+    (column, text)
+}
+
+/// The text of a Markdown list item, without its bullet (and task box)
+fn list_item(text: &str) -> Option<&str> {
+    let rest = match text.strip_prefix(['-', '*', '+']) {
+        Some(rest) => rest,
+        None => {
+            let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            (digits > 0).then(|| text[digits..].strip_prefix(['.', ')']))??
+        }
+    };
+    let item = rest.strip_prefix([' ', '\t'])?.trim_start();
+    Some(["[ ] ", "[x] ", "[X] "].iter().find_map(|task| item.strip_prefix(task)).unwrap_or(item))
+}
+
+/// The entry's relative path and how it is marked
+fn entry(text: &str, number: usize) -> Result<(String, Option<bool>)> {
+    let text = text.trim();
+    let name = match text.strip_prefix('`').and_then(|rest| rest.split_once('`')) {
+        Some((quoted, _)) => quoted,
+        None => {
+            let end = DESCRIPTION_SEPARATORS.iter().filter_map(|separator| text.find(separator)).min().unwrap_or(text.len());
+            let name = text[..end].trim();
+            name.strip_prefix("**").and_then(|name| name.strip_suffix("**")).unwrap_or(name)
+        }
+    };
+    let (name, marked_dir) = match name.strip_suffix(FILE_MARKER) {
+        Some(name) => (name.trim_end(), Some(false)),
+        None if name.ends_with('/') => (name.trim_end_matches('/'), Some(true)),
+        None => (name, None),
+    };
+    let path = Path::new(name);
+    if name.is_empty() || !path.components().all(|component| matches!(component, Component::Normal(_))) {
+        bail!("Line {}: '{}' is not a relative path", number, text);
+    }
+    Ok((name.to_string(), marked_dir))
+}
+
+/// The paths the outline describes, parents first; directories end with `/`
+pub fn parse(text: &str) -> Result<Vec<String>> {
+    let lines: Vec<(usize, &str)> = text.lines().enumerate().map(|(index, line)| (index + 1, line.trim_end())).collect();
+    let markdown = lines.iter().any(|(_, line)| list_item(indent(line).1).is_some());
+
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut by_path: HashMap<String, usize> = HashMap::new();
+    let mut open: Vec<usize> = Vec::new();
+    let mut fenced = false;
+    for (number, line) in lines {
+        let (column, text) = indent(line);
+        if text.starts_with("```") || text.starts_with("~~~") {
+            fenced = !fenced;
+            continue;
+        }
+        let text = match (markdown, list_item(text)) {
+            (true, Some(item)) if !fenced => item,
+            (true, _) => continue,
+            (false, _) if text.is_empty() => continue,
+            (false, _) => text,
+        };
+        let (name, marked_dir) = entry(text, number)?;
+        while open.last().is_some_and(|&top| nodes[top].column >= column) {
+            open.pop();
+        }
+        let mut path = match open.last() {
+            Some(&top) => {
+                nodes[top].has_children = true;
+                format!("{}/", nodes[top].path)
+            }
+            None => String::new(),
+        };
+        let mut components = name.split('/').filter(|component| !component.is_empty()).peekable();
+        while let Some(component) = components.next() {
+            path.push_str(component);
+            let last = components.peek().is_none();
+            let index = *by_path.entry(path.clone()).or_insert_with(|| {
+                nodes.push(Node { path: path.clone(), column, marked_dir: None, has_children: false });
+                nodes.len() - 1
+            });
+            if last {
+                nodes[index].column = column;
+                nodes[index].marked_dir = marked_dir.or(nodes[index].marked_dir);
+                open.push(index);
+            } else {
+                nodes[index].has_children = true;
+                path.push('/');
+            }
+        }
+    }
+    Ok(nodes.iter().map(|node| if node.is_dir() { format!("{}/", node.path) } else { node.path.clone() }).collect())
+}
+
+pub fn load(file: &Path) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(file).with_context(|| format!("Failed to read outline {}", file.display()))?;
+    parse(&text).with_context(|| format!("Invalid outline {}", file.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_markdown_outline() {
+        let plan = "# Service layout\n\
+                    \n\
+                    Everything lives under `service`:\n\
+                    \n\
+                    - **service**\n\
+                    \x20 - `src/bin/worker.rs` — the queue worker\n\
+                    \x20 - docs\n\
+                    \x20 - Makefile (file)\n\
+                    \x20 - releases/v1.2/\n\
+                    \x20 1. config.toml\n\
+                    \n\
+                    ```\n\
+                    - not/part/of/the/plan\n\
+                    ```\n";
+        assert_eq!(
+            parse(plan).unwrap(),
+            [
+                "service/",
+                "service/src/",
+                "service/src/bin/",
+                "service/src/bin/worker.rs",
+                "service/docs/",
+                "service/Makefile",
+                "service/releases/",
+                "service/releases/v1.2/",
+                "service/config.toml"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_indented_outline() {
+        let plan = "app\n\tassets\n\t\tlogo.svg\n\tv1.0\n\t\tnotes\n.env (file)\n";
+        assert_eq!(parse(plan).unwrap(), ["app/", "app/assets/", "app/assets/logo.svg", "app/v1.0/", "app/v1.0/notes/", ".env"]);
+        assert!(parse("app\n  ../escape\n").is_err());
+    }
+}
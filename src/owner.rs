@@ -0,0 +1,271 @@
+//! File ownership: resolving `user[:group]` specs, changing owners, and
+//! acting as another user for `--as-user`
+
+use crate::vfs::Filesystem;
+use anyhow::{bail, Context, Result};
+use std::ffi::{CStr, CString};
+use std::path::Path;
+
+/// Change the owner of `path` to `user`, `user:group`, `:group` or numeric
+/// ids, through `fs`; without `no_dereference` a symlink's target changes
+pub fn set_owner(fs: &dyn Filesystem, path: &Path, owner: &str, no_dereference: bool) -> Result<()> {
+    let (uid, gid) = resolve(owner)?;
+    let target = if no_dereference || !fs.is_symlink(path) {
+        path.to_path_buf()
+    } else {
+        fs.canonicalize(path).with_context(|| format!("Failed to resolve {}", path.display()))?
+    };
+    fs.set_owner(&target, uid, gid).with_context(|| format!("Failed to change owner of {} to {}", path.display(), owner))
+}
+
+/// The ids an owner spec names; a part that is left out is `None`
+pub fn resolve(owner: &str) -> Result<(Option<libc::uid_t>, Option<libc::gid_t>)> {
+    let (user, group) = match owner.split_once(':') {
+        Some((user, group)) => (user, group),
+        None => (owner, ""),
+    };
+    let uid = if user.is_empty() { None } else { Some(resolve_user(user)?) };
+    let gid = if group.is_empty() { None } else { Some(resolve_group(group)?) };
+    Ok((uid, gid))
+}
+
+/// Whether `uid` and `gid` already satisfy an owner spec
+pub fn matches(owner: &str, uid: libc::uid_t, gid: libc::gid_t) -> Result<bool> {
+    let (want_uid, want_gid) = resolve(owner)?;
+    Ok(want_uid.is_none_or(|want| want == uid) && want_gid.is_none_or(|want| want == gid))
+}
+
+/// `user:group` for the ids, falling back to the numbers for unknown ids
+pub fn describe(uid: libc::uid_t, gid: libc::gid_t) -> String {
+    let user = user_name(uid).unwrap_or_else(|| uid.to_string());
+    let group = group_name(gid).unwrap_or_else(|| gid.to_string());
+    format!("{}:{}", user, group)
+}
+
+/// While alive, the process acts as another user: effective uid and gid,
+/// supplementary groups and umask are theirs. Dropping it switches back.
+pub struct AsUser {
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+    pub umask: libc::mode_t,
+    saved_groups: Vec<libc::gid_t>,
+    saved_gid: libc::gid_t,
+    saved_umask: libc::mode_t,
+}
+
+/// Act as `user` (a name or uid) until the returned guard is dropped.
+/// Only root can do this; the real uid stays root so it can switch back.
+pub fn become_user(user: &str) -> Result<AsUser> {
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        bail!("--as-user needs bank to run as root");
+    }
+    let (uid, gid, name) = passwd_entry(user)?;
+    let groups = group_list(&name, gid)?;
+    let umask = login_umask(&read_login_defs(), uid, gid, &name);
+
+    // SAFETY: getgroups with a zero size only counts the groups
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    let mut saved_groups = vec![0; count.max(0) as usize];
+    // SAFETY: the buffer holds `count` entries
+    if unsafe { libc::getgroups(count, saved_groups.as_mut_ptr()) } < 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to read the current groups");
+    }
+    let guard = AsUser {
+        uid,
+        gid,
+        umask,
+        saved_groups,
+        // SAFETY: getegid has no preconditions
+        saved_gid: unsafe { libc::getegid() },
+        // SAFETY: umask cannot fail; the old mask is put back right away
+        saved_umask: unsafe {
+            let old = libc::umask(0o022);
+            libc::umask(old);
+            old
+        },
+    };
+    // Groups and gid first: once the euid is no longer root they cannot change.
+    // From here on `guard` restores whatever has been switched if a step fails.
+    // SAFETY: plain syscalls on ids and a buffer of `groups.len()` entries
+    unsafe {
+        if libc::setgroups(groups.len() as _, groups.as_ptr()) != 0 || libc::setegid(gid) != 0 || libc::seteuid(uid) != 0 {
+            return Err(std::io::Error::last_os_error()).with_context(|| format!("Failed to switch to user {}", name));
+        }
+        libc::umask(umask);
+    }
+    Ok(guard)
+}
+
+impl Drop for AsUser {
+    fn drop(&mut self) {
+        // SAFETY: the real and saved uid are still root, so this switches back.
+        // Failures cannot be reported from here and would leave us unprivileged,
+        // which only ever makes later operations fail, never succeed wrongly.
+        unsafe {
+            libc::seteuid(0);
+            libc::setegid(self.saved_gid);
+            libc::setgroups(self.saved_groups.len() as _, self.saved_groups.as_ptr());
+            libc::umask(self.saved_umask);
+        }
+    }
+}
+
+/// uid, primary gid and name of a user given by name or uid
+pub fn passwd_entry(user: &str) -> Result<(libc::uid_t, libc::gid_t, String)> {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    // SAFETY: passwd is plain old data
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let status = match user.parse::<libc::uid_t>() {
+        // SAFETY: every pointer refers to live, correctly sized storage owned by this frame
+        Ok(uid) => unsafe { libc::getpwuid_r(uid, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result) },
+        Err(_) => {
+            let c_name = CString::new(user).context("User name contains a NUL byte")?;
+            // SAFETY: as above
+            unsafe { libc::getpwnam_r(c_name.as_ptr(), &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result) }
+        }
+    };
+    if status != 0 || result.is_null() {
+        bail!("Unknown user: {}", user);
+    }
+    // SAFETY: pw_name points into `buffer`, which is still alive
+    let name = unsafe { CStr::from_ptr(entry.pw_name) }.to_string_lossy().into_owned();
+    Ok((entry.pw_uid, entry.pw_gid, name))
+}
+
+/// Every group `name` belongs to, as initgroups(3) would set them
+fn group_list(name: &str, gid: libc::gid_t) -> Result<Vec<libc::gid_t>> {
+    let c_name = CString::new(name).context("User name contains a NUL byte")?;
+    let mut groups: Vec<libc::gid_t> = vec![0; 64];
+    loop {
+        let mut count = groups.len() as libc::c_int;
+        // SAFETY: `groups` holds `count` entries; on overflow count is set to the size needed
+        let status = unsafe { libc::getgrouplist(c_name.as_ptr(), gid, groups.as_mut_ptr(), &mut count) };
+        if status >= 0 {
+            groups.truncate(count as usize);
+            return Ok(groups);
+        }
+        groups.resize((count as usize).max(groups.len() * 2), 0);
+    }
+}
+
+pub fn read_login_defs() -> String {
+    std::fs::read_to_string("/etc/login.defs").unwrap_or_default()
+}
+
+/// The value of `key` in login.defs text, if it is set
+pub fn login_defs_value<'a>(login_defs: &'a str, key: &str) -> Option<&'a str> {
+    login_defs
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| line.strip_prefix(key).filter(|rest| rest.starts_with([' ', '\t'])).map(str::trim))
+}
+
+/// The umask login(1) gives a user: `UMASK` from login.defs (022 without
+/// one), and with `USERGROUPS_ENAB yes` the group bits copied from the owner
+/// bits when the user has a private group of the same name and id
+pub fn login_umask(login_defs: &str, uid: libc::uid_t, gid: libc::gid_t, name: &str) -> libc::mode_t {
+    let value = |key: &str| login_defs_value(login_defs, key);
+    let umask = value("UMASK").and_then(|mask| libc::mode_t::from_str_radix(mask, 8).ok()).unwrap_or(0o022) & 0o777;
+    let private_group = uid == gid && group_name(gid).as_deref() == Some(name);
+    if private_group && value("USERGROUPS_ENAB").is_some_and(|enabled| enabled.eq_ignore_ascii_case("yes")) {
+        (umask & !0o070) | ((umask & 0o700) >> 3)
+    } else {
+        umask
+    }
+}
+
+fn resolve_user(name: &str) -> Result<libc::uid_t> {
+    // A numeric id needs no entry in the password database
+    if let Ok(uid) = name.parse() {
+        return Ok(uid);
+    }
+    Ok(passwd_entry(name)?.0)
+}
+
+fn resolve_group(name: &str) -> Result<libc::gid_t> {
+    if let Ok(gid) = name.parse() {
+        return Ok(gid);
+    }
+    let c_name = CString::new(name).context("Group name contains a NUL byte")?;
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    // SAFETY: group is plain old data
+    let mut entry: libc::group = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::group = std::ptr::null_mut();
+
+    // SAFETY: every pointer refers to live, correctly sized storage owned by this frame
+    let status = unsafe {
+        libc::getgrnam_r(c_name.as_ptr(), &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result)
+    };
+    if status != 0 || result.is_null() {
+        bail!("Unknown group: {}", name);
+    }
+    Ok(entry.gr_gid)
+}
+
+fn user_name(uid: libc::uid_t) -> Option<String> {
+    passwd_entry(&uid.to_string()).ok().map(|(_, _, name)| name)
+}
+
+fn group_name(gid: libc::gid_t) -> Option<String> {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    // SAFETY: group is plain old data
+    let mut entry: libc::group = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::group = std::ptr::null_mut();
+    // SAFETY: every pointer refers to live, correctly sized storage owned by this frame
+    let status = unsafe { libc::getgrgid_r(gid, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+    if status != 0 || result.is_null() {
+        return None;
+    }
+    // SAFETY: gr_name points into `buffer`, which is still alive
+    Some(unsafe { CStr::from_ptr(entry.gr_name) }.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{LocalFs, MemoryFs};
+    use std::os::unix::fs::MetadataExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_set_owner() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("owned");
+        std::fs::write(&path, b"").unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        let owner = describe(metadata.uid(), metadata.gid());
+
+        set_owner(&LocalFs, &path, &owner, false).unwrap();
+        set_owner(&LocalFs, &path, &metadata.uid().to_string(), false).unwrap();
+        set_owner(&LocalFs, &path, &format!(":{}", metadata.gid()), false).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().uid(), metadata.uid());
+        assert!(set_owner(&LocalFs, &path, "no-such-user-here", false).is_err());
+
+        let memory = MemoryFs::new();
+        memory.create_file(Path::new("owned")).unwrap();
+        set_owner(&memory, Path::new("owned"), "1000:1001", true).unwrap();
+        let changed = memory.metadata(Path::new("owned")).unwrap();
+        assert_eq!((changed.uid, changed.gid), (1000, 1001));
+        assert!(matches(&owner, metadata.uid(), metadata.gid()).unwrap());
+        assert!(matches(&format!(":{}", metadata.gid()), metadata.uid(), metadata.gid()).unwrap());
+        assert!(!matches(&(metadata.uid() + 1).to_string(), metadata.uid(), metadata.gid()).unwrap());
+        assert_eq!(describe(4_000_000, 4_000_000), "4000000:4000000");
+    }
+
+    #[test]
+    fn test_login_umask() {
+        let defs = "# comment\nUMASK\t\t027\nUSERGROUPS_ENAB yes\n";
+        assert_eq!(login_umask(defs, 4_000_000, 4_000_001, "nobody-here"), 0o027);
+        assert_eq!(login_umask("", 4_000_000, 4_000_000, "nobody-here"), 0o022);
+        assert_eq!(login_umask("UMASKX 077\n", 4_000_000, 4_000_000, "x"), 0o022);
+
+        // root's primary group is named root and has its id
+        if group_name(0).as_deref() == Some("root") {
+            assert_eq!(login_umask(defs, 0, 0, "root"), 0o007);
+            assert_eq!(login_umask("UMASK 027\nUSERGROUPS_ENAB no\n", 0, 0, "root"), 0o027);
+        }
+    }
+}
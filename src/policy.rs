@@ -0,0 +1,208 @@
+//! Permission policies: the modes and owners paths matching a glob must have
+//!
+//! A policy is a TOML file with one table per glob under `rules`; globs are
+//! matched against paths relative to the directory being checked, and every
+//! rule a path matches applies to it:
+//!
+//! ```toml
+//! [rules."**/*.key"]
+//! mode = "0600"          # exactly these bits
+//! owner = "root"         # user, user:group or :group
+//!
+//! [rules."srv/**"]
+//! max-mode = "0750"      # no bits beyond these
+//!
+//! [modes]
+//! "**/bin/*" = "755"     # for new paths, when no --mode is given
+//! ```
+//!
+//! New paths get the mode of the last glob they match among `[modes]` and
+//! the rules with an exact `mode`, so what bank creates already complies.
+
+use crate::glob;
+use crate::json::Value;
+use crate::owner;
+use crate::toml;
+use crate::vfs::Metadata;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub pattern: String,
+    pub mode: Option<u32>,
+    pub max_mode: Option<u32>,
+    pub owner: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    pub rules: Vec<Rule>,
+    /// Default modes for new paths, by glob
+    pub modes: Vec<(String, u32)>,
+}
+
+/// One way a path falls short of a rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub path: String,
+    /// The glob of the rule
+    pub rule: String,
+    pub attribute: &'static str,
+    pub found: String,
+    pub required: String,
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read policy {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid policy {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let document = toml::parse(text)?;
+        let tables = match document.get("rules") {
+            Some(Value::Object(tables)) => tables.clone(),
+            Some(other) => bail!("'rules' must be a table, found {}", other.type_name()),
+            None => Vec::new(),
+        };
+        let rules = tables
+            .iter()
+            .map(|(pattern, fields)| Rule::from_fields(pattern, fields).with_context(|| format!("rule \"{}\"", pattern)))
+            .collect::<Result<_>>()?;
+        let modes = match document.get("modes") {
+            Some(modes) => parse_modes(modes)?,
+            None => Vec::new(),
+        };
+        Ok(Self { rules, modes })
+    }
+
+    /// The modes new paths get by glob: `[modes]`, then the rules' exact modes
+    pub fn default_modes(&self) -> Vec<(String, u32)> {
+        let exact = self.rules.iter().filter_map(|rule| rule.mode.map(|mode| (rule.pattern.clone(), mode)));
+        self.modes.iter().cloned().chain(exact).collect()
+    }
+
+    /// The rules whose glob matches `relative`, in policy order
+    pub fn matching<'a>(&'a self, relative: &'a str) -> impl Iterator<Item = &'a Rule> + 'a {
+        self.rules.iter().filter(move |rule| glob::glob_match(&rule.pattern, relative))
+    }
+
+    /// Everything about `metadata` that the rules matching `relative` forbid
+    pub fn check(&self, relative: &str, metadata: &Metadata) -> Result<Vec<Violation>> {
+        let mode = metadata.mode & 0o7777;
+        let mut violations = Vec::new();
+        for rule in self.matching(relative) {
+            let mut violation = |attribute, found: String, required: String| {
+                violations.push(Violation { path: relative.to_string(), rule: rule.pattern.clone(), attribute, found, required })
+            };
+            if let Some(wanted) = rule.mode.filter(|wanted| *wanted != mode) {
+                violation("mode", format!("{:04o}", mode), format!("{:04o}", wanted));
+            }
+            if let Some(max) = rule.max_mode.filter(|max| mode & !max != 0) {
+                violation("mode", format!("{:04o}", mode), format!("at most {:04o}", max));
+            }
+            if let Some(spec) = &rule.owner {
+                let matches = owner::matches(spec, metadata.uid, metadata.gid).with_context(|| format!("rule \"{}\"", rule.pattern))?;
+                if !matches {
+                    violation("owner", owner::describe(metadata.uid, metadata.gid), spec.clone());
+                }
+            }
+        }
+        Ok(violations)
+    }
+
+    /// The nearest mode to `mode` that the rules matching `relative` allow:
+    /// the last exact `mode`, less the bits beyond any `max-mode`
+    pub fn compliant_mode(&self, relative: &str, mode: u32) -> u32 {
+        let rules: Vec<&Rule> = self.matching(relative).collect();
+        let mode = rules.iter().rev().find_map(|rule| rule.mode).unwrap_or(mode & 0o7777);
+        rules.iter().filter_map(|rule| rule.max_mode).fold(mode, |mode, max| mode & max)
+    }
+}
+
+impl Rule {
+    fn from_fields(pattern: &str, fields: &Value) -> Result<Self> {
+        let Value::Object(fields) = fields else {
+            bail!("expected a table, found {}", fields.type_name());
+        };
+        let mut rule = Rule { pattern: pattern.to_string(), mode: None, max_mode: None, owner: None };
+        for (key, value) in fields {
+            match key.as_str() {
+                "mode" => rule.mode = Some(parse_mode(key, value)?),
+                "max-mode" => rule.max_mode = Some(parse_mode(key, value)?),
+                "owner" => rule.owner = Some(value.as_str().with_context(|| format!("'owner' must be a string, found {}", value.type_name()))?.to_string()),
+                other => bail!("unknown key '{}'", other),
+            }
+        }
+        Ok(rule)
+    }
+}
+
+/// A table of `"glob" = "mode"` pairs, in order
+pub fn parse_modes(value: &Value) -> Result<Vec<(String, u32)>> {
+    let Value::Object(pairs) = value else {
+        bail!("'modes' must be a table, found {}", value.type_name());
+    };
+    pairs.iter().map(|(pattern, mode)| Ok((pattern.clone(), parse_mode(pattern, mode)?))).collect()
+}
+
+/// The mode of the last of `modes` whose glob matches `path`
+pub fn default_mode(modes: &[(String, u32)], path: &str) -> Option<u32> {
+    let path = match path.trim_end_matches('/') {
+        "" => path,
+        trimmed => trimmed,
+    };
+    modes.iter().rev().find(|(pattern, _)| glob::glob_match(pattern, path)).map(|(_, mode)| *mode)
+}
+
+/// An octal mode, as a string or as the digits of a bare number (`mode = 600`)
+pub fn parse_mode(key: &str, value: &Value) -> Result<u32> {
+    let text = match value {
+        Value::String(text) => text.clone(),
+        Value::Number(number) if number.fract() == 0.0 && *number >= 0.0 => format!("{}", *number as u64),
+        other => bail!("'{}' must be an octal mode, found {}", key, other.type_name()),
+    };
+    u32::from_str_radix(&text, 8).ok().filter(|mode| *mode <= 0o7777).with_context(|| format!("invalid mode '{}' for '{}'", text, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::FileKind;
+    use std::time::SystemTime;
+
+    fn metadata(mode: u32) -> Metadata {
+        // SAFETY: geteuid and getegid have no preconditions
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        Metadata { kind: FileKind::File, mode, len: 0, uid, gid, accessed: SystemTime::UNIX_EPOCH, modified: SystemTime::UNIX_EPOCH }
+    }
+
+    #[test]
+    fn test_policy_rules() {
+        let policy = Policy::parse(
+            "[rules.\"**/*.key\"]\nmode = 600\n\n[rules.\"srv/**\"]\nmax-mode = \"0750\"\nowner = \":0\"\n",
+        )
+        .unwrap();
+        assert_eq!(policy.rules[0], Rule { pattern: "**/*.key".to_string(), mode: Some(0o600), max_mode: None, owner: None });
+
+        assert!(policy.check("srv/tls/site.key", &metadata(0o600)).unwrap().iter().all(|violation| violation.attribute == "owner"));
+        let violations = policy.check("srv/tls/site.key", &metadata(0o644)).unwrap();
+        let modes: Vec<(&str, &str)> =
+            violations.iter().filter(|violation| violation.attribute == "mode").map(|violation| (violation.found.as_str(), violation.required.as_str())).collect();
+        assert_eq!(modes, [("0644", "0600"), ("0644", "at most 0750")]);
+        assert!(policy.check("srv/run.sh", &metadata(0o750)).unwrap().iter().all(|violation| violation.attribute == "owner"));
+        assert!(policy.check("README", &metadata(0o777)).unwrap().is_empty());
+        assert_eq!(policy.compliant_mode("srv/tls/site.key", 0o644), 0o600);
+        assert_eq!(policy.compliant_mode("srv/run.sh", 0o777), 0o750);
+
+        let policy = Policy::parse("[modes]\n\"**/bin/*\" = \"755\"\n\"**/*.key\" = 600\n").unwrap();
+        let modes = [policy.default_modes(), vec![("srv/bin/*".to_string(), 0o750)]].concat();
+        assert_eq!(default_mode(&modes, "bin/run"), Some(0o755));
+        assert_eq!(default_mode(&modes, "srv/bin/run"), Some(0o750));
+        assert_eq!(default_mode(&modes, "/etc/tls/site.key"), Some(0o600));
+        assert_eq!(default_mode(&modes, "srv/bin/"), None);
+        assert!(Policy::parse("[rules.\"*\"]\nmode = \"999\"\n").is_err());
+        assert!(Policy::parse("[rules.\"*\"]\ncolour = \"red\"\n").is_err());
+    }
+}
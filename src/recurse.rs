@@ -0,0 +1,317 @@
+//! `-R/--recursive`: update timestamps across an existing tree, with find-like filters
+
+use crate::glob::any_match;
+use crate::i18n::tr;
+use crate::report::outln;
+use crate::vfs::{FileKind, Filesystem};
+use crate::Args;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryType {
+    /// Regular files
+    F,
+    /// Directories
+    D,
+    /// Symbolic links
+    L,
+}
+
+impl EntryType {
+    /// Whether an entry of `kind` matches; `l` goes by whether the entry itself
+    /// is a link, even when links are followed
+    fn matches(self, kind: FileKind, link: bool) -> bool {
+        match self {
+            EntryType::F => kind == FileKind::File,
+            EntryType::D => kind == FileKind::Directory,
+            EntryType::L => link,
+        }
+    }
+}
+
+/// Parse a size like `512`, `10k`, `5M` or `1G` (binary units)
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let value: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}' (expected e.g. 512, 10k, 5M)", text))?;
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(format!("unknown size unit '{}' (use k, M or G)", unit)),
+    };
+    value.checked_mul(multiplier).ok_or_else(|| format!("size '{}' is too large", text))
+}
+
+/// Which entries of a tree are selected
+pub struct Filter<'a> {
+    include: &'a [String],
+    exclude: &'a [String],
+    types: &'a [EntryType],
+    older_than: Option<SystemTime>,
+    newer_than: Option<SystemTime>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    min_depth: usize,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+}
+
+impl<'a> Filter<'a> {
+    pub fn from_args(args: &'a Args, now: SystemTime) -> Self {
+        let before = |age: Option<Duration>| age.map(|age| now.checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH));
+        Self {
+            include: &args.include,
+            exclude: &args.exclude,
+            types: &args.entry_types,
+            older_than: before(args.older_than),
+            newer_than: before(args.newer_than),
+            min_size: args.min_size,
+            max_size: args.max_size,
+            min_depth: args.min_depth.unwrap_or(0),
+            max_depth: args.max_depth,
+            follow_symlinks: args.follow_symlinks,
+        }
+    }
+
+    fn selects(&self, fs: &dyn Filesystem, path: &Path, relative: &str, (kind, link): (FileKind, bool)) -> Result<bool> {
+        if !self.include.is_empty() && !any_match(self.include, relative) {
+            return Ok(false);
+        }
+        if !self.types.is_empty() && !self.types.iter().any(|t| t.matches(kind, link)) {
+            return Ok(false);
+        }
+        if self.older_than.is_none() && self.newer_than.is_none() && self.min_size.is_none() && self.max_size.is_none() {
+            return Ok(true);
+        }
+
+        let metadata = if self.follow_symlinks { fs.metadata(path) } else { fs.symlink_metadata(path) }
+            .with_context(|| format!("Failed to read metadata for {}", path.display()))?;
+        Ok(self.older_than.is_none_or(|limit| metadata.modified < limit)
+            && self.newer_than.is_none_or(|limit| metadata.modified > limit)
+            && self.min_size.is_none_or(|min| metadata.len >= min)
+            && self.max_size.is_none_or(|max| metadata.len <= max))
+    }
+}
+
+/// Every selected entry of the tree at `root` (including `root` itself, at depth 0)
+/// in sorted depth-first order. Excluded directories are pruned with everything
+/// below them. With `--follow-symlinks`, links are judged by their targets and
+/// linked directories are descended into, except when they lead back to a
+/// directory already being walked, which is reported and skipped. `--type l`
+/// still selects the links themselves.
+pub fn walk(fs: &dyn Filesystem, root: &Path, filter: &Filter) -> Result<Vec<PathBuf>> {
+    let mut walker = Walker { fs, root, filter, ancestors: Vec::new(), selected: Vec::new() };
+    let kind = walker.kind_of(root, None)?;
+    if filter.min_depth == 0 && filter.selects(fs, root, ".", kind)? {
+        walker.selected.push(root.to_path_buf());
+    }
+    if kind.0 == FileKind::Directory {
+        walker.walk_dir("", 1)?;
+    }
+    Ok(walker.selected)
+}
+
+struct Walker<'w, 'a> {
+    fs: &'w dyn Filesystem,
+    root: &'w Path,
+    filter: &'w Filter<'a>,
+    /// Canonical paths of the directories currently being descended, root first
+    ancestors: Vec<PathBuf>,
+    selected: Vec<PathBuf>,
+}
+
+impl Walker<'_, '_> {
+    /// The entry's kind, as seen through symlinks when following them, and
+    /// whether the entry itself is a link. Dangling links stay links.
+    fn kind_of(&self, path: &Path, listed: Option<FileKind>) -> Result<(FileKind, bool)> {
+        let own = match listed {
+            Some(kind) => kind,
+            None => {
+                self.fs
+                    .symlink_metadata(path)
+                    .with_context(|| format!("Failed to read metadata for {}", path.display()))?
+                    .kind
+            }
+        };
+        if own != FileKind::Symlink || !self.filter.follow_symlinks {
+            return Ok((own, own == FileKind::Symlink));
+        }
+        match self.fs.metadata(path) {
+            Ok(metadata) => Ok((metadata.kind, true)),
+            Err(_) => Ok((FileKind::Symlink, true)),
+        }
+    }
+
+    fn walk_dir(&mut self, relative: &str, depth: usize) -> Result<()> {
+        let dir = self.root.join(relative);
+        if self.filter.max_depth.is_some_and(|max| depth > max) {
+            return Ok(());
+        }
+        let canonical = self
+            .fs
+            .canonicalize(&dir)
+            .with_context(|| format!("Failed to resolve directory {}", dir.display()))?;
+        if self.ancestors.contains(&canonical) {
+            outln!("{} {}", tr("warning", &[]).yellow(), tr("filesystem-loop", &[("path", &dir.display().to_string())]));
+            return Ok(());
+        }
+        let entries = self
+            .fs
+            .read_dir(&dir)
+            .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+
+        self.ancestors.push(canonical);
+        for entry in entries {
+            let child = if relative.is_empty() {
+                entry.name.clone()
+            } else {
+                format!("{}/{}", relative, entry.name)
+            };
+            if any_match(self.filter.exclude, &child) {
+                continue;
+            }
+
+            let path = self.root.join(&child);
+            let kind = self.kind_of(&path, Some(entry.kind))?;
+            if depth >= self.filter.min_depth && self.filter.selects(self.fs, &path, &child, kind)? {
+                self.selected.push(path);
+            }
+            if kind.0 == FileKind::Directory {
+                self.walk_dir(&child, depth + 1)?;
+            }
+        }
+        self.ancestors.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::create_test_args;
+    use crate::vfs::{LocalFs, MemoryFs};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10k"), Ok(10 * 1024));
+        assert_eq!(parse_size("5M"), Ok(5 * 1024 * 1024));
+        assert!(parse_size("5X").is_err());
+        assert!(parse_size("k").is_err());
+    }
+
+    #[test]
+    fn test_walk_with_globs_and_types() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("logs/archive")).unwrap();
+        memory.create_dir_all(Path::new("logs/tmp")).unwrap();
+        memory.create_file(Path::new("logs/app.log")).unwrap();
+        memory.create_file(Path::new("logs/archive/old.log")).unwrap();
+        memory.create_file(Path::new("logs/tmp/scratch.log")).unwrap();
+        memory.create_file(Path::new("logs/notes.txt")).unwrap();
+
+        let mut args = create_test_args(vec!["logs".to_string()]);
+        args.include = vec!["*.log".to_string()];
+        args.exclude = vec!["tmp".to_string()];
+        let selected = walk(&memory, Path::new("logs"), &Filter::from_args(&args, SystemTime::now())).unwrap();
+        assert_eq!(selected, vec![PathBuf::from("logs/app.log"), PathBuf::from("logs/archive/old.log")]);
+
+        let mut args = create_test_args(vec!["logs".to_string()]);
+        args.entry_types = vec![EntryType::D];
+        let selected = walk(&memory, Path::new("logs"), &Filter::from_args(&args, SystemTime::now())).unwrap();
+        assert_eq!(
+            selected,
+            vec![PathBuf::from("logs"), PathBuf::from("logs/archive"), PathBuf::from("logs/tmp")]
+        );
+    }
+
+    #[test]
+    fn test_walk_depth_limits() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("top/mid/deep")).unwrap();
+        memory.create_file(Path::new("top/a.txt")).unwrap();
+        memory.create_file(Path::new("top/mid/b.txt")).unwrap();
+        memory.create_file(Path::new("top/mid/deep/c.txt")).unwrap();
+
+        let mut args = create_test_args(vec![]);
+        args.min_depth = Some(1);
+        args.max_depth = Some(2);
+        let selected = walk(&memory, Path::new("top"), &Filter::from_args(&args, SystemTime::now())).unwrap();
+        assert_eq!(
+            selected,
+            vec![
+                PathBuf::from("top/a.txt"),
+                PathBuf::from("top/mid"),
+                PathBuf::from("top/mid/b.txt"),
+                PathBuf::from("top/mid/deep"),
+            ]
+        );
+
+        args.min_depth = None;
+        args.max_depth = Some(0);
+        let selected = walk(&memory, Path::new("top"), &Filter::from_args(&args, SystemTime::now())).unwrap();
+        assert_eq!(selected, vec![PathBuf::from("top")]);
+    }
+
+    #[test]
+    fn test_walk_follows_symlinks_without_looping() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("tree");
+        let shared = temp_dir.path().join("shared");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::write(shared.join("linked.txt"), b"").unwrap();
+        std::os::unix::fs::symlink(&shared, root.join("shared")).unwrap();
+        std::os::unix::fs::symlink(&root, root.join("sub/back")).unwrap();
+
+        let mut args = create_test_args(vec![]);
+        args.entry_types = vec![EntryType::F, EntryType::L];
+        let selected = walk(&LocalFs, &root, &Filter::from_args(&args, SystemTime::now())).unwrap();
+        assert_eq!(selected, vec![root.join("shared"), root.join("sub/back")]);
+
+        args.follow_symlinks = true;
+        args.entry_types = vec![EntryType::F];
+        let selected = walk(&LocalFs, &root, &Filter::from_args(&args, SystemTime::now())).unwrap();
+        assert_eq!(selected, vec![root.join("shared/linked.txt")]);
+
+        // Links are still links when followed
+        args.entry_types = vec![EntryType::L];
+        let selected = walk(&LocalFs, &root, &Filter::from_args(&args, SystemTime::now())).unwrap();
+        assert_eq!(selected, vec![root.join("shared"), root.join("sub/back")]);
+        let selected = walk(&LocalFs, &root.join("shared"), &Filter::from_args(&args, SystemTime::now())).unwrap();
+        assert_eq!(selected, vec![root.join("shared")]);
+    }
+
+    #[test]
+    fn test_walk_with_age_and_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("big.bin"), vec![0u8; 4096]).unwrap();
+        std::fs::write(root.join("small.bin"), b"x").unwrap();
+        std::fs::write(root.join("stale.bin"), vec![0u8; 4096]).unwrap();
+        let old = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_times(root.join("stale.bin"), old, old).unwrap();
+
+        let mut args = create_test_args(vec![]);
+        args.entry_types = vec![EntryType::F];
+        args.min_size = Some(1024);
+        args.newer_than = Some(Duration::from_secs(86400));
+        let selected = walk(&LocalFs, root, &Filter::from_args(&args, SystemTime::now())).unwrap();
+        assert_eq!(selected, vec![root.join("big.bin")]);
+
+        args.newer_than = None;
+        args.older_than = Some(Duration::from_secs(86400));
+        let selected = walk(&LocalFs, root, &Filter::from_args(&args, SystemTime::now())).unwrap();
+        assert_eq!(selected, vec![root.join("stale.bin")]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Buffered standard output for the per-path report lines
+//!
+//! Rust's stdout flushes at every newline, so with tens of thousands of `✓`
+//! lines the writes dominate a run. Everything main (and what it calls while
+//! processing paths) prints goes through `outln!` into one buffer behind a
+//! lock, which keeps the lines in order. Outside the processing loop each line
+//! is flushed at once, like `println!`; inside it, the buffer is flushed when
+//! full, at least every `FLUSH_INTERVAL` so a slow run keeps showing progress,
+//! before any prompt, and when the loop ends. Write errors, such as a closed
+//! pipe, are ignored rather than aborting the run.
+//!
+//! A thread can `capture` its lines instead, which `-j` uses to print each
+//! path's lines in processing order rather than the order threads finish.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, BufWriter, Stdout, Write};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+const CAPACITY: usize = 64 * 1024;
+
+struct Out {
+    writer: BufWriter<Stdout>,
+    buffering: bool,
+    last_flush: Instant,
+}
+
+static OUT: Mutex<Option<Out>> = Mutex::new(None);
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+fn lock() -> MutexGuard<'static, Option<Out>> {
+    // A panic while printing leaves nothing inconsistent behind
+    OUT.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn with_out<T>(action: impl FnOnce(&mut Out) -> T) -> T {
+    let mut out = lock();
+    let out = out.get_or_insert_with(|| Out {
+        writer: BufWriter::with_capacity(CAPACITY, io::stdout()),
+        buffering: false,
+        last_flush: Instant::now(),
+    });
+    action(out)
+}
+
+impl Out {
+    fn flush(&mut self) {
+        let _ = self.writer.flush();
+        self.last_flush = Instant::now();
+    }
+}
+
+/// Write one line; see `outln!`
+pub fn line(args: fmt::Arguments) {
+    let captured = CAPTURED.with_borrow_mut(|captured| captured.as_mut().map(|lines| lines.push(args.to_string())));
+    if captured.is_some() {
+        return;
+    }
+    with_out(|out| {
+        let _ = writeln!(out.writer, "{}", args);
+        if !out.buffering || out.last_flush.elapsed() >= FLUSH_INTERVAL {
+            out.flush();
+        }
+    });
+}
+
+/// Hold lines back until the buffer fills or `FLUSH_INTERVAL` passes
+pub fn start_buffering() {
+    with_out(|out| out.buffering = true);
+}
+
+/// Flush, and print each line at once again
+pub fn stop_buffering() {
+    with_out(|out| {
+        out.flush();
+        out.buffering = false;
+    });
+}
+
+/// Write out the buffered lines, e.g. before asking a question
+pub fn flush() {
+    with_out(Out::flush);
+}
+
+/// Run `action` with the lines this thread prints held back, and return them
+pub fn capture<T>(action: impl FnOnce() -> T) -> (T, Vec<String>) {
+    CAPTURED.set(Some(Vec::new()));
+    let result = action();
+    (result, CAPTURED.take().unwrap_or_default())
+}
+
+/// Write lines returned by `capture`
+pub fn lines(lines: &[String]) {
+    for text in lines {
+        line(format_args!("{}", text));
+    }
+}
+
+/// `println!` through the shared buffer
+macro_rules! outln {
+    () => {
+        $crate::report::line(format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        $crate::report::line(format_args!($($arg)*))
+    };
+}
+pub(crate) use outln;
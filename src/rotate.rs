@@ -0,0 +1,137 @@
+//! `bank rotate`: shift numbered copies of a log up by one and start a fresh,
+//! empty log with the old one's mode and owner
+//!
+//! With `--keep 3`, `app.log.3` is dropped, `app.log.2` becomes `app.log.3`,
+//! `app.log.1` becomes `app.log.2`, `app.log` becomes `app.log.1`, and a new
+//! `app.log` is created straight away. Renames cannot be replayed backwards,
+//! so rotations are not recorded in the undo journal.
+
+use crate::owner;
+use crate::vfs::{FileKind, Filesystem};
+use anyhow::{bail, Context, Result};
+use clap::Args as ClapArgs;
+use colored::*;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct RotateArgs {
+    /// Log files to rotate
+    #[arg(value_name = "FILE", required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Number of rotated copies to keep (FILE.1 to FILE.N)
+    #[arg(long = "keep", value_name = "N", default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub keep: u32,
+
+    /// Skip missing files instead of failing
+    #[arg(long = "missing-ok")]
+    pub missing_ok: bool,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+/// `FILE.n`
+fn numbered(file: &Path, n: u32) -> PathBuf {
+    let mut name = OsString::from(file.as_os_str());
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Rotate one file; `false` if it was missing
+pub fn rotate(fs: &dyn Filesystem, file: &Path, keep: u32, verbose: bool) -> Result<bool> {
+    let original = match fs.symlink_metadata(file) {
+        Ok(metadata) if metadata.kind == FileKind::File => metadata,
+        Ok(_) => bail!("{} is not a regular file", file.display()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read metadata for {}", file.display())),
+    };
+
+    let oldest = numbered(file, keep);
+    if fs.symlink_metadata(&oldest).is_ok() {
+        fs.remove_file(&oldest).with_context(|| format!("Failed to remove {}", oldest.display()))?;
+        if verbose {
+            println!("Removed: {}", oldest.display());
+        }
+    }
+    for n in (1..keep).rev() {
+        let (from, to) = (numbered(file, n), numbered(file, n + 1));
+        if fs.symlink_metadata(&from).is_ok() {
+            fs.rename(&from, &to).with_context(|| format!("Failed to rename {} to {}", from.display(), to.display()))?;
+            if verbose {
+                println!("Renamed: {} -> {}", from.display(), to.display());
+            }
+        }
+    }
+    let first = numbered(file, 1);
+    fs.rename(file, &first).with_context(|| format!("Failed to rename {} to {}", file.display(), first.display()))?;
+
+    // Exclusive: if the program writing the log already reopened it, its new
+    // file is kept instead of being truncated
+    let now = SystemTime::now();
+    match fs.create_file_with_times(file, now, now) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            if verbose {
+                println!("Keeping {}, recreated in the meantime", file.display());
+            }
+            return Ok(true);
+        }
+        Err(err) => return Err(err).with_context(|| format!("Failed to create {}", file.display())),
+    }
+    fs.set_permissions(file, original.mode).with_context(|| format!("Failed to set permissions for {}", file.display()))?;
+    let created = fs.metadata(file).with_context(|| format!("Failed to read metadata for {}", file.display()))?;
+    if (created.uid, created.gid) != (original.uid, original.gid) {
+        // Only root can give the file away; the rotation itself is already done
+        if let Err(err) = owner::set_owner(fs, file, &format!("{}:{}", original.uid, original.gid), true) {
+            println!("{} {:#}", "Warning:".yellow(), err);
+        }
+    }
+    Ok(true)
+}
+
+pub fn run(fs: &dyn Filesystem, args: &RotateArgs) -> Result<()> {
+    for file in &args.files {
+        if rotate(fs, file, args.keep, args.verbose)? {
+            println!("{} Rotated {}", "✓".bright_green(), file.display().to_string().green());
+        } else if args.missing_ok {
+            if args.verbose {
+                println!("Skipping missing {}", file.display().to_string().yellow());
+            }
+        } else {
+            bail!("{} does not exist; nothing to rotate (--missing-ok skips missing files)", file.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[test]
+    fn test_rotate_shifts_numbered_copies() {
+        let fs = MemoryFs::new();
+        fs.write_file(Path::new("app.log"), b"current").unwrap();
+        fs.set_permissions(Path::new("app.log"), 0o640).unwrap();
+        fs.write_file(Path::new("app.log.1"), b"one").unwrap();
+        fs.write_file(Path::new("app.log.3"), b"three").unwrap();
+
+        assert!(rotate(&fs, Path::new("app.log"), 3, false).unwrap());
+        assert_eq!(fs.read_file(Path::new("app.log")).unwrap(), b"");
+        assert_eq!(fs.metadata(Path::new("app.log")).unwrap().mode, 0o640);
+        assert_eq!(fs.read_file(Path::new("app.log.1")).unwrap(), b"current");
+        assert_eq!(fs.read_file(Path::new("app.log.2")).unwrap(), b"one");
+        // The oldest copy made room instead of moving past --keep
+        assert_eq!(fs.read_file(Path::new("app.log.3")).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        assert!(!rotate(&fs, Path::new("missing.log"), 3, false).unwrap());
+        fs.create_dir(Path::new("logs")).unwrap();
+        assert!(rotate(&fs, Path::new("logs"), 3, false).is_err());
+    }
+}
@@ -0,0 +1,670 @@
+//! `bank new`: create a project from a scaffold, a directory whose files are
+//! templates and whose file and directory names may contain `{{ variables }}`
+//!
+//! Partials and base templates come from the scaffold's own `_partials/`
+//! directory first, then from the shared `$XDG_CONFIG_HOME/bank/partials`.
+//! Scaffolds given by name are looked up in `$XDG_CONFIG_HOME/bank/templates`;
+//! `--from-git` clones one with the system `git` into a temporary directory.
+//!
+//! A scaffold can declare the variables it needs in `_scaffold.json`:
+//!
+//! ```json
+//! {"variables": [{"name": "author", "prompt": "Author", "default": "{{ project }} developers", "pattern": "^\\S"}]}
+//! ```
+//!
+//! Each one is asked for in order (defaults may use the variables before
+//! them), unless given with `--var name=value`. Cookiecutter and
+//! cargo-generate templates are read as scaffolds too (see `cookiecutter`
+//! and `cargo_generate`).
+
+use crate::cargo_generate;
+use crate::cookiecutter;
+use crate::editorconfig::{self, FormatArgs};
+use crate::journal;
+use crate::json::{self, Value};
+use crate::keep::{self, KeepArgs};
+use crate::glob;
+use crate::secure;
+use crate::template::{Loader, Vars};
+use crate::vfs::{Filesystem, RecordingFs};
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use clap::Args as ClapArgs;
+use colored::*;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Input;
+use regex::Regex;
+use std::io::IsTerminal;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// Directory inside a scaffold that holds its partials; it is not copied
+pub const PARTIALS_DIR: &str = "_partials";
+
+/// File inside a scaffold that declares its variables; it is not copied
+pub const DECLARATIONS_FILE: &str = "_scaffold.json";
+
+/// Suffix stripped from file names, for templates that should not look like
+/// the file they produce (e.g. `Cargo.toml.tmpl`)
+const TEMPLATE_SUFFIX: &str = ".tmpl";
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct NewArgs {
+    /// Scaffold directory, or the name of one in ~/.config/bank/templates
+    /// (omitted with --from-git)
+    #[arg(value_name = "TEMPLATE")]
+    pub template: Option<String>,
+
+    /// Directory to create the project in
+    #[arg(value_name = "DEST")]
+    pub destination: Option<PathBuf>,
+
+    /// Clone the scaffold from a git repository instead
+    #[arg(long = "from-git", value_name = "URL")]
+    pub from_git: Option<String>,
+
+    /// Branch or tag to clone with --from-git
+    #[arg(long = "branch", value_name = "REF", requires = "from_git")]
+    pub branch: Option<String>,
+
+    /// Answer a scaffold variable without prompting (repeatable)
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+
+    #[command(flatten)]
+    pub format: FormatArgs,
+
+    #[command(flatten)]
+    pub keep: KeepArgs,
+
+    /// Do not record this run in the undo journal
+    #[arg(long = "no-journal")]
+    pub no_journal: bool,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+/// Where the scaffold comes from
+enum Source<'a> {
+    Local(&'a str),
+    Git(&'a str),
+}
+
+impl NewArgs {
+    /// The positional TEMPLATE and DEST; with `--from-git` the only one is DEST
+    fn source_and_destination(&self) -> Result<(Source<'_>, &Path)> {
+        match (self.from_git.as_deref(), self.template.as_deref(), self.destination.as_deref()) {
+            (Some(url), Some(destination), None) => Ok((Source::Git(url), Path::new(destination))),
+            (Some(_), _, _) => bail!("--from-git takes the repository instead of TEMPLATE: bank new --from-git <URL> <DEST>"),
+            (None, Some(template), Some(destination)) => Ok((Source::Local(template), destination)),
+            (None, _, _) => bail!("Usage: bank new <TEMPLATE> <DEST>"),
+        }
+    }
+}
+
+/// A scaffold cloned into a temporary directory, removed when dropped
+struct Checkout {
+    dir: PathBuf,
+}
+
+impl Checkout {
+    fn clone(url: &str, branch: Option<&str>) -> Result<Checkout> {
+        // git clones into the empty directory mkdtemp made
+        let dir = secure::make_temp_dir("bank-scaffold-").context("Failed to create a temporary directory")?;
+        let checkout = Checkout { dir };
+
+        let mut command = Command::new("git");
+        command.args(["clone", "--quiet", "--depth", "1"]);
+        if let Some(branch) = branch {
+            command.arg("--branch").arg(branch);
+        }
+        command.arg("--").arg(url).arg(&checkout.dir);
+        let output = command.output().context("Failed to run git")?;
+        if !output.status.success() {
+            bail!("Failed to clone {}: {}", url, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(checkout)
+    }
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn parse_var(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.to_string())),
+        _ => Err(format!("invalid variable '{}' (expected NAME=VALUE)", text)),
+    }
+}
+
+/// A variable declared in `_scaffold.json`
+#[derive(Debug)]
+pub struct Variable {
+    pub name: String,
+    pub prompt: Option<String>,
+    /// Template for the default, rendered with the variables before it
+    pub default: Option<String>,
+    pub pattern: Option<Regex>,
+}
+
+impl Variable {
+    fn from_fields(fields: &[(String, Value)]) -> Result<Self> {
+        let mut variable = Variable { name: String::new(), prompt: None, default: None, pattern: None };
+        for (key, value) in fields {
+            let text = match value {
+                Value::String(text) => text.clone(),
+                other => bail!("'{}' must be a string, found {}", key, other.type_name()),
+            };
+            match key.as_str() {
+                "name" => variable.name = text,
+                "prompt" => variable.prompt = Some(text),
+                "default" => variable.default = Some(text),
+                // Anchored so a pattern describes the whole answer, not a part of it
+                "pattern" => {
+                    variable.pattern = Some(Regex::new(&format!("^(?:{})$", text)).with_context(|| format!("invalid pattern '{}'", text))?)
+                }
+                other => bail!("unknown key '{}'", other),
+            }
+        }
+        if variable.name.is_empty() {
+            bail!("missing 'name'");
+        }
+        Ok(variable)
+    }
+
+    fn check(&self, value: &str) -> Result<(), String> {
+        match &self.pattern {
+            Some(pattern) if !pattern.is_match(value) => {
+                Err(format!("'{}' does not match the pattern {}", value, pattern.as_str()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The variables a scaffold declares, in prompting order
+pub fn load_declarations(scaffold: &Path) -> Result<Vec<Variable>> {
+    let path = scaffold.join(DECLARATIONS_FILE);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let text = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_declarations(&text).with_context(|| format!("Invalid {}", path.display()))
+}
+
+fn parse_declarations(text: &str) -> Result<Vec<Variable>> {
+    let items = match json::parse(text)?.get("variables") {
+        Some(Value::Array(items)) => items.clone(),
+        Some(other) => bail!("'variables' must be an array, found {}", other.type_name()),
+        None => Vec::new(),
+    };
+    items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| match item {
+            Value::Object(fields) => Variable::from_fields(fields).with_context(|| format!("variable {}", index + 1)),
+            other => bail!("variable {}: expected an object, found {}", index + 1, other.type_name()),
+        })
+        .collect()
+}
+
+/// Fill in every declared variable: from `--var`, else by prompting, else
+/// (without a terminal) from its default
+pub fn resolve_vars(
+    declarations: &[Variable],
+    given: &[(String, String)],
+    loader: &Loader,
+    origin: &Path,
+    mut vars: Vars,
+    interactive: bool,
+) -> Result<Vars> {
+    for (name, value) in given {
+        if let Some(variable) = declarations.iter().find(|variable| &variable.name == name) {
+            variable.check(value).map_err(anyhow::Error::msg).with_context(|| format!("--var {}", name))?;
+        }
+        vars.insert(name.clone(), value.clone());
+    }
+
+    for variable in declarations {
+        if given.iter().any(|(name, _)| name == &variable.name) {
+            continue;
+        }
+        let default = match &variable.default {
+            Some(default) => Some(loader.render(origin, default, &vars)?),
+            None => None,
+        };
+        let value = if interactive {
+            let theme = ColorfulTheme::default();
+            let mut input = Input::<String>::with_theme(&theme)
+                .with_prompt(variable.prompt.as_deref().unwrap_or(&variable.name))
+                .validate_with(|value: &String| variable.check(value));
+            if let Some(default) = &default {
+                input = input.default(default.clone());
+            }
+            input.interact_text().context("Prompting needs a terminal; pass --var NAME=VALUE")?
+        } else {
+            match default {
+                Some(default) => {
+                    variable.check(&default).map_err(anyhow::Error::msg).with_context(|| format!("default for '{}'", variable.name))?;
+                    default
+                }
+                None => bail!("No value for '{}' and no terminal to ask on; pass --var {}=VALUE", variable.name, variable.name),
+            }
+        };
+        vars.insert(variable.name.clone(), value);
+    }
+    Ok(vars)
+}
+
+/// A scaffold as `new` reads it, whichever tool it was written for
+pub struct Layout {
+    /// The directory whose contents make the project
+    pub root: PathBuf,
+    pub declarations: Vec<Variable>,
+    /// The file the declarations come from, for error messages
+    pub origin: PathBuf,
+    /// Prefix of the declared names in templates, which `--var` may leave out
+    pub namespace: &'static str,
+    /// Variables that are not asked for
+    pub constants: Vars,
+    /// Globs of paths, relative to `root`, copied without rendering
+    pub verbatim: Vec<String>,
+    /// Globs of paths, relative to `root`, left out of the project
+    pub ignore: Vec<String>,
+    /// Suffix dropped from the names of rendered files
+    pub suffix: &'static str,
+}
+
+impl Layout {
+    /// The scaffold in `scaffold`, for a project created in `destination`
+    pub fn read(scaffold: &Path, destination: &Path) -> Result<Self> {
+        if cookiecutter::is_template(scaffold) {
+            return cookiecutter::layout(scaffold);
+        }
+        if cargo_generate::is_template(scaffold) {
+            return cargo_generate::layout(scaffold, destination);
+        }
+        Ok(Layout {
+            root: scaffold.to_path_buf(),
+            declarations: load_declarations(scaffold)?,
+            origin: scaffold.join(DECLARATIONS_FILE),
+            namespace: "",
+            constants: Vars::new(),
+            verbatim: Vec::new(),
+            ignore: Vec::new(),
+            suffix: TEMPLATE_SUFFIX,
+        })
+    }
+
+    /// `--var` answers by their names in templates
+    fn qualify(&self, given: &[(String, String)]) -> Vec<(String, String)> {
+        given
+            .iter()
+            .map(|(name, value)| match name.starts_with(self.namespace) {
+                true => (name.clone(), value.clone()),
+                false => (format!("{}{}", self.namespace, name), value.clone()),
+            })
+            .collect()
+    }
+}
+
+/// One path of the rendered project, relative to the destination
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub path: PathBuf,
+    /// File contents, or `None` for a directory
+    pub contents: Option<Vec<u8>>,
+    pub mode: u32,
+}
+
+/// `$XDG_CONFIG_HOME/bank`, else `~/.config/bank`
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(config) = std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(config).join("bank"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/bank"))
+}
+
+/// A scaffold path, or a name under the templates directory
+fn locate(template: &str) -> Result<PathBuf> {
+    let direct = PathBuf::from(template);
+    if direct.is_dir() {
+        return Ok(direct);
+    }
+    if let Some(named) = config_dir().map(|dir| dir.join("templates").join(template)).filter(|dir| dir.is_dir()) {
+        return Ok(named);
+    }
+    bail!("No scaffold named '{}': not a directory, and not found in {}", template, templates_hint());
+}
+
+fn templates_hint() -> String {
+    config_dir().map_or("~/.config/bank/templates".to_string(), |dir| dir.join("templates").display().to_string())
+}
+
+/// Variables every scaffold can use: `project` (the destination's name), `year` and `date`
+pub fn builtin_vars(destination: &Path) -> Vars {
+    let project = std::path::absolute(destination)
+        .ok()
+        .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+    let today = Local::now();
+    Vars::from([
+        ("project".to_string(), project),
+        ("year".to_string(), today.format("%Y").to_string()),
+        ("date".to_string(), today.format("%Y-%m-%d").to_string()),
+    ])
+}
+
+/// The partial search path for a scaffold, in resolution order
+pub fn loader_for(scaffold: &Path) -> Loader {
+    let mut roots = vec![scaffold.join(PARTIALS_DIR)];
+    roots.extend(config_dir().map(|dir| dir.join("partials")));
+    Loader::new(roots)
+}
+
+/// Render every directory and file of the scaffold, parents before
+/// children; files matching a `verbatim` glob, or below a directory that
+/// does, keep their contents
+pub fn render_tree(layout: &Layout, loader: &Loader, vars: &Vars) -> Result<Vec<Item>> {
+    let mut items = Vec::new();
+    render_dir(layout, &layout.root, Path::new(""), loader, vars, &mut items)?;
+    Ok(items)
+}
+
+/// Whether `source` or a directory above it (inside `root`) matches one of `patterns`
+fn matches_below(root: &Path, source: &Path, patterns: &[String]) -> bool {
+    source.strip_prefix(root).is_ok_and(|relative| {
+        relative.ancestors().any(|path| !path.as_os_str().is_empty() && glob::any_match(patterns, &path.to_string_lossy()))
+    })
+}
+
+fn render_dir(layout: &Layout, dir: &Path, rendered: &Path, loader: &Loader, vars: &Vars, items: &mut Vec<Item>) -> Result<()> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read scaffold directory {}", dir.display()))?
+        .collect::<std::io::Result<_>>()
+        .with_context(|| format!("Failed to read scaffold directory {}", dir.display()))?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let source = entry.path();
+        let top_level = rendered.as_os_str().is_empty();
+        if top_level && (name == PARTIALS_DIR || name == DECLARATIONS_FILE || name == ".git") {
+            continue;
+        }
+        if matches_below(&layout.root, &source, &layout.ignore) {
+            continue;
+        }
+        let metadata = std::fs::symlink_metadata(&source).with_context(|| format!("Failed to read {}", source.display()))?;
+        let mode = metadata.permissions().mode() & 0o7777;
+
+        let mut target_name = loader.render(&source, &name, vars)?;
+        if metadata.is_dir() {
+            check_rendered_name(&source, &target_name)?;
+            let path = rendered.join(&target_name);
+            items.push(Item { path: path.clone(), contents: None, mode });
+            render_dir(layout, &source, &path, loader, vars, items)?;
+        } else if metadata.is_file() {
+            if let Some(stripped) = target_name.strip_suffix(layout.suffix).filter(|_| !layout.suffix.is_empty()) {
+                target_name = stripped.to_string();
+            }
+            check_rendered_name(&source, &target_name)?;
+            let bytes = std::fs::read(&source).with_context(|| format!("Failed to read {}", source.display()))?;
+            // Binary files are copied as they are
+            let contents = match String::from_utf8(bytes) {
+                Ok(text) if matches_below(&layout.root, &source, &layout.verbatim) => text.into_bytes(),
+                Ok(text) => loader.render(&source, &text, vars)?.into_bytes(),
+                Err(err) => err.into_bytes(),
+            };
+            items.push(Item { path: rendered.join(target_name), contents: Some(contents), mode });
+        } else {
+            println!("{} Skipping {}: only files and directories are copied from scaffolds", "Warning:".yellow(), source.display());
+        }
+    }
+    Ok(())
+}
+
+/// Refuse a rendered name that is not a single plain component, since a
+/// variable like `../../pwned` would otherwise place the entry outside the
+/// destination
+fn check_rendered_name(source: &Path, name: &str) -> Result<()> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(()),
+        _ => bail!("{} renders to '{}', which is not a plain file name", source.display(), name),
+    }
+}
+
+/// Create the rendered items under `destination`, refusing to overwrite files.
+/// Text files are formatted by the `.editorconfig` that applies to them.
+pub fn write_tree(fs: &dyn Filesystem, destination: &Path, items: &[Item], format: &FormatArgs, verbose: bool) -> Result<()> {
+    for item in items.iter().filter(|item| item.contents.is_some()) {
+        let target = destination.join(&item.path);
+        if fs.exists(&target) || fs.is_symlink(&target) {
+            bail!("{} already exists; nothing was created", target.display());
+        }
+    }
+
+    fs.create_dir_all(destination)
+        .with_context(|| format!("Failed to create directory {}", destination.display()))?;
+    for item in items {
+        let target = destination.join(&item.path);
+        match &item.contents {
+            None if fs.is_dir(&target) => continue,
+            None => fs.create_dir(&target).with_context(|| format!("Failed to create directory {}", target.display()))?,
+            Some(contents) => {
+                let contents = editorconfig::apply(&target, contents, format)?;
+                fs.write_file(&target, &contents).with_context(|| format!("Failed to write {}", target.display()))?
+            }
+        }
+        fs.set_permissions(&target, item.mode)
+            .with_context(|| format!("Failed to set permissions for {}", target.display()))?;
+        if verbose {
+            println!("Created: {}", target.display().to_string().green());
+        }
+    }
+    Ok(())
+}
+
+pub fn run(fs: &dyn Filesystem, args: &NewArgs) -> Result<()> {
+    let (source, destination) = args.source_and_destination()?;
+    let (checkout, scaffold) = match source {
+        Source::Git(url) => {
+            let checkout = Checkout::clone(url, args.branch.as_deref())?;
+            let dir = checkout.dir.clone();
+            (Some(checkout), dir)
+        }
+        Source::Local(template) => (None, locate(template)?),
+    };
+    let origin = args.from_git.clone().unwrap_or_else(|| scaffold.display().to_string());
+    let loader = loader_for(&scaffold);
+    let layout = Layout::read(&scaffold, destination)?;
+    let mut vars = builtin_vars(destination);
+    vars.extend(layout.constants.clone());
+    let vars = resolve_vars(
+        &layout.declarations,
+        &layout.qualify(&args.vars),
+        &loader,
+        &layout.origin,
+        vars,
+        std::io::stdin().is_terminal(),
+    )?;
+    let items = render_tree(&layout, &loader, &vars)?;
+    drop(checkout);
+    let keep_file = args.keep.resolved()?;
+
+    let recording = RecordingFs::new(fs);
+    let result = write_tree(&recording, destination, &items, &args.format, args.verbose).and_then(|()| {
+        // Directories the scaffold leaves empty, e.g. an empty `assets/`
+        let Some(name) = &keep_file else { return Ok(()) };
+        for dir in items.iter().filter(|item| item.contents.is_none()).map(|item| destination.join(&item.path)) {
+            keep::fill(&recording, &dir, name).with_context(|| format!("Failed to create {}", dir.join(name).display()))?;
+        }
+        Ok(())
+    });
+    if !args.no_journal {
+        if let Err(err) = journal::record(&recording, None) {
+            println!("{} {:#}", "Warning:".yellow(), err);
+        }
+    }
+    result?;
+    let files = items.iter().filter(|item| item.contents.is_some()).count();
+    println!(
+        "{} Created {} from {} ({} files)",
+        "✓".bright_green(),
+        destination.display().to_string().green(),
+        origin,
+        files.to_string().cyan()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editorconfig::{Encoding, Eol};
+    use crate::vfs::MemoryFs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render_scaffold() {
+        let temp_dir = TempDir::new().unwrap();
+        let scaffold = temp_dir.path();
+        std::fs::create_dir_all(scaffold.join("_partials")).unwrap();
+        std::fs::create_dir_all(scaffold.join("src")).unwrap();
+        std::fs::write(scaffold.join("_partials/header"), "// {{ project }} (c) {{ year }}\n").unwrap();
+        std::fs::write(scaffold.join("_partials/module.tmpl"), "{% include \"header\" %}\n{% block body %}{% endblock %}\n").unwrap();
+        std::fs::write(scaffold.join("src/{{project}}.rs.tmpl"), "{% extends \"module\" %}\n{% block body %}pub fn run() {}{% endblock %}\n").unwrap();
+        std::fs::write(scaffold.join("run.sh"), "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(scaffold.join("run.sh"), std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(scaffold.join("logo.bin"), [0xff, 0xfe, b'{', b'{']).unwrap();
+
+        let vars = Vars::from([("project".to_string(), "demo".to_string()), ("year".to_string(), "2026".to_string())]);
+        let loader = Loader::new(vec![scaffold.join(PARTIALS_DIR)]);
+        let items = render_tree(&Layout::read(scaffold, Path::new("out")).unwrap(), &loader, &vars).unwrap();
+        let paths: Vec<&Path> = items.iter().map(|item| item.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("logo.bin"), Path::new("run.sh"), Path::new("src"), Path::new("src/demo.rs")]);
+        assert_eq!(items[0].contents.as_deref(), Some(&[0xff, 0xfe, b'{', b'{'][..]));
+        assert_eq!(items[1].mode, 0o755);
+        assert_eq!(items[3].contents.as_deref(), Some(&b"// demo (c) 2026\npub fn run() {}\n"[..]));
+
+        let memory = MemoryFs::new();
+        write_tree(&memory, Path::new("out"), &items, &FormatArgs::default(), false).unwrap();
+        assert_eq!(memory.contents("out/src/demo.rs"), items[3].contents);
+        assert_eq!(memory.metadata(Path::new("out/run.sh")).unwrap().mode, 0o755);
+        // A second run would overwrite files: refused before anything is written
+        assert!(write_tree(&memory, Path::new("out"), &items, &FormatArgs::default(), false).is_err());
+    }
+
+    /// A destination whose `.editorconfig` asks for CRLF and UTF-8, ending
+    /// the search there, and the items to write into it
+    fn formatted_destination() -> (TempDir, PathBuf, Vec<Item>) {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join(".editorconfig"), "root = true\n\n[*]\ncharset = utf-8\nend_of_line = crlf\n").unwrap();
+        let destination = temp_dir.path().join("out");
+        let items = vec![
+            Item { path: PathBuf::from("logo.bin"), contents: Some(vec![0xff, b'\n']), mode: 0o644 },
+            Item { path: PathBuf::from("notes.txt"), contents: Some("café\nok\n".as_bytes().to_vec()), mode: 0o644 },
+        ];
+        (temp_dir, destination, items)
+    }
+
+    #[test]
+    fn test_write_tree_with_encoding() {
+        let (_temp_dir, destination, items) = formatted_destination();
+        let notes = destination.join("notes.txt");
+
+        let memory = MemoryFs::new();
+        write_tree(&memory, &destination, &items, &FormatArgs::default(), false).unwrap();
+        assert_eq!(memory.contents(&notes).unwrap(), "café\r\nok\r\n".as_bytes());
+
+        // --encoding replaces the .editorconfig charset; binary files stay as they are
+        let memory = MemoryFs::new();
+        let format = FormatArgs { encoding: Some(Encoding::Latin1), eol: None };
+        write_tree(&memory, &destination, &items, &format, false).unwrap();
+        assert_eq!(memory.contents(&notes).unwrap(), b"caf\xe9\r\nok\r\n");
+        assert_eq!(memory.contents(destination.join("logo.bin")).unwrap(), [0xff, b'\n']);
+
+        let memory = MemoryFs::new();
+        let format = FormatArgs { encoding: Some(Encoding::Utf16le), eol: None };
+        write_tree(&memory, &destination, &items, &format, false).unwrap();
+        assert_eq!(memory.contents(&notes).unwrap()[..4], [b'c', 0, b'a', 0]);
+
+        // A text the charset cannot hold stops before it is written
+        let memory = MemoryFs::new();
+        let euro = [Item { path: PathBuf::from("price.txt"), contents: Some("5 €\n".as_bytes().to_vec()), mode: 0o644 }];
+        let format = FormatArgs { encoding: Some(Encoding::Latin1), eol: None };
+        assert!(write_tree(&memory, &destination, &euro, &format, false).is_err());
+        assert!(!memory.exists(&destination.join("price.txt")));
+    }
+
+    #[test]
+    fn test_write_tree_with_eol() {
+        let (_temp_dir, destination, items) = formatted_destination();
+        let notes = destination.join("notes.txt");
+
+        // --eol wins over the .editorconfig end_of_line
+        let memory = MemoryFs::new();
+        write_tree(&memory, &destination, &items, &FormatArgs { encoding: None, eol: Some(Eol::Lf) }, false).unwrap();
+        assert_eq!(memory.contents(&notes).unwrap(), "café\nok\n".as_bytes());
+
+        let memory = MemoryFs::new();
+        write_tree(&memory, &destination, &items, &FormatArgs { encoding: None, eol: Some(Eol::Native) }, false).unwrap();
+        let native = if cfg!(windows) { "café\r\nok\r\n" } else { "café\nok\n" };
+        assert_eq!(memory.contents(&notes).unwrap(), native.as_bytes());
+        assert_eq!(memory.contents(destination.join("logo.bin")).unwrap(), [0xff, b'\n']);
+    }
+
+    #[test]
+    fn test_declared_variables() {
+        let declarations = parse_declarations(
+            r#"{"variables": [
+                {"name": "crate", "default": "{{ project }}-core", "pattern": "[a-z-]+"},
+                {"name": "author", "prompt": "Author"}
+            ]}"#,
+        )
+        .unwrap();
+        let loader = Loader::new(Vec::new());
+        let origin = Path::new(DECLARATIONS_FILE);
+        let builtin = Vars::from([("project".to_string(), "demo".to_string())]);
+        let given = [("author".to_string(), "Jane".to_string())];
+
+        let vars = resolve_vars(&declarations, &given, &loader, origin, builtin.clone(), false).unwrap();
+        assert_eq!(vars["crate"], "demo-core");
+        assert_eq!(vars["author"], "Jane");
+
+        // Without a terminal, a variable with no default must be given
+        assert!(resolve_vars(&declarations, &[], &loader, origin, builtin.clone(), false).is_err());
+        // Patterns must match the whole value
+        let bad = [("author".to_string(), "Jane".to_string()), ("crate".to_string(), "Demo".to_string())];
+        assert!(resolve_vars(&declarations, &bad, &loader, origin, builtin, false).is_err());
+        assert!(parse_declarations(r#"{"variables": [{"name": "x", "colour": "red"}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_from_git_takes_only_destination() {
+        let args = |template: Option<&str>, destination: Option<&str>, from_git: Option<&str>| NewArgs {
+            template: template.map(String::from),
+            destination: destination.map(PathBuf::from),
+            from_git: from_git.map(String::from),
+            branch: None,
+            vars: Vec::new(),
+            format: FormatArgs::default(),
+            keep: KeepArgs::default(),
+            no_journal: true,
+            verbose: false,
+        };
+        let git = args(Some("app"), None, Some("https://example.com/skel"));
+        assert!(matches!(git.source_and_destination().unwrap(), (Source::Git("https://example.com/skel"), dest) if dest == Path::new("app")));
+        let local = args(Some("skel"), Some("app"), None);
+        assert!(matches!(local.source_and_destination().unwrap(), (Source::Local("skel"), dest) if dest == Path::new("app")));
+        assert!(args(Some("skel"), Some("app"), Some("https://example.com/skel")).source_and_destination().is_err());
+        assert!(args(Some("skel"), None, None).source_and_destination().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! `--jobs`: process the paths on several threads without racing on parents
+//!
+//! The paths are laid out in a trie of their components. Each node of the
+//! trie belongs to the first path (in processing order) that goes through it,
+//! and a path waits for the owner of the deepest node it shares with an
+//! earlier one, which has made (or found) every directory they have in common
+//! by the time it is done. `a/b/` is therefore created before `a/b/c/d.txt`,
+//! two files below a missing `a/x/` do not both try to make it, and paths with
+//! nothing in common run side by side. Repeated paths wait for each other, so
+//! they are processed one after the other as in a serial run. The trie is
+//! lexical: `a/../b` and `b` are different paths here.
+
+use anyhow::Result;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+
+/// For each path, the position of the path it has to wait for
+pub fn dependencies<'a>(paths: impl IntoIterator<Item = &'a str>) -> Vec<Option<usize>> {
+    // Every node of the trie, by the components leading to it, and its owner
+    let mut owners: HashMap<PathBuf, usize> = HashMap::new();
+    paths
+        .into_iter()
+        .enumerate()
+        .map(|(position, path_str)| {
+            let mut node = PathBuf::new();
+            let mut waits_for = None;
+            for component in Path::new(path_str).components() {
+                node.push(component);
+                let owner = *owners.entry(node.clone()).or_insert(position);
+                if owner != position {
+                    waits_for = Some(owner);
+                }
+            }
+            waits_for
+        })
+        .collect()
+}
+
+struct State {
+    /// Smallest position first, to stay close to processing order
+    ready: BinaryHeap<Reverse<usize>>,
+    /// The paths waiting for each one
+    waiting: Vec<Vec<usize>>,
+    remaining: usize,
+    error: Option<anyhow::Error>,
+}
+
+/// Run `work` for every position of `dependencies` on up to `jobs` threads,
+/// each once the path it waits for is done, earlier positions first. The
+/// first failure stops anything new from starting and is returned once the
+/// paths already under way have finished.
+pub fn run(dependencies: &[Option<usize>], jobs: usize, work: &(dyn Fn(usize) -> Result<()> + Sync)) -> Result<()> {
+    let mut waiting = vec![Vec::new(); dependencies.len()];
+    let mut ready = BinaryHeap::new();
+    for (position, waits_for) in dependencies.iter().enumerate() {
+        match waits_for {
+            Some(owner) => waiting[*owner].push(position),
+            None => ready.push(Reverse(position)),
+        }
+    }
+    let state = Mutex::new(State { ready, waiting, remaining: dependencies.len(), error: None });
+    let wakeup = Condvar::new();
+
+    let worker = || loop {
+        let mut guard = state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let position = loop {
+            if guard.error.is_some() || guard.remaining == 0 {
+                return;
+            }
+            if let Some(Reverse(position)) = guard.ready.pop() {
+                break position;
+            }
+            guard = wakeup.wait(guard).unwrap_or_else(|poisoned| poisoned.into_inner());
+        };
+        drop(guard);
+
+        let result = work(position);
+
+        let mut guard = state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        guard.remaining -= 1;
+        match result {
+            Ok(()) => {
+                let unblocked = std::mem::take(&mut guard.waiting[position]);
+                guard.ready.extend(unblocked.into_iter().map(Reverse));
+            }
+            Err(err) => {
+                guard.error.get_or_insert(err);
+            }
+        }
+        wakeup.notify_all();
+    };
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, dependencies.len().max(1)) {
+            scope.spawn(worker);
+        }
+    });
+
+    match state.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()).error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependencies_follow_the_deepest_shared_node() {
+        let paths = ["a/b/", "a/b/c/d.txt", "a/b/c/e.txt", "a/x/1", "a/x/2", "z.txt", "z.txt"];
+        assert_eq!(dependencies(paths), [None, Some(0), Some(1), Some(0), Some(3), None, Some(5)]);
+        assert_eq!(dependencies(["/srv/a", "/srv/b", "srv/c"]), [None, Some(0), None]);
+    }
+
+    #[test]
+    fn test_run_finishes_each_path_before_those_waiting_for_it() {
+        let paths = ["a/", "a/b/", "a/b/c.txt", "a/d.txt", "e/", "e/f.txt", "g.txt"];
+        let dependencies = dependencies(paths);
+        let done = Mutex::new(Vec::new());
+        run(&dependencies, 4, &|position| {
+            done.lock().unwrap().push(position);
+            Ok(())
+        })
+        .unwrap();
+        let done = done.into_inner().unwrap();
+        assert_eq!(done.len(), paths.len());
+        for (position, waits_for) in dependencies.iter().enumerate() {
+            if let Some(owner) = waits_for {
+                let index_of = |wanted: usize| done.iter().position(|&p| p == wanted).unwrap();
+                assert!(index_of(*owner) < index_of(position));
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_stops_at_a_failure() {
+        let dependencies = dependencies(["a/", "a/b.txt", "a/c.txt"]);
+        let done = Mutex::new(Vec::new());
+        let err = run(&dependencies, 2, &|position| {
+            done.lock().unwrap().push(position);
+            if position == 0 {
+                anyhow::bail!("no room");
+            }
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "no room");
+        assert_eq!(done.into_inner().unwrap(), [0]);
+    }
+}
@@ -0,0 +1,59 @@
+//! `bank schema`: print the JSON Schema of the manifest format
+//!
+//! There is one schema per manifest version, bundled with bank, so editors
+//! can check and complete manifests and other tools can generate them. YAML
+//! manifests have the same structure, so the schema applies to them too.
+
+use crate::manifest::VERSION;
+use anyhow::{Context, Result};
+use clap::Args as ClapArgs;
+use std::path::PathBuf;
+
+/// The schema of every manifest version, oldest first
+const SCHEMAS: &[(u32, &str)] = &[(1, include_str!("schemas/manifest-v1.json")), (2, include_str!("schemas/manifest-v2.json"))];
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct SchemaArgs {
+    /// Manifest version to describe (defaults to the one bank writes)
+    #[arg(long = "manifest-version", value_name = "N", default_value_t = VERSION, value_parser = clap::value_parser!(u32).range(1..=VERSION as i64))]
+    pub version: u32,
+
+    /// Write the schema to FILE instead of printing it
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+pub fn schema(version: u32) -> Option<&'static str> {
+    SCHEMAS.iter().find(|(known, _)| *known == version).map(|(_, text)| *text)
+}
+
+pub fn run(args: &SchemaArgs) -> Result<()> {
+    let text = schema(args.version).with_context(|| format!("No schema for manifest version {}", args.version))?;
+    match &args.output {
+        Some(file) => std::fs::write(file, text).with_context(|| format!("Failed to write {}", file.display())),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::{self, Value};
+
+    #[test]
+    fn test_schema_lists_every_entry_field() {
+        let document = json::parse(schema(VERSION).unwrap()).unwrap();
+        let entry = document.get("$defs").and_then(|defs| defs.get("entry")).unwrap();
+        let Some(Value::Object(properties)) = entry.get("properties") else {
+            panic!("entry has no properties");
+        };
+        let names: Vec<&str> = properties.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["path", "type", "mode", "owner", "mtime", "hash", "change", "when", "content"]);
+        assert!(schema(VERSION + 1).is_none());
+        // Published schemas do not change; new fields go into a new version
+        assert!(!schema(1).unwrap().contains("\"when\""));
+    }
+}
@@ -0,0 +1,450 @@
+//! `--secure`: a filesystem that never follows symlinks, for setuid helpers
+//! and provisioning scripts that handle untrusted names
+//!
+//! The parent of every target is opened with `openat2(RESOLVE_NO_SYMLINKS)`
+//! (or, where that is unavailable, one `O_NOFOLLOW` component at a time), and
+//! the operation itself runs on the final name relative to that descriptor
+//! with `O_NOFOLLOW`/`AT_SYMLINK_NOFOLLOW`. Swapping a directory for a symlink
+//! half-way through therefore cannot redirect it.
+
+use crate::vfs::{DirEntry, FileKind, Filesystem, LocalFs, Metadata};
+use std::ffi::{CStr, CString, OsStr};
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+#[cfg(target_os = "linux")]
+const PATH_ONLY: libc::c_int = libc::O_PATH;
+#[cfg(not(target_os = "linux"))]
+const PATH_ONLY: libc::c_int = libc::O_RDONLY;
+
+pub struct SecureFs;
+
+fn c_name(name: &OsStr) -> io::Result<CString> {
+    CString::new(name.as_bytes()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte"))
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Reset errno, for calls like readdir that only signal errors through it
+unsafe fn clear_errno() {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        *libc::__errno_location() = 0;
+    }
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    {
+        *libc::__error() = 0;
+    }
+}
+
+/// Turn the errors a symlink produces into one that says so
+fn refuse_symlinks(path: &Path, err: io::Error) -> io::Error {
+    match err.raw_os_error() {
+        Some(libc::ELOOP) | Some(libc::EXDEV) => io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is or passes through a symlink, which --secure refuses", path.display()),
+        ),
+        _ => err,
+    }
+}
+
+fn openat(dir: libc::c_int, name: &CString, flags: libc::c_int, mode: libc::mode_t) -> io::Result<OwnedFd> {
+    // SAFETY: name is NUL-terminated and the returned descriptor is owned by the result
+    let fd = unsafe { libc::openat(dir, name.as_ptr(), flags | libc::O_CLOEXEC, mode as libc::c_uint) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd was just returned by openat and nothing else owns it
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// openat2 with RESOLVE_NO_SYMLINKS; `None` when the kernel doesn't support it
+#[cfg(target_os = "linux")]
+fn open_no_symlinks(path: &Path, flags: libc::c_int) -> Option<io::Result<OwnedFd>> {
+    let c_path = match c_name(path.as_os_str()) {
+        Ok(c_path) => c_path,
+        Err(err) => return Some(Err(err)),
+    };
+    // SAFETY: open_how is a plain C struct for which all-zero is valid
+    let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+    how.flags = (flags | libc::O_CLOEXEC) as u64;
+    how.resolve = libc::RESOLVE_NO_SYMLINKS | libc::RESOLVE_NO_MAGICLINKS;
+    // SAFETY: all pointers are valid for the duration of the call and the size matches `how`
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            libc::AT_FDCWD,
+            c_path.as_ptr(),
+            &how as *const libc::open_how,
+            std::mem::size_of::<libc::open_how>(),
+        )
+    };
+    if fd >= 0 {
+        // SAFETY: fd was just returned by openat2 and nothing else owns it
+        return Some(Ok(unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) }));
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::ENOSYS) | Some(libc::EPERM) => None,
+        _ => Some(Err(err)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_no_symlinks(_path: &Path, _flags: libc::c_int) -> Option<io::Result<OwnedFd>> {
+    None
+}
+
+/// Open a directory one component at a time, refusing symlinks at each step
+fn open_by_components(path: &Path) -> io::Result<OwnedFd> {
+    let start = if path.is_absolute() { "/" } else { "." };
+    let mut dir = openat(libc::AT_FDCWD, &c_name(OsStr::new(start))?, PATH_ONLY | libc::O_DIRECTORY, 0)?;
+    for component in path.components() {
+        let name = match component {
+            Component::Normal(name) => name,
+            Component::ParentDir => OsStr::new(".."),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => continue,
+        };
+        dir = openat(dir.as_raw_fd(), &c_name(name)?, PATH_ONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW, 0)?;
+    }
+    Ok(dir)
+}
+
+fn open_dir(path: &Path) -> io::Result<OwnedFd> {
+    let path = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+    match open_no_symlinks(path, PATH_ONLY | libc::O_DIRECTORY) {
+        Some(result) => result,
+        None => open_by_components(path),
+    }
+}
+
+/// The parent directory of `path` (opened without following symlinks) and its final name
+fn split(path: &Path) -> io::Result<(OwnedFd, CString)> {
+    let name = match path.components().next_back() {
+        Some(Component::Normal(name)) => name,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} does not name a file", path.display()))),
+    };
+    let parent = path.parent().unwrap_or(Path::new(""));
+    let dir = open_dir(parent).map_err(|err| refuse_symlinks(path, err))?;
+    Ok((dir, c_name(name)?))
+}
+
+/// Create a new directory in the system temp directory with `mkdtemp`, so
+/// its name cannot be predicted and claimed by someone else beforehand
+pub fn make_temp_dir(prefix: &str) -> io::Result<PathBuf> {
+    let template = std::env::temp_dir().join(format!("{}XXXXXX", prefix));
+    let mut bytes = c_name(template.as_os_str())?.into_bytes_with_nul();
+    // SAFETY: bytes is a NUL-terminated buffer that mkdtemp fills in place
+    if unsafe { libc::mkdtemp(bytes.as_mut_ptr() as *mut libc::c_char) }.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    bytes.pop();
+    Ok(PathBuf::from(OsStr::from_bytes(&bytes)))
+}
+
+impl SecureFs {
+    /// Open the final component itself, without following it
+    fn open(&self, path: &Path, flags: libc::c_int, mode: libc::mode_t) -> io::Result<File> {
+        let (dir, name) = split(path)?;
+        let fd = openat(dir.as_raw_fd(), &name, flags | libc::O_NOFOLLOW, mode).map_err(|err| refuse_symlinks(path, err))?;
+        Ok(File::from(fd))
+    }
+}
+
+impl Filesystem for SecureFs {
+    /// Like `symlink_metadata`: a final symlink is reported, never followed
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.symlink_metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        if path.components().next_back().is_none_or(|component| !matches!(component, Component::Normal(_))) {
+            return LocalFs::convert(File::from(open_dir(path).map_err(|err| refuse_symlinks(path, err))?).metadata()?);
+        }
+        #[cfg(target_os = "linux")]
+        let flags = libc::O_PATH;
+        // Without O_PATH a symlink cannot be opened at all; report it through lstat
+        #[cfg(not(target_os = "linux"))]
+        let flags = libc::O_RDONLY | libc::O_NONBLOCK;
+        match self.open(path, flags, 0) {
+            Ok(file) => LocalFs::convert(file.metadata()?),
+            Err(_) if !cfg!(target_os = "linux") && LocalFs.is_symlink(path) => LocalFs.symlink_metadata(path),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<()> {
+        self.open(path, libc::O_WRONLY | libc::O_CREAT, 0o666).map(|_| ())
+    }
+
+    fn create_file_with_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        let file = self.open(path, libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL, 0o666)?;
+        filetime::set_file_handle_times(
+            &file,
+            Some(filetime::FileTime::from_system_time(accessed)),
+            Some(filetime::FileTime::from_system_time(modified)),
+        )
+    }
+
+    fn create_file_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.open(path, libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL, mode as libc::mode_t).map(|_| ())
+    }
+
+    fn write_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.open(path, libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC, 0o666)?.write_all(contents)
+    }
+
+    fn append_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.open(path, libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND, 0o666)?.write_all(contents)
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        io::Read::read_to_end(&mut self.open(path, libc::O_RDONLY, 0)?, &mut contents)?;
+        Ok(contents)
+    }
+
+    fn read_file_prefix(&self, path: &Path, limit: usize) -> io::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        io::Read::read_to_end(&mut io::Read::take(self.open(path, libc::O_RDONLY, 0)?, limit as u64), &mut contents)?;
+        Ok(contents)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let (dir, name) = split(path)?;
+        // SAFETY: name is NUL-terminated and dir is an open directory
+        check(unsafe { libc::mkdirat(dir.as_raw_fd(), name.as_ptr(), 0o777) }).map_err(|err| refuse_symlinks(path, err))
+    }
+
+    fn create_dir_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        let (dir, name) = split(path)?;
+        // SAFETY: name is NUL-terminated and dir is an open directory
+        check(unsafe { libc::mkdirat(dir.as_raw_fd(), name.as_ptr(), mode as libc::mode_t) }).map_err(|err| refuse_symlinks(path, err))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut current = PathBuf::new();
+        for component in path.components() {
+            current.push(component);
+            if !matches!(component, Component::Normal(_)) {
+                continue;
+            }
+            match self.symlink_metadata(&current) {
+                Ok(metadata) if metadata.is_dir() => {}
+                Ok(metadata) if metadata.kind == FileKind::Symlink => {
+                    return Err(refuse_symlinks(&current, io::Error::from_raw_os_error(libc::ELOOP)))
+                }
+                Ok(_) => return Err(io::Error::from_raw_os_error(libc::ENOTDIR)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => match self.create_dir(&current) {
+                    Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err),
+                    _ => {}
+                },
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        let file = self.open(path, PATH_ONLY, 0)?;
+        if file.metadata()?.file_type().is_symlink() {
+            return Err(refuse_symlinks(path, io::Error::from_raw_os_error(libc::ELOOP)));
+        }
+        // fchmod does not take O_PATH descriptors; chmod through the
+        // descriptor's /proc entry reaches the same, already-checked inode
+        #[cfg(target_os = "linux")]
+        {
+            let proc_path = c_name(OsStr::new(&format!("/proc/self/fd/{}", file.as_raw_fd())))?;
+            // SAFETY: proc_path is NUL-terminated
+            check(unsafe { libc::chmod(proc_path.as_ptr(), mode as libc::mode_t) })
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            // SAFETY: the descriptor is open for the duration of the call
+            check(unsafe { libc::fchmod(file.as_raw_fd(), mode as libc::mode_t) })
+        }
+    }
+
+    /// Changes the owner of a final symlink itself rather than its target
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        let (dir, name) = split(path)?;
+        let (uid, gid) = (uid.unwrap_or(libc::uid_t::MAX), gid.unwrap_or(libc::gid_t::MAX));
+        // SAFETY: name is NUL-terminated and dir is an open directory; -1 ids leave that id unchanged
+        check(unsafe { libc::fchownat(dir.as_raw_fd(), name.as_ptr(), uid, gid, libc::AT_SYMLINK_NOFOLLOW) })
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let (dir, name) = split(path)?;
+        // SAFETY: name is NUL-terminated and dir is an open directory
+        check(unsafe { libc::unlinkat(dir.as_raw_fd(), name.as_ptr(), 0) })
+    }
+
+    /// renameat between the two pinned parents; neither final name is followed
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from_dir, from_name) = split(from)?;
+        let (to_dir, to_name) = split(to)?;
+        // SAFETY: both names are NUL-terminated and both descriptors are open directories
+        check(unsafe { libc::renameat(from_dir.as_raw_fd(), from_name.as_ptr(), to_dir.as_raw_fd(), to_name.as_ptr()) })
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let (dir, name) = split(path)?;
+        // SAFETY: name is NUL-terminated and dir is an open directory
+        check(unsafe { libc::unlinkat(dir.as_raw_fd(), name.as_ptr(), libc::AT_REMOVEDIR) })
+    }
+
+    /// Sets the times of a final symlink itself rather than its target
+    fn set_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        let (dir, name) = split(path)?;
+        let timespec = |time: SystemTime| {
+            let time = filetime::FileTime::from_system_time(time);
+            libc::timespec { tv_sec: time.unix_seconds() as libc::time_t, tv_nsec: time.nanoseconds() as _ }
+        };
+        let times = [timespec(accessed), timespec(modified)];
+        // SAFETY: name is NUL-terminated, dir is an open directory and times has two entries
+        check(unsafe { libc::utimensat(dir.as_raw_fd(), name.as_ptr(), times.as_ptr(), libc::AT_SYMLINK_NOFOLLOW) })
+    }
+
+    /// Lists the directory through a descriptor opened without following
+    /// symlinks, so a swapped-in link cannot redirect the listing
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let dir = open_dir(path).map_err(|err| refuse_symlinks(path, err))?;
+        let fd = openat(dir.as_raw_fd(), &c_name(OsStr::new("."))?, libc::O_RDONLY | libc::O_DIRECTORY, 0)?;
+        // SAFETY: fd is an open directory; on success the stream owns it
+        let stream = unsafe { libc::fdopendir(fd.as_raw_fd()) };
+        if stream.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        // The stream owns the descriptor from here on
+        let _ = fd.into_raw_fd();
+        let mut entries = Vec::new();
+        let result = loop {
+            // SAFETY: stream is open until closedir below; errno distinguishes the end from an error
+            let entry = unsafe {
+                clear_errno();
+                libc::readdir(stream)
+            };
+            if entry.is_null() {
+                let err = io::Error::last_os_error();
+                break if err.raw_os_error() == Some(0) { Ok(()) } else { Err(err) };
+            }
+            // SAFETY: d_name is NUL-terminated and lives until the next readdir
+            let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+            if name.to_bytes() == b"." || name.to_bytes() == b".." {
+                continue;
+            }
+            // SAFETY: entry is valid until the next readdir
+            let kind = match unsafe { (*entry).d_type } {
+                libc::DT_DIR => FileKind::Directory,
+                libc::DT_LNK => FileKind::Symlink,
+                libc::DT_UNKNOWN => {
+                    // SAFETY: stat is plain old data, and stream's descriptor is open
+                    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+                    if let Err(err) = check(unsafe { libc::fstatat(libc::dirfd(stream), name.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW) }) {
+                        break Err(err);
+                    }
+                    match stat.st_mode & libc::S_IFMT {
+                        libc::S_IFDIR => FileKind::Directory,
+                        libc::S_IFLNK => FileKind::Symlink,
+                        _ => FileKind::File,
+                    }
+                }
+                _ => FileKind::File,
+            };
+            entries.push(DirEntry { name: name.to_string_lossy().into_owned(), kind });
+        };
+        // SAFETY: stream came from fdopendir and is closed exactly once
+        unsafe { libc::closedir(stream) };
+        result?;
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    /// The path the descriptor for `path` was opened at; a symlink anywhere,
+    /// including the final component, is refused rather than resolved
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let file = match path.components().next_back() {
+            Some(Component::Normal(_)) => self.open(path, PATH_ONLY, 0)?,
+            _ => File::from(open_dir(path).map_err(|err| refuse_symlinks(path, err))?),
+        };
+        if file.metadata()?.file_type().is_symlink() {
+            return Err(refuse_symlinks(path, io::Error::from_raw_os_error(libc::ELOOP)));
+        }
+        #[cfg(target_os = "linux")]
+        {
+            std::fs::read_link(format!("/proc/self/fd/{}", file.as_raw_fd()))
+        }
+        // Without /proc to ask, resolve the path that was just checked again
+        #[cfg(not(target_os = "linux"))]
+        {
+            LocalFs.canonicalize(path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_symlinks_are_never_followed() {
+        let temp_dir = TempDir::new().unwrap();
+        let base = temp_dir.path();
+        std::fs::create_dir(base.join("real")).unwrap();
+        std::os::unix::fs::symlink(base.join("real"), base.join("link")).unwrap();
+        std::os::unix::fs::symlink(base.join("real/target"), base.join("dangling")).unwrap();
+
+        // Ordinary paths work as usual
+        SecureFs.create_dir_all(&base.join("real/a/b")).unwrap();
+        SecureFs.create_file(&base.join("real/a/b/file")).unwrap();
+        SecureFs.set_permissions(&base.join("real/a/b/file"), 0o600).unwrap();
+        assert_eq!(SecureFs.metadata(&base.join("real/a/b/file")).unwrap().mode, 0o600);
+
+        // Intermediate symlinks are refused
+        let err = SecureFs.create_file(&base.join("link/file")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(SecureFs.create_dir_all(&base.join("link/x/y")).is_err());
+        assert!(!base.join("real/file").exists() && !base.join("real/x").exists());
+
+        // A final symlink is reported as one and never written through
+        assert_eq!(SecureFs.metadata(&base.join("dangling")).unwrap().kind, FileKind::Symlink);
+        assert!(SecureFs.create_file(&base.join("dangling")).is_err());
+        assert!(SecureFs.set_permissions(&base.join("link"), 0o700).is_err());
+        // Listing and resolving go through the same descriptors
+        let entries = SecureFs.read_dir(&base.join("real/a")).unwrap();
+        assert_eq!((entries[0].name.as_str(), entries[0].kind), ("b", FileKind::Directory));
+        assert_eq!(SecureFs.read_dir(base).unwrap().iter().map(|entry| entry.kind).collect::<Vec<_>>(), [FileKind::Symlink, FileKind::Symlink, FileKind::Directory]);
+        assert!(SecureFs.read_dir(&base.join("link")).is_err());
+        assert_eq!(SecureFs.canonicalize(&base.join("real/a/../a/b/file")).unwrap(), base.canonicalize().unwrap().join("real/a/b/file"));
+        assert!(SecureFs.canonicalize(&base.join("link")).is_err());
+        assert!(SecureFs.canonicalize(&base.join("link/a")).is_err());
+        let uid = std::os::unix::fs::MetadataExt::uid(&std::fs::metadata(base).unwrap());
+        SecureFs.set_owner(&base.join("real/a/b/file"), Some(uid), None).unwrap();
+        SecureFs.set_owner(&base.join("dangling"), Some(uid), None).unwrap();
+        assert_eq!(SecureFs.set_owner(&base.join("link/file"), Some(uid), None).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert!(!base.join("real/target").exists());
+    }
+
+    #[test]
+    fn test_temp_dirs_are_fresh_and_private() {
+        let first = make_temp_dir("bank-test-").unwrap();
+        let second = make_temp_dir("bank-test-").unwrap();
+        assert_ne!(first, second);
+        assert!(first.file_name().unwrap().to_string_lossy().starts_with("bank-test-"));
+        assert_eq!(std::fs::metadata(&first).unwrap().permissions().mode() & 0o777, 0o700);
+        std::fs::remove_dir(first).unwrap();
+        std::fs::remove_dir(second).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+//! Numbered series of paths: `--count 3 shard-{n}.csv` gives `shard-1.csv`,
+//! `shard-2.csv` and `shard-3.csv`
+//!
+//! `--seq-format` picks how the numbers are written. Decimal, hex and custom
+//! alphabets are positional (the alphabet's first character is zero) and are
+//! padded with that zero to the width of the last number, so the names sort;
+//! letters count like spreadsheet columns (a … z, aa, ab …) and start at `a`
+//! for one.
+
+use std::str::FromStr;
+
+/// Replaced by the sequence number in path arguments
+pub const PLACEHOLDER: &str = "{n}";
+
+const DECIMAL: &str = "0123456789";
+const HEX: &str = "0123456789abcdef";
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SeqFormat {
+    /// 01, 02, … 12
+    #[default]
+    Decimal,
+    /// 0a, 0b, … 10
+    Hex,
+    /// a, b, … z, aa
+    Lower,
+    /// A, B, … Z, AA
+    Upper,
+    /// Positional digits from a custom alphabet, the first one being zero
+    Alphabet(Vec<char>),
+}
+
+impl FromStr for SeqFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "decimal" | "dec" => Ok(SeqFormat::Decimal),
+            "hex" => Ok(SeqFormat::Hex),
+            "lower" => Ok(SeqFormat::Lower),
+            "upper" => Ok(SeqFormat::Upper),
+            _ => {
+                let Some(chars) = text.strip_prefix("alphabet:") else {
+                    return Err(format!("unknown sequence format '{}' (use decimal, hex, lower, upper or alphabet:CHARS)", text));
+                };
+                let digits: Vec<char> = chars.chars().collect();
+                if digits.len() < 2 {
+                    return Err("a sequence alphabet needs at least two characters".to_string());
+                }
+                if let Some(c) = digits.iter().enumerate().find_map(|(i, c)| digits[..i].contains(c).then_some(c)) {
+                    return Err(format!("'{}' appears twice in the sequence alphabet", c));
+                }
+                if digits.contains(&'/') {
+                    return Err("a sequence alphabet cannot contain '/'".to_string());
+                }
+                Ok(SeqFormat::Alphabet(digits))
+            }
+        }
+    }
+}
+
+impl SeqFormat {
+    /// Whether the sequence has no zero, like spreadsheet columns
+    pub fn starts_at_one(&self) -> bool {
+        matches!(self, SeqFormat::Lower | SeqFormat::Upper)
+    }
+
+    fn digits(&self) -> Vec<char> {
+        match self {
+            SeqFormat::Decimal => DECIMAL.chars().collect(),
+            SeqFormat::Hex => HEX.chars().collect(),
+            SeqFormat::Lower => LOWER.chars().collect(),
+            SeqFormat::Upper => UPPER.chars().collect(),
+            SeqFormat::Alphabet(digits) => digits.clone(),
+        }
+    }
+
+    /// `n` written in this format, without padding
+    pub fn format(&self, mut n: u64) -> String {
+        let digits = self.digits();
+        let base = digits.len() as u64;
+        let mut out = Vec::new();
+        if self.starts_at_one() {
+            // Bijective numbering: a = 1, z = 26, aa = 27
+            while n > 0 {
+                n -= 1;
+                out.push(digits[(n % base) as usize]);
+                n /= base;
+            }
+        } else {
+            loop {
+                out.push(digits[(n % base) as usize]);
+                n /= base;
+                if n == 0 {
+                    break;
+                }
+            }
+        }
+        out.iter().rev().collect()
+    }
+
+    /// `count` numbers from `start`, padded to a common width where the format has a zero
+    pub fn series(&self, start: u64, count: u64) -> Result<Vec<String>, String> {
+        if self.starts_at_one() && start == 0 {
+            return Err("letter sequences start at 1 (a); use --seq-start 1 or higher".to_string());
+        }
+        let last = count
+            .checked_sub(1)
+            .and_then(|steps| start.checked_add(steps))
+            .ok_or_else(|| "the sequence is too long".to_string())?;
+        let width = self.format(last).chars().count();
+        let zero = self.digits()[0];
+        Ok((start..=last)
+            .map(|n| {
+                let text = self.format(n);
+                if self.starts_at_one() {
+                    return text;
+                }
+                let padding = width - text.chars().count();
+                std::iter::repeat_n(zero, padding).collect::<String>() + &text
+            })
+            .collect())
+    }
+}
+
+/// Repeat every path containing `{n}` once per sequence number; other paths are kept once
+pub fn expand(paths: &[String], format: &SeqFormat, start: u64, count: u64) -> Result<Vec<String>, String> {
+    if !paths.iter().any(|path| path.contains(PLACEHOLDER)) {
+        return Err(format!("--count needs a path with a {} placeholder for the sequence number", PLACEHOLDER));
+    }
+    let numbers = format.series(start, count)?;
+    Ok(paths
+        .iter()
+        .flat_map(|path| {
+            if path.contains(PLACEHOLDER) {
+                numbers.iter().map(|n| path.replace(PLACEHOLDER, n)).collect()
+            } else {
+                vec![path.clone()]
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_series_formats() {
+        assert_eq!(SeqFormat::Decimal.series(1, 12).unwrap()[..2], ["01", "02"]);
+        assert_eq!(SeqFormat::Decimal.series(8, 3).unwrap(), ["08", "09", "10"]);
+        assert_eq!(SeqFormat::Hex.series(9, 8).unwrap()[..3], ["09", "0a", "0b"]);
+        assert_eq!(SeqFormat::Lower.series(25, 4).unwrap(), ["y", "z", "aa", "ab"]);
+        assert_eq!(SeqFormat::Upper.format(703), "AAA");
+        assert!(SeqFormat::Lower.series(0, 2).is_err());
+
+        let binary: SeqFormat = "alphabet:ox".parse().unwrap();
+        assert_eq!(binary.series(0, 5).unwrap(), ["ooo", "oox", "oxo", "oxx", "xoo"]);
+        assert!("alphabet:aa".parse::<SeqFormat>().is_err());
+        assert!("alphabet:a".parse::<SeqFormat>().is_err());
+        assert!("roman".parse::<SeqFormat>().is_err());
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        let paths = vec!["data/".to_string(), "data/{n}/part-{n}.csv".to_string()];
+        assert_eq!(
+            expand(&paths, &SeqFormat::Lower, 1, 2).unwrap(),
+            ["data/", "data/a/part-a.csv", "data/b/part-b.csv"]
+        );
+        assert!(expand(&["plain.txt".to_string()], &SeqFormat::Decimal, 1, 2).is_err());
+    }
+}
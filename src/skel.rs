@@ -0,0 +1,230 @@
+//! `bank skel`: populate a home directory from a skeleton like `useradd -m`
+//!
+//! The skeleton (default `/etc/skel`) is read as root, then everything is
+//! written while acting as the target user (see `owner::become_user`), so
+//! the new entries belong to them from the start and a symlink they plant in
+//! their own home cannot redirect root's writes. Modes are the skeleton's,
+//! minus the user's login umask; a missing home directory gets `HOME_MODE`
+//! from login.defs (else 0777 minus `UMASK`). Existing entries are kept.
+
+use crate::journal;
+use crate::owner;
+use crate::vfs::{FileKind, Filesystem, RecordingFs};
+use anyhow::{bail, Context, Result};
+use clap::Args as ClapArgs;
+use colored::*;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct SkelArgs {
+    /// Home directory to populate; created if missing (its parent must exist)
+    #[arg(value_name = "HOME")]
+    pub home: PathBuf,
+
+    /// User who gets the files, by name or uid; their primary group is used
+    #[arg(long = "owner", value_name = "USER")]
+    pub owner: String,
+
+    /// Skeleton directory to copy
+    #[arg(long = "from", value_name = "DIR", default_value = "/etc/skel")]
+    pub from: PathBuf,
+
+    /// Only list what would be created
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Do not record this run in the undo journal
+    #[arg(long = "no-journal")]
+    pub no_journal: bool,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+/// One skeleton entry, read before anything is written
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SkelEntry {
+    path: PathBuf,
+    /// `None` for directories
+    contents: Option<Vec<u8>>,
+    mode: u32,
+    modified: SystemTime,
+}
+
+/// Every directory and file below `dir`, parents first; symlinks and other
+/// special files are reported and left out
+fn read_skeleton(fs: &dyn Filesystem, root: &Path, relative: &Path, out: &mut Vec<SkelEntry>) -> Result<()> {
+    let dir = root.join(relative);
+    let mut entries = fs.read_dir(&dir).with_context(|| format!("Failed to read directory {}", dir.display()))?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    for entry in entries {
+        let path = relative.join(&entry.name);
+        let source = root.join(&path);
+        if entry.kind == FileKind::Symlink {
+            println!("{} Not copying symlink {}", "Warning:".yellow(), source.display());
+            continue;
+        }
+        let metadata = fs.symlink_metadata(&source).with_context(|| format!("Failed to read metadata for {}", source.display()))?;
+        let contents = match entry.kind {
+            FileKind::Directory => None,
+            _ => Some(fs.read_file(&source).with_context(|| format!("Failed to read {}", source.display()))?),
+        };
+        let is_dir = contents.is_none();
+        out.push(SkelEntry { path: path.clone(), contents, mode: metadata.mode & 0o7777, modified: metadata.modified });
+        if is_dir {
+            read_skeleton(fs, root, &path, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// `HOME_MODE` from login.defs, else what useradd derives from the umask
+fn home_mode(login_defs: &str, umask: u32) -> u32 {
+    owner::login_defs_value(login_defs, "HOME_MODE")
+        .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+        .unwrap_or(0o777 & !umask)
+        & 0o7777
+}
+
+/// Write the entries below `home`; how many were created and how many kept
+fn populate(fs: &dyn Filesystem, home: &Path, entries: &[SkelEntry], umask: u32, verbose: bool) -> Result<(usize, usize)> {
+    let (mut created, mut kept) = (0, 0);
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let target = home.join(&entry.path);
+        if fs.exists(&target) || fs.is_symlink(&target) {
+            kept += 1;
+            if verbose {
+                println!("{} {} (exists)", "·".dimmed(), target.display());
+            }
+            continue;
+        }
+        match &entry.contents {
+            None => {
+                fs.create_dir(&target).with_context(|| format!("Failed to create directory {}", target.display()))?;
+                // Modes last, so a read-only skeleton directory can still be filled
+                dirs.push((target.clone(), entry));
+            }
+            Some(contents) => {
+                fs.create_file_with_times(&target, entry.modified, entry.modified)
+                    .with_context(|| format!("Failed to create {}", target.display()))?;
+                fs.write_file(&target, contents).with_context(|| format!("Failed to write {}", target.display()))?;
+                fs.set_permissions(&target, entry.mode & !umask)
+                    .with_context(|| format!("Failed to set permissions for {}", target.display()))?;
+                fs.set_times(&target, entry.modified, entry.modified)
+                    .with_context(|| format!("Failed to set times for {}", target.display()))?;
+            }
+        }
+        created += 1;
+        if verbose {
+            println!("Created: {}", target.display().to_string().green());
+        }
+    }
+    for (dir, entry) in dirs.iter().rev() {
+        fs.set_permissions(dir, entry.mode & !umask).with_context(|| format!("Failed to set permissions for {}", dir.display()))?;
+        fs.set_times(dir, entry.modified, entry.modified).with_context(|| format!("Failed to set times for {}", dir.display()))?;
+    }
+    Ok((created, kept))
+}
+
+pub fn run(fs: &dyn Filesystem, args: &SkelArgs) -> Result<()> {
+    if !fs.is_dir(&args.from) {
+        bail!("Skeleton is not a directory: {}", args.from.display());
+    }
+    let (uid, gid, name) = owner::passwd_entry(&args.owner)?;
+    let login_defs = owner::read_login_defs();
+    let umask = owner::login_umask(&login_defs, uid, gid, &name);
+    let mut entries = Vec::new();
+    read_skeleton(fs, &args.from, Path::new(""), &mut entries)?;
+
+    if args.dry_run {
+        if !fs.exists(&args.home) {
+            println!("+ {} (dir, mode {:04o})", args.home.display(), home_mode(&login_defs, umask));
+        }
+        for entry in entries.iter().filter(|entry| !fs.exists(&args.home.join(&entry.path))) {
+            let kind = if entry.contents.is_some() { "file" } else { "dir" };
+            println!("+ {} ({}, mode {:04o})", args.home.join(&entry.path).display(), kind, entry.mode & !umask);
+        }
+        return Ok(());
+    }
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        bail!("bank skel needs to run as root to create files for {}", name);
+    }
+
+    let recording = RecordingFs::new(fs);
+    let result = (|| {
+        match recording.symlink_metadata(&args.home) {
+            Ok(metadata) if metadata.kind != FileKind::Directory => bail!("{} exists and is not a directory", args.home.display()),
+            Ok(metadata) if metadata.uid != uid => {
+                let current = owner::describe(metadata.uid, metadata.gid);
+                println!("{} {} belongs to {}, not {}", "Warning:".yellow(), args.home.display(), current, name);
+            }
+            Ok(_) => {}
+            Err(_) => {
+                recording
+                    .create_dir(&args.home)
+                    .with_context(|| format!("Failed to create directory {}", args.home.display()))?;
+                recording
+                    .set_permissions(&args.home, home_mode(&login_defs, umask))
+                    .with_context(|| format!("Failed to set permissions for {}", args.home.display()))?;
+                owner::set_owner(&recording, &args.home, &format!("{}:{}", uid, gid), true)?;
+            }
+        }
+        let _as_user = owner::become_user(&uid.to_string())?;
+        populate(&recording, &args.home, &entries, umask, args.verbose)
+    })();
+    // As root again, so the journal is written where root keeps it
+    if !args.no_journal {
+        if let Err(err) = journal::record(&recording, None) {
+            println!("{} {:#}", "Warning:".yellow(), err);
+        }
+    }
+    let (created, kept) = result?;
+    println!(
+        "{} Populated {} for {}: {} created, {} kept",
+        "✓".bright_green(),
+        args.home.display().to_string().green(),
+        name.cyan(),
+        created,
+        kept
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[test]
+    fn test_populate_masks_modes_and_keeps_existing_files() {
+        let fs = MemoryFs::new();
+        fs.create_dir_all(Path::new("/etc/skel/.config/app")).unwrap();
+        fs.write_file(Path::new("/etc/skel/.bashrc"), b"# bashrc\n").unwrap();
+        fs.set_permissions(Path::new("/etc/skel/.bashrc"), 0o644).unwrap();
+        fs.write_file(Path::new("/etc/skel/.profile"), b"# profile\n").unwrap();
+        fs.set_permissions(Path::new("/etc/skel/.config"), 0o755).unwrap();
+        let mut entries = Vec::new();
+        read_skeleton(&fs, Path::new("/etc/skel"), Path::new(""), &mut entries).unwrap();
+        let paths: Vec<&Path> = entries.iter().map(|entry| entry.path.as_path()).collect();
+        assert_eq!(paths, [Path::new(".bashrc"), Path::new(".config"), Path::new(".config/app"), Path::new(".profile")]);
+
+        fs.create_dir_all(Path::new("/home/alice")).unwrap();
+        fs.write_file(Path::new("/home/alice/.profile"), b"mine\n").unwrap();
+        let (created, kept) = populate(&fs, Path::new("/home/alice"), &entries, 0o077, false).unwrap();
+        assert_eq!((created, kept), (3, 1));
+        assert_eq!(fs.read_file(Path::new("/home/alice/.bashrc")).unwrap(), b"# bashrc\n");
+        assert_eq!(fs.metadata(Path::new("/home/alice/.bashrc")).unwrap().mode, 0o600);
+        assert_eq!(fs.metadata(Path::new("/home/alice/.config")).unwrap().mode, 0o700);
+        assert_eq!(fs.read_file(Path::new("/home/alice/.profile")).unwrap(), b"mine\n");
+    }
+
+    #[test]
+    fn test_home_mode() {
+        assert_eq!(home_mode("UMASK 027\nHOME_MODE 0750\n", 0o027), 0o750);
+        assert_eq!(home_mode("# HOME_MODE 0700\n", 0o022), 0o755);
+    }
+}
@@ -0,0 +1,228 @@
+//! `bank snapshot`: describe an existing tree as a manifest, so `bank apply`
+//! can lay it out again elsewhere
+//!
+//! Every directory and file below DIR is recorded with its mode, owner and
+//! mtime, relative to DIR, so applying the manifest lays the tree out in the
+//! current directory. Symlinks are left out with a warning, since manifests
+//! do not record their targets, and names that are not valid UTF-8 are
+//! refused rather than recorded mangled.
+//! Paths matching an `--exclude` glob (relative to DIR) are left out with
+//! everything below them, and so are those matching a `.bankignore` file:
+//! one glob per line, relative to the directory the file is in and applying
+//! to everything below it, with `#` comments, a trailing `/` to match only
+//! directories and a leading `/` to match only from that directory.
+//!
+//! With `--embed-content`, the contents of text files up to `--max-size` are
+//! recorded too, so applying the manifest recreates config stubs and not
+//! just empty files.
+
+use crate::glob;
+use crate::i18n::tr;
+use crate::manifest::{Entry, Manifest};
+use crate::recurse;
+use crate::vfs::{FileKind, Filesystem};
+use anyhow::{bail, Context, Result};
+use clap::Args as ClapArgs;
+use colored::*;
+use std::path::{Path, PathBuf};
+
+/// Per-directory file of globs to leave out of snapshots
+pub const IGNORE_FILE: &str = ".bankignore";
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct SnapshotArgs {
+    /// Directory to describe
+    #[arg(value_name = "DIR")]
+    pub dir: PathBuf,
+
+    /// Write the manifest to FILE (YAML, or JSON for .json files) instead of printing it
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Leave out paths matching GLOB, relative to DIR, with everything below them (repeatable)
+    #[arg(long = "exclude", value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Record the contents of text files, so applying the manifest recreates them
+    #[arg(long = "embed-content")]
+    pub embed_content: bool,
+
+    /// Largest file whose contents --embed-content records
+    #[arg(long = "max-size", value_name = "SIZE", default_value = "4k", value_parser = recurse::parse_size, requires = "embed_content")]
+    pub max_size: u64,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+/// The globs of one `.bankignore`, with the directory (relative to DIR) it is in
+struct Ignore {
+    base: String,
+    patterns: Vec<String>,
+}
+
+impl Ignore {
+    fn parse(base: &str, text: &str) -> Self {
+        let patterns = text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(str::to_string).collect();
+        Ignore { base: base.to_string(), patterns }
+    }
+
+    /// Whether `relative` (relative to DIR) matches one of the globs
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        let below = match self.base.as_str() {
+            "" => relative,
+            base => match relative.strip_prefix(base).and_then(|rest| rest.strip_prefix('/')) {
+                Some(below) => below,
+                None => return false,
+            },
+        };
+        self.patterns.iter().any(|pattern| {
+            let (pattern, dirs_only) = match pattern.strip_suffix('/') {
+                Some(pattern) => (pattern, true),
+                None => (pattern.as_str(), false),
+            };
+            if dirs_only && !is_dir {
+                return false;
+            }
+            // A leading slash anchors the glob, which the matcher does for globs with a slash
+            match pattern.starts_with('/') {
+                true => glob::glob_match(pattern, &format!("/{}", below)),
+                false => glob::glob_match(pattern, below),
+            }
+        })
+    }
+}
+
+/// Describe the tree below `dir`, parents before children, with the
+/// symlinks that were left out
+pub fn snapshot(fs: &dyn Filesystem, dir: &Path, args: &SnapshotArgs) -> Result<(Manifest, Vec<PathBuf>)> {
+    let metadata = fs.metadata(dir).with_context(|| format!("Failed to read metadata for {}", dir.display()))?;
+    if !metadata.is_dir() {
+        bail!("Not a directory: {}", dir.display());
+    }
+    let mut manifest = Manifest::default();
+    let mut symlinks = Vec::new();
+    let mut ignores = Vec::new();
+    walk(fs, dir, "", args, &mut ignores, &mut manifest.entries, &mut symlinks)?;
+    Ok((manifest, symlinks))
+}
+
+fn walk(
+    fs: &dyn Filesystem,
+    root: &Path,
+    relative: &str,
+    args: &SnapshotArgs,
+    ignores: &mut Vec<Ignore>,
+    entries: &mut Vec<Entry>,
+    symlinks: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let dir = root.join(relative);
+    let ignore_file = dir.join(IGNORE_FILE);
+    let added = match fs.read_file(&ignore_file) {
+        Ok(bytes) => {
+            ignores.push(Ignore::parse(relative, &String::from_utf8_lossy(&bytes)));
+            true
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => false,
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", ignore_file.display())),
+    };
+
+    let listing = fs.read_dir(&dir).with_context(|| format!("Failed to read directory {}", dir.display()))?;
+    for child in listing {
+        let child_relative = if relative.is_empty() { child.name.clone() } else { format!("{}/{}", relative, child.name) };
+        let is_dir = child.kind == FileKind::Directory;
+        if glob::any_match(&args.exclude, &child_relative) || ignores.iter().any(|ignore| ignore.matches(&child_relative, is_dir)) {
+            continue;
+        }
+        let path = root.join(&child_relative);
+        if child.kind == FileKind::Symlink {
+            symlinks.push(path);
+            continue;
+        }
+        let metadata = match fs.symlink_metadata(&path) {
+            // The listing replaced what was not UTF-8, so the name no longer exists
+            Err(_) if child.name.contains(char::REPLACEMENT_CHARACTER) => {
+                bail!("Cannot record {}: its name is not valid UTF-8", path.display())
+            }
+            result => result.with_context(|| format!("Failed to read metadata for {}", path.display()))?,
+        };
+        let mut entry = Entry::from_metadata(&child_relative, &metadata);
+        if args.embed_content && !is_dir && metadata.len <= args.max_size {
+            let bytes = fs.read_file(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            // Binary files are listed without their contents
+            entry.content = String::from_utf8(bytes).ok();
+        }
+        entries.push(entry);
+        if is_dir {
+            walk(fs, root, &child_relative, args, ignores, entries, symlinks)?;
+        }
+    }
+
+    if added {
+        ignores.pop();
+    }
+    Ok(())
+}
+
+pub fn run(fs: &dyn Filesystem, args: &SnapshotArgs) -> Result<()> {
+    let (manifest, symlinks) = snapshot(fs, &args.dir, args)?;
+    // On stderr, since the manifest may be going to stdout
+    for symlink in &symlinks {
+        eprintln!("{} left out the symlink {}, since manifests cannot recreate it", tr("warning", &[]).yellow(), symlink.display());
+    }
+    match &args.output {
+        Some(file) => {
+            manifest.write(file)?;
+            if args.verbose {
+                println!(
+                    "{} Recorded {} paths from {} in {}",
+                    "✓".bright_green(),
+                    manifest.entries.len().to_string().cyan(),
+                    args.dir.display(),
+                    file.display().to_string().green()
+                );
+            }
+        }
+        None => print!("{}", manifest.to_yaml()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[test]
+    fn test_snapshot_excludes_and_embeds() {
+        let memory = MemoryFs::new();
+        for dir in ["app/config", "app/build/out", "app/logs", "app/src/logs"] {
+            memory.create_dir_all(Path::new(dir)).unwrap();
+        }
+        memory.write_file(Path::new("app/.bankignore"), b"# generated\n/logs/\n*.tmp\n").unwrap();
+        memory.write_file(Path::new("app/config/app.toml"), b"port = 8080\n").unwrap();
+        memory.write_file(Path::new("app/config/big.toml"), &[b'#'; 5000]).unwrap();
+        memory.write_file(Path::new("app/config/key.bin"), &[0xff, 0x00]).unwrap();
+        memory.write_file(Path::new("app/config/scratch.tmp"), b"").unwrap();
+        memory.write_file(Path::new("app/build/out/a.o"), b"").unwrap();
+
+        let args = SnapshotArgs {
+            dir: PathBuf::from("app"),
+            output: None,
+            exclude: vec!["build".to_string()],
+            embed_content: true,
+            max_size: 4096,
+            verbose: false,
+        };
+        let (manifest, symlinks) = snapshot(&memory, Path::new("app"), &args).unwrap();
+        let paths: Vec<&str> = manifest.entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, [".bankignore", "config", "config/app.toml", "config/big.toml", "config/key.bin", "src", "src/logs"]);
+        assert!(symlinks.is_empty());
+        let content = |path: &str| manifest.entries.iter().find(|entry| entry.path == path).unwrap().content.clone();
+        assert_eq!(content("config/app.toml").as_deref(), Some("port = 8080\n"));
+        assert_eq!(content("config/big.toml"), None);
+        assert_eq!(content("config/key.bin"), None);
+        assert!(manifest.to_yaml().contains("    content: \"port = 8080\\n\"\n"));
+    }
+}
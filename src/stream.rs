@@ -0,0 +1,220 @@
+//! `--stream`: create the paths of a list while it is being read
+//!
+//! Piped paths, `--from-file` lists and batch files are normally read in full,
+//! then sorted and checked as a whole before anything is created. With
+//! `--stream` each entry goes through the per-path steps (braces, expansion,
+//! `--relative-to`, `--normalize-path`, `--no-symlinked-parents`, `--root`,
+//! `--sanitize`) and is created as soon as it is read, in input order. Options
+//! that need the whole list first are refused, no remembered places are
+//! suggested, and JSON batch files, which are one document, cannot be
+//! streamed. The undo journal, the places history, `--emit-manifest` and
+//! `--changed-exit-code` keep an entry per change, so a stream needs
+//! `--no-journal`, refuses `--history` and the other two, and ignores
+//! `history = true` in config.toml; what remains per path is the target and
+//! attributes `--on-conflict` compares repeats with.
+
+use crate::batch::{self, BatchRow};
+use crate::conflict;
+use crate::error::BankError;
+use crate::i18n::tr;
+use crate::normalize::{self, NormalizeMode};
+use crate::report::outln;
+use crate::vfs::Filesystem;
+use crate::{expand, Args};
+use anyhow::{bail, Context, Result};
+use colored::*;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// A path as it was read, and the batch row it came from
+pub struct Entry {
+    pub path: String,
+    pub row: Option<BatchRow>,
+    /// A name from a NUL-separated list, which is not expanded
+    pub literal: bool,
+}
+
+pub type Entries = Box<dyn Iterator<Item = Result<Entry>>>;
+
+/// Take the positional paths (a `-` standing for stdin), the list file and
+/// the batch file out of `args`, to be read one entry at a time in that order
+pub fn open(args: &mut Args) -> Result<Entries> {
+    let paths = std::mem::take(&mut args.paths);
+    let from_stdin = paths.iter().any(|path_str| path_str == "-");
+    let list_file = args.from_file.take().map(|file| (file, false)).or_else(|| args.from_file0.take().map(|file| (file, true)));
+    let batch_file = args.batch_file.take();
+    if !from_stdin && list_file.is_none() && batch_file.is_none() {
+        bail!("--stream reads its paths from -, --from-file, --from-file0 or --batch-file");
+    }
+    if from_stdin && list_file.as_ref().is_some_and(|(file, _)| file == Path::new("-")) {
+        bail!("Cannot read both a - path argument and the --from-file list from stdin");
+    }
+    if let (true, Some(file)) = (from_stdin, &batch_file) {
+        bail!("Cannot read paths from stdin together with --batch-file {}", file.display());
+    }
+
+    let positional = paths.into_iter().flat_map(|path_str| -> Entries {
+        if path_str == "-" {
+            Box::new(PathList::new(io::stdin().lock(), "stdin", false, false).map(plain))
+        } else {
+            Box::new(std::iter::once(Ok(Entry { path: path_str, row: None, literal: false })))
+        }
+    });
+    // Files are opened now, so a missing one fails before anything is created
+    let listed: Entries = match list_file {
+        Some((file, nul)) if file == Path::new("-") => Box::new(PathList::new(io::stdin().lock(), "stdin", nul, true).map(move |path_str| listed(path_str, nul))),
+        Some((file, nul)) => {
+            let reader = BufReader::new(File::open(&file).map_err(|err| BankError::io("read", &file, err))?);
+            Box::new(PathList::new(reader, &file.display().to_string(), nul, true).map(move |path_str| listed(path_str, nul)))
+        }
+        None => Box::new(std::iter::empty()),
+    };
+    let rows: Entries = match &batch_file {
+        Some(file) => Box::new(batch::stream(file)?.map(|row| row.map(|row| Entry { path: row.path.clone(), row: Some(row), literal: false }))),
+        None => Box::new(std::iter::empty()),
+    };
+    Ok(Box::new(positional.chain(listed).chain(rows)))
+}
+
+fn plain(path_str: Result<String>) -> Result<Entry> {
+    listed(path_str, false)
+}
+
+fn listed(path_str: Result<String>, literal: bool) -> Result<Entry> {
+    path_str.map(|path| Entry { path, row: None, literal })
+}
+
+/// The entries of a path list as they are read: lines, skipping blank ones
+/// (and `#` comments in list files), or NUL-separated names
+struct PathList<R> {
+    reader: R,
+    name: String,
+    nul: bool,
+    comments: bool,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> PathList<R> {
+    fn new(reader: R, name: &str, nul: bool, comments: bool) -> Self {
+        Self { reader, name: name.to_string(), nul, comments, buf: Vec::new() }
+    }
+}
+
+impl<R: BufRead> Iterator for PathList<R> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let separator = if self.nul { b'\0' } else { b'\n' };
+        loop {
+            self.buf.clear();
+            match self.reader.read_until(separator, &mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(err) => return Some(Err(err).with_context(|| format!("Failed to read paths from {}", self.name))),
+            }
+            if self.buf.last() == Some(&separator) {
+                self.buf.pop();
+            }
+            let Ok(text) = std::str::from_utf8(&self.buf) else {
+                return Some(Err(anyhow::anyhow!("{} is not valid UTF-8", self.name)));
+            };
+            if self.nul {
+                // Names may start with # or hold newlines here, so only empty ones are dropped
+                if !text.is_empty() {
+                    return Some(Ok(text.to_string()));
+                }
+                continue;
+            }
+            let line = text.trim_end_matches('\r');
+            let comment = self.comments && line.trim_start().starts_with('#');
+            if !line.trim().is_empty() && !comment {
+                return Some(Ok(line.to_string()));
+            }
+        }
+    }
+}
+
+/// Prepare and process each entry as it is read, stopping at the first
+/// failure like any other run; with `-v`, how many paths were processed
+pub fn process(
+    fs: &dyn Filesystem,
+    entries: Entries,
+    args: &Args,
+    process: &mut dyn FnMut(&str, Option<&BatchRow>) -> Result<()>,
+) -> Result<()> {
+    let mut bookmarks = None;
+    let mut streamed = conflict::Streamed::default();
+    let mut count: u64 = 0;
+    let result = entries.into_iter().try_for_each(|entry| {
+        let Entry { path: path_str, row, literal } = entry?;
+        for path_str in prepare(fs, &path_str, row.is_some(), literal, args, &mut bookmarks)? {
+            let (admitted, warnings) = streamed.admit(&path_str, row.as_ref(), args.on_conflict)?;
+            for warning in warnings {
+                outln!("{} {}", tr("warning", &[]).yellow(), warning);
+            }
+            if !admitted {
+                continue;
+            }
+            process(&path_str, row.as_ref())?;
+            count += 1;
+        }
+        Ok(())
+    });
+    if args.verbose {
+        outln!("{}", tr("streamed-paths", &[("count", &count.to_string().cyan().to_string())]));
+    }
+    result
+}
+
+/// The steps main applies to the whole list, for one entry; like there,
+/// braces are not expanded in batch rows and nothing in literal names
+fn prepare(
+    fs: &dyn Filesystem,
+    path_str: &str,
+    is_row: bool,
+    literal: bool,
+    args: &Args,
+    bookmarks: &mut Option<crate::bookmark::Bookmarks>,
+) -> Result<Vec<String>> {
+    let expand = !args.no_expand && !literal;
+    let mut paths = if !expand || is_row { vec![path_str.to_string()] } else { expand::expand_braces(path_str)? };
+    for path_str in paths.iter_mut() {
+        if expand {
+            *path_str = crate::expand_argument(path_str, bookmarks)?;
+        }
+        if let Some(base) = &args.relative_to {
+            *path_str = crate::join_base(base, path_str);
+        }
+        if args.normalize_path != NormalizeMode::None {
+            *path_str = normalize::normalize_path(fs, path_str, args.normalize_path)?;
+        }
+        if args.no_symlinked_parents {
+            normalize::reject_symlinked_parents(fs, path_str)?;
+        }
+        if let Some(root) = &args.root {
+            *path_str = crate::confine_path(root, path_str, true)?;
+        }
+        if args.sanitize || args.sanitize_ascii {
+            *path_str = crate::sanitize_path(fs, path_str, args.sanitize_ascii);
+        }
+        crate::check_target(args, path_str)?;
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_list_reads_entries_one_at_a_time() {
+        let lines = PathList::new("a.txt\r\n\n# note\n  \nb/\n".as_bytes(), "list", false, true);
+        assert_eq!(lines.collect::<Result<Vec<_>>>().unwrap(), ["a.txt", "b/"]);
+        let piped = PathList::new("# not a comment\nc".as_bytes(), "stdin", false, false);
+        assert_eq!(piped.collect::<Result<Vec<_>>>().unwrap(), ["# not a comment", "c"]);
+        let nul = PathList::new("#x\0two\nlines\0\0".as_bytes(), "list", true, true);
+        assert_eq!(nul.collect::<Result<Vec<_>>>().unwrap(), ["#x", "two\nlines"]);
+        assert!(PathList::new(&b"ok\n\xff\n"[..], "list", false, true).nth(1).unwrap().is_err());
+    }
+}
@@ -0,0 +1,353 @@
+//! `--sudo`: re-run single operations that fail with permission denied through
+//! sudo (or polkit's pkexec), so the rest of the run stays unprivileged and
+//! whatever already succeeded is left as it is
+//!
+//! Each escalated operation maps to one standard tool run as root (`tee` for
+//! file contents, `mkdir`, `chmod`, `chown -h`, `touch -t` in UTC, `rm`,
+//! `rmdir`, `mv`). `tee`, `chmod` and `touch` follow a final symlink, so an
+//! operation on a path that is a symlink is never escalated; `touch -t` also
+//! drops the fraction of a second.
+//! Without `--sudo` the user is asked once, on a terminal, when the first
+//! operation is refused; the answer holds for the rest of the run.
+
+use crate::report::{self, outln};
+use crate::vfs::{DirEntry, Filesystem, Metadata};
+use chrono::{DateTime, Utc};
+use colored::*;
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use std::cell::Cell;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+/// Tried in order; the first one on PATH is used
+const ESCALATORS: &[&str] = &["sudo", "pkexec"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Consent {
+    Granted,
+    Refused,
+    Ask,
+}
+
+pub struct SudoFs<'a> {
+    inner: &'a dyn Filesystem,
+    /// Command prefix the operations are re-run under, e.g. `["sudo", "--"]`
+    escalator: Option<Vec<OsString>>,
+    consent: Cell<Consent>,
+    verbose: bool,
+}
+
+/// `program` in one of the PATH directories
+pub fn find_in_path(program: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+fn escalator() -> Option<Vec<OsString>> {
+    ESCALATORS.iter().find(|name| find_in_path(name).is_some()).map(|name| match *name {
+        "sudo" => vec!["sudo".into(), "--".into()],
+        name => vec![name.into()],
+    })
+}
+
+/// Tools that act on what a final symlink points to (`env` runs `touch`)
+const FOLLOWS_SYMLINKS: &[&str] = &["tee", "chmod", "env"];
+
+/// `CCYYMMDDhhmm.SS` in UTC for POSIX `touch -t`
+fn touch_stamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).format("%Y%m%d%H%M.%S").to_string()
+}
+
+impl<'a> SudoFs<'a> {
+    /// Escalate without asking when `always`, otherwise ask on a terminal.
+    /// Nothing is escalated when already running as root.
+    pub fn new(inner: &'a dyn Filesystem, always: bool, verbose: bool) -> Self {
+        // SAFETY: geteuid has no preconditions
+        let escalator = if unsafe { libc::geteuid() } == 0 { None } else { escalator() };
+        let consent = if always {
+            Consent::Granted
+        } else if io::stdin().is_terminal() && io::stderr().is_terminal() {
+            Consent::Ask
+        } else {
+            Consent::Refused
+        };
+        Self { inner, escalator, consent: Cell::new(consent), verbose }
+    }
+
+    /// Re-run refused operations under `prefix` instead of sudo, without asking
+    #[cfg(test)]
+    fn with_escalator(inner: &'a dyn Filesystem, prefix: &[&str]) -> Self {
+        let escalator = Some(prefix.iter().map(OsString::from).collect());
+        Self { inner, escalator, consent: Cell::new(Consent::Granted), verbose: false }
+    }
+
+    fn consents(&self, action: &str, path: &Path, escalator: &OsStr) -> bool {
+        if self.consent.get() == Consent::Ask {
+            let question = format!(
+                "Permission denied to {} {}; retry this and later refused operations with {}?",
+                action,
+                path.display(),
+                escalator.to_string_lossy()
+            );
+            report::flush();
+            let granted = Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(question)
+                .default(false)
+                .interact()
+                .unwrap_or(false);
+            self.consent.set(if granted { Consent::Granted } else { Consent::Refused });
+        }
+        self.consent.get() == Consent::Granted
+    }
+
+    /// Run `attempt`; if it is refused, run each of `commands` (a tool and its
+    /// arguments, with `input` on stdin) under the escalator instead
+    fn escalate(
+        &self,
+        action: &str,
+        path: &Path,
+        attempt: impl FnOnce() -> io::Result<()>,
+        commands: &[(&str, Vec<OsString>)],
+        input: Option<&[u8]>,
+    ) -> io::Result<()> {
+        let err = match attempt() {
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => err,
+            result => return result,
+        };
+        let Some(escalator) = &self.escalator else {
+            return Err(err);
+        };
+        if !self.consents(action, path, &escalator[0]) {
+            return Err(err);
+        }
+        // As root, the tool would write through a link the user could not
+        if commands.iter().any(|(tool, _)| FOLLOWS_SYMLINKS.contains(tool)) && self.inner.is_symlink(path) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} (not escalated: {} is a symlink)", err, path.display())));
+        }
+        for (tool, tool_args) in commands {
+            if self.verbose {
+                outln!("{} {} {} {}", "↑".yellow(), escalator[0].to_string_lossy(), tool, path.display());
+            }
+            let mut child = Command::new(&escalator[0])
+                .args(&escalator[1..])
+                .arg(tool)
+                .args(tool_args)
+                .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+                .stdout(Stdio::null())
+                .spawn()
+                .map_err(|spawn_err| io::Error::new(spawn_err.kind(), format!("cannot run {}: {}", escalator[0].to_string_lossy(), spawn_err)))?;
+            if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+                stdin.write_all(input)?;
+            }
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} (and `{} {}` failed too: {})", err, escalator[0].to_string_lossy(), tool, status),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tool arguments ending in `-- PATH`
+fn args_for(options: &[&str], path: &Path) -> Vec<OsString> {
+    options.iter().map(OsString::from).chain([OsString::from("--"), path.as_os_str().to_os_string()]).collect()
+}
+
+/// `touch -t` run through `env`, so the stamp is read as UTC whatever the
+/// escalator does to the environment
+fn touch_commands(path: &Path, accessed: SystemTime, modified: SystemTime) -> Vec<(&'static str, Vec<OsString>)> {
+    let touch = |options: &[&str], time: SystemTime| {
+        let stamp = touch_stamp(time);
+        let mut command = vec!["TZ=UTC0", "touch", "-c"];
+        command.extend(options);
+        command.extend(["-t", &stamp]);
+        ("env", args_for(&command, path))
+    };
+    if accessed == modified {
+        vec![touch(&[], modified)]
+    } else {
+        vec![touch(&["-a"], accessed), touch(&["-m"], modified)]
+    }
+}
+
+impl Filesystem for SudoFs<'_> {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.symlink_metadata(path)
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<()> {
+        self.escalate("create file", path, || self.inner.create_file(path), &[("tee", args_for(&[], path))], Some(&[]))
+    }
+
+    fn create_file_with_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        let mut commands = vec![("tee", args_for(&[], path))];
+        commands.extend(touch_commands(path, accessed, modified));
+        self.escalate(
+            "create file",
+            path,
+            || self.inner.create_file_with_times(path, accessed, modified),
+            &commands,
+            Some(&[]),
+        )
+    }
+
+    /// As root, through a shell with noclobber (so nothing existing, not even
+    /// a symlink, is opened) and a umask that keeps the new file private
+    /// until chmod gives it `mode`
+    fn create_file_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        let octal = format!("{:o}", mode);
+        let create = vec!["-c".into(), "set -C; umask 077; : > \"$1\"".into(), "sh".into(), path.as_os_str().to_os_string()];
+        self.escalate(
+            "create file",
+            path,
+            || self.inner.create_file_with_mode(path, mode),
+            &[("sh", create), ("chmod", args_for(&[&octal], path))],
+            None,
+        )
+    }
+
+    fn write_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.escalate("write", path, || self.inner.write_file(path, contents), &[("tee", args_for(&[], path))], Some(contents))
+    }
+
+    fn append_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.escalate("append to", path, || self.inner.append_file(path, contents), &[("tee", args_for(&["-a"], path))], Some(contents))
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.inner.read_file(path)
+    }
+
+    fn read_file_prefix(&self, path: &Path, limit: usize) -> io::Result<Vec<u8>> {
+        self.inner.read_file_prefix(path, limit)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.escalate("create directory", path, || self.inner.create_dir(path), &[("mkdir", args_for(&[], path))], None)
+    }
+
+    fn create_dir_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        let octal = format!("{:o}", mode);
+        self.escalate("create directory", path, || self.inner.create_dir_with_mode(path, mode), &[("mkdir", args_for(&["-m", &octal], path))], None)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.escalate("create directory", path, || self.inner.create_dir_all(path), &[("mkdir", args_for(&["-p"], path))], None)
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        let octal = format!("{:o}", mode);
+        self.escalate("set permissions for", path, || self.inner.set_permissions(path, mode), &[("chmod", args_for(&[&octal], path))], None)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        let owner = match (uid, gid) {
+            (Some(uid), Some(gid)) => format!("{}:{}", uid, gid),
+            (Some(uid), None) => uid.to_string(),
+            (None, Some(gid)) => format!(":{}", gid),
+            (None, None) => return self.inner.set_owner(path, uid, gid),
+        };
+        self.escalate("change owner of", path, || self.inner.set_owner(path, uid, gid), &[("chown", args_for(&["-h", &owner], path))], None)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.escalate("remove", path, || self.inner.remove_file(path), &[("rm", args_for(&[], path))], None)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.escalate("remove", path, || self.inner.remove_dir(path), &[("rmdir", args_for(&[], path))], None)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut mv_args = args_for(&["-f", "-T"], from);
+        mv_args.push(to.as_os_str().to_os_string());
+        self.escalate("rename", from, || self.inner.rename(from, to), &[("mv", mv_args)], None)
+    }
+
+    /// Never escalated: the trash belongs to the invoking user
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        self.inner.trash(path)
+    }
+
+    fn set_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        self.escalate(
+            "set times for",
+            path,
+            || self.inner.set_times(path, accessed, modified),
+            &touch_commands(path, accessed, modified),
+            None,
+        )
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        self.inner.read_dir(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+    use std::time::Duration;
+
+    #[test]
+    fn test_refused_operations_are_rerun_through_the_escalator() {
+        let temp = tempfile::tempdir().unwrap();
+        let denied = temp.path().join("denied.txt");
+        let memory = MemoryFs::new();
+        memory.fail_on(&denied, io::ErrorKind::PermissionDenied);
+
+        // `env` stands in for sudo: it runs the tool unprivileged, against the real disk
+        let fs = SudoFs::with_escalator(&memory, &["env"]);
+        fs.create_file(Path::new("fine.txt")).unwrap();
+        fs.write_file(&denied, b"one\n").unwrap();
+        fs.append_file(&denied, b"two\n").unwrap();
+        fs.set_permissions(&denied, 0o600).unwrap();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs.set_times(&denied, time, time).unwrap();
+
+        assert!(memory.exists(Path::new("fine.txt")));
+        assert!(!memory.exists(&denied));
+        assert_eq!(std::fs::read(&denied).unwrap(), b"one\ntwo\n");
+        let metadata = std::fs::metadata(&denied).unwrap();
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o7777, 0o600);
+        assert_eq!(metadata.modified().unwrap(), time);
+
+        // Other failures are not escalated, and neither is anything once refused
+        memory.fail_on("full.txt", io::ErrorKind::StorageFull);
+        assert_eq!(fs.create_file(Path::new("full.txt")).unwrap_err().kind(), io::ErrorKind::StorageFull);
+
+        // Nothing is escalated through a symlink, whose target root could reach
+        let target = temp.path().join("target");
+        let link = temp.path().join("link");
+        std::fs::write(&target, b"kept").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let local = SudoFs::with_escalator(&crate::vfs::LocalFs, &["env"]);
+        let refused = || Err(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(local.escalate("write", &link, refused, &[("tee", args_for(&[], &link))], Some(b"replaced")).is_err());
+        assert_eq!(std::fs::read(&target).unwrap(), b"kept");
+        fs.consent.set(Consent::Refused);
+        assert_eq!(fs.create_dir(&denied).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_touch_stamp() {
+        assert_eq!(touch_stamp(SystemTime::UNIX_EPOCH + Duration::new(5, 20)), "197001010000.05");
+        assert_eq!(touch_stamp(SystemTime::UNIX_EPOCH - Duration::from_secs(3)), "196912312359.57");
+    }
+}
@@ -0,0 +1,181 @@
+//! `bank sync-times`: copy timestamps from one tree onto another
+//!
+//! Every file below DEST whose counterpart at the same relative path below
+//! SRC is also a file gets SRC's access and modification times, so a tree
+//! copied without `rsync -t` (or restored by a tool that lost the times) can
+//! be fixed without copying its contents again; with `--dirs`, directories
+//! get theirs too. Paths found in only one of the trees and symlinks are
+//! left alone.
+
+use crate::glob::any_match;
+use crate::manifest::format_time;
+use crate::vfs::{FileKind, Filesystem};
+use anyhow::{bail, Context, Result};
+use clap::Args as ClapArgs;
+use colored::*;
+use std::path::PathBuf;
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct SyncTimesArgs {
+    /// Tree whose timestamps are copied
+    #[arg(long = "from", value_name = "SRC")]
+    pub from: PathBuf,
+
+    /// Tree whose timestamps are set
+    #[arg(long = "to", value_name = "DEST")]
+    pub to: PathBuf,
+
+    /// Only sync entries whose relative path matches GLOB (repeatable)
+    #[arg(long = "include", value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Skip entries matching GLOB, together with everything below them (repeatable)
+    #[arg(long = "exclude", value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Sync the timestamps of directories too
+    #[arg(long = "dirs")]
+    pub dirs: bool,
+
+    /// Only sync files whose size matches their counterpart's
+    #[arg(long = "same-size")]
+    pub same_size: bool,
+
+    /// Print what would change, without touching anything
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+/// What a run did, or would do with `--dry-run`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub updated: usize,
+    pub unchanged: usize,
+    /// Entries of DEST without a matching counterpart in SRC
+    pub unmatched: usize,
+}
+
+/// Give every entry below `args.to` the times of its counterpart below `args.from`
+pub fn sync(fs: &dyn Filesystem, args: &SyncTimesArgs) -> Result<Summary> {
+    for dir in [&args.from, &args.to] {
+        if !fs.is_dir(dir) {
+            bail!("Not a directory: {}", dir.display());
+        }
+    }
+    let mut summary = Summary::default();
+    walk(fs, args, "", &mut summary)?;
+    Ok(summary)
+}
+
+fn walk(fs: &dyn Filesystem, args: &SyncTimesArgs, relative: &str, summary: &mut Summary) -> Result<()> {
+    let dir = args.to.join(relative);
+    for entry in fs.read_dir(&dir).with_context(|| format!("Failed to read directory {}", dir.display()))? {
+        if entry.kind == FileKind::Symlink {
+            continue;
+        }
+        let child = if relative.is_empty() { entry.name } else { format!("{}/{}", relative, entry.name) };
+        if any_match(&args.exclude, &child) {
+            continue;
+        }
+        let is_dir = entry.kind == FileKind::Directory;
+        if is_dir {
+            walk(fs, args, &child, summary)?;
+        }
+        if (is_dir && !args.dirs) || !(args.include.is_empty() || any_match(&args.include, &child)) {
+            continue;
+        }
+        sync_entry(fs, args, &child, summary)?;
+    }
+    Ok(())
+}
+
+fn sync_entry(fs: &dyn Filesystem, args: &SyncTimesArgs, relative: &str, summary: &mut Summary) -> Result<()> {
+    let (source, target) = (args.from.join(relative), args.to.join(relative));
+    let current = fs.symlink_metadata(&target).with_context(|| format!("Failed to read metadata for {}", target.display()))?;
+    let wanted = match fs.symlink_metadata(&source) {
+        Ok(wanted) if wanted.kind == current.kind && (!args.same_size || current.is_dir() || wanted.len == current.len) => wanted,
+        _ => {
+            summary.unmatched += 1;
+            if args.verbose {
+                println!("{} No counterpart in {}: {}", "-".dimmed(), args.from.display(), target.display());
+            }
+            return Ok(());
+        }
+    };
+    if (wanted.accessed, wanted.modified) == (current.accessed, current.modified) {
+        summary.unchanged += 1;
+        return Ok(());
+    }
+
+    summary.updated += 1;
+    if args.dry_run || args.verbose {
+        println!("{} {}: {} → {}", "~".yellow(), target.display(), format_time(current.modified).red(), format_time(wanted.modified).green());
+    }
+    if !args.dry_run {
+        fs.set_times(&target, wanted.accessed, wanted.modified)
+            .with_context(|| format!("Failed to set timestamps for {}", target.display()))?;
+    }
+    Ok(())
+}
+
+pub fn run(fs: &dyn Filesystem, args: &SyncTimesArgs) -> Result<()> {
+    let summary = sync(fs, args)?;
+    if args.dry_run || args.verbose {
+        println!(
+            "{} to update, {} unchanged, {} without a counterpart",
+            summary.updated.to_string().yellow(),
+            summary.unchanged.to_string().bright_black(),
+            summary.unmatched.to_string().cyan()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_sync_times_by_relative_path() {
+        let memory = MemoryFs::new();
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        for tree in ["src", "dest"] {
+            memory.create_dir_all(Path::new(&format!("{}/docs", tree))).unwrap();
+            memory.write_file(Path::new(&format!("{}/docs/guide.md", tree)), b"# Guide\n").unwrap();
+            memory.write_file(Path::new(&format!("{}/notes.txt", tree)), tree.as_bytes()).unwrap();
+        }
+        memory.write_file(Path::new("dest/extra.txt"), b"").unwrap();
+        for path in ["src/docs", "src/docs/guide.md", "src/notes.txt"] {
+            memory.set_times(Path::new(path), old, old).unwrap();
+        }
+
+        let mut args = SyncTimesArgs {
+            from: PathBuf::from("src"),
+            to: PathBuf::from("dest"),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            dirs: false,
+            same_size: true,
+            dry_run: true,
+            verbose: false,
+        };
+        assert_eq!(sync(&memory, &args).unwrap(), Summary { updated: 1, unchanged: 0, unmatched: 2 });
+        assert_ne!(memory.metadata(Path::new("dest/docs/guide.md")).unwrap().modified, old);
+
+        args.dry_run = false;
+        args.dirs = true;
+        args.same_size = false;
+        assert_eq!(sync(&memory, &args).unwrap(), Summary { updated: 3, unchanged: 0, unmatched: 1 });
+        for path in ["dest/docs", "dest/docs/guide.md", "dest/notes.txt"] {
+            assert_eq!(memory.metadata(Path::new(path)).unwrap().modified, old);
+        }
+        assert_eq!(sync(&memory, &args).unwrap(), Summary { updated: 0, unchanged: 3, unmatched: 1 });
+    }
+}
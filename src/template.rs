@@ -0,0 +1,327 @@
+//! Templates for generated files: `{{ name }}` variables, `{% include "name" %}`
+//! partials, and `{% extends "name" %}` inheritance with `{% block name %}`
+//! overrides; `{% raw %}...{% endraw %}` keeps braces literally
+//!
+//! Partials and base templates are looked up in the loader's directories in
+//! order, the first match winning, trying the name as given and then with
+//! `.tmpl` appended; a match that a symlink resolves to outside its directory
+//! is refused. A `{% %}` tag on a line of its own takes the whole line
+//! with it, so tags on separate lines leave no blank lines behind.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+
+/// Variable values by name
+pub type Vars = BTreeMap<String, String>;
+
+/// Block overrides collected from the templates that extend the current one
+type Blocks = HashMap<String, Vec<Node>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Text(String),
+    Var(String),
+    Include(String),
+    Block(String, Vec<Node>),
+}
+
+#[derive(Debug, Default)]
+struct Parsed {
+    extends: Option<String>,
+    nodes: Vec<Node>,
+}
+
+/// Finds and renders templates; `roots` are searched in order
+pub struct Loader {
+    roots: Vec<PathBuf>,
+}
+
+impl Loader {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        Self { roots }
+    }
+
+    /// Render `source`; `origin` names it in error messages and cycle reports
+    pub fn render(&self, origin: &Path, source: &str, vars: &Vars) -> Result<String> {
+        let mut stack = vec![(origin.display().to_string(), origin.to_path_buf())];
+        self.render_source(source, vars, &Blocks::new(), &mut stack)
+            .with_context(|| format!("Failed to render {}", origin.display()))
+    }
+
+    fn find(&self, name: &str) -> Result<PathBuf> {
+        let relative = Path::new(name);
+        if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+            bail!("template names must be relative paths without '..': {}", name);
+        }
+        let (root, path) = self
+            .roots
+            .iter()
+            .flat_map(|root| [(root, root.join(name)), (root, root.join(format!("{}.tmpl", name)))])
+            .find(|(_, candidate)| candidate.is_file())
+            .with_context(|| format!("template '{}' not found (searched {})", name, self.search_path()))?;
+        // A symlink in the template must not pull in files from elsewhere
+        let resolved = path.canonicalize().with_context(|| format!("Failed to resolve {}", path.display()))?;
+        if !root.canonicalize().is_ok_and(|root| resolved.starts_with(root)) {
+            bail!("template '{}' resolves to {}, outside {}", name, resolved.display(), root.display());
+        }
+        Ok(path)
+    }
+
+    fn search_path(&self) -> String {
+        let roots: Vec<String> = self.roots.iter().map(|root| root.display().to_string()).collect();
+        if roots.is_empty() {
+            "nowhere".to_string()
+        } else {
+            roots.join(", ")
+        }
+    }
+
+    fn render_named(&self, name: &str, vars: &Vars, blocks: &Blocks, stack: &mut Vec<(String, PathBuf)>) -> Result<String> {
+        let path = self.find(name)?;
+        if let Some(start) = stack.iter().position(|(_, seen)| *seen == path) {
+            let chain: Vec<&str> = stack[start..].iter().map(|(name, _)| name.as_str()).collect();
+            bail!("template cycle: {} -> {}", chain.join(" -> "), name);
+        }
+        let source = std::fs::read_to_string(&path).with_context(|| format!("Failed to read template {}", path.display()))?;
+        stack.push((name.to_string(), path));
+        let rendered = self.render_source(&source, vars, blocks, stack);
+        stack.pop();
+        rendered.with_context(|| format!("in template '{}'", name))
+    }
+
+    fn render_source(&self, source: &str, vars: &Vars, blocks: &Blocks, stack: &mut Vec<(String, PathBuf)>) -> Result<String> {
+        let parsed = parse(source)?;
+        if let Some(base) = parsed.extends {
+            // Blocks from further down the inheritance chain win over ours
+            let mut merged = Blocks::new();
+            collect_blocks(&parsed.nodes, &mut merged);
+            merged.extend(blocks.iter().map(|(name, nodes)| (name.clone(), nodes.clone())));
+            return self.render_named(&base, vars, &merged, stack);
+        }
+        let mut out = String::new();
+        self.render_nodes(&parsed.nodes, vars, blocks, stack, &mut out)?;
+        Ok(out)
+    }
+
+    fn render_nodes(&self, nodes: &[Node], vars: &Vars, blocks: &Blocks, stack: &mut Vec<(String, PathBuf)>, out: &mut String) -> Result<()> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Var(name) => out.push_str(vars.get(name).with_context(|| format!("undefined variable '{}'", name))?),
+                // Partials render on their own: blocks do not reach into them
+                Node::Include(name) => out.push_str(&self.render_named(name, vars, &Blocks::new(), stack)?),
+                Node::Block(name, default) => {
+                    let body = blocks.get(name).unwrap_or(default);
+                    self.render_nodes(body, vars, blocks, stack, out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn collect_blocks(nodes: &[Node], blocks: &mut Blocks) {
+    for node in nodes {
+        if let Node::Block(name, body) = node {
+            blocks.insert(name.clone(), body.clone());
+            collect_blocks(body, blocks);
+        }
+    }
+}
+
+fn parse(source: &str) -> Result<Parsed> {
+    let mut parsed = Parsed::default();
+    // Open blocks: name, line and the nodes collected so far
+    let mut open: Vec<(String, usize, Vec<Node>)> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut rest = source;
+    let line_of = |rest: &str| source[..source.len() - rest.len()].matches('\n').count() + 1;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find("{{").into_iter().chain(rest.find("{%")).min() else {
+            nodes.push(Node::Text(rest.to_string()));
+            break;
+        };
+        if start > 0 {
+            nodes.push(Node::Text(rest[..start].to_string()));
+        }
+        let line = line_of(&rest[start..]);
+        let tag_start = source.len() - rest.len() + start;
+        let is_var = rest[start..].starts_with("{{");
+        let close = if is_var { "}}" } else { "%}" };
+        let body_start = start + 2;
+        let end = rest[body_start..]
+            .find(close)
+            .with_context(|| format!("line {}: unclosed '{}'", line, &rest[start..start + 2]))?;
+        let body = rest[body_start..body_start + end].trim();
+        rest = &rest[body_start + end + 2..];
+
+        if is_var {
+            if body.is_empty() || !body.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-')) {
+                bail!("line {}: invalid variable name '{}'", line, body);
+            }
+            nodes.push(Node::Var(body.to_string()));
+            continue;
+        }
+
+        if let Some(after) = standalone(source, tag_start, rest) {
+            rest = after;
+            trim_indentation(&mut nodes);
+        }
+        let (tag, argument) = body.split_once(char::is_whitespace).map_or((body, ""), |(tag, argument)| (tag, argument.trim()));
+        match tag {
+            "include" => nodes.push(Node::Include(quoted(argument).with_context(|| format!("line {}: include", line))?)),
+            "extends" => {
+                let before_is_blank = open.is_empty()
+                    && parsed.extends.is_none()
+                    && nodes.iter().all(|node| matches!(node, Node::Text(text) if text.trim().is_empty()));
+                if !before_is_blank {
+                    bail!("line {}: extends must come first in a template", line);
+                }
+                parsed.extends = Some(quoted(argument).with_context(|| format!("line {}: extends", line))?);
+                nodes.clear();
+            }
+            "block" => {
+                if argument.is_empty() || argument.contains(char::is_whitespace) {
+                    bail!("line {}: block needs a single name", line);
+                }
+                open.push((argument.to_string(), line, std::mem::take(&mut nodes)));
+            }
+            "endblock" => {
+                let Some((name, _, outer)) = open.pop() else {
+                    bail!("line {}: endblock without a block", line);
+                };
+                if !argument.is_empty() && argument != name {
+                    bail!("line {}: endblock {} closes block {}", line, argument, name);
+                }
+                let body = std::mem::replace(&mut nodes, outer);
+                nodes.push(Node::Block(name, body));
+            }
+            "raw" => {
+                const END: &str = "{% endraw %}";
+                let end = rest.find(END).with_context(|| format!("line {}: raw without endraw", line))?;
+                let mut text = rest[..end].to_string();
+                let end_start = source.len() - rest.len() + end;
+                rest = &rest[end + END.len()..];
+                if let Some(after) = standalone(source, end_start, rest) {
+                    rest = after;
+                    text.truncate(text.trim_end_matches([' ', '\t']).len());
+                }
+                nodes.push(Node::Text(text));
+            }
+            other => bail!("line {}: unknown tag '{}'", line, other),
+        }
+    }
+
+    if let Some((name, line, _)) = open.pop() {
+        bail!("line {}: block {} is never closed", line, name);
+    }
+    parsed.nodes = nodes;
+    Ok(parsed)
+}
+
+/// If the tag starting at `tag_start` is alone on its line, what follows its
+/// line break
+fn standalone<'a>(source: &str, tag_start: usize, after: &'a str) -> Option<&'a str> {
+    let line_start = source[..tag_start].rfind('\n').map_or(0, |index| index + 1);
+    if !source[line_start..tag_start].chars().all(|c| c == ' ' || c == '\t') {
+        return None;
+    }
+    match after {
+        "" => Some(after),
+        _ => after.strip_prefix("\r\n").or_else(|| after.strip_prefix('\n')),
+    }
+}
+
+/// Drop the indentation before a standalone tag
+fn trim_indentation(nodes: &mut Vec<Node>) {
+    if let Some(Node::Text(text)) = nodes.last_mut() {
+        text.truncate(text.trim_end_matches([' ', '\t']).len());
+        if text.is_empty() {
+            nodes.pop();
+        }
+    }
+}
+
+/// The name in `"name"` or `'name'`
+fn quoted(argument: &str) -> Result<String> {
+    let inner = argument
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .or_else(|| argument.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\'')))
+        .with_context(|| format!("expected a quoted template name, found '{}'", argument))?;
+    Ok(inner.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vars {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_variables_and_partials() {
+        let temp_dir = TempDir::new().unwrap();
+        let shared = temp_dir.path().join("shared");
+        let local = temp_dir.path().join("local");
+        std::fs::create_dir_all(&shared).unwrap();
+        std::fs::create_dir_all(&local).unwrap();
+        std::fs::write(shared.join("license-header.tmpl"), "// (c) {{ year }} {{author}}\n").unwrap();
+        std::fs::write(shared.join("shebang"), "#!/bin/sh\n").unwrap();
+        // Earlier roots win
+        std::fs::write(local.join("shebang"), "#!/usr/bin/env bash\n").unwrap();
+
+        let loader = Loader::new(vec![local, shared]);
+        let source = "{% include \"shebang\" %}\n{% include 'license-header' %}\necho {{ name }}\n{% raw %}${{ matrix.os }}{% endraw %}\n";
+        let rendered = loader
+            .render(Path::new("run.sh"), source, &vars(&[("year", "2026"), ("author", "Jane"), ("name", "demo")]))
+            .unwrap();
+        assert_eq!(rendered, "#!/usr/bin/env bash\n// (c) 2026 Jane\necho demo\n${{ matrix.os }}\n");
+
+        let err = loader.render(Path::new("x"), "{{ missing }}", &Vars::new()).unwrap_err();
+        assert!(format!("{:#}", err).contains("undefined variable 'missing'"));
+        assert!(loader.render(Path::new("x"), "{% include \"nope\" %}", &Vars::new()).is_err());
+        assert!(loader.render(Path::new("x"), "{% include \"../etc/passwd\" %}", &Vars::new()).is_err());
+        std::os::unix::fs::symlink(temp_dir.path().join("secret"), temp_dir.path().join("local/leak")).unwrap();
+        std::fs::write(temp_dir.path().join("secret"), "hunter2").unwrap();
+        assert!(loader.render(Path::new("x"), "{% include \"leak\" %}", &Vars::new()).is_err());
+        assert!(loader.render(Path::new("x"), "{% block a %}", &Vars::new()).is_err());
+        assert!(loader.render(Path::new("x"), "{{ a", &Vars::new()).is_err());
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("base"), "{% block header %}# generated{% endblock %}\n{% block body %}{% endblock %}\n-- end\n").unwrap();
+        std::fs::write(root.join("script"), "{% extends \"base\" %}\n{% block header %}#!/bin/sh{% endblock %}\n").unwrap();
+
+        let loader = Loader::new(vec![root.to_path_buf()]);
+        let child = "{% extends \"script\" %}\nignored\n{% block body %}echo {{ name }}{% endblock %}\n";
+        let rendered = loader.render(Path::new("child"), child, &vars(&[("name", "hi")])).unwrap();
+        assert_eq!(rendered, "#!/bin/sh\necho hi\n-- end\n");
+
+        assert!(loader.render(Path::new("x"), "text\n{% extends \"base\" %}", &Vars::new()).is_err());
+    }
+
+    #[test]
+    fn test_cycles_are_detected() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("a"), "{% include \"b\" %}").unwrap();
+        std::fs::write(root.join("b"), "{% extends \"c\" %}").unwrap();
+        std::fs::write(root.join("c"), "{% include \"a\" %}").unwrap();
+        std::fs::write(root.join("twice"), "{% include \"leaf\" %}{% include \"leaf\" %}").unwrap();
+        std::fs::write(root.join("leaf"), "x").unwrap();
+
+        let loader = Loader::new(vec![root.to_path_buf()]);
+        let err = loader.render(Path::new("main"), "{% include \"a\" %}", &Vars::new()).unwrap_err();
+        assert!(format!("{:#}", err).contains("template cycle: a -> b -> c -> a"));
+        // Using the same partial twice is not a cycle
+        assert_eq!(loader.render(Path::new("main"), "{% include \"twice\" %}", &Vars::new()).unwrap(), "xx");
+    }
+}
@@ -0,0 +1,421 @@
+//! Minimal TOML reader for bank's own configuration (`config.toml`),
+//! permission policies (`.bank-policy.toml`) and cargo-generate templates
+//!
+//! Covers tables, dotted keys, strings (basic, literal and multi-line),
+//! integers, floats, booleans, arrays and inline tables, read into the same
+//! `Value` as JSON. Dates and arrays of tables are not supported.
+
+use crate::json::{ParseError, Value};
+
+/// Parse a complete TOML document into an object
+pub fn parse(text: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser { chars: text.chars().collect(), pos: 0 };
+    let mut root = Value::Object(Vec::new());
+    // The keys of the table the following pairs go into
+    let mut table: Vec<String> = Vec::new();
+    loop {
+        parser.skip_blank_lines();
+        match parser.peek() {
+            None => return Ok(root),
+            Some('[') => {
+                parser.pos += 1;
+                if parser.peek() == Some('[') {
+                    return Err(parser.error("arrays of tables are not supported"));
+                }
+                parser.skip_spaces();
+                table = parser.key()?;
+                parser.skip_spaces();
+                parser.expect(']')?;
+                let start = parser.pos;
+                match descend(&mut root, &table) {
+                    Some(Value::Object(_)) => {}
+                    _ => {
+                        parser.pos = start;
+                        return Err(parser.error(&format!("'{}' is not a table", table.join("."))));
+                    }
+                }
+            }
+            Some(_) => {
+                let start = parser.pos;
+                let (key, value) = parser.pair()?;
+                let path: Vec<String> = table.iter().chain(&key).cloned().collect();
+                if !insert(&mut root, &path, value) {
+                    parser.pos = start;
+                    return Err(parser.error(&format!("'{}' is defined twice", path.join("."))));
+                }
+            }
+        }
+        parser.end_of_line()?;
+    }
+}
+
+/// The table at `path`, created if missing; `None` if something else is there
+fn descend<'a>(root: &'a mut Value, path: &[String]) -> Option<&'a mut Value> {
+    let mut current = root;
+    for name in path {
+        let Value::Object(members) = current else { return None };
+        let index = match members.iter().position(|(existing, _)| existing == name) {
+            Some(index) => index,
+            None => {
+                members.push((name.clone(), Value::Object(Vec::new())));
+                members.len() - 1
+            }
+        };
+        current = &mut members[index].1;
+    }
+    Some(current)
+}
+
+/// Set `path` to `value`; false if it is already set or its parent is not a table
+fn insert(root: &mut Value, path: &[String], value: Value) -> bool {
+    let Some((name, parents)) = path.split_last() else { return false };
+    match descend(root, parents) {
+        Some(Value::Object(members)) if !members.iter().any(|(existing, _)| existing == name) => {
+            members.push((name.clone(), value));
+            true
+        }
+        _ => false,
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> ParseError {
+        let before = &self.chars[..self.pos.min(self.chars.len())];
+        let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        ParseError { line, column, message: message.to_string() }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        self.chars[self.pos..].iter().copied().take(text.chars().count()).eq(text.chars())
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Whitespace, comments and line breaks, as between array items
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') => self.pos += 1,
+                Some('\r') if self.chars.get(self.pos + 1) == Some(&'\n') => self.pos += 2,
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), ParseError> {
+        self.skip_spaces();
+        self.skip_comment();
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some('\r') if self.chars.get(self.pos + 1) == Some(&'\n') => Ok(()),
+            _ => Err(self.error("expected the end of the line")),
+        }
+    }
+
+    /// A key, dotted or not: `name`, `"quoted name"`, `a.b`
+    fn key(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        self.pos += 1;
+                    }
+                    if self.pos == start {
+                        return Err(self.error("expected a key"));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            parts.push(part);
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(parts);
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn pair(&mut self) -> Result<(Vec<String>, Value), ParseError> {
+        let key = self.key()?;
+        self.skip_spaces();
+        self.expect('=')?;
+        self.skip_spaces();
+        Ok((key, self.value()?))
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some('"') if self.starts_with("\"\"\"") => self.multiline_string("\"\"\"", true).map(Value::String),
+            Some('\'') if self.starts_with("'''") => self.multiline_string("'''", false).map(Value::String),
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some(_) if self.starts_with("true") => {
+                self.pos += 4;
+                Ok(Value::Bool(true))
+            }
+            Some(_) if self.starts_with("false") => {
+                self.pos += 5;
+                Ok(Value::Bool(false))
+            }
+            Some('+' | '-' | '0'..='9' | 'i' | 'n') => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, ParseError> {
+        self.expect('{')?;
+        let mut table = Value::Object(Vec::new());
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(table);
+        }
+        loop {
+            self.skip_spaces();
+            let start = self.pos;
+            let (key, value) = self.pair()?;
+            if !insert(&mut table, &key, value) {
+                self.pos = start;
+                return Err(self.error(&format!("'{}' is defined twice", key.join("."))));
+            }
+            self.skip_spaces();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(table);
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, ParseError> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some('\\') => text.push(self.escape()?),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, ParseError> {
+        self.expect('\'')?;
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some('\'') => {
+                    let text = self.chars[start..self.pos].iter().collect();
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    /// `"""` (with escapes) or `'''` strings; a line break right after the
+    /// opening quotes is dropped
+    fn multiline_string(&mut self, quotes: &str, escapes: bool) -> Result<String, ParseError> {
+        self.pos += 3;
+        if self.starts_with("\r\n") {
+            self.pos += 2;
+        } else if self.peek() == Some('\n') {
+            self.pos += 1;
+        }
+        let mut text = String::new();
+        loop {
+            if self.starts_with(quotes) {
+                self.pos += 3;
+                return Ok(text);
+            }
+            match self.peek() {
+                Some('\\') if escapes => {
+                    // A backslash at the end of a line joins it to the next
+                    let rest = self.chars[self.pos + 1..].iter().take_while(|c| matches!(c, ' ' | '\t' | '\r')).count();
+                    if self.chars.get(self.pos + 1 + rest) == Some(&'\n') {
+                        self.pos += 1;
+                        while matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                            self.pos += 1;
+                        }
+                    } else {
+                        text.push(self.escape()?);
+                    }
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, ParseError> {
+        self.expect('\\')?;
+        let simple = match self.peek() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some(kind @ ('u' | 'U')) => {
+                let length = if kind == 'u' { 4 } else { 8 };
+                let digits: String = self.chars.iter().skip(self.pos + 1).take(length).collect();
+                let code = u32::from_str_radix(&digits, 16).ok().filter(|_| digits.len() == length);
+                let escaped = code.and_then(char::from_u32).ok_or_else(|| self.error("invalid unicode escape"))?;
+                self.pos += 1 + length;
+                return Ok(escaped);
+            }
+            _ => return Err(self.error("invalid escape sequence")),
+        };
+        self.pos += 1;
+        Ok(simple)
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_')) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().filter(|&&c| c != '_').collect();
+        let unsigned = text.trim_start_matches(['+', '-']);
+        let radix = [("0x", 16), ("0o", 8), ("0b", 2)].into_iter().find(|(prefix, _)| unsigned.starts_with(prefix));
+        let number = match (radix, unsigned) {
+            (Some((_, radix)), _) => i64::from_str_radix(&unsigned[2..], radix).ok().map(|number| number as f64),
+            (None, "inf") => Some(f64::INFINITY),
+            (None, "nan") => Some(f64::NAN),
+            (None, _) => unsigned.parse::<f64>().ok().filter(|_| unsigned.starts_with(|c: char| c.is_ascii_digit())),
+        };
+        match number {
+            Some(number) if text.starts_with('-') => Ok(Value::Number(-number)),
+            Some(number) => Ok(Value::Number(number)),
+            None => {
+                self.pos = start;
+                Err(self.error(&format!("invalid value '{}'", text)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tables_and_values() {
+        let document = parse(
+            r#"
+            # cargo-generate.toml
+            [template]
+            ignore = [
+                "target",   # build output
+                '*.bak',
+            ]
+
+            [placeholders.license]
+            type = "string"
+            choices = ["MIT", "Apache-2.0"]
+            default = "MIT"
+
+            [placeholders]
+            serde = { type = "bool", default = false }
+            "quoted key".port = 0x1F90
+            about = """
+            Line one \
+              continued"""
+            "#,
+        )
+        .unwrap();
+        let template = document.get("template").unwrap();
+        assert_eq!(template.get("ignore"), Some(&Value::Array(vec![Value::String("target".into()), Value::String("*.bak".into())])));
+        let placeholders = document.get("placeholders").unwrap();
+        let Value::Object(members) = placeholders else { panic!("placeholders is not a table") };
+        let names: Vec<&str> = members.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["license", "serde", "quoted key", "about"]);
+        assert_eq!(placeholders.get("serde").and_then(|serde| serde.get("default")), Some(&Value::Bool(false)));
+        assert_eq!(placeholders.get("quoted key").and_then(|key| key.get("port")), Some(&Value::Number(8080.0)));
+        assert_eq!(placeholders.get("about").and_then(Value::as_str), Some("            Line one continued"));
+
+        let err = parse("a = 1\na = 2\n").unwrap_err();
+        assert_eq!((err.line, err.message.as_str()), (2, "'a' is defined twice"));
+        assert!(parse("[[bin]]\n").is_err());
+        assert!(parse("a = 1 b = 2\n").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! `--trash`: move replaced files into the desktop trash instead of unlinking
+//! them, following the freedesktop.org trash specification
+//!
+//! A file on the filesystem of the home trash goes there. One on another
+//! filesystem goes to that filesystem's trash, `$topdir/.Trash/$uid` if the
+//! administrator set up a sticky `$topdir/.Trash`, else `$topdir/.Trash-$uid`,
+//! so trashing never copies it across devices.
+
+use chrono::Local;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// The home trash: `$XDG_DATA_HOME/Trash`, else `~/.local/share/Trash`
+pub fn home_trash() -> io::Result<PathBuf> {
+    if let Some(data) = std::env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(data).join("Trash"));
+    }
+    match std::env::var_os("HOME") {
+        Some(home) => Ok(PathBuf::from(home).join(".local/share/Trash")),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, "cannot locate the trash: neither XDG_DATA_HOME nor HOME is set")),
+    }
+}
+
+/// Move `path` into the trash of its filesystem, returning where it ended up
+pub fn trash(path: &Path) -> io::Result<PathBuf> {
+    let absolute = std::path::absolute(path)?;
+    let device = fs::symlink_metadata(&absolute)?.dev();
+    let home = home_trash()?;
+    // The home trash may not exist yet: its nearest existing ancestor decides
+    let home_device = home.ancestors().find_map(|dir| fs::metadata(dir).ok()).map(|metadata| metadata.dev());
+    if home_device == Some(device) {
+        return move_to(&absolute, &home);
+    }
+
+    let top = top_dir(&absolute, device);
+    let uid = unsafe { libc::geteuid() }.to_string();
+    let shared = top.join(".Trash");
+    let trash = match fs::symlink_metadata(&shared) {
+        Ok(metadata) if metadata.is_dir() && metadata.mode() & 0o1000 != 0 => shared.join(uid),
+        _ => top.join(format!(".Trash-{}", uid)),
+    };
+    // Entries of a volume's trash are recorded relative to its top directory
+    let relative = absolute.strip_prefix(&top).unwrap_or(&absolute);
+    move_into(&absolute, &trash, relative)
+}
+
+/// The mount point `path` is below: its highest ancestor still on `device`
+fn top_dir(path: &Path, device: u64) -> PathBuf {
+    let mut top = path.parent().unwrap_or(path);
+    while let Some(parent) = top.parent() {
+        if fs::metadata(parent).map_or(true, |metadata| metadata.dev() != device) {
+            break;
+        }
+        top = parent;
+    }
+    top.to_path_buf()
+}
+
+/// Move `path` into `trash`, returning where it ended up
+pub fn move_to(path: &Path, trash: &Path) -> io::Result<PathBuf> {
+    let absolute = std::path::absolute(path)?;
+    move_into(&absolute, trash, &absolute)
+}
+
+/// Move `absolute` into `trash`, recorded in its `.trashinfo` as `recorded`
+fn move_into(absolute: &Path, trash: &Path, recorded: &Path) -> io::Result<PathBuf> {
+    let name = absolute
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("cannot trash {}", absolute.display())))?;
+    let files = trash.join("files");
+    let info = trash.join("info");
+    // A trash may be shared with other users' files, so only its owner gets in
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(&files)?;
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(&info)?;
+
+    // The .trashinfo file is created exclusively first: it reserves the name
+    let mut attempt = 1;
+    let (trashed, info_path, mut info_file) = loop {
+        let mut candidate = name.to_os_string();
+        if attempt > 1 {
+            candidate.push(format!(".{}", attempt));
+        }
+        let trashed = files.join(&candidate);
+        candidate.push(".trashinfo");
+        let info_path = info.join(candidate);
+        if fs::symlink_metadata(&trashed).is_err() {
+            match fs::OpenOptions::new().write(true).create_new(true).open(&info_path) {
+                Ok(file) => break (trashed, info_path, file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err),
+            }
+        }
+        attempt += 1;
+    };
+    write!(
+        info_file,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(recorded.as_os_str().as_bytes()),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    )?;
+
+    if let Err(err) = fs::rename(absolute, &trashed) {
+        let _ = fs::remove_file(&info_path);
+        if err.kind() == io::ErrorKind::CrossesDevices {
+            return Err(io::Error::new(
+                err.kind(),
+                format!("{} is on a different filesystem than the trash at {}", absolute.display(), trash.display()),
+            ));
+        }
+        return Err(err);
+    }
+    Ok(trashed)
+}
+
+/// Move a file trashed at `trashed` back to `path`, which must not exist,
+/// and forget its `.trashinfo`
+pub fn restore(trashed: &Path, path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists", path.display())));
+    }
+    fs::rename(trashed, path)?;
+    if let (Some(files), Some(name)) = (trashed.parent(), trashed.file_name()) {
+        let mut info_name = OsString::from(name);
+        info_name.push(".trashinfo");
+        let info_path = files.with_file_name("info").join(info_name);
+        match fs::remove_file(&info_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Percent-encode a path for the `Path=` key, keeping `/` and unreserved characters
+fn encode_path(path: &[u8]) -> String {
+    let mut out = String::with_capacity(path.len());
+    for &byte in path {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_move_to_trash() {
+        let temp_dir = TempDir::new().unwrap();
+        let trash = temp_dir.path().join("Trash");
+        let path = temp_dir.path().join("old log.txt");
+
+        fs::write(&path, b"first").unwrap();
+        let trashed = move_to(&path, &trash).unwrap();
+        assert!(!path.exists());
+        assert_eq!(trashed, trash.join("files/old log.txt"));
+        let info = fs::read_to_string(trash.join("info/old log.txt.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath="));
+        assert!(info.contains("/old%20log.txt\nDeletionDate="));
+
+        // A second file with the same name gets a numbered slot
+        fs::write(&path, b"second").unwrap();
+        assert_eq!(move_to(&path, &trash).unwrap(), trash.join("files/old log.txt.2"));
+        assert_eq!(fs::read(trash.join("files/old log.txt")).unwrap(), b"first");
+
+        // And comes back from it, leaving the first one in the trash
+        restore(&trash.join("files/old log.txt.2"), &path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert!(!trash.join("info/old log.txt.2.trashinfo").exists());
+        assert!(trash.join("info/old log.txt.trashinfo").exists());
+        assert!(restore(&trash.join("files/old log.txt"), &path).is_err());
+    }
+
+    #[test]
+    fn test_trash_keeps_names_that_are_not_utf8() {
+        let temp_dir = TempDir::new().unwrap();
+        let trash = temp_dir.path().join("Trash");
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        let path = temp_dir.path().join(name);
+        fs::write(&path, b"").unwrap();
+
+        assert_eq!(move_to(&path, &trash).unwrap(), trash.join("files").join(name));
+        let info = fs::read_to_string(trash.join("info").join(OsStr::from_bytes(b"caf\xe9.txt.trashinfo"))).unwrap();
+        assert!(info.contains("/caf%E9.txt\n"));
+    }
+}
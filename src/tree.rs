@@ -0,0 +1,140 @@
+//! `--from-tree`: recreate a hierarchy drawn by tree(1)
+//!
+//! Both the Unicode drawing (`├── `, `└── `, `│   `) and `--charset=ascii`
+//! (`|-- `, `` `-- ``) are understood, at any indentation width: an entry's
+//! parent is the closest entry above it whose connector starts further left.
+//! Entries with children, or with the trailing `/` of `tree -F`, become
+//! directories and the others files. A root line other than `.` is the top
+//! directory; the closing `N directories, M files` line, symlink targets
+//! (`name -> target`) and Markdown code fences are ignored.
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+const CONNECTORS: [&str; 6] = ["├── ", "└── ", "|-- ", "`-- ", "+-- ", "\\-- "];
+
+/// Characters that may stand left of a connector
+const RULES: &str = "│| \t";
+
+struct Node {
+    column: usize,
+    path: String,
+    is_dir: bool,
+}
+
+/// The column of the line's connector and the text after it
+fn split_connector(line: &str) -> Option<(usize, &str)> {
+    let (start, connector) = line
+        .char_indices()
+        .find_map(|(start, _)| CONNECTORS.iter().find(|connector| line[start..].starts_with(*connector)).map(|c| (start, c)))?;
+    let prefix = &line[..start];
+    prefix.chars().all(|c| RULES.contains(c)).then(|| (prefix.chars().count(), &line[start + connector.len()..]))
+}
+
+/// `3 directories, 5 files`, as tree(1) ends its listing
+fn is_summary(line: &str) -> bool {
+    let words: Vec<&str> = line.split([' ', ',']).filter(|word| !word.is_empty()).collect();
+    let counted = |number: &str, noun: &str, singular: &str, plural: &str| {
+        number.parse::<u64>().is_ok() && (noun == singular || noun == plural)
+    };
+    match words[..] {
+        [dirs, noun] => counted(dirs, noun, "directory", "directories"),
+        [dirs, dir_noun, files, file_noun] => {
+            counted(dirs, dir_noun, "directory", "directories") && counted(files, file_noun, "file", "files")
+        }
+        _ => false,
+    }
+}
+
+/// The entry's name and whether it is marked as a directory
+fn entry_name(text: &str, number: usize) -> Result<(&str, bool)> {
+    let text = text.split_once(" -> ").map_or(text, |(name, _)| name).trim();
+    let (name, marked) = match text.strip_suffix('/') {
+        Some(name) => (name, true),
+        None => (text, false),
+    };
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        bail!("Line {}: '{}' is not a file name", number, text);
+    }
+    Ok((name, marked))
+}
+
+/// The paths drawn in `text`, parents first; directories end with `/`
+pub fn parse(text: &str) -> Result<Vec<String>> {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    let mut root: Option<String> = None;
+    for (index, raw) in text.lines().enumerate() {
+        let number = index + 1;
+        // tree pads with no-break spaces in UTF-8 locales
+        let line = raw.replace('\u{a0}', " ");
+        let line = line.trim_end();
+        if line.trim().is_empty() || line.trim_start().starts_with("```") || line.chars().all(|c| RULES.contains(c)) {
+            continue;
+        }
+        let Some((column, text)) = split_connector(line) else {
+            if root.is_none() && nodes.is_empty() {
+                let name = line.trim().trim_end_matches('/');
+                if name == "." || name.is_empty() {
+                    root = Some(String::new());
+                } else {
+                    nodes.push(Node { column: 0, path: name.to_string(), is_dir: true });
+                    root = Some(format!("{}/", name));
+                }
+                continue;
+            }
+            if is_summary(line) {
+                continue;
+            }
+            bail!("Line {} is not part of a tree drawing: {}", number, line.trim());
+        };
+        let (name, marked) = entry_name(text, number)?;
+        while open.last().is_some_and(|&top| nodes[top].column >= column) {
+            open.pop();
+        }
+        let parent = match open.last() {
+            Some(&top) => {
+                nodes[top].is_dir = true;
+                format!("{}/", nodes[top].path)
+            }
+            None => root.clone().unwrap_or_default(),
+        };
+        open.push(nodes.len());
+        nodes.push(Node { column, path: format!("{}{}", parent, name), is_dir: marked });
+    }
+    Ok(nodes.into_iter().map(|node| if node.is_dir { format!("{}/", node.path) } else { node.path }).collect())
+}
+
+pub fn load(file: &Path) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(file).with_context(|| format!("Failed to read tree listing {}", file.display()))?;
+    parse(&text).with_context(|| format!("Invalid tree listing {}", file.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tree_drawings() {
+        let unicode = "app\n\
+                       ├── src\n\
+                       │\u{a0}\u{a0} ├── main.rs\n\
+                       │\u{a0}\u{a0} └── util\n\
+                       │\u{a0}\u{a0}     └── mod.rs\n\
+                       ├── docs/\n\
+                       ├── current -> releases/1\n\
+                       └── README.md\n\
+                       \n\
+                       3 directories, 4 files\n";
+        assert_eq!(
+            parse(unicode).unwrap(),
+            ["app/", "app/src/", "app/src/main.rs", "app/src/util/", "app/src/util/mod.rs", "app/docs/", "app/current", "app/README.md"]
+        );
+
+        let ascii = "```\n.\n|-- a\n|  `-- b.txt\n`-- c\n```\n";
+        assert_eq!(parse(ascii).unwrap(), ["a/", "a/b.txt", "c"]);
+
+        assert!(parse(".\n`-- ..\n").is_err());
+        assert!(parse(".\n|-- a\nsome prose\n").is_err());
+    }
+}
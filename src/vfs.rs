@@ -0,0 +1,1257 @@
+//! Filesystem abstraction: every filesystem call bank makes goes through
+//! [`Filesystem`], implemented by the real filesystem and, for hermetic tests,
+//! an in-memory filesystem with failure injection
+
+use std::cell::{Cell, RefCell};
+#[cfg(test)]
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+#[cfg(test)]
+use std::path::Component;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+/// The subset of file metadata bank relies on
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub kind: FileKind,
+    /// Permission bits (`0o7777` mask)
+    pub mode: u32,
+    /// Size in bytes
+    pub len: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub accessed: SystemTime,
+    pub modified: SystemTime,
+}
+
+impl Metadata {
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileKind::Directory
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    /// Kind of the entry itself; symlinks are not followed
+    pub kind: FileKind,
+}
+
+pub trait Filesystem {
+    /// Metadata of the path, following symlinks
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Metadata of the path itself, without following a final symlink
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Create an empty file, truncating it if it already exists
+    fn create_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Create a new file with the given times. The path must not exist, not
+    /// even as a symlink, so nothing is created or touched through a link
+    /// planted after the caller checked
+    fn create_file_with_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        if self.is_symlink(path) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} is a symlink", path.display())));
+        }
+        self.create_file(path)?;
+        self.set_times(path, accessed, modified)
+    }
+
+    /// Create a new file, which must not exist (not even as a symlink), that
+    /// is never more open than `mode`: the bits are given to open(2), so the
+    /// umask can only take more away
+    fn create_file_with_mode(&self, path: &Path, mode: u32) -> io::Result<()>;
+
+    /// Create a file with `contents`, replacing what an existing one held
+    fn write_file(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// Add `contents` to the end of a file, creating it if missing
+    fn append_file(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// At most the first `limit` bytes of a file
+    #[cfg_attr(not(feature = "exif"), allow(dead_code))]
+    fn read_file_prefix(&self, path: &Path, limit: usize) -> io::Result<Vec<u8>> {
+        let mut contents = self.read_file(path)?;
+        contents.truncate(limit);
+        Ok(contents)
+    }
+
+    /// Create a single directory; the parent must exist
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Create a single directory that is never more open than `mode`, which
+    /// is given to mkdir(2) like `create_file_with_mode` gives it to open(2)
+    fn create_dir_with_mode(&self, path: &Path, mode: u32) -> io::Result<()>;
+
+    /// Create a directory and all missing ancestors
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()>;
+
+    /// Change the owner and group of the path itself, never what a final
+    /// symlink points to; `None` leaves that id as it is
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Remove an empty directory
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Move `from` to `to`, replacing a file already at `to`
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Move a file or symlink into the trash of its filesystem, returning
+    /// where it ended up
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        crate::trash::trash(path)
+    }
+
+    fn set_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()>;
+
+    /// Entries of a directory, sorted by name
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok_and(|m| m.is_dir())
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        self.symlink_metadata(path).is_ok_and(|m| m.kind == FileKind::Symlink)
+    }
+}
+
+/// The real filesystem
+pub struct LocalFs;
+
+impl LocalFs {
+    pub(crate) fn convert(metadata: fs::Metadata) -> io::Result<Metadata> {
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Directory
+        } else {
+            FileKind::File
+        };
+        Ok(Metadata {
+            kind,
+            mode: metadata.mode() & 0o7777,
+            len: metadata.len(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            accessed: metadata.accessed()?,
+            modified: metadata.modified()?,
+        })
+    }
+}
+
+impl Filesystem for LocalFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        Self::convert(fs::metadata(path)?)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        Self::convert(fs::symlink_metadata(path)?)
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<()> {
+        fs::File::create(path).map(|_| ())
+    }
+
+    /// `O_CREAT | O_EXCL | O_NOFOLLOW`, then futimens on the same descriptor,
+    /// so the times land on the file this call created
+    fn create_file_with_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+            .open(path)?;
+        filetime::set_file_handle_times(
+            &file,
+            Some(filetime::FileTime::from_system_time(accessed)),
+            Some(filetime::FileTime::from_system_time(modified)),
+        )
+    }
+
+    fn create_file_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+            .open(path)
+            .map(|_| ())
+    }
+
+    fn write_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn append_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::OpenOptions::new().append(true).create(true).open(path)?.write_all(contents)
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn read_file_prefix(&self, path: &Path, limit: usize) -> io::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        io::Read::read_to_end(&mut io::Read::take(fs::File::open(path)?, limit as u64), &mut contents)?;
+        Ok(contents)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir(path)
+    }
+
+    fn create_dir_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        fs::DirBuilder::new().mode(mode).create(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        std::os::unix::fs::lchown(path, uid, gid)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn set_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        filetime::set_file_times(
+            path,
+            filetime::FileTime::from_system_time(accessed),
+            filetime::FileTime::from_system_time(modified),
+        )
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let kind = if file_type.is_symlink() {
+                FileKind::Symlink
+            } else if file_type.is_dir() {
+                FileKind::Directory
+            } else {
+                FileKind::File
+            };
+            entries.push(DirEntry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                kind,
+            });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+}
+
+/// Wraps another filesystem and retries operations that fail with transient
+/// errors (EINTR, EAGAIN, ESTALE, ETIMEDOUT), as seen on NFS/CIFS mounts.
+/// The delay doubles after every attempt.
+pub struct RetryFs<'a> {
+    inner: &'a dyn Filesystem,
+    retries: u32,
+    delay: Duration,
+}
+
+impl<'a> RetryFs<'a> {
+    pub fn new(inner: &'a dyn Filesystem, retries: u32, delay: Duration) -> Self {
+        Self { inner, retries, delay }
+    }
+
+    fn retry<T>(&self, mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut delay = self.delay;
+        let mut attempt = 0;
+        loop {
+            match operation() {
+                Err(err) if attempt < self.retries && is_transient(&err) => {
+                    std::thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Like `retry`, but an `AlreadyExists` after a failed attempt means that
+    /// attempt actually went through
+    fn retry_create(&self, mut operation: impl FnMut() -> io::Result<()>) -> io::Result<()> {
+        let mut retried = false;
+        self.retry(|| match operation() {
+            Err(err) if retried && err.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+            result => {
+                retried = true;
+                result
+            }
+        })
+    }
+}
+
+/// Errors worth retrying on network filesystems
+pub fn is_transient(err: &io::Error) -> bool {
+    match err.raw_os_error() {
+        Some(code) => [libc::EINTR, libc::EAGAIN, libc::ESTALE, libc::ETIMEDOUT].contains(&code),
+        None => matches!(
+            err.kind(),
+            io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ),
+    }
+}
+
+impl Filesystem for RetryFs<'_> {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.retry(|| self.inner.metadata(path))
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.retry(|| self.inner.symlink_metadata(path))
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<()> {
+        self.retry(|| self.inner.create_file(path))
+    }
+
+    fn create_file_with_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        // As in retry_create, but a lost reply may also have hidden a failed
+        // futimens, so set the times of the file the earlier attempt created
+        let mut retried = false;
+        self.retry(|| match self.inner.create_file_with_times(path, accessed, modified) {
+            Err(err) if retried && err.kind() == io::ErrorKind::AlreadyExists && !self.inner.is_symlink(path) => {
+                self.inner.set_times(path, accessed, modified)
+            }
+            result => {
+                retried = true;
+                result
+            }
+        })
+    }
+
+    fn create_file_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.retry_create(|| self.inner.create_file_with_mode(path, mode))
+    }
+
+    fn write_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.retry(|| self.inner.write_file(path, contents))
+    }
+
+    fn append_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        // Not retried: a partial write followed by a retry would repeat the text
+        self.inner.append_file(path, contents)
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.retry(|| self.inner.read_file(path))
+    }
+
+    fn read_file_prefix(&self, path: &Path, limit: usize) -> io::Result<Vec<u8>> {
+        self.retry(|| self.inner.read_file_prefix(path, limit))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.retry_create(|| self.inner.create_dir(path))
+    }
+
+    fn create_dir_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.retry_create(|| self.inner.create_dir_with_mode(path, mode))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.retry(|| self.inner.create_dir_all(path))
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.retry(|| self.inner.set_permissions(path, mode))
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        self.retry(|| self.inner.set_owner(path, uid, gid))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.retry(|| self.inner.remove_file(path))
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.retry(|| self.inner.remove_dir(path))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        // A missing source after a failed attempt means that attempt went through
+        let mut retried = false;
+        self.retry(|| match self.inner.rename(from, to) {
+            Err(err) if retried && err.kind() == io::ErrorKind::NotFound && self.inner.symlink_metadata(to).is_ok() => Ok(()),
+            result => {
+                retried = true;
+                result
+            }
+        })
+    }
+
+    /// Not retried: an attempt that failed late may have moved the file already
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        self.inner.trash(path)
+    }
+
+    fn set_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        self.retry(|| self.inner.set_times(path, accessed, modified))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        self.retry(|| self.inner.read_dir(path))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.retry(|| self.inner.canonicalize(path))
+    }
+}
+
+/// Wraps another filesystem and counts the calls made through it, which
+/// `bank bench` reports per configuration
+pub struct CountingFs<'a> {
+    inner: &'a dyn Filesystem,
+    calls: Cell<u64>,
+}
+
+impl<'a> CountingFs<'a> {
+    pub fn new(inner: &'a dyn Filesystem) -> Self {
+        Self { inner, calls: Cell::new(0) }
+    }
+
+    pub fn calls(&self) -> u64 {
+        self.calls.get()
+    }
+
+    fn count<T>(&self, result: T) -> T {
+        self.calls.set(self.calls.get() + 1);
+        result
+    }
+}
+
+impl Filesystem for CountingFs<'_> {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.count(self.inner.metadata(path))
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.count(self.inner.symlink_metadata(path))
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<()> {
+        self.count(self.inner.create_file(path))
+    }
+
+    fn create_file_with_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        self.count(self.inner.create_file_with_times(path, accessed, modified))
+    }
+
+    fn create_file_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.count(self.inner.create_file_with_mode(path, mode))
+    }
+
+    fn write_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.count(self.inner.write_file(path, contents))
+    }
+
+    fn append_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.count(self.inner.append_file(path, contents))
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.count(self.inner.read_file(path))
+    }
+
+    fn read_file_prefix(&self, path: &Path, limit: usize) -> io::Result<Vec<u8>> {
+        self.count(self.inner.read_file_prefix(path, limit))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.count(self.inner.create_dir(path))
+    }
+
+    fn create_dir_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.count(self.inner.create_dir_with_mode(path, mode))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.count(self.inner.create_dir_all(path))
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.count(self.inner.set_permissions(path, mode))
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        self.count(self.inner.set_owner(path, uid, gid))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.count(self.inner.remove_file(path))
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.count(self.inner.remove_dir(path))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.count(self.inner.rename(from, to))
+    }
+
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        self.count(self.inner.trash(path))
+    }
+
+    fn set_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        self.count(self.inner.set_times(path, accessed, modified))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        self.count(self.inner.read_dir(path))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.count(self.inner.canonicalize(path))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.count(self.inner.exists(path))
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.count(self.inner.is_dir(path))
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        self.count(self.inner.is_symlink(path))
+    }
+}
+
+/// What a run did to a path, as seen by [`RecordingFs`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Created,
+    Modified,
+}
+
+/// What a [`RecordingFs`] hands on to another: each change with the metadata
+/// from before it and where a file it replaced was trashed, if any
+pub type Log = Vec<(PathBuf, Change, Option<Metadata>, Option<PathBuf>)>;
+
+/// Wraps another filesystem and remembers every path it creates or modifies,
+/// in the order they were first touched, along with the metadata modified
+/// and trashed paths had before
+pub struct RecordingFs<'a> {
+    inner: &'a dyn Filesystem,
+    changes: RefCell<Vec<(PathBuf, Change)>>,
+    /// Where each path is in `changes`, so long runs are not quadratic
+    index: RefCell<HashMap<PathBuf, usize>>,
+    before: RefCell<HashMap<PathBuf, Metadata>>,
+    /// Where the file that was at a path went to the trash
+    trashed: RefCell<HashMap<PathBuf, PathBuf>>,
+}
+
+impl<'a> RecordingFs<'a> {
+    pub fn new(inner: &'a dyn Filesystem) -> Self {
+        Self {
+            inner,
+            changes: RefCell::new(Vec::new()),
+            index: RefCell::new(HashMap::new()),
+            before: RefCell::new(HashMap::new()),
+            trashed: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn changes(&self) -> Vec<(PathBuf, Change)> {
+        self.changes.borrow().clone()
+    }
+
+    /// Metadata of a modified path from before this run first touched it
+    pub fn before(&self, path: &Path) -> Option<Metadata> {
+        self.before.borrow().get(path).cloned()
+    }
+
+    /// Where this run trashed the file that was at `path`
+    pub fn trashed(&self, path: &Path) -> Option<PathBuf> {
+        self.trashed.borrow().get(path).cloned()
+    }
+
+    /// Whether the run left anything different: a chmod to the same mode, a
+    /// chown to the same owner or a touch with the same times is recorded,
+    /// but is not a change
+    pub fn changed(&self) -> bool {
+        self.changes.borrow().iter().any(|(path, change)| match (change, self.before(path), self.inner.metadata(path)) {
+            (Change::Modified, Some(before), Ok(after)) => {
+                before.mode != after.mode
+                    || (before.uid, before.gid) != (after.uid, after.gid)
+                    || before.accessed != after.accessed
+                    || before.modified != after.modified
+            }
+            _ => true,
+        })
+    }
+
+    /// Remember what an existing path looks like before its first change
+    fn snapshot(&self, path: &Path) {
+        if self.index.borrow().contains_key(path) || self.before.borrow().contains_key(path) {
+            return;
+        }
+        if let Ok(metadata) = self.inner.metadata(path) {
+            self.before.borrow_mut().insert(path.to_path_buf(), metadata);
+        }
+    }
+
+    fn record(&self, path: &Path, change: Change) {
+        let mut changes = self.changes.borrow_mut();
+        let mut index = self.index.borrow_mut();
+        match index.get(path) {
+            // Creating what this run already modified still counts as creating it
+            Some(&position) => {
+                if change == Change::Created {
+                    changes[position].1 = Change::Created;
+                }
+            }
+            None => {
+                index.insert(path.to_path_buf(), changes.len());
+                changes.push((path.to_path_buf(), change));
+            }
+        }
+    }
+
+    /// What was recorded, to hand to another `RecordingFs` with `absorb`
+    pub fn into_log(self) -> Log {
+        let mut before = self.before.into_inner();
+        let mut trashed = self.trashed.into_inner();
+        self.changes.into_inner().into_iter().map(|(path, change)| {
+            let metadata = before.remove(&path);
+            let trashed = trashed.remove(&path);
+            (path, change, metadata, trashed)
+        }).collect()
+    }
+
+    /// Add the changes another `RecordingFs` made after those made here
+    pub fn absorb(&self, log: Log) {
+        for (path, change, metadata, trashed) in log {
+            if let Some(metadata) = metadata {
+                if !self.index.borrow().contains_key(&path) {
+                    self.before.borrow_mut().entry(path.clone()).or_insert(metadata);
+                }
+            }
+            if let Some(trashed) = trashed {
+                self.trashed.borrow_mut().entry(path.clone()).or_insert(trashed);
+            }
+            self.record(&path, change);
+        }
+    }
+
+    fn recorded<T>(&self, path: &Path, change: Change, result: io::Result<T>) -> io::Result<T> {
+        if result.is_ok() {
+            self.record(path, change);
+        }
+        result
+    }
+}
+
+impl Filesystem for RecordingFs<'_> {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.symlink_metadata(path)
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<()> {
+        let change = if self.inner.exists(path) { Change::Modified } else { Change::Created };
+        self.snapshot(path);
+        self.recorded(path, change, self.inner.create_file(path))
+    }
+
+    fn create_file_with_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        let existed = self.inner.symlink_metadata(path).is_ok();
+        let result = self.inner.create_file_with_times(path, accessed, modified);
+        // A file whose times could not be set was still created
+        if !existed && self.inner.symlink_metadata(path).is_ok() {
+            self.record(path, Change::Created);
+        }
+        result
+    }
+
+    fn create_file_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.recorded(path, Change::Created, self.inner.create_file_with_mode(path, mode))
+    }
+
+    fn write_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let change = if self.inner.exists(path) { Change::Modified } else { Change::Created };
+        self.snapshot(path);
+        self.recorded(path, change, self.inner.write_file(path, contents))
+    }
+
+    fn append_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let change = if self.inner.exists(path) { Change::Modified } else { Change::Created };
+        self.snapshot(path);
+        self.recorded(path, change, self.inner.append_file(path, contents))
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.inner.read_file(path)
+    }
+
+    fn read_file_prefix(&self, path: &Path, limit: usize) -> io::Result<Vec<u8>> {
+        self.inner.read_file_prefix(path, limit)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.recorded(path, Change::Created, self.inner.create_dir(path))
+    }
+
+    fn create_dir_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.recorded(path, Change::Created, self.inner.create_dir_with_mode(path, mode))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let missing: Vec<PathBuf> = path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .take_while(|ancestor| !self.inner.exists(ancestor))
+            .map(Path::to_path_buf)
+            .collect();
+        self.inner.create_dir_all(path)?;
+        for dir in missing.iter().rev() {
+            self.record(dir, Change::Created);
+        }
+        Ok(())
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.snapshot(path);
+        self.recorded(path, Change::Modified, self.inner.set_permissions(path, mode))
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        self.snapshot(path);
+        self.recorded(path, Change::Modified, self.inner.set_owner(path, uid, gid))
+    }
+
+    /// Removals are not recorded; the recorder only tracks what a run leaves behind
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.inner.remove_dir(path)
+    }
+
+    /// Like removals, not recorded: undo could not move the path back
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    /// Not a change of its own, but whatever replaces the file can be undone
+    /// by moving it back
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        let metadata = self.inner.symlink_metadata(path);
+        let trashed = self.inner.trash(path)?;
+        if !self.index.borrow().contains_key(path) {
+            if let Ok(metadata) = metadata {
+                self.before.borrow_mut().entry(path.to_path_buf()).or_insert(metadata);
+            }
+            self.trashed.borrow_mut().entry(path.to_path_buf()).or_insert_with(|| trashed.clone());
+        }
+        Ok(trashed)
+    }
+
+    fn set_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        self.snapshot(path);
+        self.recorded(path, Change::Modified, self.inner.set_times(path, accessed, modified))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        self.inner.read_dir(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone)]
+struct MemoryNode {
+    kind: FileKind,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    accessed: SystemTime,
+    modified: SystemTime,
+    contents: Vec<u8>,
+}
+
+/// A hermetic in-memory filesystem for tests, with deterministic failure injection.
+///
+/// Paths are normalized lexically; there is always a root directory. Symlinks
+/// are not modelled.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryFs {
+    nodes: RefCell<BTreeMap<PathBuf, MemoryNode>>,
+    failures: RefCell<HashMap<PathBuf, (io::ErrorKind, Option<u32>)>>,
+}
+
+#[cfg(test)]
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What was written to a file
+    pub fn contents(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        self.nodes.borrow().get(&normalize(path.as_ref())).map(|node| node.contents.clone())
+    }
+
+    /// Make every mutating operation on `path` fail with `kind`
+    /// (e.g. `StorageFull` for ENOSPC, `PermissionDenied` for EPERM)
+    pub fn fail_on(&self, path: impl AsRef<Path>, kind: io::ErrorKind) {
+        self.failures.borrow_mut().insert(normalize(path.as_ref()), (kind, None));
+    }
+
+    /// Like `fail_on`, but only for the next `times` operations on `path`
+    pub fn fail_times(&self, path: impl AsRef<Path>, kind: io::ErrorKind, times: u32) {
+        self.failures.borrow_mut().insert(normalize(path.as_ref()), (kind, Some(times)));
+    }
+
+    fn check_failure(&self, path: &Path) -> io::Result<()> {
+        let mut failures = self.failures.borrow_mut();
+        let Some((kind, remaining)) = failures.get_mut(path) else {
+            return Ok(());
+        };
+        let kind = *kind;
+        match remaining {
+            Some(0) => return Ok(()),
+            Some(count) => *count -= 1,
+            None => {}
+        }
+        Err(io::Error::new(kind, format!("injected failure for {}", path.display())))
+    }
+
+    fn node(&self, path: &Path) -> io::Result<MemoryNode> {
+        let path = normalize(path);
+        if is_root(&path) {
+            return Ok(MemoryNode {
+                kind: FileKind::Directory,
+                mode: 0o755,
+                uid: 0,
+                gid: 0,
+                accessed: SystemTime::UNIX_EPOCH,
+                modified: SystemTime::UNIX_EPOCH,
+                contents: Vec::new(),
+            });
+        }
+        self.nodes
+            .borrow()
+            .get(&path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display())))
+    }
+
+    fn require_parent_dir(&self, path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(parent) if !is_root(parent) => match self.node(parent)?.kind {
+                FileKind::Directory => Ok(()),
+                _ => Err(io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", parent.display()))),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn insert(&self, path: PathBuf, kind: FileKind) {
+        let now = SystemTime::now();
+        let mode = if kind == FileKind::Directory { 0o755 } else { 0o644 };
+        self.nodes.borrow_mut().insert(path, MemoryNode { kind, mode, uid: 0, gid: 0, accessed: now, modified: now, contents: Vec::new() });
+    }
+
+    fn update(&self, path: &Path, apply: impl FnOnce(&mut MemoryNode)) -> io::Result<()> {
+        let path = normalize(path);
+        self.check_failure(&path)?;
+        let mut nodes = self.nodes.borrow_mut();
+        let node = nodes
+            .get_mut(&path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display())))?;
+        apply(node);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Filesystem for MemoryFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let node = self.node(path)?;
+        Ok(Metadata {
+            kind: node.kind,
+            mode: node.mode,
+            len: node.contents.len() as u64,
+            uid: node.uid,
+            gid: node.gid,
+            accessed: node.accessed,
+            modified: node.modified,
+        })
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.metadata(path)
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        self.check_failure(&path)?;
+        self.require_parent_dir(&path)?;
+        if let Ok(node) = self.node(&path) {
+            if node.kind == FileKind::Directory {
+                return Err(io::Error::new(io::ErrorKind::IsADirectory, format!("{} is a directory", path.display())));
+            }
+        }
+        self.insert(path, FileKind::File);
+        Ok(())
+    }
+
+    fn create_file_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        if self.node(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", path.display())));
+        }
+        self.create_file(path)?;
+        self.update(path, |node| node.mode = mode & 0o7777)
+    }
+
+    fn write_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.create_file(path)?;
+        self.update(path, |node| node.contents = contents.to_vec())
+    }
+
+    fn append_file(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        if !self.exists(path) {
+            return self.write_file(path, contents);
+        }
+        self.update(path, |node| node.contents.extend_from_slice(contents))
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let node = self.node(path)?;
+        if node.kind == FileKind::Directory {
+            return Err(io::Error::new(io::ErrorKind::IsADirectory, format!("{} is a directory", path.display())));
+        }
+        Ok(node.contents)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        self.check_failure(&path)?;
+        if self.node(&path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", path.display())));
+        }
+        self.require_parent_dir(&path)?;
+        self.insert(path, FileKind::Directory);
+        Ok(())
+    }
+
+    fn create_dir_with_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.create_dir(path)?;
+        self.update(path, |node| node.mode = mode & 0o7777)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut current = PathBuf::new();
+        for component in path.components() {
+            current.push(component);
+            if is_root(&current) {
+                continue;
+            }
+            match self.node(&current) {
+                Ok(node) if node.kind == FileKind::Directory => {}
+                Ok(_) => {
+                    return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists and is not a directory", current.display())))
+                }
+                Err(_) => self.create_dir(&current)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.update(path, |node| node.mode = mode & 0o7777)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        self.update(path, |node| {
+            node.uid = uid.unwrap_or(node.uid);
+            node.gid = gid.unwrap_or(node.gid);
+        })
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        self.check_failure(&path)?;
+        if self.node(&path)?.kind == FileKind::Directory {
+            return Err(io::Error::new(io::ErrorKind::IsADirectory, format!("{} is a directory", path.display())));
+        }
+        self.nodes.borrow_mut().remove(&path);
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        self.check_failure(&path)?;
+        if self.node(&path)?.kind != FileKind::Directory {
+            return Err(io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", path.display())));
+        }
+        if self.nodes.borrow().keys().any(|child| child.parent() == Some(path.as_path())) {
+            return Err(io::Error::new(io::ErrorKind::DirectoryNotEmpty, format!("{} is not empty", path.display())));
+        }
+        self.nodes.borrow_mut().remove(&path);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (normalize(from), normalize(to));
+        self.check_failure(&from)?;
+        self.check_failure(&to)?;
+        let node = self.node(&from)?;
+        self.require_parent_dir(&to)?;
+        if let Ok(existing) = self.node(&to) {
+            if existing.kind == FileKind::Directory || node.kind == FileKind::Directory {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", to.display())));
+            }
+        }
+        let mut nodes = self.nodes.borrow_mut();
+        let moved: Vec<PathBuf> = nodes.keys().filter(|path| path.starts_with(&from)).cloned().collect();
+        for path in moved {
+            let node = nodes.remove(&path).expect("key was just listed");
+            let relative = path.strip_prefix(&from).expect("filtered on the prefix");
+            nodes.insert(if relative.as_os_str().is_empty() { to.clone() } else { to.join(relative) }, node);
+        }
+        Ok(())
+    }
+
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, format!("cannot trash {} in memory", path.display())))
+    }
+
+    fn set_times(&self, path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+        self.update(path, |node| {
+            node.accessed = accessed;
+            node.modified = modified;
+        })
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let path = normalize(path);
+        if self.node(&path)?.kind != FileKind::Directory {
+            return Err(io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", path.display())));
+        }
+        let nodes = self.nodes.borrow();
+        Ok(nodes
+            .iter()
+            .filter(|(child, _)| child.parent() == Some(path.as_path()))
+            .map(|(child, node)| DirEntry {
+                name: child.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                kind: node.kind,
+            })
+            .collect())
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = normalize(path);
+        self.node(&path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+fn is_root(path: &Path) -> bool {
+    path.as_os_str().is_empty() || path == Path::new("/")
+}
+
+/// Lexically normalize a path: drop `.` components and resolve `..`
+#[cfg(test)]
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_fs_basic_operations() {
+        let memory = MemoryFs::new();
+        memory.create_dir_all(Path::new("a/b")).unwrap();
+        memory.create_file(Path::new("a/b/c.txt")).unwrap();
+
+        assert!(memory.is_dir(Path::new("a/./b")));
+        assert!(!memory.is_dir(Path::new("a/b/c.txt")));
+        assert_eq!(memory.read_dir(Path::new("a/b")).unwrap()[0].name, "c.txt");
+        assert_eq!(
+            memory.create_dir(Path::new("a")).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(
+            memory.create_file(Path::new("missing/x")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_memory_fs_injected_failures() {
+        let memory = MemoryFs::new();
+        memory.fail_on("full.txt", io::ErrorKind::StorageFull);
+        assert_eq!(
+            memory.create_file(Path::new("./full.txt")).unwrap_err().kind(),
+            io::ErrorKind::StorageFull
+        );
+        assert!(!memory.exists(Path::new("full.txt")));
+    }
+
+    #[test]
+    fn test_local_fs_creates_with_mode() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let (dir, file) = (temp_dir.path().join("private"), temp_dir.path().join("private/key"));
+        LocalFs.create_dir_with_mode(&dir, 0o700).unwrap();
+        LocalFs.create_file_with_mode(&file, 0o600).unwrap();
+        assert_eq!(LocalFs.metadata(&dir).unwrap().mode, 0o700);
+        assert_eq!(LocalFs.metadata(&file).unwrap().mode, 0o600);
+        // Only ever new files, and never through a symlink
+        assert_eq!(LocalFs.create_file_with_mode(&file, 0o600).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        std::os::unix::fs::symlink(temp_dir.path().join("elsewhere"), temp_dir.path().join("link")).unwrap();
+        assert!(LocalFs.create_file_with_mode(&temp_dir.path().join("link"), 0o600).is_err());
+        assert!(!temp_dir.path().join("elsewhere").exists());
+    }
+
+    #[test]
+    fn test_recording_fs_tracks_changes() {
+        let memory = MemoryFs::new();
+        memory.create_dir(Path::new("existing")).unwrap();
+        let fs = RecordingFs::new(&memory);
+        fs.create_dir_all(Path::new("a/b")).unwrap();
+        fs.create_file(Path::new("a/b/c.txt")).unwrap();
+        fs.set_permissions(Path::new("a/b/c.txt"), 0o600).unwrap();
+        fs.set_times(Path::new("existing"), SystemTime::UNIX_EPOCH, SystemTime::UNIX_EPOCH).unwrap();
+        assert!(fs.create_dir(Path::new("missing/x")).is_err());
+
+        assert_eq!(
+            fs.changes(),
+            vec![
+                (PathBuf::from("a"), Change::Created),
+                (PathBuf::from("a/b"), Change::Created),
+                (PathBuf::from("a/b/c.txt"), Change::Created),
+                (PathBuf::from("existing"), Change::Modified),
+            ]
+        );
+        assert_eq!(fs.before(Path::new("existing")).map(|m| m.mode), Some(0o755));
+        assert!(fs.before(Path::new("a/b/c.txt")).is_none());
+        assert!(fs.changed());
+
+        // Setting what is already there is recorded, but changes nothing
+        let fs = RecordingFs::new(&memory);
+        fs.set_permissions(Path::new("existing"), 0o755).unwrap();
+        fs.set_times(Path::new("existing"), SystemTime::UNIX_EPOCH, SystemTime::UNIX_EPOCH).unwrap();
+        fs.set_owner(Path::new("existing"), Some(0), None).unwrap();
+        assert_eq!(fs.changes().len(), 1);
+        assert!(!fs.changed());
+        fs.set_owner(Path::new("existing"), None, Some(1000)).unwrap();
+        assert!(fs.changed());
+        assert_eq!(memory.metadata(Path::new("existing")).unwrap().gid, 1000);
+    }
+
+    #[test]
+    fn test_recording_fs_absorbs_another_log() {
+        let memory = MemoryFs::new();
+        memory.create_dir(Path::new("existing")).unwrap();
+        let fs = RecordingFs::new(&memory);
+        fs.create_dir(Path::new("a")).unwrap();
+        let worker = RecordingFs::new(&memory);
+        worker.create_file(Path::new("a/b.txt")).unwrap();
+        worker.set_permissions(Path::new("existing"), 0o700).unwrap();
+        fs.absorb(worker.into_log());
+
+        assert_eq!(
+            fs.changes(),
+            vec![
+                (PathBuf::from("a"), Change::Created),
+                (PathBuf::from("a/b.txt"), Change::Created),
+                (PathBuf::from("existing"), Change::Modified),
+            ]
+        );
+        assert_eq!(fs.before(Path::new("existing")).map(|m| m.mode), Some(0o755));
+    }
+
+    #[test]
+    fn test_retry_fs_retries_transient_errors() {
+        let memory = MemoryFs::new();
+        memory.fail_times("stamp", io::ErrorKind::Interrupted, 2);
+        let retrying = RetryFs::new(&memory, 2, Duration::from_millis(1));
+        retrying.create_file(Path::new("stamp")).unwrap();
+        assert!(memory.exists(Path::new("stamp")));
+
+        // Out of retries
+        memory.fail_times("stamp2", io::ErrorKind::TimedOut, 3);
+        assert!(retrying.create_file(Path::new("stamp2")).is_err());
+
+        // Permanent errors are not retried
+        memory.fail_times("full", io::ErrorKind::StorageFull, 1);
+        assert_eq!(
+            retrying.create_file(Path::new("full")).unwrap_err().kind(),
+            io::ErrorKind::StorageFull
+        );
+    }
+}
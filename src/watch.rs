@@ -0,0 +1,579 @@
+//! Long-running subcommands: `bank keepalive` and `bank trigger`
+
+use crate::glob::any_match;
+use crate::json;
+use crate::vfs::Filesystem;
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use clap::{Args as ClapArgs, ValueEnum};
+use colored::*;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often a sleeping loop wakes up to check for SIGINT/SIGTERM
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct KeepaliveArgs {
+    /// File whose timestamps are kept fresh
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// Interval between updates, e.g. 500ms, 60s, 5m, 1h
+    #[arg(long = "every", value_name = "DURATION", default_value = "60s", value_parser = parse_duration)]
+    pub every: Duration,
+
+    /// Do not create FILE if it is missing; wait for it to appear instead
+    #[arg(short = 'c', long = "no-create")]
+    pub no_create: bool,
+
+    /// Output format; `jsonl` prints one JSON event per line
+    #[arg(long = "output", value_name = "FORMAT", value_enum, default_value = "text")]
+    pub output: OutputFormat,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct TriggerArgs {
+    /// Glob of paths to watch, e.g. 'src/**' or '*.rs' (repeatable)
+    #[arg(long = "watch", value_name = "GLOB", required = true)]
+    pub watch: Vec<String>,
+
+    /// File to touch (or create) whenever a watched path changes
+    #[arg(long = "target", value_name = "FILE")]
+    pub target: PathBuf,
+
+    /// Do not create the target if it is missing
+    #[arg(short = 'c', long = "no-create")]
+    pub no_create: bool,
+
+    /// Quiet period after the last change before the target is touched
+    #[arg(long = "debounce", value_name = "DURATION", default_value = "200ms", value_parser = parse_duration)]
+    pub debounce: Duration,
+
+    /// Keep collecting changes for at least this long after the first one,
+    /// so event storms with pauses (e.g. git checkout) still cause a single touch
+    #[arg(long = "batch-window", value_name = "DURATION", value_parser = parse_duration)]
+    pub batch_window: Option<Duration>,
+
+    /// Output format; `jsonl` prints one JSON event per line
+    #[arg(long = "output", value_name = "FORMAT", value_enum, default_value = "text")]
+    pub output: OutputFormat,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable, colored messages
+    Text,
+    /// One JSON object per line, for event logs
+    Jsonl,
+}
+
+/// Print a JSONL event; `fields` values must already be JSON-encoded
+fn emit_event(event: &str, fields: &[(&str, String)]) {
+    let mut line = format!(
+        "{{\"time\":{},\"event\":{}",
+        json::quote(&Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+        json::quote(event)
+    );
+    for (key, value) in fields {
+        line.push_str(&format!(",{}:{}", json::quote(key), value));
+    }
+    line.push('}');
+    println!("{}", line);
+}
+
+/// Groups change events into one touch: due once `debounce` has passed since the
+/// last change and `window` since the first
+struct Batch {
+    debounce: Duration,
+    window: Duration,
+    first: Option<Instant>,
+    last: Option<Instant>,
+    changes: u64,
+}
+
+impl Batch {
+    fn new(debounce: Duration, window: Duration) -> Self {
+        Self { debounce, window, first: None, last: None, changes: 0 }
+    }
+
+    fn record(&mut self, now: Instant) {
+        self.first.get_or_insert(now);
+        self.last = Some(now);
+        self.changes += 1;
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        Some((self.first? + self.window).max(self.last? + self.debounce))
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        self.deadline().is_some_and(|deadline| now >= deadline)
+    }
+
+    /// How long to wait for more events before re-checking
+    fn timeout(&self, now: Instant) -> Duration {
+        match self.deadline() {
+            Some(deadline) => deadline.saturating_duration_since(now).min(POLL_INTERVAL),
+            None => POLL_INTERVAL,
+        }
+    }
+
+    /// Close the batch, returning how many changes it absorbed
+    fn take(&mut self) -> u64 {
+        self.first = None;
+        self.last = None;
+        std::mem::take(&mut self.changes)
+    }
+}
+
+/// Parse `300ms`, `60s`, `5m`, `1h`, `7d`, `2w` or a bare number of seconds
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}' (expected e.g. 300ms, 60s, 5m)", text))?;
+    let seconds = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" | "min" => value * 60.0,
+        "h" => value * 3600.0,
+        "d" => value * 86400.0,
+        "w" => value * 7.0 * 86400.0,
+        other => return Err(format!("unknown duration unit '{}' (use ms, s, m, h, d or w)", other)),
+    };
+    if seconds <= 0.0 {
+        return Err("duration must be greater than zero".to_string());
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+extern "C" fn request_stop(_signal: libc::c_int) {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Route SIGINT and SIGTERM to a flag so loops can finish their current step and exit cleanly
+pub fn install_stop_handler() {
+    let handler = request_stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Sleep for `duration`, returning early (with `false`) once a stop is requested
+pub fn interruptible_sleep(duration: Duration, should_stop: &dyn Fn() -> bool) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if should_stop() {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        std::thread::sleep((deadline - now).min(POLL_INTERVAL));
+    }
+}
+
+/// Create `path` unless `no_create`, then set both of its times to now.
+/// Returns false if the file is missing and was not created.
+fn touch_now(fs: &dyn Filesystem, path: &Path, no_create: bool) -> Result<bool> {
+    if !fs.exists(path) {
+        if no_create {
+            return Ok(false);
+        }
+        fs.create_file(path)
+            .with_context(|| format!("Failed to create file {}", path.display()))?;
+    }
+    let now = SystemTime::now();
+    fs.set_times(path, now, now)
+        .with_context(|| format!("Failed to set timestamps for {}", path.display()))?;
+    Ok(true)
+}
+
+pub fn run_keepalive(fs: &dyn Filesystem, args: &KeepaliveArgs) -> Result<()> {
+    install_stop_handler();
+    let updates = keepalive(fs, args, &stop_requested)?;
+    if args.output == OutputFormat::Jsonl {
+        emit_event("stop", &[("updates", updates.to_string())]);
+    } else if args.verbose {
+        println!("Stopped after {} updates", updates.to_string().cyan());
+    }
+    Ok(())
+}
+
+/// Touch `args.file` every `args.every` until `should_stop` says otherwise; returns the number of updates
+pub fn keepalive(fs: &dyn Filesystem, args: &KeepaliveArgs, should_stop: &dyn Fn() -> bool) -> Result<u64> {
+    let path = args.file.as_path();
+    let mut updates = 0;
+    let mut warned_missing = false;
+
+    loop {
+        let file = json::quote(&path.to_string_lossy());
+        if !touch_now(fs, path, args.no_create)? {
+            if !warned_missing {
+                if args.output == OutputFormat::Jsonl {
+                    emit_event("missing", &[("path", file)]);
+                } else {
+                    println!("{} {} does not exist, waiting for it", "Warning:".yellow(), path.display());
+                }
+                warned_missing = true;
+            }
+        } else {
+            updates += 1;
+            warned_missing = false;
+            if args.output == OutputFormat::Jsonl {
+                emit_event("touch", &[("path", file)]);
+            } else if args.verbose {
+                println!("{} Touched: {}", "✓".bright_green(), path.display().to_string().green());
+            }
+        }
+
+        if !interruptible_sleep(args.every, should_stop) {
+            return Ok(updates);
+        }
+    }
+}
+
+/// Directory to watch for a glob: its literal leading components, or `.`
+pub fn watch_root(pattern: &str) -> PathBuf {
+    let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+    if !pattern.contains('/') {
+        return PathBuf::from(".");
+    }
+    let mut root = PathBuf::new();
+    for component in pattern.split('/') {
+        if component.contains(['*', '?', '[']) {
+            break;
+        }
+        root.push(component);
+    }
+    // A literal file pattern watches the directory that contains it
+    if root.as_os_str() == pattern {
+        root.pop();
+    }
+    if root.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        root
+    }
+}
+
+/// Path as compared against the watch globs: `/`-separated, without a leading `./`
+fn match_key(path: &Path) -> String {
+    let text = path.to_string_lossy();
+    text.strip_prefix("./").unwrap_or(&text).to_string()
+}
+
+#[cfg(target_os = "linux")]
+pub fn run_trigger(fs: &dyn Filesystem, args: &TriggerArgs) -> Result<()> {
+    install_stop_handler();
+
+    let mut watcher = inotify::Watcher::new()?;
+    let mut roots: Vec<PathBuf> = args.watch.iter().map(|pattern| watch_root(pattern)).collect();
+    roots.sort();
+    roots.dedup();
+    for root in &roots {
+        watcher
+            .add_tree(root)
+            .with_context(|| format!("Failed to watch {}", root.display()))?;
+    }
+    let jsonl = args.output == OutputFormat::Jsonl;
+    let target = json::quote(&args.target.to_string_lossy());
+    if jsonl {
+        let patterns: Vec<String> = args.watch.iter().map(|pattern| json::quote(pattern)).collect();
+        emit_event("start", &[("watch", format!("[{}]", patterns.join(","))), ("target", target.clone())]);
+    } else if args.verbose {
+        println!("Watching {} for changes to {}", args.watch.join(", ").cyan(), args.target.display().to_string().green());
+    }
+
+    let target_key = match_key(&args.target);
+    let mut batch = Batch::new(args.debounce, args.batch_window.unwrap_or(Duration::ZERO));
+    while !stop_requested() {
+        for change in watcher.wait(batch.timeout(Instant::now()))? {
+            match change {
+                inotify::Change::Overflow => {
+                    if jsonl {
+                        emit_event("overflow", &[]);
+                    }
+                    batch.record(Instant::now());
+                }
+                inotify::Change::Path { path, new_dir } => {
+                    if new_dir {
+                        // Best effort: the directory may already be gone again
+                        let _ = watcher.add_tree(&path);
+                    }
+                    let key = match_key(&path);
+                    if key != target_key && any_match(&args.watch, &key) {
+                        if jsonl {
+                            emit_event("change", &[("path", json::quote(&key))]);
+                        } else if args.verbose {
+                            println!("Changed: {}", key.yellow());
+                        }
+                        batch.record(Instant::now());
+                    }
+                }
+            }
+        }
+
+        if batch.is_due(Instant::now()) {
+            let changes = batch.take();
+            let touched = touch_now(fs, &args.target, args.no_create)?;
+            if jsonl {
+                let event = if touched { "touch" } else { "missing" };
+                emit_event(event, &[("path", target.clone()), ("changes", changes.to_string())]);
+            } else if touched {
+                println!("{} Touched: {}", "✓".bright_green(), args.target.display().to_string().green());
+            } else {
+                println!("{} {} does not exist, not creating it", "Warning:".yellow(), args.target.display());
+            }
+        }
+    }
+
+    if jsonl {
+        emit_event("stop", &[]);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn run_trigger(_fs: &dyn Filesystem, _args: &TriggerArgs) -> Result<()> {
+    anyhow::bail!("bank trigger currently requires inotify (Linux)")
+}
+
+/// Minimal recursive inotify watcher
+#[cfg(target_os = "linux")]
+mod inotify {
+    use anyhow::{Context, Result};
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    const EVENT_MASK: u32 = libc::IN_CREATE
+        | libc::IN_MODIFY
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO;
+
+    pub enum Change {
+        /// Something happened to `path`; `new_dir` if it is a directory that just appeared
+        Path { path: PathBuf, new_dir: bool },
+        /// The kernel queue overflowed and events were lost
+        Overflow,
+    }
+
+    pub struct Watcher {
+        fd: OwnedFd,
+        dirs: HashMap<i32, PathBuf>,
+    }
+
+    impl Watcher {
+        pub fn new() -> Result<Self> {
+            // SAFETY: plain syscall without pointer arguments
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error()).context("Failed to initialize inotify");
+            }
+            // SAFETY: fd is a freshly created descriptor owned by nobody else
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            Ok(Self { fd, dirs: HashMap::new() })
+        }
+
+        /// Watch `dir` and every directory below it (symlinks are not followed)
+        pub fn add_tree(&mut self, dir: &Path) -> Result<()> {
+            self.add_dir(dir)?;
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    self.add_tree(&dir.join(entry.file_name()))?;
+                }
+            }
+            Ok(())
+        }
+
+        fn add_dir(&mut self, dir: &Path) -> Result<()> {
+            let c_path = CString::new(dir.as_os_str().as_bytes())?;
+            // SAFETY: c_path is a valid NUL-terminated string
+            let wd = unsafe {
+                libc::inotify_add_watch(self.fd.as_raw_fd(), c_path.as_ptr(), EVENT_MASK | libc::IN_ONLYDIR)
+            };
+            if wd < 0 {
+                return Err(io::Error::last_os_error()).with_context(|| format!("Failed to watch {}", dir.display()));
+            }
+            self.dirs.insert(wd, dir.to_path_buf());
+            Ok(())
+        }
+
+        /// Wait up to `timeout` for events and return everything that is queued
+        pub fn wait(&mut self, timeout: Duration) -> Result<Vec<Change>> {
+            let mut poll_fd = libc::pollfd { fd: self.fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            // SAFETY: poll_fd lives for the duration of the call
+            let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) };
+            if ready < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    return Ok(Vec::new());
+                }
+                return Err(err).context("Failed to wait for inotify events");
+            }
+
+            let mut changes = Vec::new();
+            let mut buffer = [0u8; 16 * 1024];
+            loop {
+                // SAFETY: buffer is writable for its full length
+                let read = unsafe { libc::read(self.fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len()) };
+                if read < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::Interrupted {
+                        break;
+                    }
+                    return Err(err).context("Failed to read inotify events");
+                }
+                self.parse(&buffer[..read as usize], &mut changes);
+            }
+            Ok(changes)
+        }
+
+        fn parse(&mut self, mut bytes: &[u8], changes: &mut Vec<Change>) {
+            let header = std::mem::size_of::<libc::inotify_event>();
+            while bytes.len() >= header {
+                // SAFETY: at least `header` bytes remain; read_unaligned copes with any alignment
+                let event: libc::inotify_event = unsafe { std::ptr::read_unaligned(bytes.as_ptr().cast()) };
+                let end = (header + event.len as usize).min(bytes.len());
+                let name = &bytes[header..end];
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                bytes = &bytes[end..];
+
+                if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                    changes.push(Change::Overflow);
+                    continue;
+                }
+                if event.mask & libc::IN_IGNORED != 0 {
+                    self.dirs.remove(&event.wd);
+                    continue;
+                }
+                let Some(dir) = self.dirs.get(&event.wd) else { continue };
+                let path = if name.is_empty() { dir.clone() } else { dir.join(OsStr::from_bytes(name)) };
+                let new_dir = event.mask & libc::IN_ISDIR != 0 && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0;
+                changes.push(Change::Path { path, new_dir });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+    use std::cell::Cell;
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("300ms").unwrap(), Duration::from_millis(300));
+        assert_eq!(parse_duration("60s").unwrap(), Duration::from_secs(60));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1.5h").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("10").unwrap(), Duration::from_secs(10));
+        assert_eq!(parse_duration("2d").unwrap(), Duration::from_secs(172_800));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("5 days").is_err());
+    }
+
+    #[test]
+    fn test_keepalive_touches_until_stopped() {
+        let memory = MemoryFs::new();
+        let args = KeepaliveArgs {
+            file: PathBuf::from("lease.lock"),
+            every: Duration::from_millis(1),
+            no_create: false,
+            output: OutputFormat::Text,
+            verbose: false,
+        };
+
+        // Allow three wake-ups, then ask the loop to stop
+        let checks = Cell::new(0);
+        let should_stop = || {
+            checks.set(checks.get() + 1);
+            checks.get() > 3
+        };
+        let updates = keepalive(&memory, &args, &should_stop).unwrap();
+
+        assert!(updates >= 1);
+        assert!(memory.exists(Path::new("lease.lock")));
+    }
+
+    #[test]
+    fn test_batch_waits_for_debounce_and_window() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut batch = Batch::new(ms(100), ms(1000));
+        assert!(!batch.is_due(start));
+
+        // A storm with pauses longer than the debounce stays one batch
+        batch.record(start);
+        batch.record(start + ms(300));
+        batch.record(start + ms(800));
+        assert!(!batch.is_due(start + ms(950)));
+        assert!(batch.is_due(start + ms(1000)));
+        assert_eq!(batch.take(), 3);
+        assert!(!batch.is_due(start + ms(5000)));
+
+        // Without a window only the debounce applies
+        let mut batch = Batch::new(ms(100), Duration::ZERO);
+        batch.record(start);
+        assert!(!batch.is_due(start + ms(50)));
+        assert!(batch.is_due(start + ms(100)));
+    }
+
+    #[test]
+    fn test_watch_root() {
+        assert_eq!(watch_root("src/**"), PathBuf::from("src"));
+        assert_eq!(watch_root("./assets/img/*.png"), PathBuf::from("assets/img"));
+        assert_eq!(watch_root("*.rs"), PathBuf::from("."));
+        assert_eq!(watch_root("config/app.toml"), PathBuf::from("config"));
+        assert_eq!(watch_root("**/*.md"), PathBuf::from("."));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_inotify_reports_nested_changes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src/deep")).unwrap();
+
+        let mut watcher = inotify::Watcher::new().unwrap();
+        watcher.add_tree(root).unwrap();
+        std::fs::write(root.join("src/deep/lib.rs"), "fn main() {}").unwrap();
+
+        let changes = watcher.wait(Duration::from_secs(2)).unwrap();
+        assert!(changes.iter().any(|change| matches!(
+            change,
+            inotify::Change::Path { path, .. } if path.ends_with("src/deep/lib.rs")
+        )));
+    }
+}
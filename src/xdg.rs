@@ -0,0 +1,194 @@
+//! `bank xdg`: create an application's config, data, cache and state
+//! directories where the platform expects them
+//!
+//! The locations follow the `directories` crate: on Linux and other Unixes
+//! the XDG base directories (`$XDG_CONFIG_HOME`, ... with the usual `~/.config`
+//! style fallbacks, ignoring relative values as the spec requires) and a
+//! lowercased application name without spaces; on macOS
+//! `~/Library/Application Support` (config, data and state) and
+//! `~/Library/Caches`, with the name's spaces turned into hyphens as in a
+//! bundle identifier. Every directory created, including missing base
+//! directories, gets mode 0700.
+
+use crate::journal;
+use crate::vfs::{Filesystem, RecordingFs};
+use anyhow::{bail, Context, Result};
+use clap::Args as ClapArgs;
+use colored::*;
+use std::path::{Path, PathBuf};
+
+/// Base directories are private to their user
+const MODE: u32 = 0o700;
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct XdgArgs {
+    /// Application name, e.g. "My Tool" (becomes `mytool` on Linux)
+    #[arg(value_name = "APPNAME")]
+    pub app: String,
+
+    /// Also create the application's directory under $XDG_RUNTIME_DIR
+    #[arg(long = "runtime")]
+    pub runtime: bool,
+
+    /// Only print the directories, creating nothing
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Do not record this run in the undo journal
+    #[arg(long = "no-journal")]
+    pub no_journal: bool,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+}
+
+/// An absolute, non-empty value of `key`; the spec says to ignore anything else
+fn absolute_var(env: &dyn Fn(&str) -> Option<String>, key: &str) -> Option<PathBuf> {
+    env(key).map(PathBuf::from).filter(|dir| dir.is_absolute())
+}
+
+/// The application's directories as `(kind, path)`, in creation order, on
+/// macOS or on the other Unixes
+fn locations(app: &str, runtime: bool, macos: bool, env: &dyn Fn(&str) -> Option<String>) -> Result<Vec<(&'static str, PathBuf)>> {
+    let home = absolute_var(env, "HOME").context("Cannot locate the XDG directories: HOME is not set to an absolute path")?;
+    if app.trim().is_empty() || app.contains('/') || app == "." || app == ".." {
+        bail!("'{}' is not usable as an application directory name", app);
+    }
+
+    let mut dirs = if macos {
+        let name: String = app.trim().chars().map(|c| if c.is_whitespace() { '-' } else { c }).collect();
+        let support = home.join("Library/Application Support").join(&name);
+        vec![
+            ("config", support.clone()),
+            ("data", support.clone()),
+            ("cache", home.join("Library/Caches").join(&name)),
+            ("state", support),
+        ]
+    } else {
+        let name: String = app.chars().filter(|c| !c.is_whitespace()).flat_map(char::to_lowercase).collect();
+        let base = |key, fallback| absolute_var(env, key).unwrap_or_else(|| home.join(fallback)).join(&name);
+        vec![
+            ("config", base("XDG_CONFIG_HOME", ".config")),
+            ("data", base("XDG_DATA_HOME", ".local/share")),
+            ("cache", base("XDG_CACHE_HOME", ".cache")),
+            ("state", base("XDG_STATE_HOME", ".local/state")),
+        ]
+    };
+    if runtime {
+        let Some(runtime_dir) = absolute_var(env, "XDG_RUNTIME_DIR") else {
+            bail!("--runtime needs XDG_RUNTIME_DIR to be set to an absolute path");
+        };
+        let name = dirs[0].1.file_name().map(PathBuf::from).unwrap_or_default();
+        dirs.push(("runtime", runtime_dir.join(name)));
+    }
+    // macOS uses one directory for several kinds
+    let mut seen = Vec::new();
+    dirs.retain(|(_, dir)| {
+        let first = !seen.contains(dir);
+        seen.push(dir.clone());
+        first
+    });
+    Ok(dirs)
+}
+
+/// Create `dir` and its missing ancestors, each with mode 0700; whether it was new
+fn create_private(fs: &dyn Filesystem, dir: &Path) -> Result<bool> {
+    let missing: Vec<&Path> = dir.ancestors().take_while(|ancestor| !fs.exists(ancestor)).collect();
+    for ancestor in missing.iter().rev() {
+        fs.create_dir(ancestor).with_context(|| format!("Failed to create directory {}", ancestor.display()))?;
+        fs.set_permissions(ancestor, MODE)
+            .with_context(|| format!("Failed to set permissions for {}", ancestor.display()))?;
+    }
+    if !missing.is_empty() {
+        return Ok(true);
+    }
+    if !fs.is_dir(dir) {
+        bail!("{} exists and is not a directory", dir.display());
+    }
+    Ok(false)
+}
+
+fn create_all(fs: &dyn Filesystem, dirs: &[(&'static str, PathBuf)], args: &XdgArgs) -> Result<()> {
+    for (kind, dir) in dirs {
+        if args.dry_run {
+            println!("{:<8} {}", kind, dir.display());
+            continue;
+        }
+        if create_private(fs, dir)? {
+            println!("{} {:<8} {}", "✓".bright_green(), kind, dir.display().to_string().green());
+            continue;
+        }
+        let mode = fs.metadata(dir).with_context(|| format!("Failed to read metadata for {}", dir.display()))?.mode;
+        if mode & 0o077 != 0 {
+            println!("{} {} is accessible to other users (mode {:o})", "Warning:".yellow(), dir.display(), mode);
+        } else if args.verbose {
+            println!("{} {:<8} {} (exists)", "·".dimmed(), kind, dir.display());
+        }
+    }
+    Ok(())
+}
+
+pub fn run(fs: &dyn Filesystem, args: &XdgArgs) -> Result<()> {
+    let dirs = locations(&args.app, args.runtime, cfg!(target_os = "macos"), &|key| std::env::var(key).ok())?;
+    let recording = RecordingFs::new(fs);
+    let result = create_all(&recording, &dirs, args);
+    if !args.dry_run && !args.no_journal {
+        if let Err(err) = journal::record(&recording, None) {
+            println!("{} {:#}", "Warning:".yellow(), err);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[test]
+    fn test_locations() {
+        let env = |key: &str| match key {
+            "HOME" => Some("/home/dev".to_string()),
+            "XDG_CACHE_HOME" => Some("/var/cache/dev".to_string()),
+            "XDG_DATA_HOME" => Some("relative/ignored".to_string()),
+            "XDG_RUNTIME_DIR" => Some("/run/user/1000".to_string()),
+            _ => None,
+        };
+        let dirs = locations("My Tool", true, false, &env).unwrap();
+        assert_eq!(
+            dirs,
+            vec![
+                ("config", PathBuf::from("/home/dev/.config/mytool")),
+                ("data", PathBuf::from("/home/dev/.local/share/mytool")),
+                ("cache", PathBuf::from("/var/cache/dev/mytool")),
+                ("state", PathBuf::from("/home/dev/.local/state/mytool")),
+                ("runtime", PathBuf::from("/run/user/1000/mytool")),
+            ]
+        );
+        assert!(locations("../x", false, false, &env).is_err());
+        assert!(locations("tool", false, false, &|_| None).is_err());
+
+        // XDG variables do not apply on macOS, and one directory serves several kinds
+        assert_eq!(
+            locations("My Tool", false, true, &env).unwrap(),
+            vec![
+                ("config", PathBuf::from("/home/dev/Library/Application Support/My-Tool")),
+                ("cache", PathBuf::from("/home/dev/Library/Caches/My-Tool")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_created_directories_are_private() {
+        let fs = MemoryFs::new();
+        fs.create_dir_all(Path::new("/home/dev")).unwrap();
+        assert!(create_private(&fs, Path::new("/home/dev/.config/tool")).unwrap());
+        assert_eq!(fs.metadata(Path::new("/home/dev/.config")).unwrap().mode, MODE);
+        assert_eq!(fs.metadata(Path::new("/home/dev/.config/tool")).unwrap().mode, MODE);
+        assert!(!create_private(&fs, Path::new("/home/dev/.config/tool")).unwrap());
+
+        fs.create_file(Path::new("/home/dev/.cache")).unwrap();
+        assert!(create_private(&fs, Path::new("/home/dev/.cache")).is_err());
+    }
+}
@@ -0,0 +1,29 @@
+//! `--changed-exit-code` decides the process's exit status, so it is checked
+//! on the built binary
+
+use assert_cmd::Command;
+use tempfile::TempDir;
+
+/// bank in `dir`, with its config and state kept there too
+fn bank(dir: &TempDir) -> Command {
+    let mut command = Command::cargo_bin("bank").unwrap();
+    command
+        .current_dir(dir.path())
+        .env("HOME", dir.path())
+        .env("XDG_CONFIG_HOME", dir.path().join("config"))
+        .env("XDG_STATE_HOME", dir.path().join("state"))
+        .env("XDG_DATA_HOME", dir.path().join("data"));
+    command
+}
+
+#[test]
+fn test_changed_exit_code() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("existing.txt"), b"").unwrap();
+
+    bank(&temp_dir).args(["--changed-exit-code", "existing.txt"]).assert().code(10);
+    bank(&temp_dir).args(["--changed-exit-code", "new.txt"]).assert().code(0);
+    assert!(temp_dir.path().join("new.txt").exists());
+    // Without the flag nothing to do is still a success
+    bank(&temp_dir).arg("existing.txt").assert().code(0);
+}